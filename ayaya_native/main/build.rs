@@ -1,7 +1,8 @@
-use image::GenericImageView;
+use image::codecs::{gif::GifDecoder, png::PngDecoder};
+use image::{AnimationDecoder, Frame, GenericImageView};
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::{env, slice};
 
@@ -324,6 +325,240 @@ pub fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> (u8, u8, u8) {
     (r as u8, g as u8, b as u8)
 }
 
+//Transparent pixels become 0, which the game canvas treats as transparent
+fn write_mc_pixels(output: &mut impl Write, rgba: &[u8]) -> Result<(), Box<dyn Error>> {
+    for pixel in rgba.chunks_exact(4) {
+        if pixel[3] != 255 {
+            let _ = output.write(slice::from_ref(&0u8))?;
+            continue;
+        }
+
+        let _ = output.write(slice::from_ref(
+            &(get_mc_index(MinecraftColor::new(pixel[0], pixel[1], pixel[2])) as u8),
+        ))?;
+    }
+
+    Ok(())
+}
+
+struct SpriteFrame {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    delay_ms: u32,
+}
+
+fn is_sheet_metadata(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sheet")
+}
+
+//Every asset can be used with bake_sprite!. Sheets are split by the grid of their .sheet file,
+//animated images get a frame per animation frame, anything else is a single frame
+fn bake_sprite(
+    path: &Path,
+    name: &str,
+    out_dir: &str,
+    width: u32,
+    height: u32,
+) -> Result<(), Box<dyn Error>> {
+    let sheet_path = path.with_extension("sheet");
+    let animation = load_animation(path)?;
+
+    if let Some(animation) = animation {
+        if sheet_path.exists() {
+            return Err(
+                format!("{} is animated, it can't be a sprite sheet", path.display()).into(),
+            );
+        }
+
+        //Frames are stacked on top of each other
+        let data_file = format!("{name}.frames.bin");
+        let mut output_file = BufWriter::new(File::create(format!("{out_dir}/{data_file}"))?);
+        let mut frames = Vec::new();
+        for (index, frame) in animation.iter().enumerate() {
+            let buffer = frame.buffer();
+            if buffer.dimensions() != (width, height) {
+                return Err(format!("Frames of {} have different sizes", path.display()).into());
+            }
+            write_mc_pixels(&mut output_file, buffer.as_raw())?;
+
+            //Browsers play tiny delays slower, most animations are made for that
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay_ms = match numer / denom.max(1) {
+                val if val < 20 => 100,
+                val => val,
+            };
+            frames.push(SpriteFrame {
+                x: 0,
+                y: index as u32 * height,
+                width,
+                height,
+                delay_ms,
+            });
+        }
+        output_file.flush()?;
+
+        let sheet_height = height * frames.len() as u32;
+        return write_sprite(
+            out_dir,
+            name,
+            Some((&data_file, width, sheet_height)),
+            &frames,
+            &[],
+        );
+    }
+
+    let data_file = format!("{name}.bin");
+    if !sheet_path.exists() {
+        let frame = SpriteFrame {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            delay_ms: 0,
+        };
+        return write_sprite(
+            out_dir,
+            name,
+            Some((&data_file, width, height)),
+            &[frame],
+            &[],
+        );
+    }
+
+    let (frames, names) = parse_sheet(&sheet_path, width, height)?;
+    write_sprite(
+        out_dir,
+        name,
+        Some((&data_file, width, height)),
+        &frames,
+        &names,
+    )
+}
+
+//Animations with a single frame are plain images
+fn load_animation(path: &Path) -> Result<Option<Vec<Frame>>, Box<dyn Error>> {
+    let frames = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gif") => GifDecoder::new(BufReader::new(File::open(path)?))?
+            .into_frames()
+            .collect_frames()?,
+        Some("png") => {
+            let decoder = PngDecoder::new(BufReader::new(File::open(path)?))?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames().collect_frames()?
+        }
+        _ => return Ok(None),
+    };
+
+    Ok((frames.len() > 1).then_some(frames))
+}
+
+//Every cell of the grid, frame names with their cell index
+type SheetFrames = (Vec<SpriteFrame>, Vec<(String, usize)>);
+
+//"grid <cell width> <cell height>" and any number of "frame <name> <column> <row>" lines.
+//Cells are numbered row by row, so frames without a name are still usable
+fn parse_sheet(path: &Path, width: u32, height: u32) -> Result<SheetFrames, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut grid: Option<(u32, u32)> = None;
+    let mut named_cells = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let invalid = || format!("{}:{}: invalid line {line:?}", path.display(), number + 1);
+
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => {}
+            ["grid", cell_width, cell_height] => {
+                let cell = (cell_width.parse()?, cell_height.parse()?);
+                if cell.0 == 0 || cell.1 == 0 || cell.0 > width || cell.1 > height {
+                    return Err(invalid().into());
+                }
+                grid = Some(cell);
+            }
+            ["frame", name, column, row] => named_cells.push((
+                name.to_string(),
+                column.parse::<u32>()?,
+                row.parse::<u32>()?,
+            )),
+            _ => return Err(invalid().into()),
+        }
+    }
+
+    let Some((cell_width, cell_height)) = grid else {
+        return Err(format!("{} has no grid line", path.display()).into());
+    };
+    let (columns, rows) = (width / cell_width, height / cell_height);
+
+    let mut frames = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            frames.push(SpriteFrame {
+                x: column * cell_width,
+                y: row * cell_height,
+                width: cell_width,
+                height: cell_height,
+                delay_ms: 0,
+            });
+        }
+    }
+
+    let mut names: Vec<(String, usize)> = Vec::new();
+    for (name, column, row) in named_cells {
+        if column >= columns || row >= rows {
+            return Err(format!("Frame {name} is outside of {}", path.display()).into());
+        }
+        if names.iter().any(|(other, _)| *other == name) {
+            return Err(format!("Frame {name} is defined twice in {}", path.display()).into());
+        }
+        names.push((name, (row * columns + column) as usize));
+    }
+
+    Ok((frames, names))
+}
+
+//Writes the Sprite expression that bake_sprite! includes. Without a sheet (skip_buildrs)
+//the sprite has no frames
+fn write_sprite(
+    out_dir: &str,
+    name: &str,
+    sheet: Option<(&str, u32, u32)>,
+    frames: &[SpriteFrame],
+    names: &[(String, usize)],
+) -> Result<(), Box<dyn Error>> {
+    let (data, width, height) = match sheet {
+        Some((data_file, width, height)) => (
+            format!("include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{data_file}\"))"),
+            width,
+            height,
+        ),
+        None => ("&[]".to_string(), 0, 0),
+    };
+
+    let mut code = String::from("crate::player::sprite::Sprite {\n");
+    code.push_str(&format!(
+        "    sheet: crate::player::game_player::BakedImage {{ width: {width}, height: {height}, data: std::borrow::Cow::Borrowed({data}) }},\n"
+    ));
+    code.push_str("    frames: &[\n");
+    for frame in frames {
+        code.push_str(&format!(
+            "        crate::player::sprite::SpriteFrame {{ x: {}, y: {}, width: {}, height: {}, delay_ms: {} }},\n",
+            frame.x, frame.y, frame.width, frame.height, frame.delay_ms
+        ));
+    }
+    code.push_str("    ],\n    names: &[\n");
+    for (frame_name, index) in names {
+        code.push_str(&format!("        ({frame_name:?}, {index}),\n"));
+    }
+    code.push_str("    ],\n}\n");
+
+    std::fs::write(format!("{out_dir}/{name}.sprite.rs"), code)?;
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=./assets");
@@ -331,8 +566,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let out_dir = env::var("OUT_DIR")?; //cargo makes sure that "OUT_DIR" exist
     let out_dir_yuv = format!("{out_dir}/cached_color_yuv.hex");
+    let out_dir_palette = format!("{out_dir}/map_palette.bin");
     let out_dir = format!("{out_dir}/cached_color.hex");
 
+    //Palette is tiny so we write it even when skipping build.rs
+    let mut palette_file = BufWriter::new(File::create(out_dir_palette)?);
+    for color in MINECRAFT_COLOR_ARRAY.iter() {
+        palette_file.write_all(&[color.red, color.green, color.blue])?;
+    }
+    palette_file.flush()?;
+
     if cfg!(feature = "skip_buildrs") {
         let mut color_file = BufWriter::new(File::create(out_dir)?);
         let _ = color_file.write(&[0])?;
@@ -343,12 +586,17 @@ fn main() -> Result<(), Box<dyn Error>> {
             .collect::<Result<Vec<_>, std::io::Error>>()?;
 
         for ele in asstets_entries {
+            if is_sheet_metadata(&ele) {
+                continue;
+            }
+
             let mut name = ele.to_str().unwrap().replace("./assets/", "");
             if let Some(val) = ele.extension() {
                 name = name.replace('.', "").replace(val.to_str().unwrap(), "")
             }
 
             let out_dir = env::var("OUT_DIR").unwrap(); //cargo makes sure that "OUT_DIR" exist
+            write_sprite(&out_dir, &name, None, &[], &[])?;
             let out_path = format!("{out_dir}/{name}.bin");
 
             let mut output_file = BufWriter::new(File::create(out_path)?);
@@ -389,12 +637,16 @@ fn main() -> Result<(), Box<dyn Error>> {
         .collect::<Result<Vec<_>, std::io::Error>>()?;
 
     for ele in asstets_entries {
+        if is_sheet_metadata(&ele) {
+            continue;
+        }
+
         let mut name = ele.to_str().unwrap().replace("./assets/", "");
         if let Some(val) = ele.extension() {
             name = name.replace('.', "").replace(val.to_str().unwrap(), "")
         }
 
-        let img = image::open(ele)?;
+        let img = image::open(&ele)?;
         let (width, height) = img.dimensions();
 
         let img = match img {
@@ -410,24 +662,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         //println!("D: {:?}", data);
 
-        for y in 0..height {
-            for x in 0..width {
-                let a = input_data[((((y * width) + x) * 4) + 3) as usize];
-
-                if a != 255 {
-                    let _ = output_file.write(slice::from_ref(&0u8))?;
-                    continue;
-                }
-
-                let r = input_data[(((y * width) + x) * 4) as usize];
-                let g = input_data[((((y * width) + x) * 4) + 1) as usize];
-                let b = input_data[((((y * width) + x) * 4) + 2) as usize];
-
-                let _ = output_file.write(slice::from_ref(
-                    &(get_mc_index(MinecraftColor::new(r, g, b)) as u8),
-                ))?;
-            }
-        }
+        write_mc_pixels(&mut output_file, &input_data)?;
 
         let mut dimensions_file = BufWriter::new(File::create(format!("{out_dir}/{name}.dim"))?);
         let _ = dimensions_file.write(&width.to_be_bytes())?;
@@ -435,6 +670,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         dimensions_file.flush()?;
         output_file.flush()?;
+
+        bake_sprite(&ele, &name, &out_dir, width, height)?;
     }
 
     if cfg!(feature = "external_player") {
@@ -1,12 +1,19 @@
-use std::sync::mpsc::Receiver;
+use std::collections::VecDeque;
+use std::time::Duration;
 
 use anyhow::anyhow;
-use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 
 use crate::{
-    bake_image,
+    bake_image, bake_sprite,
     colorlib::Color,
-    player::game_player::{BakedImage, Game, GameInputDirection, VideoCanvas},
+    player::{
+        game_input::{InputEvent, Key, PlayerId},
+        game_player::{
+            create_game, BakedImage, Game, GameConfig, GameEntry, GameState, StepTimer, VideoCanvas,
+        },
+        sprite::Sprite,
+    },
 };
 
 static FRAME_COLOR: Color = Color::new(30, 40, 112);
@@ -14,19 +21,25 @@ static INNER_FRAME_COLOR: Color = Color::new(60, 60, 60);
 static SPAWN_TICKS: usize = 30;
 static FALL_TICKS: usize = 60;
 static FAST_FALL_TICKS: usize = 4;
+//Tick counts above are in steps
+static STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
-static NUMBER_0: BakedImage = bake_image!(tetris_0);
-static NUMBER_1: BakedImage = bake_image!(tetris_1);
-static NUMBER_2: BakedImage = bake_image!(tetris_2);
-static NUMBER_3: BakedImage = bake_image!(tetris_3);
-static NUMBER_4: BakedImage = bake_image!(tetris_4);
-static NUMBER_5: BakedImage = bake_image!(tetris_5);
-static NUMBER_6: BakedImage = bake_image!(tetris_6);
-static NUMBER_7: BakedImage = bake_image!(tetris_7);
-static NUMBER_8: BakedImage = bake_image!(tetris_8);
-static NUMBER_9: BakedImage = bake_image!(tetris_9);
+//Frames are named after the digit
+static DIGITS: Sprite = bake_sprite!(tetris_digits);
 static GAME_OVER_SCREEN: BakedImage = bake_image!(tetris_gameover);
 
+pub static ENTRY: GameEntry = GameEntry {
+    id: "falling_blocks",
+    name: "Falling Blocks",
+    description: "Stack falling blocks and clear full lines",
+    width: 640,
+    height: 640,
+    max_players: 1,
+    create: create_game::<FallingBlocks>,
+};
+
+inventory::submit!(crate::apps::GameRegistration(&ENTRY));
+
 static RANDOM_COLORS: [Color; 7] = [
     Color::new(0, 240, 240),
     Color::new(0, 0, 240),
@@ -47,7 +60,10 @@ pub struct FallingBlocks {
     last_color: Color,
     lines_cleared: usize,
     game_over: bool,
-    rand: ThreadRng,
+    rand: StdRng,
+    //Moves wait here until move_ticks allow them
+    pending: VecDeque<Key>,
+    timer: StepTimer,
 }
 
 #[derive(Debug, Clone, PartialEq, Copy)]
@@ -120,34 +136,26 @@ impl Game for FallingBlocks {
         60
     }
 
-    fn new() -> Self
+    fn new(config: GameConfig) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
-        Self {
-            blocks: vec![None; 140],
-            spawn_ticks: 0usize,
-            fall_ticks: 0usize,
-            move_ticks: 0usize,
-            fast_fall_ticks: 0usize,
-            current_block: BlockType::I, //We do not want to have a I block as the first one
-            last_color: Color::BLACK,    //Does not matter
-            lines_cleared: 0,
-            game_over: false,
-            rand: rand::thread_rng(),
+        Ok(Self::start(StdRng::seed_from_u64(config.seed)))
+    }
+
+    fn tick(&mut self, dt: Duration) -> anyhow::Result<()> {
+        for _ in 0..self.timer.advance(dt) {
+            self.step()?;
         }
+        Ok(())
     }
 
-    fn draw(&mut self, input_rx: &Receiver<GameInputDirection>) -> anyhow::Result<VideoCanvas> {
-        let mut canvas = VideoCanvas::new(
-            self.width() as usize,
-            self.height() as usize,
-            &Color::hex("464B46")?,
-        );
+    fn render(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+        canvas.clear(&Color::hex("464B46")?);
 
         if self.game_over {
-            self.draw_game_over_screen(&mut canvas)?;
-            return Ok(canvas);
+            self.draw_game_over_screen(canvas)?;
+            return Ok(());
         }
 
         canvas.draw_square(0, 20, 580, 40, &FRAME_COLOR);
@@ -158,7 +166,66 @@ impl Game for FallingBlocks {
         canvas.draw_square(0, 40, 20, 100, &FRAME_COLOR);
         canvas.draw_square(20, 80, 140, 100, &FRAME_COLOR);
 
-        self.draw_score_display(&mut canvas, 20, 40)?;
+        self.draw_score_display(canvas, 20, 40)?;
+
+        for y in 0..14 {
+            for x in 0..10 {
+                if let Some(block) = &self.blocks[y * 10 + x] {
+                    canvas.draw_square(
+                        160 + (x * 40),
+                        40 + (y * 40),
+                        160 + ((x + 1) * 40),
+                        40 + ((y + 1) * 40),
+                        &block.color,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_input(&mut self, _player_id: PlayerId, event: InputEvent) {
+        if let InputEvent::KeyDown(key) = event {
+            self.pending.push_back(key);
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::start(self.rand.clone());
+    }
+
+    fn state(&self) -> GameState {
+        let score = self.lines_cleared as u32;
+        match self.game_over {
+            true => GameState::Over { score },
+            false => GameState::Playing { score },
+        }
+    }
+}
+
+impl FallingBlocks {
+    fn start(rand: StdRng) -> Self {
+        Self {
+            blocks: vec![None; 140],
+            spawn_ticks: 0usize,
+            fall_ticks: 0usize,
+            move_ticks: 0usize,
+            fast_fall_ticks: 0usize,
+            current_block: BlockType::I, //We do not want to have a I block as the first one
+            last_color: Color::BLACK,    //Does not matter
+            lines_cleared: 0,
+            game_over: false,
+            rand,
+            pending: VecDeque::new(),
+            timer: StepTimer::new(STEP),
+        }
+    }
+
+    fn step(&mut self) -> anyhow::Result<()> {
+        if self.game_over {
+            return Ok(());
+        }
 
         if self.spawn_ticks == SPAWN_TICKS {
             if !self
@@ -284,12 +351,12 @@ impl Game for FallingBlocks {
             self.spawn_ticks += 1;
         }
 
-        while let Ok(val) = input_rx.try_recv() {
+        while let Some(val) = self.pending.pop_front() {
             if self.move_ticks != 0 {
                 continue;
             };
             match val {
-                GameInputDirection::Right => {
+                Key::Right => {
                     let mut blocks_clone = self.blocks.clone();
                     let mut allow_swap = false;
                     'block_loop: for block in self
@@ -321,7 +388,7 @@ impl Game for FallingBlocks {
                     self.move_ticks = 10;
                     break;
                 }
-                GameInputDirection::Left => {
+                Key::Left => {
                     let mut blocks_clone = self.blocks.clone();
                     let mut allow_swap = false;
                     'block_loop: for block in self
@@ -353,7 +420,7 @@ impl Game for FallingBlocks {
                     self.move_ticks = 10;
                     break;
                 }
-                GameInputDirection::Up => {
+                Key::Jump => {
                     self.blocks
                         .iter_mut()
                         .rev()
@@ -363,20 +430,21 @@ impl Game for FallingBlocks {
                             block.falling = true;
                         });
                 }
-                GameInputDirection::Forward => {
+                Key::Forward => {
                     //Right rotate
                     if self.rotate_block(false) {
                         self.move_ticks = 10;
                         break;
                     }
                 }
-                GameInputDirection::Backwards => {
+                Key::Backwards => {
                     //left rotate
                     if self.rotate_block(true) {
                         self.move_ticks = 10;
                         break;
                     }
                 }
+                Key::Sneak | Key::Sprint => {}
             };
         }
 
@@ -477,25 +545,9 @@ impl Game for FallingBlocks {
             self.fall_ticks += 1;
         }
 
-        for y in 0..14 {
-            for x in 0..10 {
-                if let Some(block) = &self.blocks[y * 10 + x] {
-                    canvas.draw_square(
-                        160 + (x * 40),
-                        40 + (y * 40),
-                        160 + ((x + 1) * 40),
-                        40 + ((y + 1) * 40),
-                        &block.color,
-                    );
-                }
-            }
-        }
-
-        Ok(canvas)
+        Ok(())
     }
-}
 
-impl FallingBlocks {
     fn rotate_block(&mut self, left: bool) -> bool {
         if self.current_block == BlockType::O {
             return false;
@@ -619,26 +671,12 @@ impl FallingBlocks {
         }
 
         for (id, x) in chars.iter().enumerate() {
-            let img = match x {
-                0 => &NUMBER_0,
-                1 => &NUMBER_1,
-                2 => &NUMBER_2,
-                3 => &NUMBER_3,
-                4 => &NUMBER_4,
-                5 => &NUMBER_5,
-                6 => &NUMBER_6,
-                7 => &NUMBER_7,
-                8 => &NUMBER_8,
-                9 => &NUMBER_9,
-                _ => return Err(anyhow!("Unable to draw segment screen")),
-            };
-
-            canvas.draw_image(x1 + (40 * id), y1, img);
+            canvas.draw_sprite((x1 + (40 * id)) as i32, y1 as i32, &DIGITS, &x.to_string())?;
         }
         Ok(())
     }
 
-    fn draw_game_over_screen(&mut self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+    fn draw_game_over_screen(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
         canvas.draw_image(0, 0, &GAME_OVER_SCREEN);
         self.draw_score_display(canvas, 320, 240)?;
         Ok(())
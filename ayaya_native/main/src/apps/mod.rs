@@ -1,2 +1,54 @@
+use crate::player::game_player::GameEntry;
+
 pub mod falling_blocks;
+pub mod pong;
 pub mod snake;
+
+/// Registers a game when the library is linked. Every game submits its ENTRY:
+/// inventory::submit!(GameRegistration(&ENTRY)), so a game behind a cargo feature
+/// or in another crate only has to be compiled in
+pub struct GameRegistration(pub &'static GameEntry);
+
+inventory::collect!(GameRegistration);
+
+/// Every game compiled into the library, sorted by id. Link order is not stable
+pub fn games() -> Vec<&'static GameEntry> {
+    let mut games: Vec<&'static GameEntry> = inventory::iter::<GameRegistration>
+        .into_iter()
+        .map(|registration| registration.0)
+        .collect();
+    games.sort_by_key(|entry| entry.id);
+    games
+}
+
+pub fn find_game(id: &str) -> Option<&'static GameEntry> {
+    inventory::iter::<GameRegistration>
+        .into_iter()
+        .map(|registration| registration.0)
+        .find(|entry| entry.id == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::game_player::GameConfig;
+
+    #[test]
+    fn test_registry() {
+        let games = games();
+        for id in ["falling_blocks", "pong", "snake"] {
+            assert!(games.iter().any(|entry| entry.id == id));
+        }
+
+        for (i, entry) in games.iter().enumerate() {
+            assert!(games[..i].iter().all(|other| other.id < entry.id));
+            assert!(std::ptr::eq(find_game(entry.id).unwrap(), *entry));
+
+            let game = (entry.create)(GameConfig::default()).unwrap();
+            assert!(game.width() as usize == entry.width);
+            assert!(game.height() as usize == entry.height);
+            assert!(entry.max_players >= 1);
+        }
+        assert!(find_game("tetris").is_none());
+    }
+}
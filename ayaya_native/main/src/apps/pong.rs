@@ -0,0 +1,470 @@
+use std::time::Duration;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    colorlib::Color,
+    player::{
+        game_input::{InputEvent, Key, PlayerId},
+        game_player::{
+            create_game, Game, GameConfig, GameEntry, GameState, StepTimer, VideoCanvas,
+        },
+        text::{TextAlign, TextStyle},
+    },
+};
+
+static CANVAS_WIDTH: usize = 640;
+static CANVAS_HEIGHT: usize = 384;
+
+static PADDLE_WIDTH: f32 = 12.0;
+static PADDLE_HEIGHT: f32 = 72.0;
+//Distance between the wall and the paddle
+static PADDLE_MARGIN: f32 = 24.0;
+static BALL_RADIUS: f32 = 5.0;
+
+//Speeds are in pixels per step
+static PADDLE_SPEED: f32 = 6.0;
+static BALL_SPEED: f32 = 4.0;
+static MAX_BALL_SPEED: f32 = 9.0;
+static BALL_SPEED_UP: f32 = 0.25;
+//Angle of a ball that hits the edge of a paddle, in radians
+static MAX_BOUNCE_ANGLE: f32 = 1.0;
+
+static WINNING_SCORE: u32 = 5;
+//The plugin only sees moves, which are sent as a press and a release of the key in the same tick.
+//A press moves the paddle for a while, held keys move it until they are released
+static PRESS_STEPS: u32 = 12;
+static SERVE_STEPS: u32 = 60;
+static STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+static BACKGROUND_COLOR: Color = Color::new(20, 20, 20);
+static LINE_COLOR: Color = Color::new(90, 90, 90);
+static WHITE: Color = Color::new(255, 255, 255);
+
+pub static ENTRY: GameEntry = GameEntry {
+    id: "pong",
+    name: "Pong",
+    description: "Two players bounce a ball past each other's paddle",
+    width: CANVAS_WIDTH,
+    height: CANVAS_HEIGHT,
+    max_players: 2,
+    create: create_game::<Pong>,
+};
+
+inventory::submit!(crate::apps::GameRegistration(&ENTRY));
+
+#[derive(Default)]
+struct Paddle {
+    //None while the side waits for a player
+    player_id: Option<PlayerId>,
+    //Top edge
+    y: f32,
+    //-1 moves up, 1 down
+    direction: f32,
+    held: bool,
+    press_steps: u32,
+    score: u32,
+}
+
+pub struct Pong {
+    //Left and right
+    paddles: [Paddle; 2],
+    ball_x: f32,
+    ball_y: f32,
+    velocity_x: f32,
+    velocity_y: f32,
+    //Steps until the ball is served, the ball does not move before that
+    serve_steps: u32,
+    //Index of the paddle the ball is served towards
+    serve_side: usize,
+    rand: StdRng,
+    timer: StepTimer,
+}
+
+impl Game for Pong {
+    fn width(&self) -> i32 {
+        CANVAS_WIDTH as i32
+    }
+
+    fn height(&self) -> i32 {
+        CANVAS_HEIGHT as i32
+    }
+
+    fn fps(&self) -> i32 {
+        20
+    }
+
+    fn new(config: GameConfig) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self::start(StdRng::seed_from_u64(config.seed)))
+    }
+
+    fn tick(&mut self, dt: Duration) -> anyhow::Result<()> {
+        for _ in 0..self.timer.advance(dt) {
+            self.step();
+        }
+        Ok(())
+    }
+
+    fn render(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+        canvas.clear(&BACKGROUND_COLOR);
+
+        let center_x = CANVAS_WIDTH as i32 / 2;
+        for y in (0..CANVAS_HEIGHT as i32).step_by(24) {
+            canvas.fill_rect(center_x - 2, y + 4, center_x + 1, y + 15, &LINE_COLOR);
+        }
+
+        for (side, paddle) in self.paddles.iter().enumerate() {
+            let x = Self::paddle_x(side) as i32;
+            canvas.fill_rect(
+                x,
+                paddle.y as i32,
+                x + PADDLE_WIDTH as i32 - 1,
+                (paddle.y + PADDLE_HEIGHT) as i32 - 1,
+                &WHITE,
+            );
+
+            let score_x = side as i32 * center_x;
+            canvas.draw_text_box(
+                score_x,
+                16,
+                score_x + center_x - 1,
+                80,
+                &Self::text_style(48.0),
+                &paddle.score.to_string(),
+            )?;
+        }
+
+        let message = match self.winner() {
+            Some(0) => "Left player wins!\nJump to play again",
+            Some(_) => "Right player wins!\nJump to play again",
+            None if self.is_waiting() => "Waiting for a second player",
+            None => "",
+        };
+        if !message.is_empty() {
+            canvas.draw_text_box(
+                0,
+                CANVAS_HEIGHT as i32 / 2 - 40,
+                CANVAS_WIDTH as i32 - 1,
+                CANVAS_HEIGHT as i32 - 1,
+                &Self::text_style(32.0),
+                message,
+            )?;
+            return Ok(());
+        }
+
+        canvas.fill_rect(
+            (self.ball_x - BALL_RADIUS) as i32,
+            (self.ball_y - BALL_RADIUS) as i32,
+            (self.ball_x + BALL_RADIUS) as i32 - 1,
+            (self.ball_y + BALL_RADIUS) as i32 - 1,
+            &WHITE,
+        );
+
+        Ok(())
+    }
+
+    fn on_input(&mut self, player_id: PlayerId, event: InputEvent) {
+        let Some(paddle) = self
+            .paddles
+            .iter_mut()
+            .find(|paddle| paddle.player_id == Some(player_id))
+        else {
+            return;
+        };
+
+        match event {
+            InputEvent::KeyDown(key @ (Key::Forward | Key::Backwards)) => {
+                paddle.direction = Self::key_direction(key);
+                paddle.held = true;
+                paddle.press_steps = PRESS_STEPS;
+            }
+            InputEvent::KeyUp(key @ (Key::Forward | Key::Backwards))
+                if paddle.direction == Self::key_direction(key) =>
+            {
+                paddle.held = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn on_join(&mut self, player_id: PlayerId) {
+        if self
+            .paddles
+            .iter()
+            .any(|paddle| paddle.player_id == Some(player_id))
+        {
+            return;
+        }
+        if let Some(paddle) = self
+            .paddles
+            .iter_mut()
+            .find(|paddle| paddle.player_id.is_none())
+        {
+            paddle.player_id = Some(player_id);
+        }
+    }
+
+    //The match starts over when the other player comes
+    fn on_leave(&mut self, player_id: PlayerId) {
+        for paddle in self.paddles.iter_mut() {
+            if paddle.player_id == Some(player_id) {
+                paddle.player_id = None;
+            }
+        }
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        let players = self.paddles.each_ref().map(|paddle| paddle.player_id);
+        *self = Self::start(self.rand.clone());
+        for (paddle, player_id) in self.paddles.iter_mut().zip(players) {
+            paddle.player_id = player_id;
+        }
+    }
+
+    fn state(&self) -> GameState {
+        let score = self
+            .paddles
+            .iter()
+            .map(|paddle| paddle.score)
+            .max()
+            .unwrap_or(0);
+        match self.winner() {
+            Some(_) => GameState::Won { score },
+            None => GameState::Playing { score },
+        }
+    }
+}
+
+impl Pong {
+    fn start(mut rand: StdRng) -> Self {
+        let serve_side = rand.gen_range(0..2);
+
+        let mut pong = Self {
+            paddles: Default::default(),
+            ball_x: 0.0,
+            ball_y: 0.0,
+            velocity_x: 0.0,
+            velocity_y: 0.0,
+            serve_steps: 0,
+            serve_side,
+            rand,
+            timer: StepTimer::new(STEP),
+        };
+        for paddle in pong.paddles.iter_mut() {
+            paddle.y = (CANVAS_HEIGHT as f32 - PADDLE_HEIGHT) / 2.0;
+        }
+        pong.center_ball();
+        pong
+    }
+
+    fn text_style(font_size: f32) -> TextStyle {
+        TextStyle {
+            align: TextAlign::Center,
+            ..TextStyle::new(font_size, WHITE)
+        }
+    }
+
+    fn key_direction(key: Key) -> f32 {
+        match key {
+            Key::Forward => -1.0,
+            _ => 1.0,
+        }
+    }
+
+    //Left edge of the paddle
+    fn paddle_x(side: usize) -> f32 {
+        match side {
+            0 => PADDLE_MARGIN,
+            _ => CANVAS_WIDTH as f32 - PADDLE_MARGIN - PADDLE_WIDTH,
+        }
+    }
+
+    fn winner(&self) -> Option<usize> {
+        self.paddles
+            .iter()
+            .position(|paddle| paddle.score >= WINNING_SCORE)
+    }
+
+    fn is_waiting(&self) -> bool {
+        self.paddles.iter().any(|paddle| paddle.player_id.is_none())
+    }
+
+    fn center_ball(&mut self) {
+        (self.ball_x, self.ball_y) = (CANVAS_WIDTH as f32 / 2.0, CANVAS_HEIGHT as f32 / 2.0);
+        (self.velocity_x, self.velocity_y) = (0.0, 0.0);
+        self.serve_steps = SERVE_STEPS;
+    }
+
+    fn serve(&mut self) {
+        let angle = self.rand.gen_range(-0.5..0.5f32);
+        let direction = match self.serve_side {
+            0 => -1.0,
+            _ => 1.0,
+        };
+        self.velocity_x = BALL_SPEED * angle.cos() * direction;
+        self.velocity_y = BALL_SPEED * angle.sin();
+    }
+
+    fn step(&mut self) {
+        for paddle in self.paddles.iter_mut() {
+            if paddle.held || paddle.press_steps > 0 {
+                paddle.y = (paddle.y + paddle.direction * PADDLE_SPEED)
+                    .clamp(0.0, CANVAS_HEIGHT as f32 - PADDLE_HEIGHT);
+            }
+            paddle.press_steps = paddle.press_steps.saturating_sub(1);
+        }
+
+        if self.is_waiting() {
+            self.center_ball();
+            return;
+        }
+        if self.winner().is_some() {
+            return;
+        }
+        if self.serve_steps > 0 {
+            self.serve_steps -= 1;
+            if self.serve_steps == 0 {
+                self.serve();
+            }
+            return;
+        }
+
+        let previous_x = self.ball_x;
+        self.ball_x += self.velocity_x;
+        self.ball_y += self.velocity_y;
+
+        if self.ball_y < BALL_RADIUS {
+            self.ball_y = BALL_RADIUS;
+            self.velocity_y = self.velocity_y.abs();
+        } else if self.ball_y > CANVAS_HEIGHT as f32 - BALL_RADIUS {
+            self.ball_y = CANVAS_HEIGHT as f32 - BALL_RADIUS;
+            self.velocity_y = -self.velocity_y.abs();
+        }
+
+        //The ball bounces when it crossed the face of the paddle during this step
+        let side = match self.velocity_x < 0.0 {
+            true => 0,
+            false => 1,
+        };
+        let (face, direction) = match side {
+            0 => (Self::paddle_x(0) + PADDLE_WIDTH + BALL_RADIUS, 1.0),
+            _ => (Self::paddle_x(1) - BALL_RADIUS, -1.0),
+        };
+        let paddle_y = self.paddles[side].y;
+        let crossed =
+            (previous_x - face) * direction >= 0.0 && (self.ball_x - face) * direction < 0.0;
+        if crossed
+            && self.ball_y + BALL_RADIUS >= paddle_y
+            && self.ball_y - BALL_RADIUS <= paddle_y + PADDLE_HEIGHT
+        {
+            //Hits further from the center of the paddle bounce at a steeper angle
+            let offset = ((self.ball_y - (paddle_y + PADDLE_HEIGHT / 2.0)) / (PADDLE_HEIGHT / 2.0))
+                .clamp(-1.0, 1.0);
+            let speed =
+                (self.velocity_x.hypot(self.velocity_y) + BALL_SPEED_UP).min(MAX_BALL_SPEED);
+            let angle = offset * MAX_BOUNCE_ANGLE;
+
+            self.ball_x = face;
+            self.velocity_x = speed * angle.cos() * direction;
+            self.velocity_y = speed * angle.sin();
+            return;
+        }
+
+        //The ball is served towards the player that missed it
+        let missed = match self.ball_x {
+            x if x < -BALL_RADIUS => 0,
+            x if x > CANVAS_WIDTH as f32 + BALL_RADIUS => 1,
+            _ => return,
+        };
+        self.paddles[1 - missed].score += 1;
+        self.serve_side = missed;
+        self.center_ball();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> Pong {
+        let mut game = Pong::new(GameConfig::default()).unwrap();
+        game.on_join(0);
+        game.on_join(1);
+        game
+    }
+
+    #[test]
+    fn test_waits_for_second_player() {
+        let mut game = Pong::new(GameConfig::default()).unwrap();
+        game.on_join(4);
+        game.tick(STEP * (SERVE_STEPS + 10)).unwrap();
+        assert!(game.ball_x == CANVAS_WIDTH as f32 / 2.0 && game.velocity_x == 0.0);
+
+        game.on_join(2);
+        game.tick(STEP * (SERVE_STEPS + 10)).unwrap();
+        assert!(game.ball_x != CANVAS_WIDTH as f32 / 2.0);
+
+        //Only two sides
+        game.on_join(3);
+        assert!(game.paddles[0].player_id == Some(4) && game.paddles[1].player_id == Some(2));
+
+        //Leaving restarts the match
+        game.paddles[0].score = 2;
+        game.on_leave(4);
+        assert!(game.is_waiting());
+        assert!(game.paddles[0].score == 0 && game.paddles[1].player_id == Some(2));
+    }
+
+    #[test]
+    fn test_paddle_press_and_hold() {
+        let mut game = game();
+        let start = game.paddles[1].y;
+
+        //A press moves the paddle for PRESS_STEPS
+        game.on_input(1, InputEvent::KeyDown(Key::Forward));
+        game.on_input(1, InputEvent::KeyUp(Key::Forward));
+        game.tick(STEP * (PRESS_STEPS + 10)).unwrap();
+        assert!(game.paddles[1].y == start - PRESS_STEPS as f32 * PADDLE_SPEED);
+
+        //Held keys move it to the wall
+        game.on_input(1, InputEvent::KeyDown(Key::Backwards));
+        game.tick(STEP * 120).unwrap();
+        assert!(game.paddles[1].y == CANVAS_HEIGHT as f32 - PADDLE_HEIGHT);
+        assert!(game.paddles[0].y == start);
+    }
+
+    #[test]
+    fn test_bounce_score_and_win() {
+        let mut game = game();
+        game.serve_steps = 0;
+
+        //Straight into the center of the left paddle
+        game.velocity_x = -BALL_SPEED;
+        game.tick(STEP * 80).unwrap();
+        assert!(game.velocity_x == BALL_SPEED + BALL_SPEED_UP);
+        assert!(game.velocity_y.abs() < 0.01);
+
+        //Past the right paddle
+        game.paddles[1].y = 0.0;
+        game.tick(STEP * 150).unwrap();
+        assert!(game.paddles[0].score == 1);
+        assert!(game.serve_side == 1 && game.serve_steps > 0);
+
+        game.paddles[0].score = WINNING_SCORE;
+        game.tick(STEP).unwrap();
+        assert!(
+            game.state()
+                == GameState::Won {
+                    score: WINNING_SCORE
+                }
+        );
+
+        game.reset();
+        assert!(game.state() == GameState::Playing { score: 0 });
+        assert!(!game.is_waiting());
+    }
+}
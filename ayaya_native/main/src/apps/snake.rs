@@ -1,10 +1,14 @@
-use std::{collections::LinkedList, sync::mpsc::Receiver};
+use std::{collections::LinkedList, sync::Arc, time::Duration};
 
-use crate::{bake_image, colorlib::Color};
+use crate::{assets::AssetLoader, bake_image, colorlib::Color};
 use anyhow::anyhow;
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::player::game_player::{BakedImage, Game, GameInputDirection, VideoCanvas};
+use crate::player::game_input::{InputEvent, Key, PlayerId};
+use crate::player::game_player::{
+    create_game, BakedImage, Game, GameConfig, GameEntry, GameState, StepTimer, VideoCanvas,
+};
+use crate::player::text::TextStyle;
 
 static BOARD_WIDTH: usize = 10;
 static BOARD_HEIGHT: usize = 10;
@@ -21,14 +25,42 @@ static DRAW_CELL_SIZE_Y: usize = CELL_SIZE_Y / 2;
 static DRAW_X_OFFSET: usize = DRAW_CELL_SIZE_X / 2;
 static DRAW_Y_OFFSET: usize = DRAW_CELL_SIZE_Y / 2;
 
-static SNAKE_COLOR: Color = Color::new(35, 90, 35);
+//Every player has their own color, the first one is used in single player
+static SNAKE_COLORS: [Color; 4] = [
+    Color::new(35, 90, 35),
+    Color::new(35, 45, 100),
+    Color::new(110, 90, 20),
+    Color::new(90, 35, 90),
+];
 static APPLE_COLOR: Color = Color::new(85, 27, 27);
 
+//Snakes start in the corners, moving along the walls
+static SPAWNS: [(usize, usize, SnakeDirection); 4] = [
+    (0, 0, SnakeDirection::Right),
+    (BOARD_WIDTH - 1, BOARD_HEIGHT - 1, SnakeDirection::Left),
+    (BOARD_WIDTH - 1, 0, SnakeDirection::Down),
+    (0, BOARD_HEIGHT - 1, SnakeDirection::Up),
+];
+
 static DEATH_FRAMES: i8 = 1;
+static STEP: Duration = Duration::from_nanos(1_000_000_000 / 6);
 
 static SNAKE_LOSE_SCREEN: BakedImage = bake_image!(snake_lose);
 static SNAKE_WIN_SCREEN: BakedImage = bake_image!(snake_win);
 
+pub static ENTRY: GameEntry = GameEntry {
+    id: "snake",
+    name: "Snake",
+    description: "Eat apples and grow without hitting the walls or any snake",
+    width: CANVAS_WIDTH,
+    height: CANVAS_HEIGHT,
+    max_players: SPAWNS.len(),
+    create: create_game::<SnakeGame>,
+};
+
+inventory::submit!(crate::apps::GameRegistration(&ENTRY));
+
+#[derive(Clone, Copy)]
 enum SnakeDirection {
     Up,
     Down,
@@ -37,14 +69,14 @@ enum SnakeDirection {
 }
 
 impl SnakeDirection {
-    fn to_x_diff(&self) -> i32 {
+    fn to_x_diff(self) -> i32 {
         match self {
             Self::Up | Self::Down => 0,
             Self::Right => 1,
             Self::Left => -1,
         }
     }
-    fn to_y_diff(&self) -> i32 {
+    fn to_y_diff(self) -> i32 {
         match self {
             Self::Left | Self::Right => 0,
             Self::Up => -1,
@@ -53,7 +85,7 @@ impl SnakeDirection {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct SnakeCell {
     x: usize,
     y: usize,
@@ -71,14 +103,28 @@ enum SnakeGameState {
     Playing,
 }
 
-pub struct SnakeGame {
-    snake: LinkedList<SnakeCell>,
+struct Snake {
+    player_id: PlayerId,
+    //Index of the spawn and the color
+    slot: usize,
+    cells: LinkedList<SnakeCell>,
     direction: SnakeDirection,
+    //Direction of the last step, several inputs between two steps are checked against it
+    moving: SnakeDirection,
+    death_timer: i8,
+    //Dead snakes are removed from the board, but their length still counts for the score
+    alive: bool,
+}
+
+pub struct SnakeGame {
+    snakes: Vec<Snake>,
     apple_x: usize,
     apple_y: usize,
-    rand: ThreadRng,
-    death_timer: i8,
+    rand: StdRng,
     game_state: SnakeGameState,
+    timer: StepTimer,
+    lose_screen: Arc<BakedImage>,
+    win_screen: Arc<BakedImage>,
 }
 
 impl Game for SnakeGame {
@@ -94,62 +140,158 @@ impl Game for SnakeGame {
         6
     }
 
-    fn new() -> Self
+    fn new(config: GameConfig) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
-        let mut rand = rand::thread_rng();
-        let apple_x = rand.gen_range(0..BOARD_WIDTH);
-        let apple_y = rand.gen_range(0..BOARD_HEIGHT);
+        //snake_lose.png and snake_win.png from the asset directory replace the baked screens
+        let lose_screen = Self::screen(&config.assets, "snake_lose", &SNAKE_LOSE_SCREEN)?;
+        let win_screen = Self::screen(&config.assets, "snake_win", &SNAKE_WIN_SCREEN)?;
+
+        Ok(Self::start(
+            lose_screen,
+            win_screen,
+            StdRng::seed_from_u64(config.seed),
+        ))
+    }
 
-        //For now static start possition
-        Self {
-            snake: LinkedList::from([SnakeCell::new(0, 0)]),
-            direction: SnakeDirection::Right,
-            apple_x,
-            apple_y,
-            rand,
-            death_timer: DEATH_FRAMES,
-            game_state: SnakeGameState::Playing,
+    fn tick(&mut self, dt: Duration) -> anyhow::Result<()> {
+        for _ in 0..self.timer.advance(dt) {
+            self.step()?;
         }
+        Ok(())
     }
 
-    fn draw(&mut self, input_rx: &Receiver<GameInputDirection>) -> anyhow::Result<VideoCanvas> {
-        if matches!(self.game_state, SnakeGameState::Lose) {
-            return self.draw_lose_screen();
+    fn render(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+        match self.game_state {
+            SnakeGameState::Lose => return self.draw_lose_screen(canvas),
+            SnakeGameState::Win => {
+                canvas.draw_image(0, 0, &self.win_screen);
+                return Ok(());
+            }
+            SnakeGameState::Playing => {}
         }
 
-        if matches!(self.game_state, SnakeGameState::Win) {
-            return self.draw_win_screen();
+        canvas.clear(&Color::hex("464B46")?);
+
+        for snake in self.snakes.iter().filter(|snake| snake.alive) {
+            snake.draw(canvas)?;
         }
 
-        while let Ok(val) = input_rx.try_recv() {
-            match val {
-                GameInputDirection::Forward => {
-                    if !matches!(self.direction, SnakeDirection::Down) {
-                        self.direction = SnakeDirection::Up
-                    }
-                }
-                GameInputDirection::Backwards => {
-                    if !matches!(self.direction, SnakeDirection::Up) {
-                        self.direction = SnakeDirection::Down
-                    }
-                }
-                GameInputDirection::Left => {
-                    if !matches!(self.direction, SnakeDirection::Right) {
-                        self.direction = SnakeDirection::Left
-                    }
-                }
-                GameInputDirection::Right => {
-                    if !matches!(self.direction, SnakeDirection::Left) {
-                        self.direction = SnakeDirection::Right
-                    }
-                }
-                GameInputDirection::Up => {}
+        //Draw apple
+        canvas.draw_square(
+            self.apple_x * CELL_SIZE_X,
+            self.apple_y * CELL_SIZE_Y,
+            (self.apple_x + 1) * CELL_SIZE_X - 1,
+            (self.apple_y + 1) * CELL_SIZE_Y - 1,
+            &APPLE_COLOR,
+        );
+
+        Ok(())
+    }
+
+    fn on_input(&mut self, player_id: PlayerId, event: InputEvent) {
+        let InputEvent::KeyDown(key) = event else {
+            return;
+        };
+        let Some(snake) = self
+            .snakes
+            .iter_mut()
+            .find(|snake| snake.player_id == player_id)
+        else {
+            return;
+        };
+        //The snake cannot turn back into itself
+        match key {
+            Key::Forward if !matches!(snake.moving, SnakeDirection::Down) => {
+                snake.direction = SnakeDirection::Up
+            }
+            Key::Backwards if !matches!(snake.moving, SnakeDirection::Up) => {
+                snake.direction = SnakeDirection::Down
             }
+            Key::Left if !matches!(snake.moving, SnakeDirection::Right) => {
+                snake.direction = SnakeDirection::Left
+            }
+            Key::Right if !matches!(snake.moving, SnakeDirection::Left) => {
+                snake.direction = SnakeDirection::Right
+            }
+            _ => {}
         }
+    }
 
-        let head_cell = match self.snake.front() {
+    //Players joining during a game start right away in a free corner. Corners with a snake
+    //on them are skipped, the player does not join when there is none left
+    fn on_join(&mut self, player_id: PlayerId) {
+        if self.snakes.iter().any(|snake| snake.player_id == player_id) {
+            return;
+        }
+        let Some(slot) = (0..SPAWNS.len()).find(|slot| {
+            let (x, y, _) = SPAWNS[*slot];
+            let spawn = SnakeCell::new(x, y);
+            self.snakes
+                .iter()
+                .all(|snake| snake.slot != *slot && !(snake.alive && snake.cells.contains(&spawn)))
+        }) else {
+            println!("[ProjectAyaya] Snake player {player_id} did not join, every corner is taken");
+            return;
+        };
+        self.snakes.push(Snake::spawn(player_id, slot));
+    }
+
+    fn on_leave(&mut self, player_id: PlayerId) {
+        self.snakes.retain(|snake| snake.player_id != player_id);
+    }
+
+    fn reset(&mut self) {
+        let players: Vec<PlayerId> = self.snakes.iter().map(|snake| snake.player_id).collect();
+        //The generator continues, so a replayed session gets the same apples after a reset
+        *self = Self::start(
+            self.lose_screen.clone(),
+            self.win_screen.clone(),
+            self.rand.clone(),
+        );
+        for player_id in players {
+            self.on_join(player_id);
+        }
+    }
+
+    fn state(&self) -> GameState {
+        //Length of the longest snake
+        let score = self
+            .snakes
+            .iter()
+            .map(|snake| snake.cells.len() as u32)
+            .max()
+            .unwrap_or(0);
+        match self.game_state {
+            SnakeGameState::Playing => GameState::Playing { score },
+            SnakeGameState::Win => GameState::Won { score },
+            SnakeGameState::Lose => GameState::Over { score },
+        }
+    }
+}
+
+impl Snake {
+    fn spawn(player_id: PlayerId, slot: usize) -> Self {
+        let (x, y, direction) = SPAWNS[slot];
+        Self {
+            player_id,
+            slot,
+            cells: LinkedList::from([SnakeCell::new(x, y)]),
+            direction,
+            moving: direction,
+            death_timer: DEATH_FRAMES,
+            alive: true,
+        }
+    }
+
+    //Cells after the next step, None when the snake would hit a wall
+    fn moved(
+        &self,
+        apple_x: usize,
+        apple_y: usize,
+    ) -> anyhow::Result<Option<LinkedList<SnakeCell>>> {
+        let head_cell = match self.cells.front() {
             Some(val) => val,
             None => return Err(anyhow!("Snake is empty")),
         };
@@ -158,67 +300,43 @@ impl Game for SnakeGame {
         let head_y = head_cell.y;
 
         //edge cases
-        if let SnakeDirection::Left = self.direction {
-            if head_x == 0 {
-                return self.draw_lose_screen();
-            }
-        }
-        if let SnakeDirection::Right = self.direction {
-            if head_x == BOARD_WIDTH - 1 {
-                return self.draw_lose_screen();
-            }
-        }
-        if let SnakeDirection::Up = self.direction {
-            if head_y == 0 {
-                return self.draw_lose_screen();
-            }
-        }
-        if let SnakeDirection::Down = self.direction {
-            if head_y == BOARD_HEIGHT - 1 {
-                return self.draw_lose_screen();
-            }
+        let blocked = match self.direction {
+            SnakeDirection::Left => head_x == 0,
+            SnakeDirection::Right => head_x == BOARD_WIDTH - 1,
+            SnakeDirection::Up => head_y == 0,
+            SnakeDirection::Down => head_y == BOARD_HEIGHT - 1,
+        };
+        if blocked {
+            return Ok(None);
         }
 
         let new_head_x = (head_x as i32 + self.direction.to_x_diff()) as usize;
         let new_head_y = (head_y as i32 + self.direction.to_y_diff()) as usize;
 
-        let apple_eaten = new_head_x == self.apple_x && new_head_y == self.apple_y;
+        let apple_eaten = new_head_x == apple_x && new_head_y == apple_y;
 
         //We might lose at some point and then we need the original snake
-        let mut snake_clone = self.snake.clone();
-        snake_clone.push_front(SnakeCell::new(new_head_x, new_head_y));
-
+        let mut cells = self.cells.clone();
+        cells.push_front(SnakeCell::new(new_head_x, new_head_y));
         if !apple_eaten {
-            snake_clone.pop_back();
-        } else {
-            let mut recurstion = 0;
-            'apple_loop: loop {
-                if recurstion == CANVAS_WIDTH * CANVAS_HEIGHT {
-                    return self.draw_win_screen();
-                }
+            cells.pop_back();
+        }
 
-                self.apple_x = self.rand.gen_range(0..BOARD_WIDTH);
-                self.apple_y = self.rand.gen_range(0..BOARD_HEIGHT);
+        Ok(Some(cells))
+    }
 
-                if !snake_clone
-                    .iter()
-                    .any(|cell| cell.x == self.apple_x && cell.y == self.apple_y)
-                {
-                    break 'apple_loop;
-                }
-                recurstion += 1;
-            }
+    //The snake stops for DEATH_FRAMES steps before losing, so a last turn can still save it
+    fn die(&mut self) {
+        if self.death_timer == 0 {
+            self.alive = false;
+        } else {
+            self.death_timer -= 1;
         }
+    }
 
-        //Late init of canvas to save memory if we quit early
-        let mut canvas = VideoCanvas::new(
-            self.width() as usize,
-            self.height() as usize,
-            &Color::hex("464B46")?,
-        );
-
-        let mut cursor = snake_clone.cursor_front();
-        let mut check_for_head_collision = false;
+    fn draw(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+        let color = &SNAKE_COLORS[self.slot];
+        let mut cursor = self.cells.cursor_front();
 
         'cursor_loop: loop {
             let cell = match cursor.current() {
@@ -226,10 +344,6 @@ impl Game for SnakeGame {
                 None => break 'cursor_loop,
             };
 
-            if check_for_head_collision && cell.x == new_head_x && cell.y == new_head_y {
-                return self.draw_lose_screen();
-            }
-
             if let Some(next_cell) = cursor.peek_next() {
                 let first_cell = if cell.x != next_cell.x {
                     if next_cell.x > cell.x {
@@ -260,7 +374,7 @@ impl Game for SnakeGame {
                     first_cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET,
                     second_cell.x * CELL_SIZE_X + DRAW_X_OFFSET + DRAW_CELL_SIZE_X - 1,
                     second_cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET + DRAW_CELL_SIZE_Y - 1,
-                    &SNAKE_COLOR,
+                    color,
                 );
             } else {
                 canvas.draw_square(
@@ -268,148 +382,297 @@ impl Game for SnakeGame {
                     cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET,
                     cell.x * CELL_SIZE_X + DRAW_X_OFFSET + DRAW_CELL_SIZE_X - 1,
                     cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET + DRAW_CELL_SIZE_Y - 1,
-                    &SNAKE_COLOR,
+                    color,
                 );
             }
 
-            check_for_head_collision = true;
             cursor.move_next();
         }
 
+        let head_cell = match self.cells.front() {
+            Some(val) => val,
+            None => return Err(anyhow!("Empty snake")),
+        };
+
+        let head_x = head_cell.x;
+        let head_y = head_cell.y;
+
         //We start drawing at 0. (9 + 1) * 64 = 10 * 64 * 640
         //640 is out of bounds
         canvas.draw_square(
-            new_head_x * CELL_SIZE_X + HEAD_OFFSET,
-            new_head_y * CELL_SIZE_Y + HEAD_OFFSET,
-            (new_head_x + 1) * CELL_SIZE_X - 1 - HEAD_OFFSET,
-            (new_head_y + 1) * CELL_SIZE_Y - 1 - HEAD_OFFSET,
-            &SNAKE_COLOR,
+            head_x * CELL_SIZE_X + HEAD_OFFSET,
+            head_y * CELL_SIZE_Y + HEAD_OFFSET,
+            (head_x + 1) * CELL_SIZE_X - 1 - HEAD_OFFSET,
+            (head_y + 1) * CELL_SIZE_Y - 1 - HEAD_OFFSET,
+            color,
         );
 
-        //Draw apple
-        canvas.draw_square(
-            self.apple_x * CELL_SIZE_X,
-            self.apple_y * CELL_SIZE_Y,
-            (self.apple_x + 1) * CELL_SIZE_X - 1,
-            (self.apple_y + 1) * CELL_SIZE_Y - 1,
-            &APPLE_COLOR,
-        );
-
-        self.death_timer = DEATH_FRAMES;
-        self.snake = snake_clone;
-
-        Ok(canvas)
+        Ok(())
     }
 }
 
 impl SnakeGame {
-    fn draw_lose_screen(&mut self) -> anyhow::Result<VideoCanvas> {
-        if self.death_timer == 0 {
-            self.game_state = SnakeGameState::Lose;
-            let mut canvas = VideoCanvas::new_from_image(&SNAKE_LOSE_SCREEN);
-            canvas.draw_default_text(
-                439,
-                352,
-                56,
-                23,
-                &Color::new(255, 0, 0),
-                &self.snake.len().to_string(),
-            )?;
-            Ok(canvas)
-        } else {
-            let mut canvas = VideoCanvas::new(
-                self.width() as usize,
-                self.height() as usize,
-                &Color::hex("464B46")?,
+    fn start(lose_screen: Arc<BakedImage>, win_screen: Arc<BakedImage>, mut rand: StdRng) -> Self {
+        let apple_x = rand.gen_range(0..BOARD_WIDTH);
+        let apple_y = rand.gen_range(0..BOARD_HEIGHT);
+
+        //Snakes are spawned when players join
+        Self {
+            snakes: Vec::new(),
+            apple_x,
+            apple_y,
+            rand,
+            game_state: SnakeGameState::Playing,
+            timer: StepTimer::new(STEP),
+            lose_screen,
+            win_screen,
+        }
+    }
+
+    //Screens are the whole canvas, so images of another size would break the frame splitting
+    fn screen(
+        assets: &AssetLoader,
+        name: &str,
+        baked: &'static BakedImage,
+    ) -> anyhow::Result<Arc<BakedImage>> {
+        let image = assets.image_or(name, baked)?;
+        if image.width != CANVAS_WIDTH as u32 || image.height != CANVAS_HEIGHT as u32 {
+            println!(
+                "[ProjectAyaya] Asset {name} has to be {CANVAS_WIDTH}x{CANVAS_HEIGHT}, using the built in one"
             );
+            return Ok(Arc::new(baked.clone()));
+        }
 
-            let mut cursor = self.snake.cursor_front();
+        Ok(image)
+    }
 
-            'cursor_loop: loop {
-                let cell = match cursor.current() {
-                    Some(val) => val,
-                    None => break 'cursor_loop,
-                };
+    //Every snake moves at once, so two heads meeting stop both snakes
+    fn step(&mut self) -> anyhow::Result<()> {
+        if !matches!(self.game_state, SnakeGameState::Playing) {
+            return Ok(());
+        }
 
-                if let Some(next_cell) = cursor.peek_next() {
-                    let first_cell = if cell.x != next_cell.x {
-                        if next_cell.x > cell.x {
-                            cell
-                        } else {
-                            next_cell
-                        }
-                    } else if next_cell.y > cell.y {
-                        cell
-                    } else {
-                        next_cell
-                    };
-
-                    let second_cell = if cell.x != next_cell.x {
-                        if next_cell.x > cell.x {
-                            next_cell
-                        } else {
-                            cell
-                        }
-                    } else if next_cell.y > cell.y {
-                        next_cell
-                    } else {
-                        cell
-                    };
-
-                    canvas.draw_square(
-                        first_cell.x * CELL_SIZE_X + DRAW_X_OFFSET,
-                        first_cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET,
-                        second_cell.x * CELL_SIZE_X + DRAW_X_OFFSET + DRAW_CELL_SIZE_X - 1,
-                        second_cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET + DRAW_CELL_SIZE_Y - 1,
-                        &SNAKE_COLOR,
-                    );
-                } else {
-                    canvas.draw_square(
-                        cell.x * CELL_SIZE_X + DRAW_X_OFFSET,
-                        cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET,
-                        cell.x * CELL_SIZE_X + DRAW_X_OFFSET + DRAW_CELL_SIZE_X - 1,
-                        cell.y * CELL_SIZE_Y + DRAW_Y_OFFSET + DRAW_CELL_SIZE_Y - 1,
-                        &SNAKE_COLOR,
-                    );
-                }
+        let mut moved = Vec::with_capacity(self.snakes.len());
+        for snake in &self.snakes {
+            moved.push(match snake.alive {
+                true => snake.moved(self.apple_x, self.apple_y)?,
+                false => None,
+            });
+        }
 
-                cursor.move_next();
+        //Snakes that do not move keep their cells, dead ones are not on the board
+        let board: Vec<Option<&LinkedList<SnakeCell>>> = self
+            .snakes
+            .iter()
+            .zip(&moved)
+            .map(|(snake, cells)| snake.alive.then(|| cells.as_ref().unwrap_or(&snake.cells)))
+            .collect();
+        let collided: Vec<bool> = moved
+            .iter()
+            .enumerate()
+            .map(|(i, cells)| {
+                let Some(head) = cells.as_ref().and_then(|cells| cells.front()) else {
+                    return false;
+                };
+                //The head is the first cell
+                board.iter().enumerate().any(|(j, cells)| {
+                    cells.is_some_and(|cells| {
+                        cells
+                            .iter()
+                            .skip((i == j) as usize)
+                            .any(|cell| cell == head)
+                    })
+                })
+            })
+            .collect();
+
+        let mut apple_eaten = false;
+        for ((snake, cells), collided) in self.snakes.iter_mut().zip(moved).zip(collided) {
+            if !snake.alive {
+                continue;
+            }
+            match cells {
+                Some(cells) if !collided => {
+                    apple_eaten |= cells.len() > snake.cells.len();
+                    snake.cells = cells;
+                    snake.moving = snake.direction;
+                    snake.death_timer = DEATH_FRAMES;
+                }
+                _ => snake.die(),
             }
+        }
 
-            let head_cell = match self.snake.front() {
-                Some(val) => val,
-                None => return Err(anyhow!("Empty snake")),
-            };
+        if apple_eaten {
+            self.move_apple();
+        }
+        if !self.snakes.is_empty() && self.snakes.iter().all(|snake| !snake.alive) {
+            self.game_state = SnakeGameState::Lose;
+        }
 
-            let head_x = head_cell.x;
-            let head_y = head_cell.y;
-
-            //We start drawing at 0. (9 + 1) * 64 = 10 * 64 * 640
-            //640 is out of bounds
-            canvas.draw_square(
-                head_x * CELL_SIZE_X + HEAD_OFFSET,
-                head_y * CELL_SIZE_Y + HEAD_OFFSET,
-                (head_x + 1) * CELL_SIZE_X - 1 - HEAD_OFFSET,
-                (head_y + 1) * CELL_SIZE_Y - 1 - HEAD_OFFSET,
-                &SNAKE_COLOR,
-            );
+        Ok(())
+    }
 
-            //Draw apple
-            canvas.draw_square(
-                self.apple_x * CELL_SIZE_X,
-                self.apple_y * CELL_SIZE_Y,
-                (self.apple_x + 1) * CELL_SIZE_X - 1,
-                (self.apple_y + 1) * CELL_SIZE_Y - 1,
-                &APPLE_COLOR,
+    //The game is won when there is no free cell left
+    fn move_apple(&mut self) {
+        for _ in 0..CANVAS_WIDTH * CANVAS_HEIGHT {
+            let apple = SnakeCell::new(
+                self.rand.gen_range(0..BOARD_WIDTH),
+                self.rand.gen_range(0..BOARD_HEIGHT),
             );
 
-            self.death_timer -= 1;
-            Ok(canvas)
+            if !self
+                .snakes
+                .iter()
+                .filter(|snake| snake.alive)
+                .any(|snake| snake.cells.contains(&apple))
+            {
+                (self.apple_x, self.apple_y) = (apple.x, apple.y);
+                return;
+            }
         }
-    }
 
-    fn draw_win_screen(&mut self) -> anyhow::Result<VideoCanvas> {
         self.game_state = SnakeGameState::Win;
-        Ok(VideoCanvas::new_from_image(&SNAKE_WIN_SCREEN))
+    }
+
+    fn draw_lose_screen(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+        canvas.draw_image(0, 0, &self.lose_screen);
+        canvas.draw_text(
+            439,
+            352,
+            &TextStyle::new(56.0, Color::new(255, 0, 0)),
+            &self.state().score().to_string(),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The apple is moved to the corner, so it is not eaten by accident
+    fn game() -> SnakeGame {
+        let mut game = SnakeGame::new(GameConfig::default()).unwrap();
+        game.on_join(0);
+        (game.apple_x, game.apple_y) = (BOARD_WIDTH - 1, BOARD_HEIGHT - 1);
+        game
+    }
+
+    //Head of the first snake
+    fn head(game: &SnakeGame) -> (usize, usize) {
+        let head = game.snakes[0].cells.front().unwrap();
+        (head.x, head.y)
+    }
+
+    #[test]
+    fn test_tick_steps() {
+        let mut game = game();
+        game.tick(STEP / 2).unwrap();
+        assert!(head(&game) == (0, 0));
+        game.tick(STEP / 2).unwrap();
+        assert!(head(&game) == (1, 0));
+
+        //Several steps in one tick
+        game.on_input(0, InputEvent::KeyDown(Key::Backwards));
+        game.tick(STEP * 3).unwrap();
+        assert!(head(&game) == (1, 3));
+    }
+
+    #[test]
+    fn test_lose_and_reset() {
+        let mut game = game();
+        game.on_input(0, InputEvent::KeyDown(Key::Forward));
+
+        //One step of grace at the wall
+        game.tick(STEP).unwrap();
+        assert!(game.state() == GameState::Playing { score: 1 });
+        game.tick(STEP).unwrap();
+        assert!(game.state() == GameState::Over { score: 1 });
+
+        //Finished games do not move
+        game.tick(STEP * 10).unwrap();
+        assert!(game.state().is_finished());
+
+        game.reset();
+        assert!(game.state() == GameState::Playing { score: 1 });
+        assert!(head(&game) == (0, 0));
+    }
+
+    #[test]
+    fn test_multiplayer() {
+        let mut game = game();
+        game.on_join(5);
+        //Inside of the board, away from both snakes
+        (game.apple_x, game.apple_y) = (5, 5);
+        assert!(game.snakes[1].slot == 1);
+        assert!(game.snakes[1].cells.front() == Some(&SnakeCell::new(9, 9)));
+
+        //Player 5 goes up along the right wall and meets the first snake head on in the corner
+        game.on_input(5, InputEvent::KeyDown(Key::Forward));
+        game.tick(STEP * 8).unwrap();
+        assert!(head(&game) == (8, 0));
+        game.tick(STEP).unwrap();
+        assert!(head(&game) == (8, 0));
+        assert!(game.state() == GameState::Playing { score: 1 });
+        game.tick(STEP).unwrap();
+        assert!(game.snakes.iter().all(|snake| !snake.alive));
+        assert!(game.state() == GameState::Over { score: 1 });
+
+        //Players stay after a reset, a player that left frees the corner
+        game.reset();
+        assert!(game.snakes.len() == 2);
+        game.on_leave(0);
+        game.on_join(2);
+        game.on_join(3);
+        game.on_join(4);
+        let slots: Vec<(PlayerId, usize)> = game
+            .snakes
+            .iter()
+            .map(|snake| (snake.player_id, snake.slot))
+            .collect();
+        assert!(slots == [(5, 1), (2, 0), (3, 2), (4, 3)]);
+
+        //Every corner is taken
+        game.on_join(6);
+        assert!(game.snakes.len() == 4);
+    }
+
+    #[test]
+    fn test_turn_back_between_steps() {
+        let mut game = game();
+        game.tick(STEP).unwrap();
+        assert!(head(&game) == (1, 0));
+
+        //Down and then left before the next step would reverse the snake
+        game.on_input(0, InputEvent::KeyDown(Key::Backwards));
+        game.on_input(0, InputEvent::KeyDown(Key::Left));
+        game.tick(STEP).unwrap();
+        assert!(head(&game) == (1, 1));
+
+        //Moving down, so left is allowed now
+        game.on_input(0, InputEvent::KeyDown(Key::Left));
+        game.tick(STEP).unwrap();
+        assert!(head(&game) == (0, 1));
+    }
+
+    #[test]
+    fn test_join_occupied_corner() {
+        let mut game = game();
+        game.on_join(5);
+        (game.apple_x, game.apple_y) = (5, 5);
+
+        //The snakes move into the two free corners
+        game.tick(STEP * 9).unwrap();
+        assert!(head(&game) == (9, 0));
+        assert!(game.snakes[1].cells.front() == Some(&SnakeCell::new(0, 9)));
+        game.on_join(6);
+        assert!(game.snakes.len() == 2);
+
+        //Free again once the snakes leave them
+        game.on_input(0, InputEvent::KeyDown(Key::Backwards));
+        game.on_input(5, InputEvent::KeyDown(Key::Forward));
+        game.tick(STEP).unwrap();
+        game.on_join(6);
+        assert!(game.snakes.len() == 3 && game.snakes[2].slot == 2);
     }
 }
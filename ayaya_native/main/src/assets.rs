@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use png::{ColorType, Transformations};
+
+use crate::colorlib::Color;
+use crate::player::game_player::BakedImage;
+
+//Larger images would not fit any screen anyway
+static MAX_DIMENSION: u32 = 4096;
+
+//Shared by every player, screens using the same directory load an image only once.
+//Images are stored with the modification time of their file and are loaded again when the
+//file changes. Images that are still shown stay alive after that, until their Arc is dropped
+static CACHE: Lazy<Mutex<HashMap<PathBuf, CachedImage>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+//Modification time of the file when the image was loaded
+type CachedImage = (SystemTime, Arc<BakedImage>);
+
+/// PNG images loaded from a directory on the server. They are quantized with the same
+/// color table as the baked ones, so server owners can replace art without rebuilding
+#[derive(Debug, Clone, Default)]
+pub struct AssetLoader {
+    //None when the plugin did not pass an asset directory
+    directory: Option<PathBuf>,
+}
+
+impl AssetLoader {
+    pub fn new(directory: Option<&str>) -> Self {
+        Self {
+            directory: directory
+                .filter(|directory| !directory.is_empty())
+                .map(PathBuf::from),
+        }
+    }
+
+    //Names are file names without ".png", so images outside the directory cannot be read
+    fn path(&self, name: &str) -> anyhow::Result<Option<PathBuf>> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+        {
+            return Err(anyhow!("Invalid asset name ({name})"));
+        }
+
+        Ok(self
+            .directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{name}.png"))))
+    }
+
+    /// Image "<name>.png" from the asset directory
+    pub fn image(&self, name: &str) -> anyhow::Result<Arc<BakedImage>> {
+        match self.find(name)? {
+            Some(image) => Ok(image),
+            None => Err(anyhow!("Asset {name} does not exist")),
+        }
+    }
+
+    /// Image from the asset directory, or the baked one when the directory does not have it
+    pub fn image_or(
+        &self,
+        name: &str,
+        baked: &'static BakedImage,
+    ) -> anyhow::Result<Arc<BakedImage>> {
+        //Baked pixels are borrowed, so the copy is only the dimensions
+        Ok(self.find(name)?.unwrap_or_else(|| Arc::new(baked.clone())))
+    }
+
+    //Images are looked up when a game starts or a command shows them, so missing files
+    //are checked every time and can be added while the server runs
+    fn find(&self, name: &str) -> anyhow::Result<Option<Arc<BakedImage>>> {
+        let Some(path) = self.path(name)? else {
+            return Ok(None);
+        };
+
+        //The lock is held while decoding, so an image is never decoded twice
+        let mut cache = CACHE
+            .lock()
+            .map_err(|_| anyhow!("Asset cache lock is poisoned"))?;
+        let modified = match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata.modified()?,
+            _ => {
+                cache.remove(&path);
+                return Ok(None);
+            }
+        };
+        if let Some((cached, image)) = cache.get(&path) {
+            if *cached == modified {
+                return Ok(Some(image.clone()));
+            }
+        }
+
+        let image =
+            load_png(&path).map_err(|err| anyhow!("Unable to load asset {name}! Reason: {err}"))?;
+        println!(
+            "[ProjectAyaya] Loaded asset {name} ({}x{})",
+            image.width, image.height
+        );
+        let image = Arc::new(image);
+        cache.insert(path, (modified, image.clone()));
+
+        Ok(Some(image))
+    }
+}
+
+fn load_png(path: &Path) -> anyhow::Result<BakedImage> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let (width, height) = (reader.info().width, reader.info().height);
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(anyhow!(
+            "Image is too large ({width}x{height}, max {MAX_DIMENSION}x{MAX_DIMENSION})"
+        ));
+    }
+
+    let mut buffer = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let data = match info.color_type {
+        ColorType::Rgba => quantize(pixels.chunks_exact(4).map(|p| (p[0], p[1], p[2], p[3]))),
+        ColorType::Rgb => quantize(pixels.chunks_exact(3).map(|p| (p[0], p[1], p[2], 255))),
+        ColorType::GrayscaleAlpha => {
+            quantize(pixels.chunks_exact(2).map(|p| (p[0], p[0], p[0], p[1])))
+        }
+        ColorType::Grayscale => quantize(pixels.iter().map(|p| (*p, *p, *p, 255))),
+        //Expanded by normalize_to_color8
+        ColorType::Indexed => return Err(anyhow!("Indexed image was not expanded")),
+    };
+
+    Ok(BakedImage {
+        width,
+        height,
+        data: data.into(),
+    })
+}
+
+//Same rules as build.rs, pixels that are not fully opaque are transparent
+fn quantize(pixels: impl Iterator<Item = (u8, u8, u8, u8)>) -> Vec<u8> {
+    pixels
+        .map(|(red, green, blue, alpha)| match alpha {
+            255 => Color::new(red, green, blue).convert_to_mc(),
+            _ => 0,
+        })
+        .collect()
+}
+
+#[cfg(all(test, not(feature = "skip_buildrs")))]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    //Every test gets its own directory, the cache is shared
+    fn asset_dir(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("ayaya_assets_{test}_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn write_png(path: &Path, width: u32, height: u32, color: ColorType, data: &[u8]) {
+        let file = File::create(path).unwrap();
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_load_image() {
+        let directory = asset_dir("load");
+        write_png(
+            &directory.join("logo.png"),
+            2,
+            1,
+            ColorType::Rgba,
+            &[255, 0, 0, 255, 0, 0, 255, 100],
+        );
+        write_png(
+            &directory.join("gray.png"),
+            1,
+            1,
+            ColorType::Grayscale,
+            &[255],
+        );
+
+        let loader = AssetLoader::new(directory.to_str());
+        let logo = loader.image("logo").unwrap();
+        assert!(logo.width == 2 && logo.height == 1);
+        assert!(*logo.data == [Color::new(255, 0, 0).convert_to_mc(), 0]);
+
+        let gray = loader.image("gray").unwrap();
+        assert!(*gray.data == [Color::new(255, 255, 255).convert_to_mc()]);
+
+        //Cached while the modification time stays, the file is not read again
+        let path = directory.join("logo.png");
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, b"not a png").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(Arc::ptr_eq(&loader.image("logo").unwrap(), &logo));
+
+        //Changed files are loaded again
+        write_png(&path, 1, 1, ColorType::Grayscale, &[255]);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+        let changed = loader.image("logo").unwrap();
+        assert!(changed.width == 1 && changed.data == gray.data);
+        //The old image stays alive while it is used
+        assert!(logo.width == 2);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(loader.image("logo").is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_missing_and_invalid() {
+        static BAKED: BakedImage = BakedImage {
+            width: 1,
+            height: 1,
+            data: Cow::Borrowed(&[4]),
+        };
+
+        let directory = asset_dir("missing");
+        std::fs::write(directory.join("broken.png"), b"not a png").unwrap();
+        let loader = AssetLoader::new(directory.to_str());
+
+        assert!(loader.image("missing").is_err());
+        let baked = loader.image_or("missing", &BAKED).unwrap();
+        assert!(std::ptr::eq(baked.data.as_ptr(), BAKED.data.as_ptr()));
+        assert!(loader.image_or("broken", &BAKED).is_err());
+
+        //Misses are not cached, files added later are found
+        write_png(
+            &directory.join("missing.png"),
+            1,
+            1,
+            ColorType::Grayscale,
+            &[0],
+        );
+        assert!(*loader.image("missing").unwrap().data == [Color::new(0, 0, 0).convert_to_mc()]);
+
+        //Names cannot leave the directory
+        assert!(loader.image("../logo").is_err());
+        assert!(loader.image("logo.png").is_err());
+        assert!(loader.image("").is_err());
+
+        //Without a directory only baked images are used
+        let disabled = AssetLoader::new(None);
+        assert!(disabled.image("logo").is_err());
+        let baked = disabled.image_or("logo", &BAKED).unwrap();
+        assert!(std::ptr::eq(baked.data.as_ptr(), BAKED.data.as_ptr()));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+use std::io::{Seek, Write};
+
+use anyhow::anyhow;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+pub static SAMPLE_RATE: u32 = 44100;
+//Resource pack format of 1.18.2
+pub static PACK_FORMAT: u32 = 8;
+pub static SOUND_NAMESPACE: &str = "ayaya";
+
+/// Single sound file of the resource pack
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioSegment {
+    pub index: usize,
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    //First video frame that this segment should be played with
+    pub start_frame: u64,
+}
+
+impl AudioSegment {
+    //Name of the sound event, use it as "ayaya:<event>" in /playsound
+    pub fn sound_event(&self, pack_name: &str) -> String {
+        format!("{pack_name}.{}", self.index)
+    }
+
+    fn sound_path(&self, pack_name: &str) -> String {
+        format!("{pack_name}/{}", self.index)
+    }
+}
+
+/// Splits audio of a given length into segments of segment_ms (the last one might be shorter)
+pub fn plan_segments(duration_ms: u64, segment_ms: u64, fps: i32) -> Vec<AudioSegment> {
+    let mut segments = Vec::new();
+    let mut start_ms = 0;
+
+    while start_ms < duration_ms {
+        let index = segments.len();
+        segments.push(AudioSegment {
+            index,
+            start_ms,
+            duration_ms: segment_ms.min(duration_ms - start_ms),
+            start_frame: start_ms * fps as u64 / 1000,
+        });
+        start_ms += segment_ms;
+    }
+
+    segments
+}
+
+//Resource locations only allow [a-z0-9_.-]
+fn verify_pack_name(pack_name: &str) -> anyhow::Result<()> {
+    let valid = !pack_name.is_empty()
+        && pack_name.chars().all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.' || c == '-'
+        });
+
+    if !valid {
+        return Err(anyhow!(
+            "Invalid sound pack name ({pack_name}). Only a-z, 0-9, _, . and - are allowed"
+        ));
+    }
+    Ok(())
+}
+
+pub fn sounds_json(pack_name: &str, segments: &[AudioSegment]) -> String {
+    let events: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            format!(
+                "  \"{}\": {{\"sounds\": [{{\"name\": \"{SOUND_NAMESPACE}:{}\", \"stream\": true}}]}}",
+                segment.sound_event(pack_name),
+                segment.sound_path(pack_name)
+            )
+        })
+        .collect();
+
+    format!("{{\n{}\n}}\n", events.join(",\n"))
+}
+
+/// Table that the plugin uses to start every segment at the right video frame
+pub fn timing_table_json(pack_name: &str, segments: &[AudioSegment], fps: i32) -> String {
+    let entries: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            format!(
+                "    {{\"sound\": \"{SOUND_NAMESPACE}:{}\", \"start_frame\": {}, \"start_ms\": {}, \"duration_ms\": {}}}",
+                segment.sound_event(pack_name),
+                segment.start_frame,
+                segment.start_ms,
+                segment.duration_ms
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"fps\": {fps},\n  \"segments\": [\n{}\n  ]\n}}\n",
+        entries.join(",\n")
+    )
+}
+
+/// Writes resource pack zip. ogg_files have to be in the same order as segments
+pub fn write_resource_pack<W: Write + Seek>(
+    writer: W,
+    pack_name: &str,
+    segments: &[AudioSegment],
+    ogg_files: &[Vec<u8>],
+    fps: i32,
+) -> anyhow::Result<W> {
+    verify_pack_name(pack_name)?;
+    if segments.len() != ogg_files.len() {
+        return Err(anyhow!(
+            "Segment count ({}) does not match ogg file count ({})",
+            segments.len(),
+            ogg_files.len()
+        ));
+    }
+
+    let mut zip = ZipWriter::new(writer);
+    //Ogg is already compressed
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file("pack.mcmeta", options)?;
+    zip.write_all(
+        format!(
+            "{{\"pack\": {{\"pack_format\": {PACK_FORMAT}, \"description\": \"ProjectAyaya audio: {pack_name}\"}}}}\n"
+        )
+        .as_bytes(),
+    )?;
+
+    zip.start_file(format!("assets/{SOUND_NAMESPACE}/sounds.json"), options)?;
+    zip.write_all(sounds_json(pack_name, segments).as_bytes())?;
+
+    for (segment, ogg) in segments.iter().zip(ogg_files.iter()) {
+        zip.start_file(
+            format!(
+                "assets/{SOUND_NAMESPACE}/sounds/{}.ogg",
+                segment.sound_path(pack_name)
+            ),
+            options,
+        )?;
+        zip.write_all(ogg)?;
+    }
+
+    //Minecraft ignores files outside of assets, so the timing table can live inside of the pack
+    zip.start_file("timings.json", options)?;
+    zip.write_all(timing_table_json(pack_name, segments, fps).as_bytes())?;
+
+    Ok(zip.finish()?)
+}
+
+#[cfg(feature = "ffmpeg")]
+fn encode_vorbis(samples: &[f32], output_path: &std::path::Path) -> anyhow::Result<Vec<u8>> {
+    use ffmpeg::{codec, format, frame, util::channel_layout::ChannelLayout};
+
+    let codec = ffmpeg::encoder::find_by_name("libvorbis")
+        .ok_or_else(|| anyhow!("FFmpeg was built without libvorbis"))?;
+
+    let mut octx = format::output(&output_path)?;
+    let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+
+    let mut stream = octx.add_stream(codec)?;
+    let context = codec::context::Context::from_parameters(stream.parameters())?;
+    let mut encoder = context.encoder().audio()?;
+
+    encoder.set_rate(SAMPLE_RATE as i32);
+    encoder.set_channel_layout(ChannelLayout::MONO);
+    encoder.set_channels(1);
+    encoder.set_format(format::Sample::F32(format::sample::Type::Planar));
+    encoder.set_bit_rate(64_000);
+    encoder.set_time_base((1, SAMPLE_RATE as i32));
+    if global_header {
+        encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+    }
+
+    let mut encoder = encoder.open_as(codec)?;
+    stream.set_parameters(&encoder);
+    stream.set_time_base((1, SAMPLE_RATE as i32));
+
+    octx.write_header()?;
+    let stream_time_base = octx.stream(0).unwrap().time_base();
+
+    let frame_size = match encoder.frame_size() {
+        0 => 1024,
+        val => val as usize,
+    };
+
+    let write_packets = |encoder: &mut ffmpeg::encoder::Audio,
+                         octx: &mut format::context::Output|
+     -> anyhow::Result<()> {
+        let mut packet = ffmpeg::Packet::empty();
+        while encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts((1, SAMPLE_RATE as i32), stream_time_base);
+            packet.write_interleaved(octx)?;
+        }
+        Ok(())
+    };
+
+    for (i, chunk) in samples.chunks(frame_size).enumerate() {
+        let mut audio_frame = frame::Audio::new(
+            format::Sample::F32(format::sample::Type::Planar),
+            chunk.len(),
+            ChannelLayout::MONO,
+        );
+        audio_frame.set_rate(SAMPLE_RATE);
+        audio_frame.plane_mut::<f32>(0).copy_from_slice(chunk);
+        audio_frame.set_pts(Some((i * frame_size) as i64));
+
+        encoder.send_frame(&audio_frame)?;
+        write_packets(&mut encoder, &mut octx)?;
+    }
+
+    encoder.send_eof()?;
+    write_packets(&mut encoder, &mut octx)?;
+    octx.write_trailer()?;
+    drop(octx);
+
+    let ogg = std::fs::read(output_path)?;
+    std::fs::remove_file(output_path)?;
+    Ok(ogg)
+}
+
+/// Decodes audio of the video, converts it to mono Ogg Vorbis and writes it as a resource pack
+#[cfg(feature = "ffmpeg")]
+pub fn extract_audio(
+    file_name: String,
+    output_file_name: String,
+    pack_name: String,
+    segment_seconds: u32,
+) -> anyhow::Result<Vec<AudioSegment>> {
+    use std::{fs::File, io::BufWriter};
+
+    use ffmpeg::{format, frame, media::Type, util::channel_layout::ChannelLayout};
+
+    verify_pack_name(&pack_name)?;
+    if segment_seconds == 0 {
+        return Err(anyhow!("Segment length has to be greater than 0"));
+    }
+
+    ffmpeg::init()?;
+    let mut ictx = format::input(&file_name)?;
+
+    //Without video we still want usable frame indexes, so we use minecraft ticks
+    let fps = match ictx.streams().best(Type::Video) {
+        Some(stream) => stream.rate().0 / stream.rate().1,
+        None => 20,
+    };
+
+    let audio_stream = ictx
+        .streams()
+        .best(Type::Audio)
+        .ok_or_else(|| anyhow!("This file does not have an audio stream"))?;
+    let audio_stream_index = audio_stream.index();
+
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(audio_stream.parameters())?
+        .decoder()
+        .audio()?;
+    if decoder.channel_layout().is_empty() {
+        decoder.set_channel_layout(ChannelLayout::default(decoder.channels() as i32));
+    }
+
+    //Minecraft plays mono sounds positionally, which is what we want for a screen
+    let mut resampler = decoder.resampler(
+        format::Sample::F32(format::sample::Type::Planar),
+        ChannelLayout::MONO,
+        SAMPLE_RATE,
+    )?;
+
+    let segment_samples = (segment_seconds * SAMPLE_RATE) as usize;
+    let mut samples = Vec::<f32>::with_capacity(segment_samples * 2);
+    let mut ogg_files = Vec::<Vec<u8>>::new();
+    let mut total_samples = 0usize;
+
+    let temp_ogg =
+        std::env::temp_dir().join(format!("ayaya_{pack_name}_{}.ogg", std::process::id()));
+
+    let mut flush_segments = |samples: &mut Vec<f32>, last: bool| -> anyhow::Result<()> {
+        while samples.len() >= segment_samples || (last && !samples.is_empty()) {
+            let len = samples.len().min(segment_samples);
+            ogg_files.push(encode_vorbis(&samples[..len], &temp_ogg)?);
+            total_samples += len;
+            samples.drain(..len);
+        }
+        Ok(())
+    };
+
+    let mut decoded = frame::Audio::empty();
+    let mut resampled = frame::Audio::empty();
+
+    let mut receive_and_resample =
+        |decoder: &mut ffmpeg::decoder::Audio, samples: &mut Vec<f32>| -> anyhow::Result<()> {
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                if decoded.channel_layout().is_empty() {
+                    decoded.set_channel_layout(ChannelLayout::default(decoded.channels() as i32));
+                }
+                resampler.run(&decoded, &mut resampled)?;
+                samples.extend_from_slice(&resampled.plane::<f32>(0)[..resampled.samples()]);
+            }
+            Ok(())
+        };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != audio_stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet)?;
+        receive_and_resample(&mut decoder, &mut samples)?;
+        flush_segments(&mut samples, false)?;
+    }
+
+    decoder.send_eof()?;
+    receive_and_resample(&mut decoder, &mut samples)?;
+
+    //The resampler holds back the last samples until it is flushed
+    let mut flushed = frame::Audio::empty();
+    resampler.flush(&mut flushed)?;
+    if flushed.samples() > 0 {
+        samples.extend_from_slice(&flushed.plane::<f32>(0)[..flushed.samples()]);
+    }
+    flush_segments(&mut samples, true)?;
+
+    //Round up so a tiny last segment is not lost
+    let duration_ms = (total_samples as u64 * 1000).div_ceil(SAMPLE_RATE as u64);
+    let segments = plan_segments(duration_ms, segment_seconds as u64 * 1000, fps);
+
+    let output = BufWriter::new(File::create(output_file_name)?);
+    write_resource_pack(output, &pack_name, &segments, &ogg_files, fps)?;
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    #[test]
+    fn test_plan_segments() {
+        let segments = plan_segments(25_500, 10_000, 30);
+
+        assert!(segments.len() == 3);
+        assert!(segments[0].start_ms == 0 && segments[0].duration_ms == 10_000);
+        assert!(segments[1].start_ms == 10_000 && segments[1].start_frame == 300);
+        assert!(segments[2].duration_ms == 5_500 && segments[2].start_frame == 600);
+
+        assert!(plan_segments(0, 10_000, 30).is_empty());
+    }
+
+    #[test]
+    fn test_sounds_json() {
+        let segments = plan_segments(15_000, 10_000, 24);
+        let json = sounds_json("naruto_1", &segments);
+
+        assert!(json.contains(
+            "\"naruto_1.0\": {\"sounds\": [{\"name\": \"ayaya:naruto_1/0\", \"stream\": true}]}"
+        ));
+        assert!(json.contains("\"naruto_1.1\""));
+        assert!(!json.contains("\"naruto_1.2\""));
+    }
+
+    #[test]
+    fn test_resource_pack() {
+        let segments = plan_segments(20_000, 10_000, 20);
+        let ogg_files = vec![b"OggS first".to_vec(), b"OggS second".to_vec()];
+
+        let data = write_resource_pack(
+            Cursor::new(Vec::new()),
+            "episode",
+            &segments,
+            &ogg_files,
+            20,
+        )
+        .unwrap()
+        .into_inner();
+        let mut zip = ZipArchive::new(Cursor::new(data)).unwrap();
+
+        let mut read_file = |name: &str| {
+            let mut content = Vec::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            content
+        };
+
+        assert!(String::from_utf8(read_file("pack.mcmeta"))
+            .unwrap()
+            .contains("\"pack_format\": 8"));
+        assert!(read_file("assets/ayaya/sounds/episode/0.ogg") == ogg_files[0]);
+        assert!(read_file("assets/ayaya/sounds/episode/1.ogg") == ogg_files[1]);
+        assert!(String::from_utf8(read_file("assets/ayaya/sounds.json"))
+            .unwrap()
+            .contains("ayaya:episode/1"));
+        assert!(String::from_utf8(read_file("timings.json"))
+            .unwrap()
+            .contains("{\"sound\": \"ayaya:episode.1\", \"start_frame\": 200, \"start_ms\": 10000, \"duration_ms\": 10000}"));
+    }
+
+    #[test]
+    fn test_invalid_resource_pack() {
+        let segments = plan_segments(10_000, 10_000, 20);
+
+        assert!(
+            write_resource_pack(Cursor::new(Vec::new()), "Naruto", &segments, &[vec![]], 20)
+                .is_err()
+        );
+        assert!(
+            write_resource_pack(Cursor::new(Vec::new()), "naruto", &segments, &[], 20).is_err()
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::player::sync_clock::ClockSource;
+
+//Every sink gets the same format. It is what discord uses, so it does not have to resample again
+pub static PCM_SAMPLE_RATE: u32 = 48000;
+pub static PCM_CHANNELS: u16 = 2;
+
+/// Output for decoded audio. Samples are interleaved stereo f32 at PCM_SAMPLE_RATE
+pub trait AudioSink: Send {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()>;
+    fn play(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    fn pause(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    //Buffered audio should be dropped, next samples start at position
+    fn seek(&mut self, position: Duration) -> anyhow::Result<()>;
+    //Some if the sink knows what the listeners hear right now. Otherwise the wall clock is used
+    fn clock_source(&self) -> Option<Arc<dyn ClockSource>> {
+        None
+    }
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioSinkOptions {
+    //(guild id, channel id), None for the channel from DiscordOptions
+    Discord { channel: Option<(u64, u64)> },
+    Wav { path: String },
+    Tcp { bind: String },
+}
+
+impl AudioSinkOptions {
+    //"discord", "discord:<guild id>:<channel id>", "wav:<path>" or "tcp:<ip>:<port>"
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        match spec.split_once(':') {
+            None if spec == "discord" => Ok(Self::Discord { channel: None }),
+            Some(("discord", channel)) => {
+                let channel = channel
+                    .split_once(':')
+                    .and_then(|(guild_id, channel_id)| {
+                        Some((guild_id.parse().ok()?, channel_id.parse().ok()?))
+                    })
+                    .ok_or_else(|| anyhow!("Invalid discord channel ({channel})"))?;
+
+                Ok(Self::Discord {
+                    channel: Some(channel),
+                })
+            }
+            Some(("wav", path)) if !path.is_empty() => Ok(Self::Wav {
+                path: path.to_string(),
+            }),
+            Some(("tcp", bind)) if bind.parse::<SocketAddr>().is_ok() => Ok(Self::Tcp {
+                bind: bind.to_string(),
+            }),
+            _ => Err(anyhow!("Invalid audio sink ({spec})")),
+        }
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Writes 16 bit PCM WAV. The file contains exactly what was sent to the sink, so seeks and
+/// pauses are not visible in it
+pub struct WavSink<W: Write + Seek> {
+    writer: W,
+    data_len: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create(path: &str) -> anyhow::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W) -> anyhow::Result<Self> {
+        let block_align = PCM_CHANNELS * 2;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?; //Patched in finish
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; //PCM
+        writer.write_all(&PCM_CHANNELS.to_le_bytes())?;
+        writer.write_all(&PCM_SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(PCM_SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?; //Patched in finish
+
+        Ok(Self {
+            writer,
+            data_len: 0,
+        })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek + Send> AudioSink for WavSink<W> {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| to_i16(*sample).to_le_bytes())
+            .collect();
+
+        self.writer.write_all(&data)?;
+        self.data_len += data.len() as u32;
+        Ok(())
+    }
+
+    fn seek(&mut self, _position: Duration) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+static TCP_MAGIC: &[u8; 4] = b"AYAP";
+static TCP_PCM: u8 = 0;
+static TCP_PLAY: u8 = 1;
+static TCP_PAUSE: u8 = 2;
+static TCP_SEEK: u8 = 3;
+
+/// Streams raw PCM to every connected client (for example a client side mod)
+///
+/// Protocol (all numbers are big endian):
+/// - on connect: magic, sample rate (u32), channels (u16), then play or pause
+/// - pcm: 0, sample count (u32), signed 16 bit interleaved samples
+/// - play: 1
+/// - pause: 2
+/// - seek: 3, position in ms (u64). Clients should drop buffered audio
+pub struct TcpPcmSink {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    playing: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    local_addr: SocketAddr,
+}
+
+impl TcpPcmSink {
+    pub fn bind(bind: &str) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(bind)?;
+        //Non blocking, so the accept thread can notice that the sink was dropped
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        println!("Binding PCM audio server on: {local_addr}");
+
+        let clients = Arc::new(Mutex::new(Vec::<TcpStream>::new()));
+        let playing = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (clients_clone, playing_clone, stop_clone) =
+            (clients.clone(), playing.clone(), stop.clone());
+        thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((mut stream, addr)) => {
+                        println!("GOT PCM CONNECTION FROM: {addr:?}");
+                        let state = match playing_clone.load(Ordering::Relaxed) {
+                            true => TCP_PLAY,
+                            false => TCP_PAUSE,
+                        };
+
+                        let mut header = TCP_MAGIC.to_vec();
+                        header.extend_from_slice(&PCM_SAMPLE_RATE.to_be_bytes());
+                        header.extend_from_slice(&PCM_CHANNELS.to_be_bytes());
+                        header.push(state);
+
+                        let accepted = stream.set_nonblocking(false).is_ok()
+                            && stream.set_nodelay(true).is_ok()
+                            && stream
+                                .set_write_timeout(Some(Duration::from_secs(1)))
+                                .is_ok()
+                            && stream.write_all(&header).is_ok();
+
+                        if accepted {
+                            if let Ok(mut clients) = clients_clone.lock() {
+                                clients.push(stream);
+                            }
+                        }
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(err) => {
+                        println!("[ProjectAyaya] PCM audio server stopped! Err: {err:?}");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            clients,
+            playing,
+            stop,
+            local_addr,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    //Clients that cannot keep up are disconnected
+    fn broadcast(&self, message: &[u8]) -> anyhow::Result<()> {
+        let mut clients = match self.clients.lock() {
+            Ok(val) => val,
+            Err(_) => return Err(anyhow!("Cannot lock PCM clients!")),
+        };
+
+        clients.retain_mut(|client| client.write_all(message).is_ok());
+        Ok(())
+    }
+}
+
+impl AudioSink for TcpPcmSink {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        let mut message = Vec::with_capacity(5 + samples.len() * 2);
+        message.push(TCP_PCM);
+        message.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+        for sample in samples {
+            message.extend_from_slice(&to_i16(*sample).to_be_bytes());
+        }
+
+        self.broadcast(&message)
+    }
+
+    fn play(&mut self) -> anyhow::Result<()> {
+        self.playing.store(true, Ordering::Relaxed);
+        self.broadcast(&[TCP_PLAY])
+    }
+
+    fn pause(&mut self) -> anyhow::Result<()> {
+        self.playing.store(false, Ordering::Relaxed);
+        self.broadcast(&[TCP_PAUSE])
+    }
+
+    fn seek(&mut self, position: Duration) -> anyhow::Result<()> {
+        let mut message = vec![TCP_SEEK];
+        message.extend_from_slice(&(position.as_millis() as u64).to_be_bytes());
+        self.broadcast(&message)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for TcpPcmSink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        assert!(
+            AudioSinkOptions::parse("discord").unwrap()
+                == AudioSinkOptions::Discord { channel: None }
+        );
+        assert!(
+            AudioSinkOptions::parse("discord:123:456").unwrap()
+                == AudioSinkOptions::Discord {
+                    channel: Some((123, 456))
+                }
+        );
+        assert!(
+            AudioSinkOptions::parse("wav:/tmp/out.wav").unwrap()
+                == AudioSinkOptions::Wav {
+                    path: "/tmp/out.wav".to_string()
+                }
+        );
+        assert!(
+            AudioSinkOptions::parse("tcp:127.0.0.1:25570").unwrap()
+                == AudioSinkOptions::Tcp {
+                    bind: "127.0.0.1:25570".to_string()
+                }
+        );
+
+        assert!(AudioSinkOptions::parse("discord:123").is_err());
+        assert!(AudioSinkOptions::parse("wav:").is_err());
+        assert!(AudioSinkOptions::parse("tcp:localhost").is_err());
+        assert!(AudioSinkOptions::parse("speakers").is_err());
+    }
+
+    #[test]
+    fn test_wav_sink() {
+        let mut sink = WavSink::new(Cursor::new(Vec::new())).unwrap();
+        sink.write(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+        sink.write(&[2.0, 0.0]).unwrap();
+        sink.finish().unwrap();
+
+        let wav = sink.into_inner().into_inner();
+        assert!(wav.len() == 44 + 12);
+        assert!(&wav[0..4] == b"RIFF" && &wav[8..16] == b"WAVEfmt ");
+        assert!(u32::from_le_bytes(wav[4..8].try_into().unwrap()) == 36 + 12);
+        assert!(u16::from_le_bytes(wav[22..24].try_into().unwrap()) == PCM_CHANNELS);
+        assert!(u32::from_le_bytes(wav[24..28].try_into().unwrap()) == PCM_SAMPLE_RATE);
+        assert!(u32::from_le_bytes(wav[40..44].try_into().unwrap()) == 12);
+
+        let samples: Vec<i16> = wav[44..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert!(samples == vec![0, i16::MAX, -i16::MAX, i16::MAX / 2, i16::MAX, 0]);
+    }
+
+    #[test]
+    fn test_tcp_sink() {
+        let mut sink = TcpPcmSink::bind("127.0.0.1:0").unwrap();
+        sink.play().unwrap();
+
+        let mut client = TcpStream::connect(sink.local_addr()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut header = [0u8; 11];
+        client.read_exact(&mut header).unwrap();
+        assert!(&header[0..4] == TCP_MAGIC);
+        assert!(u32::from_be_bytes(header[4..8].try_into().unwrap()) == PCM_SAMPLE_RATE);
+        assert!(header[10] == TCP_PLAY);
+
+        //Client is registered after the header was sent
+        while sink.clients.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        sink.write(&[1.0, -1.0]).unwrap();
+        sink.seek(Duration::from_millis(1500)).unwrap();
+        sink.pause().unwrap();
+
+        let mut messages = [0u8; 5 + 4 + 9 + 1];
+        client.read_exact(&mut messages).unwrap();
+        assert!(messages[0] == TCP_PCM);
+        assert!(u32::from_be_bytes(messages[1..5].try_into().unwrap()) == 2);
+        assert!(i16::from_be_bytes([messages[5], messages[6]]) == i16::MAX);
+        assert!(i16::from_be_bytes([messages[7], messages[8]]) == -i16::MAX);
+        assert!(messages[9] == TCP_SEEK);
+        assert!(u64::from_be_bytes(messages[10..18].try_into().unwrap()) == 1500);
+        assert!(messages[18] == TCP_PAUSE);
+
+        sink.finish().unwrap();
+    }
+}
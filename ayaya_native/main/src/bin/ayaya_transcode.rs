@@ -0,0 +1,305 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+
+use ayaya_native::colorlib::map_index_to_color;
+use ayaya_native::frame_cache::{FrameCacheWriter, StageTimings, TranscodeOptions, Transcoder};
+
+static USAGE: &str = "Usage: ayaya_transcode <input> [options]
+
+Runs the native decode -> color conversion -> split pipeline without a Minecraft server
+
+Options:
+  --width <px>             Output width (default: video width)
+  --height <px>            Output height (default: video height)
+  --dither                 Use Floyd-Steinberg dithering during color conversion
+  --palette <map|index>    Colors of the PNG previews: map colors or raw color indexes as grayscale (default: map)
+  --png <dir>              Write PNG previews of the converted frames into dir
+  --every <n>              Write a PNG preview every n frames (default: 30)
+  --bake <file>            Write a baked video that can be played with the BAKED render type
+  --raw <file>             Write raw split frames (color indexes, one frame after another)
+  --frames <n>             Stop after n frames";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PreviewPalette {
+    Map,
+    Index,
+}
+
+#[derive(Debug)]
+struct Options {
+    input: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    dither: bool,
+    palette: PreviewPalette,
+    png_dir: Option<PathBuf>,
+    png_every: usize,
+    bake_file: Option<PathBuf>,
+    raw_file: Option<PathBuf>,
+    max_frames: Option<usize>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut options = Options {
+            input: String::new(),
+            width: None,
+            height: None,
+            dither: false,
+            palette: PreviewPalette::Map,
+            png_dir: None,
+            png_every: 30,
+            bake_file: None,
+            raw_file: None,
+            max_frames: None,
+        };
+
+        let mut input = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for {arg}"))
+            };
+
+            match arg.as_str() {
+                "--width" => options.width = Some(value()?.parse()?),
+                "--height" => options.height = Some(value()?.parse()?),
+                "--dither" => options.dither = true,
+                "--palette" => {
+                    options.palette = match value()?.as_str() {
+                        "map" => PreviewPalette::Map,
+                        "index" => PreviewPalette::Index,
+                        val => return Err(anyhow!("Invalid palette ({val})")),
+                    }
+                }
+                "--png" => options.png_dir = Some(value()?.into()),
+                "--every" => options.png_every = value()?.parse()?,
+                "--bake" => options.bake_file = Some(value()?.into()),
+                "--raw" => options.raw_file = Some(value()?.into()),
+                "--frames" => options.max_frames = Some(value()?.parse()?),
+                val if val.starts_with("--") => return Err(anyhow!("Unknown option ({val})")),
+                val => {
+                    if input.is_some() {
+                        return Err(anyhow!("Only one input file is supported"));
+                    }
+                    input = Some(val.to_string());
+                }
+            }
+        }
+
+        options.input = input.ok_or_else(|| anyhow!("Missing input file"))?;
+
+        if options.png_every == 0 {
+            return Err(anyhow!("--every has to be greater than 0"));
+        }
+
+        Ok(options)
+    }
+}
+
+fn print_timings(timings: &StageTimings, output: Duration, frames: usize, total: Duration) {
+    let frames_f = frames.max(1) as f64;
+    let stages = [
+        ("decode", timings.decode),
+        ("scale", timings.scale),
+        ("color conversion", timings.convert),
+        ("split", timings.split),
+        ("output", output),
+    ];
+
+    println!("Processed {frames} frames in {:.2}s", total.as_secs_f64());
+    for (name, duration) in stages {
+        println!(
+            "  {name:<17} {:>10.2}ms total {:>8.3}ms/frame",
+            duration.as_secs_f64() * 1000.0,
+            duration.as_secs_f64() * 1000.0 / frames_f
+        );
+    }
+    println!(
+        "  {:<17} {:>10.2} fps",
+        "throughput",
+        frames_f / total.as_secs_f64()
+    );
+}
+
+fn write_png(
+    path: PathBuf,
+    frame: &[i8],
+    width: usize,
+    height: usize,
+    palette: PreviewPalette,
+) -> anyhow::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = match palette {
+        PreviewPalette::Map => {
+            encoder.set_color(png::ColorType::Rgb);
+            frame
+                .iter()
+                .flat_map(|index| {
+                    let color = map_index_to_color(*index);
+                    [color.red, color.green, color.blue]
+                })
+                .collect()
+        }
+        PreviewPalette::Index => {
+            encoder.set_color(png::ColorType::Grayscale);
+            frame.iter().map(|index| *index as u8).collect()
+        }
+    };
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+fn run(options: Options) -> anyhow::Result<()> {
+    //Same pipeline as bake, so the output matches what the players send
+    let mut transcoder = Transcoder::new(
+        &options.input,
+        &TranscodeOptions {
+            width: options.width,
+            height: options.height,
+            dither: options.dither,
+        },
+    )?;
+    let header = transcoder.header().clone();
+    let (source_width, source_height) = transcoder.source_size();
+
+    if let Some(png_dir) = &options.png_dir {
+        fs::create_dir_all(png_dir)?;
+    }
+
+    let mut bake_writer = match &options.bake_file {
+        Some(path) => Some(FrameCacheWriter::new(
+            BufWriter::new(File::create(path)?),
+            &header,
+        )?),
+        None => None,
+    };
+    let mut raw_writer = match &options.raw_file {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+
+    println!(
+        "Transcoding {} ({source_width}x{source_height} -> {}x{}, {} fps, {}x{} maps)",
+        options.input,
+        header.width,
+        header.height,
+        header.fps,
+        header.all_frames_x,
+        header.all_frames_y
+    );
+
+    let start = Instant::now();
+    let mut output = Duration::ZERO;
+    let mut frames = 0;
+
+    while !matches!(options.max_frames, Some(max) if frames >= max) {
+        let Some(frame) = transcoder.next_frame()? else {
+            break;
+        };
+
+        let now = Instant::now();
+        if let Some(png_dir) = &options.png_dir {
+            if frames % options.png_every == 0 {
+                write_png(
+                    png_dir.join(format!("frame_{frames:06}.png")),
+                    &frame.converted,
+                    header.width as usize,
+                    header.height as usize,
+                    options.palette,
+                )?;
+            }
+        }
+        if let Some(bake_writer) = &mut bake_writer {
+            bake_writer.write_frame(&frame.splitted)?;
+        }
+        if let Some(raw_writer) = &mut raw_writer {
+            raw_writer.write_all(bytemuck::cast_slice(&frame.splitted))?;
+        }
+        output += now.elapsed();
+
+        frames += 1;
+    }
+
+    let now = Instant::now();
+    if let Some(bake_writer) = bake_writer {
+        bake_writer.finish()?;
+    }
+    if let Some(raw_writer) = &mut raw_writer {
+        raw_writer.flush()?;
+    }
+    output += now.elapsed();
+
+    print_timings(transcoder.timings(), output, frames, start.elapsed());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+
+    let options = match Options::parse(&args) {
+        Ok(val) => val,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            exit(2);
+        }
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("Transcoding failed: {err:?}");
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(&args(
+            "video.mp4 --width 256 --height 128 --dither --palette index --png out --every 5 --bake video.ayc --frames 100",
+        ))
+        .unwrap();
+
+        assert!(options.input == "video.mp4");
+        assert!(options.width == Some(256) && options.height == Some(128));
+        assert!(options.dither);
+        assert!(options.palette == PreviewPalette::Index);
+        assert!(options.png_dir == Some(PathBuf::from("out")) && options.png_every == 5);
+        assert!(options.bake_file == Some(PathBuf::from("video.ayc")));
+        assert!(options.raw_file.is_none());
+        assert!(options.max_frames == Some(100));
+    }
+
+    #[test]
+    fn test_parse_invalid_options() {
+        assert!(Options::parse(&args("--dither")).is_err());
+        assert!(Options::parse(&args("video.mp4 --width")).is_err());
+        assert!(Options::parse(&args("video.mp4 --palette rainbow")).is_err());
+        assert!(Options::parse(&args("video.mp4 --every 0")).is_err());
+        assert!(Options::parse(&args("video.mp4 other.mp4")).is_err());
+        assert!(Options::parse(&args("video.mp4 --fast")).is_err());
+    }
+}
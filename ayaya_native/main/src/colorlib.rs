@@ -40,6 +40,11 @@ impl Color {
     pub fn convert_to_mc(&self) -> u8 {
         get_cached_index(self) as u8
     }
+
+    //Closest map color, drawing it keeps the exact color after conversion
+    pub fn to_palette(&self) -> Color {
+        map_index_to_color(get_cached_index(self))
+    }
 }
 
 // static CONVERSION_TABLE_DIR: String = format!("{}/cached_color.hex", env::var("OUT_DIR").unwrap());
@@ -61,6 +66,68 @@ pub fn get_cached_index(color: &Color) -> i8 {
         as i8
 }
 
+//RGB of every map color, in map color index order
+pub static MAP_PALETTE: &[u8; 744] = include_bytes!(concat!(env!("OUT_DIR"), "/map_palette.bin"));
+
+pub fn map_index_to_color(index: i8) -> Color {
+    let offset = index as u8 as usize * 3;
+    if offset + 2 >= MAP_PALETTE.len() {
+        return Color::BLACK;
+    }
+
+    Color::new(
+        MAP_PALETTE[offset],
+        MAP_PALETTE[offset + 1],
+        MAP_PALETTE[offset + 2],
+    )
+}
+
+//Same as transform_frame_to_mc but with Floyd-Steinberg dithering. Much slower, but gradients look a lot better
+pub fn dither_frame_to_mc(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    add_width: usize,
+) -> Vec<i8> {
+    let mut buffer = Vec::<i8>::with_capacity(width * height);
+
+    //Errors are multiplied by 16, one pixel of padding on both sides
+    let mut current_errors = vec![[0i32; 3]; width + 2];
+    let mut next_errors = vec![[0i32; 3]; width + 2];
+
+    for y in 0..height {
+        for x in 0..width {
+            let offset = (y * add_width) + (x * 3);
+            let error = current_errors[x + 1];
+
+            let wanted: [i32; 3] =
+                [0, 1, 2].map(|c| (data[offset + c] as i32 + error[c] / 16).clamp(0, 255));
+            let index = get_cached_index(&Color::new(
+                wanted[0] as u8,
+                wanted[1] as u8,
+                wanted[2] as u8,
+            ));
+            let actual = map_index_to_color(index);
+            let actual = [actual.red as i32, actual.green as i32, actual.blue as i32];
+
+            for c in 0..3 {
+                let diff = wanted[c] - actual[c];
+                current_errors[x + 2][c] += diff * 7;
+                next_errors[x][c] += diff * 3;
+                next_errors[x + 1][c] += diff * 5;
+                next_errors[x + 2][c] += diff;
+            }
+
+            buffer.push(index);
+        }
+
+        std::mem::swap(&mut current_errors, &mut next_errors);
+        next_errors.fill([0; 3]);
+    }
+
+    buffer
+}
+
 #[cfg(feature = "ffmpeg")]
 pub fn transform_frame_to_mc(
     data: &[u8],
@@ -143,6 +210,31 @@ mod tests {
         assert!(do_vecs_match(&fast_conversion, &normal_conversion))
     }
 
+    #[test]
+    fn test_map_palette() {
+        //Every usable map color has to convert back to itself
+        for index in 4..248usize {
+            let color = map_index_to_color(index as u8 as i8);
+            assert!(map_index_to_color(color.convert_to_mc() as i8) == color);
+        }
+    }
+
+    #[test]
+    fn test_dither_solid_color() {
+        let width = 256usize;
+        let height = 128usize;
+
+        //Solid map colors do not produce any error to spread
+        for index in [4i8, 18, 34, 119] {
+            let color = map_index_to_color(index);
+            let values: Vec<u8> = [color.red, color.green, color.blue]
+                .repeat(width * height);
+
+            let dithered = dither_frame_to_mc(&values, width, height, width * 3);
+            assert!(dithered.iter().all(|val| map_index_to_color(*val) == color));
+        }
+    }
+
     #[bench]
     fn bench_color_conversion(b: &mut Bencher) {
         let width = 3840usize;
@@ -0,0 +1,139 @@
+use std::sync::{Arc, Mutex};
+
+use aes::cipher::{generic_array::GenericArray, BlockEncryptMut, KeyIvInit};
+use anyhow::anyhow;
+
+pub type Aes128Cfb8Enc = cfb8::Encryptor<aes::Aes128>;
+#[cfg(test)]
+pub type Aes128Cfb8Dec = cfb8::Decryptor<aes::Aes128>;
+
+/// Encryption state of a single online-mode player connection
+///
+/// Minecraft uses AES/CFB8 with the shared secret as both the key and the IV. CFB8 is a stream
+/// cipher, so the state depends on every byte encrypted before. A connection can't join a stream
+/// that is already running: it has to be created before the server encrypts its first byte, and
+/// from then on ALL bytes sent to that player have to go through it, otherwise the client will not
+/// be able to decrypt anything
+pub struct EncryptedConnection {
+    cipher: Aes128Cfb8Enc,
+}
+
+impl EncryptedConnection {
+    pub fn new(shared_secret: &[u8]) -> anyhow::Result<Self> {
+        if shared_secret.len() != 16 {
+            return Err(anyhow!(
+                "Shared secret has to be 16 bytes long (got {})",
+                shared_secret.len()
+            ));
+        }
+
+        let cipher = Aes128Cfb8Enc::new(
+            GenericArray::from_slice(shared_secret),
+            GenericArray::from_slice(shared_secret),
+        );
+
+        Ok(Self { cipher })
+    }
+
+    /// Encrypts data in place, continuing the stream from the last call
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_exact_mut(1) {
+            self.cipher
+                .encrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
+
+macro_rules! get_connection {
+    (
+        $PTR: ident
+    ) => {{
+        let arc_ptr = $PTR as *const () as *const Arc<Mutex<EncryptedConnection>>;
+        Arc::clone(unsafe { &*arc_ptr })
+    }};
+}
+
+pub fn wrap_to_ptr(connection: EncryptedConnection) -> i64 {
+    let arc = Arc::new(Mutex::new(connection));
+    Box::into_raw(Box::new(arc)) as *const () as i64
+}
+
+pub fn encrypt(ptr: i64, data: &mut [u8]) -> anyhow::Result<()> {
+    let connection = get_connection!(ptr);
+    let mut connection = match connection.lock() {
+        Ok(val) => val,
+        Err(_) => return Err(anyhow!("Cannot lock arc!")),
+    };
+
+    connection.encrypt(data);
+    Ok(())
+}
+
+pub fn destroy(ptr: i64) -> anyhow::Result<()> {
+    let connection = unsafe { Box::from_raw(ptr as *mut Arc<Mutex<EncryptedConnection>>) };
+    drop(connection);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::AsyncStreamCipher;
+
+    use super::*;
+
+    static SHARED_SECRET: &[u8; 16] = b"hello from aes!!";
+
+    fn decrypt(data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        Aes128Cfb8Dec::new(SHARED_SECRET.into(), SHARED_SECRET.into()).decrypt(&mut data);
+        data
+    }
+
+    #[test]
+    fn test_stream_encryption() {
+        let plaintext: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+
+        //Encrypting in parts has to give the same result as encrypting everything at once
+        let mut connection = EncryptedConnection::new(SHARED_SECRET).unwrap();
+        let mut ciphertext = Vec::<u8>::new();
+        for chunk in plaintext.chunks(777) {
+            let mut chunk = chunk.to_vec();
+            connection.encrypt(&mut chunk);
+            ciphertext.extend_from_slice(&chunk);
+        }
+
+        let mut expected = plaintext.clone();
+        Aes128Cfb8Enc::new(SHARED_SECRET.into(), SHARED_SECRET.into()).encrypt(&mut expected);
+
+        assert!(ciphertext == expected);
+        assert!(decrypt(&ciphertext) == plaintext);
+    }
+
+    #[test]
+    fn test_invalid_secret() {
+        assert!(EncryptedConnection::new(&[0u8; 15]).is_err());
+    }
+
+    #[test]
+    fn test_connections_are_independent() {
+        let first_frame = vec![7u8; 1024];
+        let second_frame = vec![9u8; 1024];
+
+        let old_connection = wrap_to_ptr(EncryptedConnection::new(SHARED_SECRET).unwrap());
+        let mut first_data = first_frame.clone();
+        encrypt(old_connection, &mut first_data).unwrap();
+
+        let new_connection = wrap_to_ptr(EncryptedConnection::new(SHARED_SECRET).unwrap());
+        let mut old_data = second_frame.clone();
+        encrypt(old_connection, &mut old_data).unwrap();
+        let mut new_data = second_frame.clone();
+        encrypt(new_connection, &mut new_data).unwrap();
+
+        first_data.extend_from_slice(&old_data);
+        assert!(decrypt(&first_data)[1024..] == second_frame[..]);
+        assert!(decrypt(&new_data) == second_frame);
+
+        destroy(old_connection).unwrap();
+        destroy(new_connection).unwrap();
+    }
+}
@@ -0,0 +1,588 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use anyhow::anyhow;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::splitting::SplittedFrame;
+
+static MAGIC: &[u8; 4] = b"AYAC";
+static FORMAT_VERSION: u32 = 1;
+//Palette the map indexes were quantized with. Change it when the color table in build.rs changes
+pub static PALETTE_ID: u32 = 1;
+
+/// Pre-rendered video (map color indexes already split into tiles)
+///
+/// Layout (all numbers are big endian):
+/// - magic, format version
+/// - width, height, fps, palette id, frames x, frames y
+/// - width and height of every tile
+/// - frame count, offset of the seek index
+/// - frames (length + zlib compressed tile data). Every frame is compressed on its own, so a seek
+///   only inflates the frame it lands on
+/// - seek index (offset of every frame)
+#[derive(Debug, Clone)]
+pub struct FrameCacheHeader {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub palette_id: u32,
+    pub all_frames_x: u32,
+    pub all_frames_y: u32,
+    pub tiles: Vec<SplittedFrame>,
+}
+
+impl FrameCacheHeader {
+    pub fn new(width: usize, height: usize, fps: i32) -> anyhow::Result<Self> {
+        let (tiles, all_frames_x, all_frames_y) = SplittedFrame::initialize_frames(width, height)?;
+
+        Ok(Self {
+            width: width as u32,
+            height: height as u32,
+            fps: fps as u32,
+            palette_id: PALETTE_ID,
+            all_frames_x: all_frames_x as u32,
+            all_frames_y: all_frames_y as u32,
+            tiles,
+        })
+    }
+}
+
+pub(crate) fn write_u32<W: Write>(writer: &mut W, value: u32) -> anyhow::Result<()> {
+    writer.write_all(&value.to_be_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn write_u64<W: Write>(writer: &mut W, value: u64) -> anyhow::Result<()> {
+    writer.write_all(&value.to_be_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> anyhow::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> anyhow::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+pub struct FrameCacheWriter<W: Write + Seek> {
+    writer: W,
+    //Position of the frame count, we do not know it until we finish
+    frame_count_position: u64,
+    offsets: Vec<u64>,
+}
+
+impl<W: Write + Seek> FrameCacheWriter<W> {
+    pub fn new(mut writer: W, header: &FrameCacheHeader) -> anyhow::Result<Self> {
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, FORMAT_VERSION)?;
+
+        write_u32(&mut writer, header.width)?;
+        write_u32(&mut writer, header.height)?;
+        write_u32(&mut writer, header.fps)?;
+        write_u32(&mut writer, header.palette_id)?;
+        write_u32(&mut writer, header.all_frames_x)?;
+        write_u32(&mut writer, header.all_frames_y)?;
+
+        for tile in &header.tiles {
+            write_u32(&mut writer, tile.width as u32)?;
+            write_u32(&mut writer, tile.height as u32)?;
+        }
+
+        let frame_count_position = writer.stream_position()?;
+        write_u32(&mut writer, 0)?;
+        write_u64(&mut writer, 0)?;
+
+        Ok(Self {
+            writer,
+            frame_count_position,
+            offsets: Vec::new(),
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &[i8]) -> anyhow::Result<()> {
+        let mut encoder = ZlibEncoder::new(
+            Vec::<u8>::with_capacity(frame.len() / 4),
+            Compression::default(),
+        );
+        encoder.write_all(bytemuck::cast_slice(frame))?;
+        let compressed = encoder.finish()?;
+
+        self.offsets.push(self.writer.stream_position()?);
+        write_u32(&mut self.writer, compressed.len() as u32)?;
+        self.writer.write_all(&compressed)?;
+        Ok(())
+    }
+
+    /// Writes the seek index and returns the inner writer
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        let index_offset = self.writer.stream_position()?;
+        for offset in &self.offsets {
+            write_u64(&mut self.writer, *offset)?;
+        }
+
+        self.writer
+            .seek(SeekFrom::Start(self.frame_count_position))?;
+        write_u32(&mut self.writer, self.offsets.len() as u32)?;
+        write_u64(&mut self.writer, index_offset)?;
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Checks only the magic, so other files are not read
+pub fn is_frame_cache(file_name: &str) -> bool {
+    let mut magic = [0u8; 4];
+    match File::open(file_name) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && &magic == MAGIC,
+        Err(_) => false,
+    }
+}
+
+/// Reads the header without the seek index
+pub fn read_header<R: Read>(reader: &mut R) -> anyhow::Result<FrameCacheHeader> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(anyhow!("This is not a baked video file"));
+    }
+
+    let version = read_u32(reader)?;
+    if version != FORMAT_VERSION {
+        return Err(anyhow!(
+            "Unsupported baked video version (got {version}, expected {FORMAT_VERSION})"
+        ));
+    }
+
+    let width = read_u32(reader)?;
+    let height = read_u32(reader)?;
+    let fps = read_u32(reader)?;
+    let palette_id = read_u32(reader)?;
+    let all_frames_x = read_u32(reader)?;
+    let all_frames_y = read_u32(reader)?;
+
+    if palette_id != PALETTE_ID {
+        return Err(anyhow!(
+            "Baked video uses a different palette (got {palette_id}, expected {PALETTE_ID}). Please bake it again"
+        ));
+    }
+
+    let mut tiles = Vec::<SplittedFrame>::with_capacity((all_frames_x * all_frames_y) as usize);
+    for _ in 0..(all_frames_x * all_frames_y) {
+        let tile_width = read_u32(reader)? as usize;
+        let tile_height = read_u32(reader)? as usize;
+        tiles.push(SplittedFrame {
+            width: tile_width,
+            height: tile_height,
+            frame_length: tile_width * tile_height,
+        });
+    }
+
+    Ok(FrameCacheHeader {
+        width,
+        height,
+        fps,
+        palette_id,
+        all_frames_x,
+        all_frames_y,
+        tiles,
+    })
+}
+
+pub struct FrameCacheReader<R: Read + Seek> {
+    reader: R,
+    header: FrameCacheHeader,
+    index: Vec<u64>,
+    next_frame: usize,
+}
+
+impl<R: Read + Seek> FrameCacheReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let header = read_header(&mut reader)?;
+
+        let frame_count = read_u32(&mut reader)?;
+        let index_offset = read_u64(&mut reader)?;
+        let first_frame_position = reader.stream_position()?;
+
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut index = Vec::<u64>::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            index.push(read_u64(&mut reader)?);
+        }
+        reader.seek(SeekFrom::Start(first_frame_position))?;
+
+        Ok(Self {
+            reader,
+            header,
+            index,
+            next_frame: 0,
+        })
+    }
+
+    pub fn header(&self) -> &FrameCacheHeader {
+        &self.header
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns None after the last frame
+    pub fn read_frame(&mut self) -> anyhow::Result<Option<Vec<i8>>> {
+        if self.next_frame >= self.index.len() {
+            return Ok(None);
+        }
+
+        let compressed_len = read_u32(&mut self.reader)? as usize;
+        let mut compressed = vec![0u8; compressed_len];
+        self.reader.read_exact(&mut compressed)?;
+
+        let mut frame = Vec::<u8>::new();
+        ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut frame)?;
+
+        self.next_frame += 1;
+        Ok(Some(bytemuck::cast_vec(frame)))
+    }
+
+    /// Seeking past the last frame moves to the end of the video
+    pub fn seek_frame(&mut self, frame: usize) -> anyhow::Result<()> {
+        let frame = frame.min(self.index.len());
+
+        if let Some(offset) = self.index.get(frame) {
+            self.reader.seek(SeekFrom::Start(*offset))?;
+        }
+
+        self.next_frame = frame;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ffmpeg")]
+#[derive(Debug, Clone, Default)]
+pub struct TranscodeOptions {
+    //Video size when None
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    //Floyd-Steinberg dithering during color conversion
+    pub dither: bool,
+}
+
+#[cfg(feature = "ffmpeg")]
+#[derive(Debug, Clone, Default)]
+pub struct StageTimings {
+    pub decode: std::time::Duration,
+    pub scale: std::time::Duration,
+    pub convert: std::time::Duration,
+    pub split: std::time::Duration,
+}
+
+#[cfg(feature = "ffmpeg")]
+pub struct TranscodedFrame {
+    //Map color indexes of the whole frame, row by row
+    pub converted: Vec<i8>,
+    //Same indexes split into tiles, what the players send and bake writes
+    pub splitted: Vec<i8>,
+}
+
+/// Decode -> scale -> color conversion -> split without a player or a server.
+/// Used by bake and the ayaya_transcode binary
+#[cfg(feature = "ffmpeg")]
+pub struct Transcoder {
+    input: ffmpeg::format::context::Input,
+    decoder: ffmpeg::decoder::Video,
+    scaler: ffmpeg::software::scaling::Context,
+    video_stream_index: usize,
+    header: FrameCacheHeader,
+    dither: bool,
+    eof_sent: bool,
+    timings: StageTimings,
+}
+
+#[cfg(feature = "ffmpeg")]
+impl Transcoder {
+    pub fn new(file_name: &str, options: &TranscodeOptions) -> anyhow::Result<Self> {
+        use ffmpeg::format::{input, Pixel};
+        use ffmpeg::media::Type;
+        use ffmpeg::software::scaling::{Context, Flags};
+
+        ffmpeg::init()?;
+
+        let input = input(file_name)?;
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or(ffmpeg::Error::StreamNotFound)?;
+        let video_stream_index = stream.index();
+        let fps = stream.rate().0 / stream.rate().1;
+
+        let mut decoder =
+            ffmpeg::codec::context::Context::from_parameters(stream.parameters())?.decoder();
+        crate::ffmpeg_set_multithreading(&mut decoder, file_name.to_string());
+        let decoder = decoder.video()?;
+
+        let width = options.width.unwrap_or(decoder.width());
+        let height = options.height.unwrap_or(decoder.height());
+
+        let scaler = Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGB24,
+            width,
+            height,
+            Flags::BILINEAR,
+        )?;
+
+        Ok(Self {
+            input,
+            decoder,
+            scaler,
+            video_stream_index,
+            header: FrameCacheHeader::new(width as usize, height as usize, fps)?,
+            dither: options.dither,
+            eof_sent: false,
+            timings: StageTimings::default(),
+        })
+    }
+
+    /// Size and tiles of the transcoded frames
+    pub fn header(&self) -> &FrameCacheHeader {
+        &self.header
+    }
+
+    pub fn source_size(&self) -> (u32, u32) {
+        (self.decoder.width(), self.decoder.height())
+    }
+
+    pub fn timings(&self) -> &StageTimings {
+        &self.timings
+    }
+
+    /// Returns None after the last frame
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<TranscodedFrame>> {
+        use std::time::Instant;
+
+        use crate::colorlib::{dither_frame_to_mc, transform_frame_to_mc};
+
+        let mut decoded = ffmpeg::frame::Video::empty();
+
+        loop {
+            let now = Instant::now();
+            let received = self.decoder.receive_frame(&mut decoded);
+            self.timings.decode += now.elapsed();
+
+            if received.is_ok() {
+                break;
+            }
+            if self.eof_sent {
+                return Ok(None);
+            }
+
+            //The decoder needs more packets
+            let now = Instant::now();
+            match self.input.packets().next() {
+                Some((stream, packet)) => {
+                    if stream.index() == self.video_stream_index {
+                        self.decoder.send_packet(&packet)?;
+                    }
+                }
+                None => {
+                    //Flush frames that are still inside of the decoder
+                    self.decoder.send_eof()?;
+                    self.eof_sent = true;
+                }
+            }
+            self.timings.decode += now.elapsed();
+        }
+
+        let width = self.header.width as usize;
+        let height = self.header.height as usize;
+
+        let now = Instant::now();
+        let mut rgb_frame = ffmpeg::frame::Video::empty();
+        self.scaler.run(&decoded, &mut rgb_frame)?;
+        self.timings.scale += now.elapsed();
+
+        let now = Instant::now();
+        let converted = if self.dither {
+            dither_frame_to_mc(rgb_frame.data(0), width, height, rgb_frame.stride(0))
+        } else {
+            transform_frame_to_mc(rgb_frame.data(0), width, height, rgb_frame.stride(0))
+        };
+        self.timings.convert += now.elapsed();
+
+        let now = Instant::now();
+        let splitted = SplittedFrame::split_frames(
+            converted.as_slice(),
+            &self.header.tiles,
+            width,
+            self.header.all_frames_x as usize,
+            self.header.all_frames_y as usize,
+        )?;
+        self.timings.split += now.elapsed();
+
+        Ok(Some(TranscodedFrame {
+            converted,
+            splitted,
+        }))
+    }
+}
+
+/// Runs a video through the normal decoding pipeline once and saves the result
+#[cfg(feature = "ffmpeg")]
+pub fn bake(file_name: String, output_file_name: String) -> anyhow::Result<()> {
+    use std::io::BufWriter;
+
+    let mut transcoder = Transcoder::new(&file_name, &TranscodeOptions::default())?;
+
+    let output = BufWriter::new(File::create(output_file_name)?);
+    let mut writer = FrameCacheWriter::new(output, transcoder.header())?;
+
+    while let Some(frame) = transcoder.next_frame()? {
+        writer.write_frame(&frame.splitted)?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn test_frame(header: &FrameCacheHeader, seed: usize) -> Vec<i8> {
+        let len = (header.all_frames_x * header.all_frames_y * 128 * 128) as usize;
+        (0..len).map(|i| ((i / 64 + seed) % 120) as i8).collect()
+    }
+
+    fn bake_test_video(frames: usize) -> (FrameCacheHeader, Vec<u8>) {
+        let header = FrameCacheHeader::new(384, 256, 30).unwrap();
+        let mut writer = FrameCacheWriter::new(Cursor::new(Vec::new()), &header).unwrap();
+
+        for i in 0..frames {
+            writer.write_frame(&test_frame(&header, i)).unwrap();
+        }
+
+        (header, writer.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let (header, data) = bake_test_video(10);
+        let mut reader = FrameCacheReader::new(Cursor::new(data)).unwrap();
+
+        let read_header = reader.header();
+        assert!(read_header.width == 384 && read_header.height == 256 && read_header.fps == 30);
+        assert!(read_header.all_frames_x == 3 && read_header.all_frames_y == 2);
+        assert!(read_header.tiles.len() == header.tiles.len());
+        for (read_tile, tile) in read_header.tiles.iter().zip(header.tiles.iter()) {
+            assert!(read_tile.width == tile.width && read_tile.height == tile.height);
+        }
+
+        assert!(reader.frame_count() == 10);
+        for i in 0..10 {
+            let frame = reader.read_frame().unwrap().unwrap();
+            assert!(frame == test_frame(&header, i));
+        }
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cache_seek() {
+        let (header, data) = bake_test_video(20);
+        let mut reader = FrameCacheReader::new(Cursor::new(data)).unwrap();
+
+        reader.seek_frame(15).unwrap();
+        assert!(reader.read_frame().unwrap().unwrap() == test_frame(&header, 15));
+        assert!(reader.read_frame().unwrap().unwrap() == test_frame(&header, 16));
+
+        reader.seek_frame(3).unwrap();
+        assert!(reader.read_frame().unwrap().unwrap() == test_frame(&header, 3));
+        assert!(reader.read_frame().unwrap().unwrap() == test_frame(&header, 4));
+
+        reader.seek_frame(500).unwrap();
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cache_is_compact() {
+        let (header, data) = bake_test_video(5);
+        let raw_len = test_frame(&header, 0).len() * 5;
+
+        assert!(data.len() < raw_len / 4);
+    }
+
+    #[test]
+    fn test_invalid_cache() {
+        assert!(FrameCacheReader::new(Cursor::new(b"not a cache file".to_vec())).is_err());
+
+        let (_, mut data) = bake_test_video(1);
+        //Palette id
+        data[20..24].copy_from_slice(&(PALETTE_ID + 1).to_be_bytes());
+        assert!(FrameCacheReader::new(Cursor::new(data)).is_err());
+    }
+
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        read: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.read += read;
+            Ok(read)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_seek_reads_one_frame() {
+        let (header, data) = bake_test_video(20);
+        let mut reader = FrameCacheReader::new(CountingReader {
+            inner: Cursor::new(data),
+            read: 0,
+        })
+        .unwrap();
+
+        reader.reader.read = 0;
+        reader.seek_frame(15).unwrap();
+        assert!(reader.read_frame().unwrap().unwrap() == test_frame(&header, 15));
+
+        let chunk_len = (reader.index[16] - reader.index[15]) as usize;
+        assert!(reader.reader.read == chunk_len);
+    }
+
+    #[test]
+    fn test_is_frame_cache() {
+        let (_, data) = bake_test_video(3);
+        let dir = std::env::temp_dir();
+        let cache_file = dir.join(format!("ayaya_cache_test_{}.ayac", std::process::id()));
+        let other_file = dir.join(format!("ayaya_cache_test_{}.mp4", std::process::id()));
+        std::fs::write(&cache_file, &data).unwrap();
+        std::fs::write(&other_file, b"\0\0\0\x20ftypisom").unwrap();
+
+        assert!(is_frame_cache(cache_file.to_str().unwrap()));
+        assert!(!is_frame_cache(other_file.to_str().unwrap()));
+        assert!(!is_frame_cache("does_not_exist.ayac"));
+
+        let header = read_header(&mut File::open(&cache_file).unwrap()).unwrap();
+        assert!(header.width == 384 && header.all_frames_x == 3);
+
+        std::fs::remove_file(cache_file).unwrap();
+        std::fs::remove_file(other_file).unwrap();
+    }
+}
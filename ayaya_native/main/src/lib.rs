@@ -4,16 +4,15 @@
 #![feature(portable_simd)]
 #![feature(test)]
 
-use aes::cipher::{AsyncStreamCipher, KeyIvInit};
-type Aes128Cfb8Enc = cfb8::Encryptor<aes::Aes128>;
-type Aes128Cfb8Dec = cfb8::Decryptor<aes::Aes128>;
-
 extern crate core;
 extern crate test;
 
 #[cfg(feature = "ffmpeg")]
 extern crate ffmpeg_next as ffmpeg;
 
+use std::fs::File;
+use std::io::BufReader;
+
 use anyhow::anyhow;
 
 #[cfg(feature = "ffmpeg")]
@@ -32,23 +31,40 @@ use {
 };
 
 use jni::objects::*;
-use jni::sys::{jbyteArray, jint, jlong, jobject, jsize};
+use jni::sys::{jboolean, jbyteArray, jint, jintArray, jlong, jlongArray, jobject, jobjectArray, jsize};
 use jni::JNIEnv;
 
+use audio_sink::AudioSinkOptions;
 use map_server::ServerOptions;
+use media_tracks::TrackSelection;
 
 use once_cell::sync::Lazy;
 use player::{player_context::VideoPlayer, blazing_linux_player::LinuxBlazingPlayer};
 use player::player_context::{self, NativeCommunication};
-use player::{game_player::GameInputDirection, game_player::GamePlayer};
+use player::{game_input, game_player::GamePlayer};
+use player::baked_video_player::BakedVideoPlayer;
+use player::osd::OsdCommand;
+#[cfg(feature = "discord")]
+use player::discord_audio;
 use tokio::runtime::{Builder, Runtime};
 
-mod colorlib;
+mod assets;
+pub mod audio_pack;
+pub mod audio_sink;
+pub mod colorlib;
+mod encryption;
+pub mod frame_cache;
 mod map_server;
+pub mod media_tracks;
+mod minecraft_packet;
 
 mod apps;
 mod player;
-mod splitting;
+pub mod splitting;
+pub mod subtitles;
+
+#[cfg(test)]
+mod test_jvm;
 
 static TOKIO_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     Builder::new_multi_thread()
@@ -62,7 +78,7 @@ static TOKIO_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
 });
 
 #[cfg(feature = "ffmpeg")]
-fn ffmpeg_set_multithreading(target_decoder: &mut Decoder, file_name: String) {
+pub fn ffmpeg_set_multithreading(target_decoder: &mut Decoder, file_name: String) {
     let copy_input = input(&file_name).unwrap();
     let copy_input = copy_input
         .streams()
@@ -122,7 +138,10 @@ fn verify_capabilities(
             if #[cfg(feature = "discord")]
             {
                 use discord_audio::DiscordClient;
-                if DiscordClient::is_used()? {
+
+                //Screens with a channel selected in init are checked when they connect
+                let client = DiscordClient::get()?;
+                if client.is_used(client.resolve_channel(None)?.guild_id)? {
                     let discord_in_use = env
                         .call_static_method(
                             "me/wcaleniewolny/ayaya/library/VideoRequestCapablyResponse",
@@ -139,9 +158,27 @@ fn verify_capabilities(
         }
     };
 
+    //Baked videos do not need ffmpeg, the dimensions are stored in the file header
+    let native_file_name: String = env.get_string(&file_name)?.into();
+    //Only the magic is read from other files, the header (no seek index) only from baked ones
+    if frame_cache::is_frame_cache(&native_file_name) {
+        let header = frame_cache::read_header(&mut BufReader::new(File::open(&native_file_name)?))?;
+
+        let response = if header.width > width as u32 || header.height > height as u32 {
+            "TO_SMALL"
+        } else if header.all_frames_x != (width / 128) as u32 || header.all_frames_y != (height / 128) as u32 {
+            "TO_LARGE"
+        } else {
+            "OK"
+        };
+
+        let response = env.call_static_method("me/wcaleniewolny/ayaya/library/VideoRequestCapablyResponse", "valueOf", "(Ljava/lang/String;)Lme/wcaleniewolny/ayaya/library/VideoRequestCapablyResponse;", &[(&env.new_string(response)?).into()])?.l()?;
+        return Ok(response.into_raw());
+    }
+
     cfg_if::cfg_if! {
         if #[cfg(feature = "ffmpeg")] {
-            let file_name: String = env.get_string(&file_name)?.into();
+            let file_name = native_file_name;
 
             ffmpeg::init()?;
             if let Ok(ictx) = input(&file_name) {
@@ -197,6 +234,98 @@ fn init(
     render_type: JObject,
     server_options: JObject,
     use_discord: bool,
+) -> anyhow::Result<jlong> {
+    let audio_sink = use_discord.then_some(AudioSinkOptions::Discord { channel: None });
+    create_player(env, file_name, render_type, server_options, audio_sink, TrackSelection::Default)
+}
+
+fn init_with_audio_sink(
+    env: &mut JNIEnv,
+    file_name: JString,
+    render_type: JObject,
+    server_options: JObject,
+    audio_sink: JString,
+    audio_track: JString,
+) -> anyhow::Result<jlong> {
+    let audio_sink: String = env.get_string(&audio_sink)?.into();
+    let audio_sink = AudioSinkOptions::parse(&audio_sink)?;
+    let audio_track: String = env.get_string(&audio_track)?.into();
+    let audio_track = TrackSelection::parse(&audio_track)?;
+    create_player(env, file_name, render_type, server_options, Some(audio_sink), audio_track)
+}
+
+#[cfg(feature = "ffmpeg")]
+fn create_audio_player(
+    file_name: String,
+    boxed_player: Box<dyn VideoPlayer>,
+    options: AudioSinkOptions,
+    audio_track: TrackSelection,
+) -> anyhow::Result<jlong> {
+    use audio_sink::{AudioSink, TcpPcmSink, WavSink};
+    use player::audio_player::AudioPlayer;
+
+    let audio_sink: Box<dyn AudioSink> = match options {
+        AudioSinkOptions::Discord { channel } => {
+            cfg_if::cfg_if! {
+                if #[cfg(feature = "discord")] {
+                    use discord_audio::{DiscordClient, DiscordSink};
+
+                    //Guild is reserved before joining, the session belongs to the handle so it is registered after wrapping
+                    let client = DiscordClient::get()?;
+                    let channel = client.resolve_channel(channel)?;
+                    //Slash commands show only the name, not the path on the server
+                    let title = std::path::Path::new(&file_name)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    client.reserve_guild(channel.guild_id)?;
+                    let player = DiscordSink::connect(channel).and_then(|audio_sink| {
+                        AudioPlayer::create_with_sink(file_name, boxed_player, Box::new(audio_sink), audio_track)
+                    });
+                    let player_context = match player {
+                        Ok(val) => val,
+                        Err(err) => {
+                            if let Err(release_err) = client.release_guild(channel.guild_id) {
+                                println!("[ProjectAyaya] Unable to release discord guild! Reason: {release_err}");
+                            }
+                            return Err(err);
+                        }
+                    };
+
+                    let ptr = player_context::wrap_to_ptr(Box::new(player_context));
+                    if let Err(err) = client.register_session(ptr, channel, title) {
+                        //Finishing the sink leaves the channel
+                        if let Err(destroy_err) = player_context::destroy(ptr) {
+                            println!("[ProjectAyaya] Unable to destroy player! Reason: {destroy_err}");
+                        }
+                        if let Err(release_err) = client.release_guild(channel.guild_id) {
+                            println!("[ProjectAyaya] Unable to release discord guild! Reason: {release_err}");
+                        }
+                        return Err(err);
+                    }
+                    return Ok(ptr);
+                } else {
+                    return Err(anyhow!("Discord flag disabled at compile time"))
+                }
+            }
+        }
+        AudioSinkOptions::Wav { path } => Box::new(WavSink::create(&path)?),
+        AudioSinkOptions::Tcp { bind } => Box::new(TcpPcmSink::bind(&bind)?),
+    };
+
+    let player_context = AudioPlayer::create_with_sink(file_name, boxed_player, audio_sink, audio_track)?;
+    Ok(player_context::wrap_to_ptr(Box::new(player_context)))
+}
+
+#[allow(unused_variables)]
+fn create_player(
+    env: &mut JNIEnv,
+    file_name: JString,
+    render_type: JObject,
+    server_options: JObject,
+    audio_sink: Option<AudioSinkOptions>,
+    audio_track: TrackSelection,
 ) -> anyhow::Result<jlong> {
     let file_name: String = env.get_string(&file_name)?.into();
 
@@ -212,10 +341,23 @@ fn init(
     let port = env.call_method(&server_options, "getPort", "()I", &[])?;
     let port = port.i()?;
 
+    let asset_dir = env.call_method(
+        &server_options,
+        "getAssetDirectory",
+        "()Ljava/lang/String;",
+        &[],
+    )?;
+    let asset_dir = asset_dir.l()?;
+    let asset_dir: Option<String> = match asset_dir.is_null() {
+        true => None,
+        false => Some(env.get_string(&JString::from(asset_dir))?.into()),
+    };
+
     let server_options = ServerOptions {
         use_server,
         bind_ip,
         port,
+        asset_dir,
     };
 
     let render_type = env.call_method(render_type, "ordinal", "()I", &[])?;
@@ -241,14 +383,14 @@ fn init(
                 }
             }
         }
-        2 => Box::new(GamePlayer::create(file_name, server_options)?),
+        2 => Box::new(GamePlayer::create(file_name.clone(), server_options)?),
         3 => {
             cfg_if::cfg_if! {
                 if #[cfg(feature = "ffmpeg")] {
-                    if use_discord {
-                        return Err(anyhow!("X11 player does not suport discord audio!"));
+                    if audio_sink.is_some() {
+                        return Err(anyhow!("X11 player does not suport audio sinks!"));
                     }
-                    Box::new(X11Player::create(file_name, server_options)?)
+                    Box::new(X11Player::create(file_name.clone(), server_options)?)
                 }else {
                     return Err(anyhow!("FFmpeg feature not compiled!"))
                 }
@@ -259,7 +401,7 @@ fn init(
                 if #[cfg(all(feature = "external_player", feature = "ffmpeg"))] {
                     use player::external_player::ExternalPlayer;
 
-                    Box::new(ExternalPlayer::create(file_name, server_options)?)
+                    Box::new(ExternalPlayer::create(file_name.clone(), server_options)?)
                 }else {
                     return Err(anyhow!("external_player feature not compiled!"))
                 }
@@ -270,28 +412,27 @@ fn init(
                 if #[cfg(all(target_os = "linux", feature = "ffmpeg"))] {
                     use player::blazing_linux_player::LinuxBlazingPlayer;
 
-                    Box::new(LinuxBlazingPlayer::create(file_name, server_options)?)
+                    Box::new(LinuxBlazingPlayer::create(file_name.clone(), server_options)?)
                 }else {
                     return Err(anyhow!("You are not on linux OR you do not have ffmpeg feature enabled"))
                 }
             }
         }
+        6 => Box::new(BakedVideoPlayer::create(file_name.clone(), server_options)?),
         _ => return Err(anyhow::Error::msg(format!("Invalid id ({render_type})"))),
     };
 
-    let ptr = if use_discord {
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "discord")]
-            {
-                use crate::discord_audio::DiscordPlayer;
-                let player_context = DiscordPlayer::create_with_discord(file_name, Box::new(boxed_player), use_server)?;
-                player_context::wrap_to_ptr(Box::new(player_context))
-            } else {
-                return Err(anyhow!("Discord flag disabled at compile time"))
+    let ptr = match audio_sink {
+        Some(audio_sink) => {
+            cfg_if::cfg_if! {
+                if #[cfg(feature = "ffmpeg")] {
+                    create_audio_player(file_name, boxed_player, audio_sink, audio_track)?
+                } else {
+                    return Err(anyhow!("FFmpeg feature not compiled!"))
+                }
             }
         }
-    } else {
-        player_context::wrap_to_ptr(boxed_player)
+        None => player_context::wrap_to_ptr(boxed_player),
     };
 
     return Ok(ptr);
@@ -299,24 +440,6 @@ fn init(
 
 //According to kotlin "@return Byte array of transformed frame (color index)"
 fn load_frame(env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<jbyteArray> {
-    //proof of concept
-    if ptr == 1000 {
-        let key = *b"hello from aes!!";
-        let iv = *b"shitty iv do not";
-        let plaintext = b"hello world! this is my plaintex".to_vec();
-        let mut padded = vec![0u8; 16];
-        padded.extend_from_slice(&plaintext);
-        let mut plaintext = padded;
-        Aes128Cfb8Enc::new(&key.into(), &iv.into()).encrypt(&mut plaintext);
-        let output = env.new_byte_array(plaintext.len() as jsize)?; //Can't fail to create array unless system is out of memory
-        let slice = unsafe {
-            std::slice::from_raw_parts(plaintext.as_mut_ptr() as *mut i8, plaintext.len())
-        };
-
-        env.set_byte_array_region(&output, 0, slice)?;
-        return Ok(output.into_raw())
-    }
-
     let data = player_context::load_frame(ptr)?;
     let data_vec = data.data();
 
@@ -326,6 +449,101 @@ fn load_frame(env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<jbyteArray> {
     Ok(output.into_raw())
 }
 
+//[drift in ms, dropped frames, repeated frames]
+fn sync_stats(env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<jlongArray> {
+    let stats = player_context::sync_stats(ptr)?;
+    let stats = [
+        stats.drift_ms,
+        stats.dropped_frames as jlong,
+        stats.repeated_frames as jlong,
+    ];
+
+    let output = env.new_long_array(stats.len() as jsize)?;
+    env.set_long_array_region(&output, 0, &stats)?;
+    Ok(output.into_raw())
+}
+
+//Slash commands can pause the player too, so the plugin asks instead of keeping its own flag
+fn is_playing(_env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<jboolean> {
+    match player_context::clock(ptr)? {
+        Some(clock) => Ok(clock.is_playing() as jboolean),
+        None => Err(anyhow!("This player is not synchronized to a clock")),
+    }
+}
+
+fn create_encrypted_connection(env: &mut JNIEnv, shared_secret: JByteArray) -> anyhow::Result<jlong> {
+    let shared_secret = env.convert_byte_array(&shared_secret)?;
+    let connection = encryption::EncryptedConnection::new(&shared_secret)?;
+
+    Ok(encryption::wrap_to_ptr(connection))
+}
+
+fn encrypt_for_connection(
+    env: &mut JNIEnv,
+    connection_ptr: jlong,
+    data: JByteArray,
+) -> anyhow::Result<jbyteArray> {
+    let mut data = env.convert_byte_array(&data)?;
+    encryption::encrypt(connection_ptr, &mut data)?;
+
+    let output = env.byte_array_from_slice(&data)?;
+    Ok(output.into_raw())
+}
+
+#[allow(unused_variables)]
+fn bake_video(env: &mut JNIEnv, file_name: JString, output_file_name: JString) -> anyhow::Result<()> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "ffmpeg")] {
+            let file_name: String = env.get_string(&file_name)?.into();
+            let output_file_name: String = env.get_string(&output_file_name)?.into();
+
+            frame_cache::bake(file_name, output_file_name)
+        } else {
+            Err(anyhow!("FFmpeg feature not compiled!"))
+        }
+    }
+}
+
+//Returns the first video frame of every sound segment
+#[allow(unused_variables)]
+fn extract_audio(
+    env: &mut JNIEnv,
+    file_name: JString,
+    output_file_name: JString,
+    pack_name: JString,
+    segment_seconds: jint,
+) -> anyhow::Result<jintArray> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "ffmpeg")] {
+            let file_name: String = env.get_string(&file_name)?.into();
+            let output_file_name: String = env.get_string(&output_file_name)?.into();
+            let pack_name: String = env.get_string(&pack_name)?.into();
+            if segment_seconds <= 0 {
+                return Err(anyhow!("Segment length has to be greater than 0"));
+            }
+
+            let segments = audio_pack::extract_audio(file_name, output_file_name, pack_name, segment_seconds as u32)?;
+            let start_frames: Vec<jint> = segments.iter().map(|segment| segment.start_frame as jint).collect();
+
+            let output = env.new_int_array(start_frames.len() as jsize)?;
+            env.set_int_array_region(&output, 0, start_frames.as_slice())?;
+            Ok(output.into_raw())
+        } else {
+            Err(anyhow!("FFmpeg feature not compiled!"))
+        }
+    }
+}
+
+fn destroy_encrypted_connection(_env: &mut JNIEnv, connection_ptr: jlong) -> anyhow::Result<()> {
+    encryption::destroy(connection_ptr)
+}
+
+//Blazing player builds the packets itself, so it fails on protocol versions it doesn't know
+fn verify_blazing_protocol(_env: &mut JNIEnv, protocol_version: jint) -> anyhow::Result<()> {
+    minecraft_packet::PacketIds::for_protocol(protocol_version)?;
+    Ok(())
+}
+
 fn recive_jvm_msg(
     env: &mut JNIEnv,
     ptr: jlong,
@@ -343,30 +561,19 @@ fn recive_jvm_msg(
             NativeCommunication::StartRendering { fps }
         }
         1 => NativeCommunication::StopRendering,
-        2 => {
-            let info_str_vec = info_string.split('_');
-            let mut game_input_vec = Vec::<GameInputDirection>::with_capacity(4);
-            for val in info_str_vec {
-                let input = match val {
-                    "F" => GameInputDirection::Forward,
-                    "B" => GameInputDirection::Backwards,
-                    "L" => GameInputDirection::Left,
-                    "R" => GameInputDirection::Right,
-                    "U" => GameInputDirection::Up,
-                    "" => continue,
-                    _ => return Err(anyhow!("Invalid short game input")),
-                };
-                game_input_vec.push(input);
-            }
-
-            NativeCommunication::GameInput {
-                input: game_input_vec,
-            }
-        }
+        2 => NativeCommunication::GameInput {
+            input: game_input::parse_short_input(&info_string)?,
+        },
         3 => {
             let second = info_string.parse::<i32>()?;
             NativeCommunication::VideoSeek { second }
         }
+        4 => NativeCommunication::AudioTrack {
+            track: TrackSelection::parse(&info_string)?,
+        },
+        5 => NativeCommunication::Osd {
+            command: OsdCommand::parse(&info_string)?,
+        },
         _ => return Err(anyhow!("Invalid msg enum")),
     };
 
@@ -374,12 +581,22 @@ fn recive_jvm_msg(
     Ok(())
 }
 
+fn send_game_input(env: &mut JNIEnv, ptr: jlong, events: JByteArray) -> anyhow::Result<()> {
+    let events = env.convert_byte_array(events)?;
+    let input = game_input::decode_events(&events)?;
+
+    player_context::pass_jvm_msg(ptr, NativeCommunication::GameInput { input })?;
+    Ok(())
+}
+
 #[allow(unused)]
 fn init_discord_bot(env: &mut JNIEnv, discord_options: JObject) -> anyhow::Result<()> {
     cfg_if::cfg_if! {
         if #[cfg(feature = "discord")]
         {
-            use std::num::NonZeroU64;
+            use discord_audio::DiscordOptions;
+            use player::voice_sessions::VoiceChannel;
+
             let discord_options = {
                 let use_discord = env
                     .call_method(&discord_options, "getUseDiscord", "()Z", &[])?
@@ -413,21 +630,10 @@ fn init_discord_bot(env: &mut JNIEnv, discord_options: JObject) -> anyhow::Resul
                 let guild_id: u64 = guild_id.parse()?;
                 let channel_id: u64 = channel_id.parse()?;
 
-                let guild_id = match NonZeroU64::new(guild_id) {
-                    Some(val) => val,
-                    None => return Err(anyhow!("Guild ID is zero")),
-                };
-
-                let channel_id = match NonZeroU64::new(channel_id) {
-                    Some(val) => val,
-                    None => return Err(anyhow!("Channel ID is zero")),
-                };
-
                 DiscordOptions {
                     use_discord,
                     discord_token,
-                    guild_id,
-                    channel_id,
+                    default_channel: VoiceChannel::new(guild_id, channel_id)?,
                 }
             };
 
@@ -444,10 +650,158 @@ fn init_discord_bot(env: &mut JNIEnv, discord_options: JObject) -> anyhow::Resul
 
 //Destroy function must be called to drop video_player struct
 fn destroy(_env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<()> {
+    //Frees the guild for other screens. Session goes first, slash commands must not find a freed player
+    #[cfg(feature = "discord")]
+    if let Ok(client) = discord_audio::DiscordClient::get() {
+        if let Err(err) = client.unregister_session(ptr) {
+            println!("[ProjectAyaya] Unable to unregister discord session! Reason: {err}");
+        }
+    }
     player_context::destroy(ptr)?;
     Ok(())
 }
 
+//Audio and subtitle streams of a file, with their language tags
+#[allow(unused_variables)]
+fn get_media_tracks(env: &mut JNIEnv, file_name: JString) -> anyhow::Result<jobjectArray> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "ffmpeg")] {
+            use media_tracks::TrackKind;
+
+            let file_name: String = env.get_string(&file_name)?.into();
+            let tracks = media_tracks::probe(&file_name)?;
+
+            let output = env.new_object_array(tracks.len() as jsize, "me/wcaleniewolny/ayaya/library/MediaTrack", JObject::null())?;
+            for (i, track) in tracks.iter().enumerate() {
+                let track_type = match track.kind {
+                    TrackKind::Audio => "AUDIO",
+                    TrackKind::Subtitle => "SUBTITLE",
+                };
+                let track_type = env
+                    .call_static_method(
+                        "me/wcaleniewolny/ayaya/library/MediaTrackType",
+                        "valueOf",
+                        "(Ljava/lang/String;)Lme/wcaleniewolny/ayaya/library/MediaTrackType;",
+                        &[(&env.new_string(track_type)?).into()],
+                    )?
+                    .l()?;
+
+                let language = match &track.language {
+                    Some(language) => JObject::from(env.new_string(language)?),
+                    None => JObject::null(),
+                };
+                let title = match &track.title {
+                    Some(title) => JObject::from(env.new_string(title)?),
+                    None => JObject::null(),
+                };
+                let codec = env.new_string(&track.codec)?;
+
+                let jobject = env.new_object(
+                    "me/wcaleniewolny/ayaya/library/MediaTrack",
+                    "(ILme/wcaleniewolny/ayaya/library/MediaTrackType;Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Z)V",
+                    &[
+                        JValue::Int(track.index as jint),
+                        JValue::Object(&track_type),
+                        JValue::Object(&language),
+                        JValue::Object(&title),
+                        JValue::Object(&codec),
+                        JValue::Bool(track.default as u8),
+                    ],
+                )?;
+                env.set_object_array_element(&output, i as jsize, jobject)?;
+            }
+
+            Ok(output.into_raw())
+        } else {
+            Err(anyhow!("FFmpeg feature not compiled!"))
+        }
+    }
+}
+
+fn get_games(env: &mut JNIEnv) -> anyhow::Result<jobjectArray> {
+    let games = apps::games();
+    let output = env.new_object_array(games.len() as jsize, "me/wcaleniewolny/ayaya/library/GameInfo", JObject::null())?;
+    for (i, game) in games.iter().enumerate() {
+        let id = env.new_string(game.id)?;
+        let name = env.new_string(game.name)?;
+        let description = env.new_string(game.description)?;
+
+        let jobject = env.new_object(
+            "me/wcaleniewolny/ayaya/library/GameInfo",
+            "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;III)V",
+            &[
+                JValue::Object(&id),
+                JValue::Object(&name),
+                JValue::Object(&description),
+                JValue::Int(game.width as jint),
+                JValue::Int(game.height as jint),
+                JValue::Int(game.max_players as jint),
+            ],
+        )?;
+        env.set_object_array_element(&output, i as jsize, jobject)?;
+    }
+
+    Ok(output.into_raw())
+}
+
+//Null options turn the subtitles off
+fn set_subtitles(env: &mut JNIEnv, ptr: jlong, options: JObject) -> anyhow::Result<()> {
+    use colorlib::Color;
+    use subtitles::{SubtitleOptions, SubtitlePosition, SubtitleStyle};
+
+    let options = match options.is_null() {
+        true => None,
+        false => {
+            let track = env
+                .call_method(&options, "getTrack", "()Ljava/lang/String;", &[])?
+                .l()?;
+            let track: String = env.get_string(&track.into())?.into();
+
+            let position = env
+                .call_method(
+                    &options,
+                    "getPosition",
+                    "()Lme/wcaleniewolny/ayaya/library/SubtitlePosition;",
+                    &[],
+                )?
+                .l()?;
+            let position = match env.call_method(position, "ordinal", "()I", &[])?.i()? {
+                0 => SubtitlePosition::Bottom,
+                1 => SubtitlePosition::Top,
+                _ => return Err(anyhow!("Invalid subtitle position enum")),
+            };
+
+            let mut get_int = |getter: &str| -> anyhow::Result<jint> {
+                Ok(env.call_method(&options, getter, "()I", &[])?.i()?)
+            };
+            let font_size = get_int("getFontSize")?;
+            let margin = get_int("getMargin")?;
+            let color = get_int("getColor")?;
+            let outline_color = get_int("getOutlineColor")?;
+            let outline_width = get_int("getOutlineWidth")?;
+
+            if !(1..=256).contains(&font_size) || !(0..=16).contains(&outline_width) || margin < 0 {
+                return Err(anyhow!("Invalid subtitle size ({font_size}, {outline_width}, {margin})"));
+            }
+            let rgb = |color: jint| Color::new((color >> 16) as u8, (color >> 8) as u8, color as u8);
+
+            Some(SubtitleOptions {
+                track,
+                style: SubtitleStyle {
+                    font_size: font_size as u32,
+                    position,
+                    margin: margin as u32,
+                    color: rgb(color),
+                    outline_color: rgb(outline_color),
+                    outline_width: outline_width as u32,
+                },
+            })
+        }
+    };
+
+    player_context::pass_jvm_msg(ptr, NativeCommunication::Subtitles { options })
+}
+
 fn get_video_data(env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<jobject> {
     // let jclass = env.find_class("me/wcaleniewolny/ayaya/library/VideoData")?;
     // let jconstructor = env.get_method_id(jclass, "<init>", "(III)V")?;
@@ -472,6 +826,27 @@ fn get_video_data(env: &mut JNIEnv, ptr: jlong) -> anyhow::Result<jobject> {
 //This could be optimized to have ony one bracket but I do not understand macros well
 macro_rules! jvm_impl {
 
+    (
+        $BOILERPLATE_NAME: ident,
+        $IMPLEMENTATION_NAME: ident,
+        $RETURN_TYPE: tt,
+        {}
+    ) => {
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub extern "system" fn $BOILERPLATE_NAME(mut environment: JNIEnv, _class: JClass) -> $RETURN_TYPE {
+            match $IMPLEMENTATION_NAME(&mut environment) {
+                Ok(some) => some,
+                Err(error) => {
+                    environment.throw_new("java/lang/RuntimeException", format!("{:?}", error))
+                        .expect("Couldn't throw java error");
+
+                    return 0 as $RETURN_TYPE
+                }
+            }
+        }
+    };
+
     (
         $BOILERPLATE_NAME: ident,
         $IMPLEMENTATION_NAME: ident,
@@ -535,20 +910,72 @@ jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_init, init,
     server_options: JObject,
     use_discord: bool
 });
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_initWithAudioSink, init_with_audio_sink, jlong, {
+    filename: JString,
+    render_type: JObject,
+    server_options: JObject,
+    audio_sink: JString,
+    audio_track: JString
+});
 jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_loadFrame, load_frame, jbyteArray, {
     ptr: jlong,
 });
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_getSyncStats, sync_stats, jlongArray, {
+    ptr: jlong,
+});
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_isPlaying, is_playing, jboolean, {
+    ptr: jlong,
+});
 jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_communicate, recive_jvm_msg, {
     ptr: jlong,
     native_lib_communication: JObject,
     info: JString
 });
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_sendGameInput, send_game_input, {
+    ptr: jlong,
+    events: JByteArray
+});
 jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_verifyScreenCapabilities, verify_capabilities, jobject, {
     file_name: JString,
     width: jint,
     height: jint,
     use_discord: bool
 });
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_createEncryptedConnection, create_encrypted_connection, jlong, {
+    shared_secret: JByteArray
+});
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_encryptForConnection, encrypt_for_connection, jbyteArray, {
+    connection_ptr: jlong,
+    data: JByteArray
+});
+jvm_impl!(
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_destroyEncryptedConnection,
+    destroy_encrypted_connection,
+    { connection_ptr: jlong }
+);
+jvm_impl!(
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_verifyBlazingProtocol,
+    verify_blazing_protocol,
+    { protocol_version: jint }
+);
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_bakeVideo, bake_video, {
+    file_name: JString,
+    output_file_name: JString
+});
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_extractAudio, extract_audio, jintArray, {
+    file_name: JString,
+    output_file_name: JString,
+    pack_name: JString,
+    segment_seconds: jint
+});
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_getMediaTracks, get_media_tracks, jobjectArray, {
+    file_name: JString
+});
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_getGames, get_games, jobjectArray, {});
+jvm_impl!(Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_setSubtitles, set_subtitles, {
+    ptr: jlong,
+    options: JObject
+});
 jvm_impl!(
     Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_initDiscordBot,
     init_discord_bot,
@@ -574,6 +1001,7 @@ mod tests {
                 use_server: false,
                 port: 0,
                 bind_ip: "".to_string(),
+                asset_dir: None,
             },
         )
         .unwrap();
@@ -25,13 +25,19 @@ use tokio::{
 };
 
 #[cfg(feature = "ffmpeg")]
-use crate::player::player_context::{FrameWithIdentifier, NativeCommunication};
+use crate::player::{
+    osd::PausedFrame,
+    player_context::{FrameWithIdentifier, NativeCommunication},
+    sync_clock::{FrameAction, FrameSync, MasterClock},
+};
 
 #[derive(Debug, Clone)]
 pub struct ServerOptions {
     pub use_server: bool,
     pub bind_ip: String,
     pub port: i32,
+    //Directory of runtime assets, None when the plugin did not pass one
+    pub asset_dir: Option<String>,
 }
 
 #[cfg(feature = "ffmpeg")]
@@ -40,6 +46,8 @@ pub struct MapServer {
     options: ServerOptions,
     frame_index: Arc<AtomicI64>,
     command_sender: Sender<NativeCommunication>,
+    //Wall clock until an audio source is attached
+    clock: Arc<MasterClock>,
 }
 
 #[cfg(feature = "ffmpeg")]
@@ -51,6 +59,8 @@ impl MapServer {
         options: &ServerOptions,
         frame_index: Arc<AtomicI64>,
         map_reciver: Receiver<FrameWithIdentifier>,
+        //None for players without an OSD
+        paused_frame: Option<PausedFrame>,
     ) -> anyhow::Result<MapServerData> {
         let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(8);
 
@@ -58,9 +68,10 @@ impl MapServer {
             options: options.clone(),
             frame_index,
             command_sender: cmd_tx,
+            clock: MasterClock::wall_clock(),
         });
 
-        server.init(map_reciver, cmd_rx).await?;
+        server.init(map_reciver, cmd_rx, paused_frame).await?;
 
         Ok(Some(server))
     }
@@ -69,6 +80,7 @@ impl MapServer {
         &self,
         mut map_reciver: Receiver<FrameWithIdentifier>,
         mut cmd_reciver: Receiver<NativeCommunication>,
+        mut paused_frame: Option<PausedFrame>,
     ) -> anyhow::Result<()> {
         let bind = format!(
             "{}:{}",
@@ -78,6 +90,7 @@ impl MapServer {
         println!("Binding map server on: {bind}");
         let listener = TcpListener::bind(bind).await?;
         let frame_index = self.frame_index.clone();
+        let clock = self.clock.clone();
 
         //Note
         //1. encode packet data (https://crates.io/crates/libflate or https://github.com/rust-lang/flate2-rs#Backends) USE ZLIB
@@ -91,74 +104,132 @@ impl MapServer {
         let (tcp_frame_tx, tcp_frame_rx) = broadcast::channel::<Arc<Vec<u8>>>(512);
 
         tokio::spawn(async move {
-            let msg = cmd_reciver
+            //Stop before the first start has nothing to stop
+            let fps = loop {
+                match cmd_reciver.recv().await {
+                    Some(NativeCommunication::StartRendering { fps }) => break fps,
+                    Some(msg) => {
+                        println!("[ProjectAyaya] Map server is not rendering yet, ignoring {msg:?}")
+                    }
+                    None => return,
+                }
+            };
+
+            let mut data_size = 0;
+            let mut frame = map_reciver
                 .recv()
                 .await
-                .expect("Couldn't recive NativeCommunication send_message");
-            println!("GOT: {msg:?}");
+                .expect("Couldn't recive first frame")
+                .data;
 
-            match msg {
-                NativeCommunication::StartRendering { fps } => {
-                    let mut data_size = 0;
-                    let data = map_reciver
-                        .recv()
-                        .await
-                        .expect("Couldn't recive first frame");
-
-                    let mut data = Self::prepare_frame(data.data, &mut data_size)
-                        .await
-                        .expect("Couldn't preprare tcp frame");
+            let mut data = Self::prepare_frame(&frame, &mut data_size)
+                .await
+                .expect("Couldn't preprare tcp frame");
+            //Raw frame that clients show, the pause glyph is drawn over it
+            let mut shown_frame = frame.clone();
 
-                    //1000000000 nanoseconds = 1 second. Rust feature for that is unsable
-                    let dur = Duration::from_millis(1000_u64 / (fps as u64));
-                    let mut interval = time::interval(dur);
-                    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+            //1000000000 nanoseconds = 1 second. Rust feature for that is unsable
+            let dur = Duration::from_millis(1000_u64 / (fps as u64));
+            let mut interval = time::interval(dur);
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
-                    loop {
-                        interval.tick().await;
-                        tcp_frame_tx
-                            .send(Arc::new(data))
-                            .expect("Couldn't send tcp frame");
+            //The interval only wakes us up, the clock decides which frame is sent
+            let mut frame_sync = FrameSync::new(clock.clone(), fps);
+            clock.play();
 
-                        let temp_data = map_reciver
-                            .recv()
-                            .await
-                            .expect("Couldn't recive frame from main map server loop");
-                        frame_index.fetch_add(1, Ordering::Relaxed);
+            loop {
+                interval.tick().await;
+
+                //On repeat nothing is sent, clients keep the last frame
+                if let FrameAction::Show { skip } = frame_sync.plan() {
+                    if skip > 0 {
+                        let mut temp_data = None;
+                        for _ in 0..skip {
+                            temp_data = map_reciver.recv().await;
+                            frame_index.fetch_add(1, Ordering::Relaxed);
+                        }
+                        frame = temp_data
+                            .expect("Couldn't recive frame from main map server loop")
+                            .data;
 
-                        data = Self::prepare_frame(temp_data.data, &mut data_size)
+                        data = Self::prepare_frame(&frame, &mut data_size)
                             .await
                             .expect("Couldn't prepare tcp frame");
+                    }
+
+                    tcp_frame_tx
+                        .send(Arc::new(data))
+                        .expect("Couldn't send tcp frame");
+                    if let Some(paused_frame) = &mut paused_frame {
+                        paused_frame.reset();
+                    }
 
-                        if let Ok(msg) = cmd_reciver.try_recv() {
-                            if let NativeCommunication::StopRendering = msg {
-                                match cmd_reciver.recv().await {
-                                    Some(msg) => match msg {
-                                        NativeCommunication::StartRendering { fps } => {
-                                            let dur =
-                                                Duration::from_millis(1000_u64 / (fps as u64));
-                                            interval = time::interval(dur);
-                                            continue;
-                                        }
-                                        _ => {
-                                            panic!("Invalid message! (Expected StartRendering, got: {msg:?})");
-                                        }
-                                    },
-                                    None => {
-                                        println!("Couldn't recive JVM msg");
+                    let temp_data = map_reciver
+                        .recv()
+                        .await
+                        .expect("Couldn't recive frame from main map server loop");
+                    frame_index.fetch_add(1, Ordering::Relaxed);
+
+                    shown_frame = mem::replace(&mut frame, temp_data.data);
+                    data = Self::prepare_frame(&frame, &mut data_size)
+                        .await
+                        .expect("Couldn't prepare tcp frame");
+                }
+
+                if let Ok(msg) = cmd_reciver.try_recv() {
+                    //The players only forward start and stop, anything else is logged and ignored
+                    let NativeCommunication::StopRendering = msg else {
+                        println!("[ProjectAyaya] Map server is already rendering, ignoring {msg:?}");
+                        continue;
+                    };
+                    clock.pause();
+
+                    //Nothing is decoded while paused, so OSD changes are drawn over the frame on screen
+                    let fps = loop {
+                        tokio::select! {
+                            msg = cmd_reciver.recv() => match msg {
+                                Some(NativeCommunication::StartRendering { fps }) => break Some(fps),
+                                Some(msg) => println!("[ProjectAyaya] Map server is paused, ignoring {msg:?}"),
+                                None => break None,
+                            },
+                            _ = interval.tick(), if paused_frame.is_some() => {
+                                let Some(paused_frame) = &mut paused_frame else {
+                                    continue;
+                                };
+                                let redrawn = match paused_frame.redraw(&shown_frame) {
+                                    Ok(val) => val,
+                                    Err(err) => {
+                                        println!("[ProjectAyaya] Unable to redraw paused frame! Reason: {err}");
+                                        None
                                     }
+                                };
+                                if let Some(redrawn) = redrawn {
+                                    let redrawn_data =
+                                        Self::prepare_frame(&redrawn, &mut data_size)
+                                            .await
+                                            .expect("Couldn't prepare tcp frame");
+                                    tcp_frame_tx
+                                        .send(Arc::new(redrawn_data))
+                                        .expect("Couldn't send tcp frame");
+                                    shown_frame = redrawn;
                                 }
-                            } else {
-                                panic!("Invalid message! (Expected StopRendering, got: {msg:?})");
                             }
-                            break;
+                        }
+                    };
+                    match fps {
+                        Some(fps) => {
+                            let dur = Duration::from_millis(1000_u64 / (fps as u64));
+                            interval = time::interval(dur);
+                            clock.play();
+                            continue;
+                        }
+                        None => {
+                            println!("Couldn't recive JVM msg");
                         }
                     }
+                    break;
                 }
-                _ => {
-                    println!("[FastMapServer] You cannot stop a non working render thread! MapServer will exit!");
-                }
-            };
+            }
         });
 
         tokio::spawn(async move {
@@ -192,7 +263,7 @@ impl MapServer {
         Ok(())
     }
 
-    async fn prepare_frame(data: Vec<i8>, data_size: &mut usize) -> anyhow::Result<Vec<u8>> {
+    async fn prepare_frame(data: &[i8], data_size: &mut usize) -> anyhow::Result<Vec<u8>> {
         let encoder_capacity: usize = match data_size {
             0 => 2048,
             _ => *data_size,
@@ -203,15 +274,7 @@ impl MapServer {
         encoder_vec.write_u32(0).await.unwrap(); //Future TCP frame length
         let mut encoder = ZlibEncoder::new(encoder_vec, Compression::new(1));
 
-        let mut data = mem::ManuallyDrop::new(data);
-        let data = unsafe {
-            let data_ptr = data.as_mut_ptr() as *mut u8;
-            let data_len = data.len();
-            let data_cap = data.capacity();
-            Vec::from_raw_parts(data_ptr, data_len, data_cap)
-        };
-
-        encoder.write_all(&data)?;
+        encoder.write_all(bytemuck::cast_slice(data))?;
         let mut buffer = encoder.finish()?;
 
         //Write len
@@ -228,6 +291,10 @@ impl MapServer {
         Ok(buffer)
     }
 
+    pub fn clock(&self) -> Arc<MasterClock> {
+        self.clock.clone()
+    }
+
     pub fn send_message(&self, message: NativeCommunication) -> anyhow::Result<()> {
         self.command_sender.blocking_send(message)?;
         Ok(())
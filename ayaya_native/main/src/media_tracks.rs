@@ -0,0 +1,191 @@
+use anyhow::anyhow;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackKind {
+    Audio,
+    Subtitle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaTrack {
+    //Stream index in the container
+    pub index: usize,
+    pub kind: TrackKind,
+    //ISO 639-2 tag (for example "jpn"), as written by the muxer
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub codec: String,
+    pub default: bool,
+}
+
+/// Track picked by the plugin: "" or "default", a stream index ("2") or a language ("jpn")
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackSelection {
+    Default,
+    Stream(usize),
+    Language(String),
+}
+
+impl TrackSelection {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let spec = spec.trim();
+
+        if spec.is_empty() || spec.eq_ignore_ascii_case("default") {
+            return Ok(Self::Default);
+        }
+
+        if let Ok(index) = spec.parse::<usize>() {
+            return Ok(Self::Stream(index));
+        }
+
+        match spec.chars().all(|char| char.is_ascii_alphabetic()) {
+            true => Ok(Self::Language(spec.to_ascii_lowercase())),
+            false => Err(anyhow!("Invalid track ({spec})")),
+        }
+    }
+}
+
+//None means there is no track of that kind and the caller can let ffmpeg decide
+pub fn select(
+    tracks: &[MediaTrack],
+    kind: TrackKind,
+    selection: &TrackSelection,
+) -> anyhow::Result<Option<usize>> {
+    let mut candidates = tracks.iter().filter(|track| track.kind == kind);
+
+    let found = match selection {
+        TrackSelection::Default => {
+            let candidates: Vec<&MediaTrack> = candidates.collect();
+            return Ok(candidates
+                .iter()
+                .find(|track| track.default)
+                .or(candidates.first())
+                .map(|track| track.index));
+        }
+        TrackSelection::Stream(index) => candidates.find(|track| track.index == *index),
+        TrackSelection::Language(language) => {
+            let matching: Vec<&MediaTrack> = candidates
+                .filter(|track| {
+                    track
+                        .language
+                        .as_ref()
+                        .is_some_and(|track_language| track_language.eq_ignore_ascii_case(language))
+                })
+                .collect();
+
+            //Prefer the main track over commentary and signs only tracks
+            matching
+                .iter()
+                .find(|track| track.default)
+                .or(matching.first())
+                .copied()
+        }
+    };
+
+    match found {
+        Some(track) => Ok(Some(track.index)),
+        None => Err(anyhow!("No {kind:?} track matches {selection:?}")),
+    }
+}
+
+#[cfg(feature = "ffmpeg")]
+pub fn from_input(input: &ffmpeg::format::context::Input) -> Vec<MediaTrack> {
+    use ffmpeg::format::stream::Disposition;
+    use ffmpeg::media::Type;
+
+    input
+        .streams()
+        .filter_map(|stream| {
+            let kind = match stream.parameters().medium() {
+                Type::Audio => TrackKind::Audio,
+                Type::Subtitle => TrackKind::Subtitle,
+                _ => return None,
+            };
+            let metadata = stream.metadata();
+
+            Some(MediaTrack {
+                index: stream.index(),
+                kind,
+                language: metadata
+                    .get("language")
+                    .filter(|language| *language != "und")
+                    .map(|language| language.to_string()),
+                title: metadata.get("title").map(|title| title.to_string()),
+                codec: stream.parameters().id().name().to_string(),
+                default: stream.disposition().contains(Disposition::DEFAULT),
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "ffmpeg")]
+pub fn probe(file_name: &str) -> anyhow::Result<Vec<MediaTrack>> {
+    ffmpeg::init()?;
+    let input = ffmpeg::format::input(&file_name)?;
+    Ok(from_input(&input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(index: usize, kind: TrackKind, language: Option<&str>, default: bool) -> MediaTrack {
+        MediaTrack {
+            index,
+            kind,
+            language: language.map(|language| language.to_string()),
+            title: None,
+            codec: "aac".to_string(),
+            default,
+        }
+    }
+
+    fn anime_release() -> Vec<MediaTrack> {
+        vec![
+            track(1, TrackKind::Audio, Some("jpn"), false),
+            track(2, TrackKind::Audio, Some("eng"), true),
+            track(3, TrackKind::Audio, Some("jpn"), false),
+            track(4, TrackKind::Subtitle, Some("eng"), false),
+            track(5, TrackKind::Subtitle, None, false),
+        ]
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert!(TrackSelection::parse("").unwrap() == TrackSelection::Default);
+        assert!(TrackSelection::parse("Default").unwrap() == TrackSelection::Default);
+        assert!(TrackSelection::parse("3").unwrap() == TrackSelection::Stream(3));
+        assert!(
+            TrackSelection::parse(" JPN ").unwrap() == TrackSelection::Language("jpn".to_string())
+        );
+        assert!(TrackSelection::parse("-1").is_err());
+        assert!(TrackSelection::parse("en-US").is_err());
+    }
+
+    #[test]
+    fn test_select_track() {
+        let tracks = anime_release();
+        let select_audio = |spec: &str| {
+            select(
+                &tracks,
+                TrackKind::Audio,
+                &TrackSelection::parse(spec).unwrap(),
+            )
+        };
+
+        assert!(select_audio("").unwrap() == Some(2));
+        assert!(select_audio("jpn").unwrap() == Some(1));
+        assert!(select_audio("ENG").unwrap() == Some(2));
+        assert!(select_audio("3").unwrap() == Some(3));
+
+        //Subtitle stream is not an audio track
+        assert!(select_audio("4").is_err());
+        assert!(select_audio("ger").is_err());
+
+        //Without a default flag the first track is used
+        assert!(select(&tracks, TrackKind::Subtitle, &TrackSelection::Default).unwrap() == Some(4));
+        assert!(select(&[], TrackKind::Subtitle, &TrackSelection::Default)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,399 @@
+use std::borrow::Cow;
+use std::io::Write;
+
+use anyhow::anyhow;
+use flate2::{write::ZlibEncoder, Compression};
+use rayon::prelude::*;
+
+//Client refuses to handle bundles with more packets than that
+static MAX_BUNDLE_PACKETS: usize = 4096;
+//Versions that PacketIds::for_protocol knows (protocol versions in brackets)
+pub static SUPPORTED_VERSIONS: &str = "1.18.2 (758), 1.19.4 - 1.20.4 (762 - 765)";
+
+pub fn write_var_int(mut value: i32) -> (usize, [u8; 5]) {
+    let mut output = [0u8; 5];
+    let mut i = 0usize;
+
+    loop {
+        if (value & !0x7F) == 0 {
+            output[i] = value as u8;
+            i += 1;
+            return (i, output);
+        }
+
+        output[i] = (((value & 0x7F) | 0x80) as u8).to_be();
+        i += 1;
+
+        // Note: >>> means that the sign bit is shifted with the rest of the number rather than being left alone
+        value >>= 7;
+    }
+}
+
+/// Packet ids that change between protocol versions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacketIds {
+    pub map_data: u8,
+    /// None if the protocol does not support bundles (before 1.19.4)
+    pub bundle_delimiter: Option<u8>,
+}
+
+impl PacketIds {
+    pub fn for_protocol(protocol_version: i32) -> anyhow::Result<Self> {
+        //Source: https://wiki.vg/Protocol_version_numbers
+        let (map_data, bundle_delimiter) = match protocol_version {
+            758 => (0x27, None),             //1.18.2
+            762 | 763 => (0x29, Some(0x00)), //1.19.4, 1.20 - 1.20.1
+            764 | 765 => (0x2A, Some(0x00)), //1.20.2, 1.20.3 - 1.20.4
+            _ => {
+                return Err(anyhow!(
+                    "Unsupported protocol version ({protocol_version}), supported versions: {SUPPORTED_VERSIONS}"
+                ))
+            }
+        };
+
+        Ok(Self {
+            map_data,
+            bundle_delimiter,
+        })
+    }
+}
+
+//This struct skips some fields, as they will always be constant!
+pub struct MinecraftMapPacket {
+    pub map_id: i32,
+    pub columns: u8,
+    pub rows: u8,
+    pub x: u8,
+    pub z: u8,
+    pub data: Vec<i8>,
+}
+
+impl MinecraftMapPacket {
+    fn serialize_to_mc(&self, packet_id: u8, compression_threshold: i32) -> anyhow::Result<Vec<u8>> {
+        let (map_id_len, map_id) = write_var_int(self.map_id);
+        let (data_varint_len, data_varint) = write_var_int(self.data.len() as i32);
+        let packet_len = 1 + map_id_len + 7 + data_varint_len + self.data.len();
+
+        let mut body = Vec::<u8>::with_capacity(packet_len);
+        body.extend_from_slice(&[packet_id]); //Packet id
+        body.extend_from_slice(&map_id[..map_id_len]);
+        //scale = 0, locked = true, Has Icons = false, columns, rows, x, z
+
+        body.extend_from_slice(&[0x00i8 as u8, 0x01u8, 0x00u8, self.columns, self.rows, self.x, self.z]);
+        body.extend_from_slice(&data_varint[..data_varint_len]);
+
+        let data_slice = unsafe {
+            std::slice::from_raw_parts(self.data.as_ptr() as *mut u8, self.data.len())
+        };
+
+        body.extend_from_slice(data_slice);
+
+        frame_packet(&body, compression_threshold)
+    }
+}
+
+/// Encodes map packets of a whole video frame into bytes that can be written directly into a
+/// player channel
+pub struct MapPacketEncoder {
+    packet_ids: PacketIds,
+    compression_threshold: i32,
+    use_bundles: bool,
+}
+
+impl MapPacketEncoder {
+    /// Bundles are only used if the protocol version supports them
+    pub fn new(
+        protocol_version: i32,
+        compression_threshold: i32,
+        use_bundles: bool,
+    ) -> anyhow::Result<Self> {
+        let packet_ids = PacketIds::for_protocol(protocol_version)?;
+
+        Ok(Self {
+            packet_ids,
+            compression_threshold,
+            use_bundles: use_bundles && packet_ids.bundle_delimiter.is_some(),
+        })
+    }
+
+    pub fn encode_frame(&self, packets: &[MinecraftMapPacket]) -> anyhow::Result<Vec<u8>> {
+        //Every tile is compressed on its own, so we can do it in parallel
+        let serialized_packets = packets
+            .par_iter()
+            .map(|packet| packet.serialize_to_mc(self.packet_ids.map_data, self.compression_threshold))
+            .collect::<anyhow::Result<Vec<Vec<u8>>>>()?;
+
+        //The client applies everything between two delimiters at once, so all tiles of a frame
+        //are shown on the same tick
+        let delimiter = match (self.use_bundles, self.packet_ids.bundle_delimiter) {
+            (true, Some(packet_id)) => Some(frame_packet(&[packet_id], self.compression_threshold)?),
+            _ => None,
+        };
+
+        //Final len so we do not have to realloc
+        let final_len = serialized_packets.iter().map(|packet| packet.len()).sum::<usize>()
+            + delimiter.as_ref().map_or(0, |delimiter| {
+                delimiter.len() * 2 * ((serialized_packets.len() / MAX_BUNDLE_PACKETS) + 1)
+            });
+        let mut final_frame = Vec::<u8>::with_capacity(final_len);
+
+        for bundle in serialized_packets.chunks(MAX_BUNDLE_PACKETS) {
+            if let Some(delimiter) = &delimiter {
+                final_frame.extend_from_slice(delimiter);
+            }
+            for packet in bundle {
+                final_frame.extend_from_slice(packet);
+            }
+            if let Some(delimiter) = &delimiter {
+                final_frame.extend_from_slice(delimiter);
+            }
+        }
+
+        Ok(final_frame)
+    }
+}
+
+/// Wraps packet id + packet data into the format the client expects on the wire
+///
+/// With compression disabled (negative threshold) it is just a length prefix.
+/// Otherwise the "compressed packet" format is used: packet length, data length
+/// (0 when the packet is below the threshold) and a zlib body
+fn frame_packet(body: &[u8], compression_threshold: i32) -> anyhow::Result<Vec<u8>> {
+    //We DO need to say how long is the packet!
+    if compression_threshold < 0 {
+        let (packet_len_varint_len, packet_len_varint) = write_var_int(body.len() as i32);
+
+        let mut output = Vec::<u8>::with_capacity(packet_len_varint_len + body.len());
+        output.extend_from_slice(&packet_len_varint[..packet_len_varint_len]);
+        output.extend_from_slice(body);
+        return Ok(output);
+    }
+
+    let (data_len, payload) = if body.len() < compression_threshold as usize {
+        (0, Cow::Borrowed(body))
+    } else {
+        let mut encoder = ZlibEncoder::new(Vec::<u8>::with_capacity(body.len() / 2), Compression::new(1));
+        encoder.write_all(body)?;
+        (body.len() as i32, Cow::Owned(encoder.finish()?))
+    };
+
+    let (data_len_varint_len, data_len_varint) = write_var_int(data_len);
+    let packet_len = data_len_varint_len + payload.len();
+    let (packet_len_varint_len, packet_len_varint) = write_var_int(packet_len as i32);
+
+    let mut output = Vec::<u8>::with_capacity(packet_len_varint_len + packet_len);
+    output.extend_from_slice(&packet_len_varint[..packet_len_varint_len]);
+    output.extend_from_slice(&data_len_varint[..data_len_varint_len]);
+    output.extend_from_slice(&payload);
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static MAP_PACKET_ID: u8 = 0x27u8;
+
+    #[test]
+    fn test_varint_write() {
+        //Source: https://wiki.vg/Protocol
+        let first = write_var_int(0);
+        assert!(first.0 == 1);
+        assert!(first.1[0] == 0x00);
+
+        let second = write_var_int(2147483647);
+        assert!(second.0 == 5);
+        assert!(do_vecs_match(&second.1.to_vec(), &vec![0xff, 0xff, 0xff, 0xff, 0x07]));
+
+        let third = write_var_int(2097151);
+        assert!(third.0 == 3);
+        let third_vec = third.1[..3].to_vec();
+        assert!(do_vecs_match(&third_vec, &vec![0xff, 0xff, 0x7f]));
+
+        let (map_data_packet_id_len, map_data_packet_id) = write_var_int(MAP_PACKET_ID as i32);
+        assert!(do_vecs_match(&map_data_packet_id[..map_data_packet_id_len].to_vec(), &vec![MAP_PACKET_ID]));
+    }
+
+    fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
+        let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
+        matching == a.len() && matching == b.len()
+    }
+
+    fn read_var_int(data: &[u8]) -> (usize, i32) {
+        let mut value = 0i32;
+        for (i, byte) in data.iter().enumerate().take(5) {
+            value |= ((byte & 0x7F) as i32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return (i + 1, value);
+            }
+        }
+        panic!("VarInt is too big");
+    }
+
+    fn test_packet(data_len: usize) -> MinecraftMapPacket {
+        MinecraftMapPacket {
+            map_id: 300,
+            columns: 128,
+            rows: 128,
+            x: 0,
+            z: 0,
+            data: (0..data_len).map(|i| (i % 61) as i8).collect(),
+        }
+    }
+
+    fn packet_body(packet: &MinecraftMapPacket) -> Vec<u8> {
+        let uncompressed = packet.serialize_to_mc(MAP_PACKET_ID, -1).unwrap();
+        let (len_size, len) = read_var_int(&uncompressed);
+        assert!(len as usize == uncompressed.len() - len_size);
+        uncompressed[len_size..].to_vec()
+    }
+
+    //Reads framed packets and returns their (decompressed) bodies
+    fn read_packets(stream: &[u8], compressed: bool) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        let mut offset = 0usize;
+
+        while offset != stream.len() {
+            let (packet_len_size, packet_len) = read_var_int(&stream[offset..]);
+            let frame = &stream[offset + packet_len_size..][..packet_len as usize];
+
+            if !compressed {
+                packets.push(frame.to_vec());
+            } else {
+                let (data_len_size, data_len) = read_var_int(frame);
+                if data_len == 0 {
+                    packets.push(frame[data_len_size..].to_vec());
+                } else {
+                    //Decode with a different zlib implementation than the one used for encoding
+                    let decoded =
+                        miniz_oxide::inflate::decompress_to_vec_zlib(&frame[data_len_size..])
+                            .unwrap();
+                    assert!(decoded.len() == data_len as usize);
+                    packets.push(decoded);
+                }
+            }
+
+            offset += packet_len_size + packet_len as usize;
+        }
+
+        packets
+    }
+
+    #[test]
+    fn test_uncompressed_framing() {
+        let packet = test_packet(128 * 128);
+        let body = packet_body(&packet);
+
+        assert!(body[0] == MAP_PACKET_ID);
+        let (map_id_len, map_id) = read_var_int(&body[1..]);
+        assert!(map_id == 300);
+        let (data_len_size, data_len) = read_var_int(&body[1 + map_id_len + 7..]);
+        assert!(data_len as usize == 128 * 128);
+        assert!(body.len() == 1 + map_id_len + 7 + data_len_size + 128 * 128);
+    }
+
+    #[test]
+    fn test_compressed_framing() {
+        let packet = test_packet(128 * 128);
+        let body = packet_body(&packet);
+        let framed = packet.serialize_to_mc(MAP_PACKET_ID, 256).unwrap();
+
+        let (packet_len_size, packet_len) = read_var_int(&framed);
+        assert!(packet_len as usize == framed.len() - packet_len_size);
+
+        let (data_len_size, data_len) = read_var_int(&framed[packet_len_size..]);
+        assert!(data_len as usize == body.len());
+
+        let decoded = miniz_oxide::inflate::decompress_to_vec_zlib(
+            &framed[packet_len_size + data_len_size..],
+        )
+        .unwrap();
+        assert!(do_vecs_match(&decoded, &body));
+        assert!(framed.len() < body.len());
+    }
+
+    #[test]
+    fn test_below_threshold_framing() {
+        let packet = test_packet(16);
+        let body = packet_body(&packet);
+        let framed = packet.serialize_to_mc(MAP_PACKET_ID, 256).unwrap();
+
+        let (packet_len_size, packet_len) = read_var_int(&framed);
+        assert!(packet_len as usize == framed.len() - packet_len_size);
+
+        //Data length 0 = packet is not compressed
+        let (data_len_size, data_len) = read_var_int(&framed[packet_len_size..]);
+        assert!(data_len == 0);
+        assert!(do_vecs_match(&framed[packet_len_size + data_len_size..].to_vec(), &body));
+    }
+
+    #[test]
+    fn test_compressed_frame_stream() {
+        let packets: Vec<MinecraftMapPacket> = (0..12).map(|i| test_packet(i * 1500)).collect();
+        let encoder = MapPacketEncoder::new(758, 64, false).unwrap();
+
+        let decoded = read_packets(&encoder.encode_frame(&packets).unwrap(), true);
+
+        assert!(decoded.len() == packets.len());
+        for (decoded, packet) in decoded.iter().zip(packets.iter()) {
+            assert!(do_vecs_match(decoded, &packet_body(packet)));
+        }
+    }
+
+    #[test]
+    fn test_bundle_wrapping() {
+        let packets: Vec<MinecraftMapPacket> = (0..6).map(|_| test_packet(128 * 128)).collect();
+
+        for compression_threshold in [-1, 256] {
+            let compressed = compression_threshold >= 0;
+            let encoder = MapPacketEncoder::new(763, compression_threshold, true).unwrap();
+            let decoded = read_packets(&encoder.encode_frame(&packets).unwrap(), compressed);
+
+            assert!(decoded.len() == packets.len() + 2);
+            assert!(do_vecs_match(&decoded[0], &vec![0x00]));
+            assert!(do_vecs_match(&decoded[packets.len() + 1], &vec![0x00]));
+            for packet in &decoded[1..=packets.len()] {
+                assert!(packet[0] == 0x29);
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_bundle_split() {
+        let packets: Vec<MinecraftMapPacket> =
+            (0..MAX_BUNDLE_PACKETS + 10).map(|_| test_packet(4)).collect();
+        let encoder = MapPacketEncoder::new(765, -1, true).unwrap();
+        let decoded = read_packets(&encoder.encode_frame(&packets).unwrap(), false);
+
+        let delimiters: Vec<usize> = decoded
+            .iter()
+            .enumerate()
+            .filter(|(_, packet)| packet.len() == 1 && packet[0] == 0x00)
+            .map(|(i, _)| i)
+            .collect();
+        assert!(delimiters == vec![0, MAX_BUNDLE_PACKETS + 1, MAX_BUNDLE_PACKETS + 2, MAX_BUNDLE_PACKETS + 13]);
+    }
+
+    #[test]
+    fn test_no_bundles_for_old_protocol() {
+        let packets: Vec<MinecraftMapPacket> = (0..4).map(|_| test_packet(128)).collect();
+        let encoder = MapPacketEncoder::new(758, -1, true).unwrap();
+        let decoded = read_packets(&encoder.encode_frame(&packets).unwrap(), false);
+
+        assert!(decoded.len() == packets.len());
+        assert!(decoded.iter().all(|packet| packet[0] == MAP_PACKET_ID));
+        assert!(MapPacketEncoder::new(1, -1, true).is_err());
+    }
+
+    #[test]
+    fn test_supported_versions() {
+        for protocol_version in [758, 762, 763, 764, 765] {
+            assert!(PacketIds::for_protocol(protocol_version).is_ok());
+        }
+        for protocol_version in [757, 759, 760, 761, 766] {
+            let err = PacketIds::for_protocol(protocol_version).unwrap_err();
+            assert!(err.to_string().contains(SUPPORTED_VERSIONS));
+        }
+    }
+}
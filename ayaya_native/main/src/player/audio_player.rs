@@ -0,0 +1,355 @@
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use ffmpeg::format::context::Input;
+use ffmpeg::format::{input, Sample};
+use ffmpeg::media::Type;
+use ffmpeg::software::resampling;
+use ffmpeg::util::channel_layout::ChannelLayout;
+use ffmpeg::{frame, rescale, Error, Rescale};
+
+use crate::audio_sink::{AudioSink, PCM_CHANNELS, PCM_SAMPLE_RATE};
+use crate::map_server::ServerOptions;
+use crate::media_tracks::{self, MediaTrack, TrackKind, TrackSelection};
+
+use super::player_context::{NativeCommunication, VideoData, VideoFrame, VideoPlayer};
+use super::sync_clock::MasterClock;
+use super::synced_player::SyncedPlayer;
+
+//The sink always gets this much audio ahead of the clock, so it never runs dry
+static AUDIO_LEAD: Duration = Duration::from_millis(300);
+
+/// Decodes the audio stream of a file into PCM in the format of the audio sinks
+pub struct PcmDecoder {
+    file_name: String,
+    tracks: Vec<MediaTrack>,
+    input: Input,
+    decoder: ffmpeg::decoder::Audio,
+    resampler: resampling::Context,
+    stream_index: usize,
+    //Position of the next returned sample
+    position: Duration,
+    eof: bool,
+}
+
+impl PcmDecoder {
+    pub fn open(file_name: &str) -> anyhow::Result<Self> {
+        Self::open_stream(file_name, &TrackSelection::Default)
+    }
+
+    pub fn open_stream(file_name: &str, selection: &TrackSelection) -> anyhow::Result<Self> {
+        ffmpeg::init()?;
+        let input = input(&file_name)?;
+        let tracks = media_tracks::from_input(&input);
+
+        let stream = match media_tracks::select(&tracks, TrackKind::Audio, selection)? {
+            Some(index) => input.stream(index),
+            None => input.streams().best(Type::Audio),
+        }
+        .ok_or(Error::StreamNotFound)?;
+        let stream_index = stream.index();
+
+        let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+        if decoder.channel_layout().is_empty() {
+            decoder.set_channel_layout(ChannelLayout::default(decoder.channels() as i32));
+        }
+
+        let resampler = decoder.resampler(
+            Sample::F32(ffmpeg::format::sample::Type::Packed),
+            ChannelLayout::STEREO,
+            PCM_SAMPLE_RATE,
+        )?;
+
+        Ok(Self {
+            file_name: file_name.to_string(),
+            tracks,
+            input,
+            decoder,
+            resampler,
+            stream_index,
+            position: Duration::ZERO,
+            eof: false,
+        })
+    }
+
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    pub fn tracks(&self) -> &[MediaTrack] {
+        &self.tracks
+    }
+
+    pub fn stream_index(&self) -> usize {
+        self.stream_index
+    }
+
+    //Decoding continues at position, in the new stream. The old stream is kept if the new one can't be opened
+    pub fn switch_stream(&mut self, stream_index: usize, position: Duration) -> anyhow::Result<()> {
+        let mut switched =
+            Self::open_stream(&self.file_name, &TrackSelection::Stream(stream_index))?;
+        switched.seek(position)?;
+
+        *self = switched;
+        Ok(())
+    }
+
+    //None at the end of the stream
+    pub fn decode(&mut self) -> anyhow::Result<Option<Vec<f32>>> {
+        while !self.eof {
+            match self.input.packets().next() {
+                Some((stream, packet)) => {
+                    if stream.index() != self.stream_index {
+                        continue;
+                    }
+                    self.decoder.send_packet(&packet)?;
+                }
+                None => {
+                    self.decoder.send_eof()?;
+                    self.eof = true;
+                }
+            }
+
+            let samples = self.receive_samples()?;
+            if !samples.is_empty() {
+                return Ok(Some(samples));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn receive_samples(&mut self) -> anyhow::Result<Vec<f32>> {
+        let mut samples = Vec::<f32>::new();
+        let mut decoded = frame::Audio::empty();
+        let mut resampled = frame::Audio::empty();
+
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            if decoded.channel_layout().is_empty() {
+                decoded.set_channel_layout(ChannelLayout::default(decoded.channels() as i32));
+            }
+            self.resampler.run(&decoded, &mut resampled)?;
+
+            //Packed audio has every channel in the first plane
+            let len = resampled.samples() * PCM_CHANNELS as usize * 4;
+            samples.extend(
+                resampled.data(0)[..len]
+                    .chunks_exact(4)
+                    .map(|sample| f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]])),
+            );
+        }
+
+        self.position += Duration::from_secs_f64(
+            samples.len() as f64 / PCM_CHANNELS as f64 / PCM_SAMPLE_RATE as f64,
+        );
+        Ok(samples)
+    }
+
+    pub fn seek(&mut self, position: Duration) -> anyhow::Result<()> {
+        let timestamp = (position.as_millis() as i64).rescale((1, 1000), rescale::TIME_BASE);
+        self.input.seek(timestamp, ..timestamp)?;
+        self.decoder.flush();
+
+        self.position = position;
+        self.eof = false;
+        Ok(())
+    }
+}
+
+enum AudioCommand {
+    Stop,
+    //Result is sent back once the new stream is opened
+    SwitchTrack {
+        stream_index: usize,
+        result_tx: Sender<anyhow::Result<()>>,
+    },
+}
+
+//Pushes decoded audio to the sink, following the clock. Pause and seek of the clock are
+//passed to the sink. Runs until Stop, at the end of the file it waits for a seek back
+fn feed_sink(
+    decoder: &mut PcmDecoder,
+    sink: &mut dyn AudioSink,
+    clock: &MasterClock,
+    command_rx: &Receiver<AudioCommand>,
+) -> anyhow::Result<()> {
+    let mut playing = false;
+    let mut seek_generation = clock.seek_generation();
+    let mut finished = false;
+
+    loop {
+        match command_rx.try_recv() {
+            Ok(AudioCommand::SwitchTrack {
+                stream_index,
+                result_tx,
+            }) => {
+                //Buffered audio of the old track is dropped like on a seek
+                let position = clock.position();
+                let result = decoder.switch_stream(stream_index, position);
+                if result.is_ok() {
+                    sink.seek(position)?;
+                    finished = false;
+                }
+                let _ = result_tx.send(result);
+            }
+            Err(TryRecvError::Empty) => {}
+            _ => return Ok(()),
+        }
+
+        if clock.seek_generation() != seek_generation {
+            seek_generation = clock.seek_generation();
+            let position = clock.position();
+            decoder.seek(position)?;
+            sink.seek(position)?;
+            finished = false;
+        }
+
+        if clock.is_playing() != playing {
+            playing = !playing;
+            match playing {
+                true => sink.play()?,
+                false => sink.pause()?,
+            }
+        }
+
+        if finished || decoder.position() > clock.position() + AUDIO_LEAD {
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+
+        match decoder.decode()? {
+            Some(samples) => sink.write(&samples)?,
+            None => finished = true,
+        }
+    }
+}
+
+//Plays the audio of a file on any sink, next to the video of the inner player
+pub struct AudioPlayer {
+    inner: Box<dyn VideoPlayer>,
+    clock: Arc<MasterClock>,
+    tracks: Vec<MediaTrack>,
+    command_tx: Sender<AudioCommand>,
+}
+
+impl AudioPlayer {
+    pub fn create_with_sink(
+        file_name: String,
+        player: Box<dyn VideoPlayer>,
+        mut sink: Box<dyn AudioSink>,
+        audio_track: TrackSelection,
+    ) -> anyhow::Result<Self> {
+        let source = sink.clock_source();
+
+        //Map server has its own clock, JVM paced players have to be wrapped
+        let (inner, clock): (Box<dyn VideoPlayer>, Arc<MasterClock>) = match player.clock() {
+            Some(clock) => {
+                if source.is_some() {
+                    clock.set_source(source);
+                }
+                (player, clock)
+            }
+            None => {
+                let clock = match source {
+                    Some(source) => MasterClock::audio(source),
+                    None => MasterClock::wall_clock(),
+                };
+                (Box::new(SyncedPlayer::new(player, clock.clone())?), clock)
+            }
+        };
+
+        let (command_tx, command_rx) = channel::<AudioCommand>();
+        let (ready_tx, ready_rx) = channel::<anyhow::Result<Vec<MediaTrack>>>();
+        let clock_clone = clock.clone();
+
+        //FFmpeg contexts can't be moved between threads, so the decoder is created there
+        thread::spawn(move || {
+            let mut decoder = match PcmDecoder::open_stream(&file_name, &audio_track) {
+                Ok(val) => {
+                    let _ = ready_tx.send(Ok(val.tracks().to_vec()));
+                    val
+                }
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                    return;
+                }
+            };
+
+            if let Err(err) = feed_sink(&mut decoder, sink.as_mut(), &clock_clone, &command_rx) {
+                println!("[ProjectAyaya] Audio playback failed! Reason: {err}");
+            }
+            if let Err(err) = sink.finish() {
+                println!("[ProjectAyaya] Unable to finish audio sink! Reason: {err}");
+            }
+        });
+
+        let tracks = match ready_rx.recv() {
+            Ok(result) => result?,
+            Err(_) => return Err(anyhow!("Audio thread exited before opening the file")),
+        };
+
+        Ok(Self {
+            inner,
+            clock,
+            tracks,
+            command_tx,
+        })
+    }
+}
+
+impl VideoPlayer for AudioPlayer {
+    fn create(_file_name: String, _server_options: ServerOptions) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(anyhow!("Please use AudioPlayer::create_with_sink!"))
+    }
+
+    fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
+        self.inner.load_frame()
+    }
+
+    fn video_data(&self) -> anyhow::Result<VideoData> {
+        self.inner.video_data()
+    }
+
+    //The inner player moves the clock, the audio thread follows it
+    fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
+        match msg {
+            NativeCommunication::AudioTrack { track } => {
+                let stream_index =
+                    match media_tracks::select(&self.tracks, TrackKind::Audio, &track)? {
+                        Some(val) => val,
+                        None => return Err(anyhow!("Video has no audio tracks")),
+                    };
+
+                let (result_tx, result_rx) = channel();
+                self.command_tx.send(AudioCommand::SwitchTrack {
+                    stream_index,
+                    result_tx,
+                })?;
+
+                match result_rx.recv() {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow!("Audio thread has stopped")),
+                }
+            }
+            _ => self.inner.handle_jvm_msg(msg),
+        }
+    }
+
+    fn clock(&self) -> Option<Arc<MasterClock>> {
+        Some(self.clock.clone())
+    }
+
+    fn destroy(&self) -> anyhow::Result<()> {
+        //Audio thread might have already finished
+        let _ = self.command_tx.send(AudioCommand::Stop);
+        self.inner.destroy()
+    }
+}
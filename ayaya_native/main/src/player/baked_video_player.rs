@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use anyhow::anyhow;
+
+use crate::frame_cache::FrameCacheReader;
+use crate::map_server::ServerOptions;
+use crate::player::player_context::{VideoData, VideoPlayer};
+
+use super::player_context::{wrap_frame, NativeCommunication, VideoFrame};
+
+//Plays videos baked with frame_cache::bake. No decoding or color conversion happens here
+pub struct BakedVideoPlayer {
+    reader: FrameCacheReader<BufReader<File>>,
+    seek_tx: Sender<i32>,
+    seek_rx: Receiver<i32>,
+    paused: AtomicBool,
+    //Shown again while paused
+    last_frame: Option<Vec<i8>>,
+}
+
+impl VideoPlayer for BakedVideoPlayer {
+    fn create(file_name: String, server_options: ServerOptions) -> anyhow::Result<Self> {
+        if server_options.use_server {
+            return Err(anyhow!("Baked video player does not support map server"));
+        }
+
+        let reader = FrameCacheReader::new(BufReader::new(File::open(file_name)?))?;
+        let (seek_tx, seek_rx) = channel::<i32>();
+
+        Ok(Self {
+            reader,
+            seek_tx,
+            seek_rx,
+            paused: AtomicBool::new(false),
+            last_frame: None,
+        })
+    }
+
+    fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
+        while let Ok(second) = self.seek_rx.try_recv() {
+            let frame = second.max(0) as usize * self.reader.header().fps as usize;
+            self.reader.seek_frame(frame)?;
+            //A seek while paused shows the frame it lands on
+            self.last_frame = None;
+        }
+
+        if self.paused.load(Ordering::Relaxed) {
+            if let Some(frame) = &self.last_frame {
+                return Ok(wrap_frame(frame.clone()));
+            }
+        }
+
+        match self.reader.read_frame()? {
+            Some(frame) => {
+                self.last_frame = Some(frame.clone());
+                Ok(wrap_frame(frame))
+            }
+            None => Err(anyhow!("End of baked video")),
+        }
+    }
+
+    fn video_data(&self) -> anyhow::Result<VideoData> {
+        let header = self.reader.header();
+
+        Ok(VideoData {
+            width: header.width as i32,
+            height: header.height as i32,
+            fps: header.fps as i32,
+        })
+    }
+
+    fn destroy(&self) -> anyhow::Result<()> {
+        Ok(()) //Nothing to do
+    }
+
+    fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
+        match msg {
+            NativeCommunication::StartRendering { .. } => {
+                self.paused.store(false, Ordering::Relaxed);
+            }
+            NativeCommunication::StopRendering => {
+                self.paused.store(true, Ordering::Relaxed);
+            }
+            NativeCommunication::VideoSeek { second } => {
+                self.seek_tx.send(second)?;
+            }
+            _ => return Err(anyhow!("Expected StartRendering, StopRendering or VideoSeek msg")),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::frame_cache::{FrameCacheHeader, FrameCacheWriter};
+
+    #[test]
+    fn test_pause_and_resume() {
+        let header = FrameCacheHeader::new(128, 128, 1).unwrap();
+        let mut writer = FrameCacheWriter::new(Cursor::new(Vec::new()), &header).unwrap();
+        for i in 0..5 {
+            writer.write_frame(&vec![i as i8; 128 * 128]).unwrap();
+        }
+        let file_name = std::env::temp_dir().join(format!("ayaya_baked_test_{}.ayac", std::process::id()));
+        std::fs::write(&file_name, writer.finish().unwrap().into_inner()).unwrap();
+
+        let server_options = ServerOptions {
+            use_server: false,
+            bind_ip: String::new(),
+            port: 0,
+            asset_dir: None,
+        };
+        let mut player = BakedVideoPlayer::create(file_name.to_str().unwrap().to_string(), server_options).unwrap();
+
+        assert!(player.load_frame().unwrap().data()[0] == 0);
+        player.handle_jvm_msg(NativeCommunication::StopRendering).unwrap();
+        assert!(player.load_frame().unwrap().data()[0] == 0);
+        assert!(player.load_frame().unwrap().data()[0] == 0);
+
+        //A seek while paused shows the frame it lands on, then holds it
+        player.handle_jvm_msg(NativeCommunication::VideoSeek { second: 3 }).unwrap();
+        assert!(player.load_frame().unwrap().data()[0] == 3);
+        assert!(player.load_frame().unwrap().data()[0] == 3);
+
+        player.handle_jvm_msg(NativeCommunication::StartRendering { fps: 1 }).unwrap();
+        assert!(player.load_frame().unwrap().data()[0] == 4);
+        assert!(player.load_frame().is_err());
+
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
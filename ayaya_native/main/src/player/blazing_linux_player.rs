@@ -15,42 +15,12 @@ use ffmpeg::{rescale, Error, Rescale};
 
 use crate::colorlib::{transform_frame_to_mc, fast_frame_to_mc};
 use crate::map_server::ServerOptions;
+use crate::minecraft_packet::{MapPacketEncoder, MinecraftMapPacket, PacketIds};
 use crate::player::player_context::{receive_and_process_decoded_frames, VideoData, VideoPlayer};
 use crate::{ffmpeg_set_multithreading, SplittedFrame, TOKIO_RUNTIME};
 
 use super::player_context;
 
-static MAP_PACKET_ID: u8 = 0x27u8;
-
-fn write_var_int(mut value: i32) -> (usize, [u8; 5]) {
-    let mut output = [0u8; 5];
-    let mut i = 0usize;
-
-    loop {
-        if (value & !0x7F) == 0 {
-            output[i] = (value as u8);
-            i += 1;
-            return (i, output);
-        }
-
-        output[i] = (((value & 0x7F) | 0x80) as u8).to_be();
-        i += 1;
-
-        // Note: >>> means that the sign bit is shifted with the rest of the number rather than being left alone
-        value >>= 7;
-    }
-}
-
-//This struct skips some fields, as they will always be constant!
-struct MinecraftMapPacket {
-    map_id: i32,
-    columns: u8,
-    rows: u8,
-    x: u8,
-    z: u8,
-    data: Vec<i8>
-}
-
 struct CompressionRange {
     start: usize,
     end: usize,
@@ -71,10 +41,29 @@ impl VideoPlayer for LinuxBlazingPlayer {
             return Err(anyhow!("Single video player does not support map server"));
         }
 
-        let (prefix, file_name) = file_name.split_once("$$$").ok_or_else(|| anyhow!("Expected filename format START_MAP_ID$$$FILE_NAME"))?;
-        let start_map_id: i32 = prefix.parse()?;
+        let mut file_name_parts = file_name.splitn(5, "$$$");
+        let (start_map_id, compression_threshold, protocol_version, use_bundles, file_name) = match (file_name_parts.next(), file_name_parts.next(), file_name_parts.next(), file_name_parts.next(), file_name_parts.next()) {
+            (Some(start_map_id), Some(compression_threshold), Some(protocol_version), Some(use_bundles), Some(file_name)) => (start_map_id, compression_threshold, protocol_version, use_bundles, file_name),
+            _ => return Err(anyhow!("Expected filename format START_MAP_ID$$$COMPRESSION_THRESHOLD$$$PROTOCOL_VERSION$$$USE_BUNDLES$$$FILE_NAME")),
+        };
+        let start_map_id: i32 = start_map_id.parse()?;
+        //Same as the server.properties "network-compression-threshold", negative value disables compression
+        let compression_threshold: i32 = compression_threshold.parse()?;
+        let protocol_version: i32 = protocol_version.parse()?;
+        //Bundles make the client apply all tiles of a frame at once (no tearing on big screens).
+        //Empty means on if the protocol has them (1.19.4+)
+        let bundles_supported = PacketIds::for_protocol(protocol_version)?.bundle_delimiter.is_some();
+        let use_bundles = match use_bundles {
+            "" => bundles_supported,
+            flag => flag.parse().map_err(|_| anyhow!("Invalid bundle flag ({flag})"))?,
+        };
+        if use_bundles && !bundles_supported {
+            return Err(anyhow!("Bundles are not supported by protocol version {protocol_version}, they need 1.19.4 or newer"));
+        }
         let file_name = file_name.to_owned();
 
+        let packet_encoder = MapPacketEncoder::new(protocol_version, compression_threshold, use_bundles)?;
+
         ffmpeg::init()?;
 
         if let Ok(mut ictx) = input(&file_name) {
@@ -104,8 +93,6 @@ impl VideoPlayer for LinuxBlazingPlayer {
             let (frame_tx, frame_rx) = sync_channel::<Vec<i8>>(90);
 
             let mem_cpy_ranges = SplittedFrame::prepare_external_ranges(&splitted_frames, width as usize, height as usize, all_frames_x, all_frames_y)?;
-            let compression_ranges = prepare_compression_ranges(&splitted_frames);
-            let mut prev_data: Option<Vec<u8>> = None;
 
             TOKIO_RUNTIME.spawn_blocking(move || {
                 let mut scaler = Context::get(
@@ -143,9 +130,7 @@ impl VideoPlayer for LinuxBlazingPlayer {
 
                         let mut prev_frame_i = 0usize;
 
-                        //Final len so we do not have to realloc (27 is a magic val, see MinecraftMapPacket code below) 
-                        let mut final_frame = Vec::<u8>::with_capacity((width as usize * height as usize) + (27 * splitted_frames.len()));
-                        splitted_frames
+                        let packets: Vec<MinecraftMapPacket> = splitted_frames
                             .iter()
                             .enumerate()
                             .map(|(i, frame)| {
@@ -160,17 +145,11 @@ impl VideoPlayer for LinuxBlazingPlayer {
                                 prev_frame_i += frame.frame_length;
                                 packet
                             })
-                            .try_for_each(|packet| -> anyhow::Result<()> {
-                                let vec = packet.serialize_to_mc()?;
-                                final_frame.extend_from_slice(&vec);
-                                Ok(())
-                            }).expect("Cannot perform final packet encoding");
-
-                      
-                        if let Some(old_data) = prev_data {
-                            compress_final_data(&compression_ranges, &final_frame, &old_data, width as usize * height as usize)
-                        }
-                        prev_data = Some(final_frame.clone());
+                            .collect();
+
+                        let final_frame = packet_encoder
+                            .encode_frame(&packets)
+                            .expect("Cannot perform final packet encoding");
                         frame_tx.send(bytemuck::cast_vec(final_frame)).expect("Cannot send final frame!");
                     }
                 };
@@ -208,6 +187,8 @@ impl VideoPlayer for LinuxBlazingPlayer {
     }
 }
 
+//Only for measuring how much sending the changed parts of a frame would save, nothing calls it while playing
+#[allow(dead_code)]
 fn prepare_compression_ranges(splitted_frames: &Vec<SplittedFrame>) -> Vec<CompressionRange> {
     splitted_frames
         .iter()
@@ -237,7 +218,10 @@ impl CompressedDataRange {
     }
 }
 
-fn compress_final_data(compression_ranges: &Vec<CompressionRange>, new_data: &[u8], old_data: &[u8], max: usize) {
+//Returns the unchanged pixels covered by the merged ranges and all unchanged pixels. The ranges point into the
+//map data, not into the encoded packets (headers, compression and bundle delimiters move it around)
+#[allow(dead_code)]
+fn compress_final_data(compression_ranges: &Vec<CompressionRange>, new_data: &[u8], old_data: &[u8], max: usize) -> (usize, usize) {
     let a = compression_ranges
         .iter()
         .map(|e| (&new_data[e.start..e.end], &old_data[e.start..e.end], e.width, e.height))
@@ -290,7 +274,7 @@ fn compress_final_data(compression_ranges: &Vec<CompressionRange>, new_data: &[u
             .filter(|(&a, &b)| a == b)
             .count();
 
-        println!("{a}/{max}, absolute_max: {absolute_max}");
+        (a, absolute_max)
 }
 
 fn mutate_change_square(changes: &mut Vec<u8>, start_x: &mut usize, end_x: &mut usize, start_y: &mut usize, end_y: &mut usize, x: usize, y: usize, width: usize, height: usize) {
@@ -316,64 +300,10 @@ fn mutate_change_square(changes: &mut Vec<u8>, start_x: &mut usize, end_x: &mut
     }
 }
 
-impl MinecraftMapPacket {
-    fn serialize_to_mc(&self) -> anyhow::Result<Vec<u8>> {
-        //27 for the packet wrapping
-        let mut output = Vec::<u8>::with_capacity(27 + self.data.len());
-
-        let (map_id_len, map_id) = write_var_int(self.map_id);
-        let (data_varint_len, data_varint) = write_var_int(self.data.len() as i32);
-        let packet_len = 1 + map_id_len + 7 + data_varint_len + self.data.len();
-        let (packet_len_varint_len, packet_len_varint) = write_var_int(packet_len as i32);
-
-        //We DO need to say how long is the packet!
-        output.extend_from_slice(&packet_len_varint[..packet_len_varint_len]);
-        output.extend_from_slice(&[MAP_PACKET_ID]); //Packet id
-        output.extend_from_slice(&map_id[..map_id_len]);
-        //scale = 0, locked = true, Has Icons = false, columns, rows, x, z
-
-        output.extend_from_slice(&[0x00i8 as u8, 0x01u8, 0x00u8, self.columns, self.rows, self.x, self.z]);
-        output.extend_from_slice(&data_varint[..data_varint_len]);
-
-        let data_slice = unsafe {
-            std::slice::from_raw_parts(self.data.as_ptr() as *mut u8, self.data.len())
-        };
-
-        output.extend_from_slice(data_slice);
-
-        Ok(output)       
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_varint_write() {
-        //Source: https://wiki.vg/Protocol
-        let first = write_var_int(0);
-        assert!(first.0 == 1);
-        assert!(first.1[0] == 0x00);
-
-        let second = write_var_int(2147483647);
-        assert!(second.0 == 5);
-        assert!(do_vecs_match(&second.1.to_vec(), &vec![0xff, 0xff, 0xff, 0xff, 0x07]));
-
-        let third = write_var_int(2097151);
-        assert!(third.0 == 3);
-        let third_vec = third.1[..3].to_vec();
-        assert!(do_vecs_match(&third_vec, &vec![0xff, 0xff, 0x7f]));
-
-        let (map_data_packet_id_len, map_data_packet_id) = write_var_int(MAP_PACKET_ID as i32);
-        assert!(do_vecs_match(&map_data_packet_id[..map_data_packet_id_len].to_vec(), &vec![MAP_PACKET_ID]));
-    }
-
-    fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
-        let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
-        matching == a.len() && matching == b.len()
-    }
-
     #[test]
     fn test_compression() {
         let frame = vec![12u8; 128 * 128];
@@ -382,6 +312,7 @@ mod tests {
 
         let compression_range = vec![ CompressionRange { start: 0, end: frame.len(), width: 128, height: 128 }];
 
-        compress_final_data(&compression_range, &sec_frame, &frame, 128 * 128);
+        let (_, absolute_max) = compress_final_data(&compression_range, &sec_frame, &frame, 128 * 128);
+        assert!(absolute_max == 128 * 128 - 2224);
     }
 }
@@ -1,80 +1,109 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::num::NonZeroU64;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
 
 use once_cell::sync::OnceCell;
+use serenity::all::{
+    CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage, Interaction, Ready,
+};
+use serenity::async_trait;
+use serenity::prelude::{Context, EventHandler};
 use serenity::{prelude::GatewayIntents, Client};
-use songbird::input::File;
-use songbird::tracks::TrackHandle;
+use songbird::input::{Input, RawAdapter};
+use songbird::tracks::{PlayMode, TrackHandle};
 use songbird::{SerenityInit, Songbird};
+use symphonia::core::io::MediaSource;
 
 use crate::anyhow;
-use crate::{map_server::ServerOptions, TOKIO_RUNTIME};
+use crate::audio_sink::{AudioSink, PCM_CHANNELS, PCM_SAMPLE_RATE};
+use crate::TOKIO_RUNTIME;
 
-use super::player_context::{NativeCommunication, VideoData, VideoFrame, VideoPlayer};
+use super::discord_commands::{
+    self, CommandEvent, PlaybackStatus, PlayerControl, COMMANDS, SECOND_OPTION,
+};
+use super::player_context::{self, NativeCommunication, VideoPlayer};
+use super::sync_clock::ClockSource;
+use super::voice_sessions::{VoiceChannel, VoiceSessions};
 
 static DISCORD_CLIENT: OnceCell<DiscordClient> = OnceCell::new();
+//Songbird only gives us the position asynchronously, so we poll it and extrapolate in between
+static TRACK_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Debug, Clone)]
 pub struct DiscordOptions {
     pub use_discord: bool,
     pub discord_token: String,
-    pub guild_id: NonZeroU64,
-    pub channel_id: NonZeroU64,
+    //Used when init does not select a channel
+    pub default_channel: VoiceChannel,
 }
 
 pub struct DiscordClient {
     options: DiscordOptions,
     songbird: Arc<Songbird>,
+    sessions: Mutex<VoiceSessions>,
 }
 
 impl DiscordClient {
-    #[allow(unused)]
+    pub fn get() -> anyhow::Result<&'static DiscordClient> {
+        match DISCORD_CLIENT.get() {
+            Some(val) => Ok(val),
+            None => Err(anyhow!("Discord client not initialized")),
+        }
+    }
+
+    fn lock_sessions(&self) -> anyhow::Result<MutexGuard<'_, VoiceSessions>> {
+        match self.sessions.lock() {
+            Ok(val) => Ok(val),
+            Err(_) => Err(anyhow!("Cannot lock discord sessions!")),
+        }
+    }
+
+    //(guild id, channel id) of the init call or the channel from DiscordOptions
+    pub fn resolve_channel(&self, channel: Option<(u64, u64)>) -> anyhow::Result<VoiceChannel> {
+        match channel {
+            Some((guild_id, channel_id)) => VoiceChannel::new(guild_id, channel_id),
+            None => Ok(self.options.default_channel),
+        }
+    }
+
+    //Guild has to be reserved with reserve_guild first
     pub fn connect_and_play(
         &self,
-        audio_path: String,
-        use_map_server: bool,
+        channel: VoiceChannel,
+        input: Input,
     ) -> anyhow::Result<TrackHandle> {
         let songbird = self.songbird.clone();
-        let options = self.options.clone();
-
-        let handler_lock = songbird.get(self.options.guild_id);
-        if handler_lock.is_some() {
-            return Err(anyhow!("Discord client connected to a channel"));
-        }
 
         let join_handle: anyhow::Result<TrackHandle> =
             TOKIO_RUNTIME.handle().clone().block_on(async move {
-                let join_result = songbird.join(options.guild_id, options.channel_id).await;
+                let join_result = songbird.join(channel.guild_id, channel.channel_id).await;
                 let handler_lock = join_result?;
                 let mut handler = handler_lock.lock().await;
 
-                let input = File::new(audio_path);
-                let track = handler.play_input(input.into());
-
-                if use_map_server {
-                    track.pause()?;
-                } else {
-                    track.make_playable_async().await?;
-                }
+                //Audio sink starts paused, the audio thread calls play together with the clock
+                let track = handler.play_input(input);
+                track.pause()?;
                 Ok(track)
             });
         join_handle
     }
 
-    pub fn leave_channel(&self) -> anyhow::Result<()> {
+    pub fn leave_channel(&self, guild_id: NonZeroU64) -> anyhow::Result<()> {
         let songbird = self.songbird.clone();
-        let options = self.options.clone();
 
         TOKIO_RUNTIME.handle().clone().spawn(async move {
-            let handler_lock = songbird.get(options.guild_id);
+            let handler_lock = songbird.get(guild_id);
 
             if handler_lock.is_none() {
                 println!("[ProjectAyaya] Discord bot not connected! Cannot leave channel!");
                 return;
             }
 
-            if let Err(err) = songbird.remove(options.guild_id).await {
+            if let Err(err) = songbird.remove(guild_id).await {
                 println!("[ProjectAyaya] Cannot leave discord audio channel! Err: {err:?}");
             }
         });
@@ -82,15 +111,176 @@ impl DiscordClient {
         Ok(())
     }
 
-    pub fn is_used() -> anyhow::Result<bool> {
-        let client = DISCORD_CLIENT.get();
+    //Voice connection that is still joining or leaving counts as used
+    pub fn is_used(&self, guild_id: NonZeroU64) -> anyhow::Result<bool> {
+        Ok(self.lock_sessions()?.is_guild_used(guild_id) || self.songbird.get(guild_id).is_some())
+    }
+
+    //Check and reservation happen under one lock, so two screens can't join the same guild
+    pub fn reserve_guild(&self, guild_id: NonZeroU64) -> anyhow::Result<()> {
+        let mut sessions = self.lock_sessions()?;
+
+        if self.songbird.get(guild_id).is_some() {
+            return Err(anyhow!(
+                "Discord client connected to a channel of guild {guild_id}"
+            ));
+        }
+        sessions.reserve(guild_id)
+    }
+
+    //Undoes reserve_guild when the player could not be created. The channel might be joined already
+    pub fn release_guild(&self, guild_id: NonZeroU64) -> anyhow::Result<()> {
+        self.lock_sessions()?.cancel_reservation(guild_id);
+
+        if self.songbird.get(guild_id).is_some() {
+            self.leave_channel(guild_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn register_session(
+        &self,
+        handle: i64,
+        channel: VoiceChannel,
+        title: String,
+    ) -> anyhow::Result<()> {
+        let mut sessions = self.lock_sessions()?;
+        sessions.insert(handle, channel)?;
+        sessions.set_title(handle, title);
+        Ok(())
+    }
+
+    pub fn unregister_session(&self, handle: i64) -> anyhow::Result<()> {
+        self.lock_sessions()?.remove(handle);
+        Ok(())
+    }
+
+    //Players are retained while the sessions are locked, destroy unregisters before freeing them.
+    //Lock is released before the players are used, so the gateway does not wait for a frame
+    pub fn handle_command(&self, event: &CommandEvent) -> String {
+        let (sessions, players) = match self.lock_sessions() {
+            Ok(sessions) => (sessions.clone(), NativePlayers::retain(&sessions)),
+            Err(err) => return format!("Error: {err}"),
+        };
+
+        discord_commands::respond(&sessions, &players, event)
+    }
+}
+
+//Player handles are the pointers of player_context
+struct NativePlayers {
+    players: HashMap<i64, Arc<Mutex<Box<dyn VideoPlayer>>>>,
+}
+
+impl NativePlayers {
+    fn retain(sessions: &VoiceSessions) -> Self {
+        let players = sessions
+            .handles()
+            .map(|handle| (handle, player_context::retain(handle)))
+            .collect();
 
-        let client = match client {
+        Self { players }
+    }
+
+    fn lock(&self, handle: i64) -> anyhow::Result<MutexGuard<'_, Box<dyn VideoPlayer>>> {
+        let player = match self.players.get(&handle) {
             Some(val) => val,
-            None => return Err(anyhow!("Discord client not initialized")),
+            None => return Err(anyhow!("Player {handle} is not registered")),
         };
 
-        Ok(client.songbird.get(client.options.guild_id).is_some())
+        match player.lock() {
+            Ok(val) => Ok(val),
+            Err(_) => Err(anyhow!("Cannot lock player!")),
+        }
+    }
+}
+
+impl PlayerControl for NativePlayers {
+    fn send(&self, handle: i64, msg: NativeCommunication) -> anyhow::Result<()> {
+        self.lock(handle)?.handle_jvm_msg(msg)
+    }
+
+    fn status(&self, handle: i64) -> anyhow::Result<PlaybackStatus> {
+        let player = self.lock(handle)?;
+        let clock = match player.clock() {
+            Some(val) => val,
+            None => return Err(anyhow!("This player is not synchronized to a clock")),
+        };
+        let video_data = player.video_data()?;
+
+        Ok(PlaybackStatus {
+            position: clock.position(),
+            started: clock.is_started(),
+            playing: clock.is_playing(),
+            width: video_data.width,
+            height: video_data.height,
+            fps: video_data.fps,
+        })
+    }
+}
+
+struct CommandHandler;
+
+#[async_trait]
+impl EventHandler for CommandHandler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        let commands: Vec<CreateCommand> = COMMANDS
+            .iter()
+            .map(|command| {
+                let builder = CreateCommand::new(command.name).description(command.description);
+                match command.takes_second {
+                    true => builder.add_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            SECOND_OPTION,
+                            "Second of the video",
+                        )
+                        .min_int_value(0)
+                        .required(true),
+                    ),
+                    false => builder,
+                }
+            })
+            .collect();
+
+        //Guild commands show up instantly, global ones can take an hour
+        for guild in ready.guilds {
+            if let Err(err) = guild.id.set_commands(&ctx.http, commands.clone()).await {
+                println!(
+                    "[ProjectAyaya] Cannot register slash commands in guild {}! Err: {err:?}",
+                    guild.id
+                );
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Interaction::Command(command) = interaction else {
+            return;
+        };
+
+        let event = CommandEvent {
+            guild_id: command.guild_id.map(|guild_id| guild_id.get()),
+            name: command.data.name.clone(),
+            second: command
+                .data
+                .options
+                .iter()
+                .find(|option| option.name == SECOND_OPTION)
+                .and_then(|option| option.value.as_i64()),
+        };
+
+        let content = match DiscordClient::get() {
+            Ok(client) => client.handle_command(&event),
+            Err(err) => format!("Error: {err}"),
+        };
+
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new().content(content),
+        );
+        if let Err(err) = command.create_response(&ctx.http, response).await {
+            println!("[ProjectAyaya] Cannot respond to slash command! Err: {err:?}");
+        }
     }
 }
 
@@ -106,6 +296,7 @@ pub fn init(options: &DiscordOptions) -> anyhow::Result<()> {
         let songbird = Songbird::serenity();
 
         let mut client = Client::builder(&options_clone.discord_token, intents)
+            .event_handler(CommandHandler)
             .register_songbird_with(songbird.clone())
             .await
             .expect("Err creating client");
@@ -113,6 +304,7 @@ pub fn init(options: &DiscordOptions) -> anyhow::Result<()> {
         let discord_static_client = DiscordClient {
             songbird,
             options: options_clone.clone(),
+            sessions: Mutex::new(VoiceSessions::default()),
         };
 
         if DISCORD_CLIENT.set(discord_static_client).is_err() {
@@ -129,80 +321,236 @@ pub fn init(options: &DiscordOptions) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub struct DiscordPlayer {
-    inner: Box<dyn VideoPlayer>,
-    track_handle: TrackHandle,
-    use_map_server: bool,
+struct TrackPosition {
+    position: Duration,
+    sampled_at: Instant,
+    playing: bool,
 }
 
-impl VideoPlayer for DiscordPlayer {
-    fn create(_file_name: String, _server_options: ServerOptions) -> anyhow::Result<Self>
-    where
-        Self: Sized,
-    {
-        return Err(anyhow!("Please use the other init function!"));
+//Drives the master clock with the position of the discord track
+pub struct TrackClockSource {
+    last_position: Mutex<Option<TrackPosition>>,
+    //Track position, video position and played silence at the last seek. The track does not know
+    //about seeks, it just plays whatever is in the PCM buffer
+    seek_base: Mutex<(Duration, Duration, Duration)>,
+    buffer: Arc<PcmBuffer>,
+}
+
+impl TrackClockSource {
+    fn spawn(track_handle: TrackHandle, buffer: Arc<PcmBuffer>) -> Arc<Self> {
+        let source = Arc::new(Self {
+            last_position: Mutex::new(None),
+            seek_base: Mutex::new((Duration::ZERO, Duration::ZERO, Duration::ZERO)),
+            buffer,
+        });
+        let weak_source: Weak<Self> = Arc::downgrade(&source);
+
+        TOKIO_RUNTIME.handle().spawn(async move {
+            let mut interval = tokio::time::interval(TRACK_POLL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                //Sink was dropped
+                let Some(source) = weak_source.upgrade() else {
+                    break;
+                };
+
+                //Track ended or was removed
+                let Ok(info) = track_handle.get_info().await else {
+                    break;
+                };
+
+                if let Ok(mut last_position) = source.last_position.lock() {
+                    *last_position = Some(TrackPosition {
+                        position: info.position,
+                        sampled_at: Instant::now(),
+                        playing: info.playing == PlayMode::Play,
+                    });
+                }
+            }
+        });
+
+        source
     }
 
-    fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
-        self.inner.load_frame()
+    fn track_position(&self) -> Option<Duration> {
+        let last_position = self.last_position.lock().ok()?;
+        let last_position = last_position.as_ref()?;
+
+        match last_position.playing {
+            true => Some(last_position.position + last_position.sampled_at.elapsed()),
+            false => Some(last_position.position),
+        }
     }
 
-    fn video_data(&self) -> anyhow::Result<VideoData> {
-        self.inner.video_data()
+    fn rebase(&self, position: Duration) {
+        let track_position = self.track_position().unwrap_or_default();
+        if let Ok(mut seek_base) = self.seek_base.lock() {
+            *seek_base = (track_position, position, self.buffer.silence());
+        }
     }
+}
 
-    fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
-        match msg {
-            NativeCommunication::StartRendering { .. } => {
-                self.track_handle.play()?;
-                if !self.use_map_server {
-                    return Ok(());
-                }
-            }
-            NativeCommunication::StopRendering { .. } => {
-                self.track_handle.pause()?;
-                if !self.use_map_server {
-                    return Ok(());
-                }
-            }
-            NativeCommunication::VideoSeek { second } => {
-                self.track_handle
-                    .seek(Duration::from_secs(second as u64))
-                    .result()?;
-            }
-            _ => {}
-        };
+impl ClockSource for TrackClockSource {
+    //Silence played while the buffer was empty moves the track, not the audio, so it is not counted
+    fn position(&self) -> Option<Duration> {
+        let track_position = self.track_position()?;
+        let (base_track_position, base_position, base_silence) = *self.seek_base.lock().ok()?;
+        let silence = self.buffer.silence().saturating_sub(base_silence);
 
-        self.inner.handle_jvm_msg(msg)
+        let played = track_position.saturating_sub(base_track_position);
+
+        Some(base_position + played.saturating_sub(silence))
     }
+}
 
-    fn destroy(&self) -> anyhow::Result<()> {
-        if let Some(discord_client) = DISCORD_CLIENT.get() {
-            discord_client.leave_channel()?;
-        };
+//PCM that is waiting for songbird. Closed when the sink is finished
+struct PcmBuffer {
+    data: Mutex<(VecDeque<u8>, bool)>,
+    //Bytes of silence given to songbird because the buffer was empty
+    silent_bytes: AtomicU64,
+}
 
-        self.inner.destroy()
+impl PcmBuffer {
+    //Bytes of one f32 sample of every channel
+    fn frame_bytes() -> usize {
+        4 * PCM_CHANNELS as usize
+    }
+
+    fn silence(&self) -> Duration {
+        let frames = self.silent_bytes.load(Ordering::Relaxed) / Self::frame_bytes() as u64;
+        Duration::from_secs_f64(frames as f64 / PCM_SAMPLE_RATE as f64)
     }
 }
 
-impl DiscordPlayer {
-    #[allow(unused)]
-    pub fn create_with_discord(
-        filename: String,
-        player: Box<dyn VideoPlayer>,
-        use_map_server: bool,
-    ) -> anyhow::Result<Self> {
-        let discord_client = match DISCORD_CLIENT.get() {
-            Some(val) => val,
-            None => return Err(anyhow!("Discord client not initialized")),
+//Songbird reads raw f32 PCM from here
+struct PcmPipe {
+    buffer: Arc<PcmBuffer>,
+}
+
+impl Read for PcmPipe {
+    //Called from the songbird mixer, which mixes every voice connection of the process, so it
+    //can not wait for the decoder. An empty buffer plays silence until more PCM is written
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut data = self
+            .buffer
+            .data
+            .lock()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Cannot lock PCM buffer"))?;
+
+        if data.0.is_empty() && !data.1 {
+            //Whole frames, so the channels are not swapped when PCM comes back
+            let len = match buf.len() - buf.len() % PcmBuffer::frame_bytes() {
+                0 => buf.len(),
+                len => len,
+            };
+            buf[..len].fill(0);
+            self.buffer
+                .silent_bytes
+                .fetch_add(len as u64, Ordering::Relaxed);
+            return Ok(len);
+        }
+
+        //Empty and closed is the end of the stream
+        let len = buf.len().min(data.0.len());
+        for (target, byte) in buf.iter_mut().zip(data.0.drain(..len)) {
+            *target = byte;
+        }
+        Ok(len)
+    }
+}
+
+impl Seek for PcmPipe {
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "PCM pipe is not seekable",
+        ))
+    }
+}
+
+impl MediaSource for PcmPipe {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Plays audio in the discord voice channel
+pub struct DiscordSink {
+    channel: VoiceChannel,
+    track_handle: TrackHandle,
+    buffer: Arc<PcmBuffer>,
+    source: Arc<TrackClockSource>,
+}
+
+impl DiscordSink {
+    pub fn connect(channel: VoiceChannel) -> anyhow::Result<Self> {
+        let discord_client = DiscordClient::get()?;
+
+        let buffer = Arc::new(PcmBuffer {
+            data: Mutex::new((VecDeque::new(), false)),
+            silent_bytes: AtomicU64::new(0),
+        });
+        let pipe = PcmPipe {
+            buffer: buffer.clone(),
         };
+        let input = RawAdapter::new(pipe, PCM_SAMPLE_RATE, PCM_CHANNELS as u32);
 
-        let track_handle = discord_client.connect_and_play(filename, use_map_server)?;
+        let track_handle = discord_client.connect_and_play(channel, input.into())?;
+        let source = TrackClockSource::spawn(track_handle.clone(), buffer.clone());
 
         Ok(Self {
-            inner: player,
+            channel,
             track_handle,
-            use_map_server,
+            buffer,
+            source,
         })
     }
+
+    fn lock_buffer(&self) -> anyhow::Result<MutexGuard<'_, (VecDeque<u8>, bool)>> {
+        match self.buffer.data.lock() {
+            Ok(val) => Ok(val),
+            Err(_) => Err(anyhow!("Cannot lock PCM buffer")),
+        }
+    }
+}
+
+impl AudioSink for DiscordSink {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        let mut data = self.lock_buffer()?;
+        data.0
+            .extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        Ok(())
+    }
+
+    fn play(&mut self) -> anyhow::Result<()> {
+        self.track_handle.play()?;
+        Ok(())
+    }
+
+    fn pause(&mut self) -> anyhow::Result<()> {
+        self.track_handle.pause()?;
+        Ok(())
+    }
+
+    fn seek(&mut self, position: Duration) -> anyhow::Result<()> {
+        self.lock_buffer()?.0.clear();
+        self.source.rebase(position);
+        Ok(())
+    }
+
+    fn clock_source(&self) -> Option<Arc<dyn ClockSource>> {
+        Some(self.source.clone())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.lock_buffer()?.1 = true;
+
+        DiscordClient::get()?.leave_channel(self.channel.guild_id)
+    }
 }
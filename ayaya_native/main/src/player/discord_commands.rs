@@ -0,0 +1,368 @@
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use super::player_context::NativeCommunication;
+use super::voice_sessions::VoiceSessions;
+
+pub static SECOND_OPTION: &str = "second";
+
+pub struct CommandDefinition {
+    pub name: &'static str,
+    pub description: &'static str,
+    //Command has a required integer SECOND_OPTION
+    pub takes_second: bool,
+}
+
+pub static COMMANDS: [CommandDefinition; 5] = [
+    CommandDefinition {
+        name: "play",
+        description: "Resume the video of this guild",
+        takes_second: false,
+    },
+    CommandDefinition {
+        name: "pause",
+        description: "Pause the video of this guild",
+        takes_second: false,
+    },
+    CommandDefinition {
+        name: "seek",
+        description: "Jump to a second of the video",
+        takes_second: true,
+    },
+    CommandDefinition {
+        name: "queue",
+        description: "Show videos playing in this guild",
+        takes_second: false,
+    },
+    CommandDefinition {
+        name: "nowplaying",
+        description: "Show the current video and position",
+        takes_second: false,
+    },
+];
+
+/// Slash command interaction, as delivered by the gateway
+#[derive(Debug, Clone)]
+pub struct CommandEvent {
+    pub guild_id: Option<u64>,
+    pub name: String,
+    pub second: Option<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SlashCommand {
+    Play,
+    Pause,
+    Seek { second: i32 },
+    Queue,
+    NowPlaying,
+}
+
+impl SlashCommand {
+    pub fn parse(event: &CommandEvent) -> anyhow::Result<Self> {
+        match event.name.as_str() {
+            "play" => Ok(Self::Play),
+            "pause" => Ok(Self::Pause),
+            "seek" => {
+                let second = match event.second {
+                    Some(val) => val,
+                    None => return Err(anyhow!("Missing {SECOND_OPTION} option")),
+                };
+
+                match i32::try_from(second) {
+                    Ok(second) if second >= 0 => Ok(Self::Seek { second }),
+                    _ => Err(anyhow!("Invalid second ({second})")),
+                }
+            }
+            "queue" => Ok(Self::Queue),
+            "nowplaying" => Ok(Self::NowPlaying),
+            name => Err(anyhow!("Unknown command /{name}")),
+        }
+    }
+}
+
+pub struct PlaybackStatus {
+    pub position: Duration,
+    //Not started players are still waiting for the server to send StartRendering
+    pub started: bool,
+    pub playing: bool,
+    pub width: i32,
+    pub height: i32,
+    pub fps: i32,
+}
+
+/// Players that the commands are routed to, by player handle
+pub trait PlayerControl {
+    fn send(&self, handle: i64, msg: NativeCommunication) -> anyhow::Result<()>;
+    fn status(&self, handle: i64) -> anyhow::Result<PlaybackStatus>;
+}
+
+fn format_position(position: Duration) -> String {
+    let seconds = position.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+//Errors are replied too, so the user knows why nothing happened
+pub fn respond(
+    sessions: &VoiceSessions,
+    players: &dyn PlayerControl,
+    event: &CommandEvent,
+) -> String {
+    match route(sessions, players, event) {
+        Ok(val) => val,
+        Err(err) => format!("Error: {err}"),
+    }
+}
+
+fn route(
+    sessions: &VoiceSessions,
+    players: &dyn PlayerControl,
+    event: &CommandEvent,
+) -> anyhow::Result<String> {
+    let command = SlashCommand::parse(event)?;
+
+    let guild_id = match event.guild_id.and_then(NonZeroU64::new) {
+        Some(val) => val,
+        None => return Err(anyhow!("Commands can only be used in a guild")),
+    };
+
+    let handle = match sessions.handle_for_guild(guild_id) {
+        Some(val) => val,
+        None => return Ok("Nothing is playing in this guild".to_string()),
+    };
+    let title = sessions.title(handle).unwrap_or("Unknown video");
+    let status = players.status(handle)?;
+
+    //State is checked first, so the reply tells if nothing changed
+    match command {
+        SlashCommand::Play => {
+            if !status.started {
+                return Ok(format!("{title} was not started by the server yet"));
+            }
+            if status.playing {
+                return Ok(format!("{title} is already playing"));
+            }
+
+            players.send(
+                handle,
+                NativeCommunication::StartRendering { fps: status.fps },
+            )?;
+            Ok(format!(
+                "Resumed {title} at {}",
+                format_position(status.position)
+            ))
+        }
+        SlashCommand::Pause => {
+            if !status.playing {
+                return Ok(format!("{title} is not playing"));
+            }
+
+            players.send(handle, NativeCommunication::StopRendering)?;
+            Ok(format!(
+                "Paused {title} at {}",
+                format_position(status.position)
+            ))
+        }
+        SlashCommand::Seek { second } => {
+            players.send(handle, NativeCommunication::VideoSeek { second })?;
+            Ok(format!(
+                "Moved {title} to {}",
+                format_position(Duration::from_secs(second as u64))
+            ))
+        }
+        //Upcoming videos live in the plugin, native side only knows the current one
+        SlashCommand::Queue => Ok(format!(
+            "1. {title} ({} played)",
+            format_position(status.position)
+        )),
+        SlashCommand::NowPlaying => {
+            let state = match status.playing {
+                true => "Playing",
+                false => "Paused",
+            };
+
+            Ok(format!(
+                "{state} {title} at {} ({}x{}, {} fps)",
+                format_position(status.position),
+                status.width,
+                status.height,
+                status.fps
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use crate::player::voice_sessions::VoiceChannel;
+
+    use super::*;
+
+    struct StubPlayer {
+        position: Duration,
+        started: bool,
+        playing: bool,
+        messages: Vec<NativeCommunication>,
+    }
+
+    #[derive(Default)]
+    struct StubPlayers {
+        players: RefCell<HashMap<i64, StubPlayer>>,
+    }
+
+    impl PlayerControl for StubPlayers {
+        fn send(&self, handle: i64, msg: NativeCommunication) -> anyhow::Result<()> {
+            let mut players = self.players.borrow_mut();
+            let player = players.get_mut(&handle).unwrap();
+
+            match msg {
+                NativeCommunication::StartRendering { .. } => player.playing = true,
+                NativeCommunication::StopRendering => player.playing = false,
+                NativeCommunication::VideoSeek { second } => {
+                    player.position = Duration::from_secs(second as u64)
+                }
+                _ => {}
+            }
+            player.messages.push(msg);
+            Ok(())
+        }
+
+        fn status(&self, handle: i64) -> anyhow::Result<PlaybackStatus> {
+            let players = self.players.borrow();
+            let player = players.get(&handle).unwrap();
+
+            Ok(PlaybackStatus {
+                position: player.position,
+                started: player.started,
+                playing: player.playing,
+                width: 256,
+                height: 128,
+                fps: 30,
+            })
+        }
+    }
+
+    //Delivers interactions the way the gateway would
+    struct StubGateway {
+        sessions: VoiceSessions,
+        players: StubPlayers,
+    }
+
+    impl StubGateway {
+        fn new() -> Self {
+            let mut sessions = VoiceSessions::default();
+            let players = StubPlayers::default();
+
+            for (handle, guild_id, title) in [(1, 10, "op.mp4"), (2, 20, "episode.mp4")] {
+                sessions
+                    .insert(handle, VoiceChannel::new(guild_id, 100).unwrap())
+                    .unwrap();
+                sessions.set_title(handle, title.to_string());
+                players.players.borrow_mut().insert(
+                    handle,
+                    StubPlayer {
+                        position: Duration::from_secs(75),
+                        started: true,
+                        playing: true,
+                        messages: Vec::new(),
+                    },
+                );
+            }
+
+            Self { sessions, players }
+        }
+
+        fn interaction(&self, guild_id: Option<u64>, name: &str, second: Option<i64>) -> String {
+            let event = CommandEvent {
+                guild_id,
+                name: name.to_string(),
+                second,
+            };
+            respond(&self.sessions, &self.players, &event)
+        }
+
+        fn messages(&self, handle: i64) -> usize {
+            self.players.players.borrow()[&handle].messages.len()
+        }
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let event = |name: &str, second| CommandEvent {
+            guild_id: Some(1),
+            name: name.to_string(),
+            second,
+        };
+
+        assert!(SlashCommand::parse(&event("play", None)).unwrap() == SlashCommand::Play);
+        assert!(
+            SlashCommand::parse(&event("seek", Some(90))).unwrap()
+                == SlashCommand::Seek { second: 90 }
+        );
+        assert!(SlashCommand::parse(&event("seek", None)).is_err());
+        assert!(SlashCommand::parse(&event("seek", Some(-1))).is_err());
+        assert!(SlashCommand::parse(&event("seek", Some(i64::MAX))).is_err());
+        assert!(SlashCommand::parse(&event("skip", None)).is_err());
+
+        //Every registered command can be parsed. Discord limits descriptions to 100 characters
+        for command in COMMANDS.iter() {
+            assert!(!command.description.is_empty() && command.description.len() <= 100);
+            let second = command.takes_second.then_some(1);
+            assert!(SlashCommand::parse(&event(command.name, second)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_route_to_guild_player() {
+        let gateway = StubGateway::new();
+
+        assert!(gateway.interaction(Some(10), "pause", None) == "Paused op.mp4 at 1:15");
+        assert!(gateway.interaction(Some(10), "pause", None) == "op.mp4 is not playing");
+        assert!(gateway.interaction(Some(10), "seek", Some(605)) == "Moved op.mp4 to 10:05");
+        assert!(
+            gateway.interaction(Some(10), "nowplaying", None)
+                == "Paused op.mp4 at 10:05 (256x128, 30 fps)"
+        );
+        assert!(gateway.interaction(Some(10), "play", None) == "Resumed op.mp4 at 10:05");
+        assert!(gateway.interaction(Some(10), "play", None) == "op.mp4 is already playing");
+        assert!(gateway.interaction(Some(20), "queue", None) == "1. episode.mp4 (1:15 played)");
+
+        //Only the player of the guild got messages
+        assert!(gateway.messages(1) == 3 && gateway.messages(2) == 0);
+        let players = gateway.players.players.borrow();
+        assert!(matches!(
+            players[&1].messages[..],
+            [
+                NativeCommunication::StopRendering,
+                NativeCommunication::VideoSeek { second: 605 },
+                NativeCommunication::StartRendering { fps: 30 }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_route_errors() {
+        let gateway = StubGateway::new();
+        let mut players = gateway.players.players.borrow_mut();
+        let waiting = players.get_mut(&2).unwrap();
+        (waiting.started, waiting.playing) = (false, false);
+        drop(players);
+
+        assert!(gateway.interaction(Some(30), "play", None) == "Nothing is playing in this guild");
+        assert!(
+            gateway.interaction(None, "play", None)
+                == "Error: Commands can only be used in a guild"
+        );
+        assert!(gateway.interaction(Some(10), "seek", None) == "Error: Missing second option");
+        assert!(
+            gateway.interaction(Some(20), "play", None)
+                == "episode.mp4 was not started by the server yet"
+        );
+        assert!(gateway.messages(1) == 0 && gateway.messages(2) == 0);
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::anyhow;
+
+//Players are numbered from 0, the short JVM input belongs to player 0
+pub type PlayerId = u8;
+
+/// Keys with a held state. Movement keys are relative to the screen, not to the world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Forward,
+    Backwards,
+    Left,
+    Right,
+    Jump,
+    Sneak,
+    Sprint,
+}
+
+/// Buttons that only fire once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Attack,
+    Use,
+    Drop,
+    SwapHands,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    KeyDown(Key),
+    KeyUp(Key),
+    Action(Action),
+    //Selected hotbar slot (0-8)
+    Hotbar(u8),
+    //Camera rotation since the last look event, in degrees
+    Look { yaw: f32, pitch: f32 },
+}
+
+/// Everything the plugin sends for a player. Join and leave are handled by GamePlayer,
+/// input of players that did not join is dropped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEvent {
+    Join,
+    Leave,
+    Input(InputEvent),
+}
+
+impl Key {
+    fn from_id(id: u8) -> anyhow::Result<Self> {
+        match id {
+            0 => Ok(Self::Forward),
+            1 => Ok(Self::Backwards),
+            2 => Ok(Self::Left),
+            3 => Ok(Self::Right),
+            4 => Ok(Self::Jump),
+            5 => Ok(Self::Sneak),
+            6 => Ok(Self::Sprint),
+            _ => Err(anyhow!("Invalid game key ({id})")),
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Self::Forward => 0,
+            Self::Backwards => 1,
+            Self::Left => 2,
+            Self::Right => 3,
+            Self::Jump => 4,
+            Self::Sneak => 5,
+            Self::Sprint => 6,
+        }
+    }
+}
+
+impl Action {
+    fn from_id(id: u8) -> anyhow::Result<Self> {
+        match id {
+            0 => Ok(Self::Attack),
+            1 => Ok(Self::Use),
+            2 => Ok(Self::Drop),
+            3 => Ok(Self::SwapHands),
+            _ => Err(anyhow!("Invalid game action ({id})")),
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Self::Attack => 0,
+            Self::Use => 1,
+            Self::Drop => 2,
+            Self::SwapHands => 3,
+        }
+    }
+}
+
+/// Events sent by NativeRenderControler.sendGameInput. Every event is a player id byte, a type byte
+/// and its payload: key down (0) and key up (1) have a key byte, action (2) an action byte,
+/// hotbar (3) a slot byte and look (4) yaw and pitch as big endian floats. Join (5) and leave (6)
+/// have no payload
+pub fn decode_events(mut data: &[u8]) -> anyhow::Result<Vec<(PlayerId, GameEvent)>> {
+    let mut events = Vec::with_capacity(data.len() / 3);
+
+    while !data.is_empty() {
+        let (header, rest) = split(data, 2)?;
+        let (player_id, event_type) = (header[0], header[1]);
+
+        let (payload_length, event) = match event_type {
+            5 => (0, GameEvent::Join),
+            6 => (0, GameEvent::Leave),
+            _ => {
+                let (payload_length, event) = decode_input(event_type, rest)?;
+                (payload_length, GameEvent::Input(event))
+            }
+        };
+
+        events.push((player_id, event));
+        data = &rest[payload_length..];
+    }
+
+    Ok(events)
+}
+
+//Payload length and the event
+fn decode_input(event_type: u8, rest: &[u8]) -> anyhow::Result<(usize, InputEvent)> {
+    let input = match event_type {
+        0 => (1, InputEvent::KeyDown(Key::from_id(first(rest)?)?)),
+        1 => (1, InputEvent::KeyUp(Key::from_id(first(rest)?)?)),
+        2 => (1, InputEvent::Action(Action::from_id(first(rest)?)?)),
+        3 => {
+            let slot = first(rest)?;
+            if slot > 8 {
+                return Err(anyhow!("Invalid hotbar slot ({slot})"));
+            }
+            (1, InputEvent::Hotbar(slot))
+        }
+        4 => {
+            let payload = split(rest, 8)?.0;
+            let yaw = f32::from_be_bytes(payload[0..4].try_into()?);
+            let pitch = f32::from_be_bytes(payload[4..8].try_into()?);
+            if !yaw.is_finite() || !pitch.is_finite() {
+                return Err(anyhow!("Invalid look delta"));
+            }
+            (8, InputEvent::Look { yaw, pitch })
+        }
+        _ => return Err(anyhow!("Invalid game input type ({event_type})")),
+    };
+
+    Ok(input)
+}
+
+fn split(data: &[u8], length: usize) -> anyhow::Result<(&[u8], &[u8])> {
+    match data.len() >= length {
+        true => Ok(data.split_at(length)),
+        false => Err(anyhow!("Game input is truncated")),
+    }
+}
+
+fn first(data: &[u8]) -> anyhow::Result<u8> {
+    Ok(split(data, 1)?.0[0])
+}
+
+//Inverse of decode_events
+pub fn encode_events(events: &[(PlayerId, GameEvent)]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(events.len() * 3);
+
+    for (player_id, event) in events {
+        data.push(*player_id);
+        match event {
+            GameEvent::Join => data.push(5),
+            GameEvent::Leave => data.push(6),
+            GameEvent::Input(InputEvent::KeyDown(key)) => data.extend([0, key.id()]),
+            GameEvent::Input(InputEvent::KeyUp(key)) => data.extend([1, key.id()]),
+            GameEvent::Input(InputEvent::Action(action)) => data.extend([2, action.id()]),
+            GameEvent::Input(InputEvent::Hotbar(slot)) => data.extend([3, *slot]),
+            GameEvent::Input(InputEvent::Look { yaw, pitch }) => {
+                data.push(4);
+                data.extend(yaw.to_be_bytes());
+                data.extend(pitch.to_be_bytes());
+            }
+        }
+    }
+
+    data
+}
+
+/// Old "F_B_L_R_U" input of GAME_INPUT. Every move is a press and a release of the key by player 0
+pub fn parse_short_input(input: &str) -> anyhow::Result<Vec<(PlayerId, GameEvent)>> {
+    let mut events = Vec::with_capacity(8);
+    for val in input.split('_') {
+        let key = match val {
+            "F" => Key::Forward,
+            "B" => Key::Backwards,
+            "L" => Key::Left,
+            "R" => Key::Right,
+            "U" => Key::Jump,
+            "" => continue,
+            _ => return Err(anyhow!("Invalid short game input")),
+        };
+        events.push((0, GameEvent::Input(InputEvent::KeyDown(key))));
+        events.push((0, GameEvent::Input(InputEvent::KeyUp(key))));
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_events() {
+        let data = [
+            0, 0, 4, //Player 0 jumps
+            3, 1, 5, //Player 3 stops sneaking
+            1, 2, 0, //Player 1 attacks
+            0, 3, 8, //Last hotbar slot
+            2, 4, 0x3f, 0x80, 0, 0, 0xc0, 0, 0, 0, //Yaw 1, pitch -2
+            4, 5, //Player 4 joins
+            1, 6, //Player 1 leaves
+        ];
+        let events = decode_events(&data).unwrap();
+        assert!(
+            events
+                == [
+                    (0, GameEvent::Input(InputEvent::KeyDown(Key::Jump))),
+                    (3, GameEvent::Input(InputEvent::KeyUp(Key::Sneak))),
+                    (1, GameEvent::Input(InputEvent::Action(Action::Attack))),
+                    (0, GameEvent::Input(InputEvent::Hotbar(8))),
+                    (
+                        2,
+                        GameEvent::Input(InputEvent::Look {
+                            yaw: 1.0,
+                            pitch: -2.0
+                        })
+                    ),
+                    (4, GameEvent::Join),
+                    (1, GameEvent::Leave),
+                ]
+        );
+        assert!(encode_events(&events) == data);
+        assert!(decode_events(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        //Truncated header and payloads
+        assert!(decode_events(&[0]).is_err());
+        assert!(decode_events(&[0, 0]).is_err());
+        assert!(decode_events(&[0, 4, 0, 0, 0, 0]).is_err());
+
+        assert!(decode_events(&[0, 7, 0]).is_err());
+        assert!(decode_events(&[0, 0, 7]).is_err());
+        assert!(decode_events(&[0, 2, 4]).is_err());
+        assert!(decode_events(&[0, 3, 9]).is_err());
+        //NaN yaw
+        assert!(decode_events(&[0, 4, 0x7f, 0xc0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_parse_short_input() {
+        let events = parse_short_input("_F_U").unwrap();
+        assert!(
+            events
+                == [
+                    (0, GameEvent::Input(InputEvent::KeyDown(Key::Forward))),
+                    (0, GameEvent::Input(InputEvent::KeyUp(Key::Forward))),
+                    (0, GameEvent::Input(InputEvent::KeyDown(Key::Jump))),
+                    (0, GameEvent::Input(InputEvent::KeyUp(Key::Jump))),
+                ]
+        );
+        assert!(parse_short_input("F_Q").is_err());
+    }
+}
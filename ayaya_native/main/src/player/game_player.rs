@@ -1,150 +1,651 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::sync::{
     mpsc::{channel, Receiver, Sender},
-    Arc,
+    Arc, Mutex, Weak,
 };
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
-use font_kit::{
-    canvas::{Canvas, Format, RasterizationOptions},
-    handle::Handle,
-    hinting::HintingOptions,
-};
+use font_kit::handle::Handle;
 use once_cell::sync::Lazy;
-use pathfinder_geometry::{
-    transform2d::Transform2F,
-    vector::{Vector2F, Vector2I},
-};
 
+use super::game_input::{GameEvent, InputEvent, Key, PlayerId};
+use super::game_replay::{GameRecorder, GameReplay, RecordingHeader};
 use super::player_context::{wrap_frame, NativeCommunication, VideoData, VideoFrame, VideoPlayer};
+use super::sprite::Sprite;
+use super::text::{TextLayout, TextMetrics, TextStyle};
 use crate::{
-    apps::{falling_blocks::FallingBlocks, snake::SnakeGame},
-    colorlib::Color,
+    apps,
+    assets::AssetLoader,
+    colorlib::{map_index_to_color, Color},
     map_server::ServerOptions,
     splitting::SplittedFrame,
 };
 
+//Per player, one tick never needs more
+static MAX_QUEUED_INPUT: usize = 256;
+static BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
 #[allow(dead_code)]
 static DEFAULT_FONT_BYTES: &[u8] = include_bytes!("../../WorkSans-VariableFont_wght.ttf");
 #[allow(dead_code)]
-static DEFAULT_FONT: Lazy<Handle> =
+pub(crate) static DEFAULT_FONT: Lazy<Handle> =
     Lazy::new(|| Handle::from_memory(Arc::new(DEFAULT_FONT_BYTES.to_vec()), 0));
 
+/// Map color canvas. Drawing outside of the canvas (or the clip rectangle) is ignored
+///
+/// Coordinates are inclusive and can be negative, so shapes may be partially off screen
+#[derive(Clone)]
 pub struct VideoCanvas {
     pub width: usize,
     pub height: usize,
     vec: Vec<u8>,
+    //x1, y1, x2, y2 (inclusive), always inside of the canvas
+    clip: (i32, i32, i32, i32),
+    //Share of the pixels that drawing writes
+    opacity: f32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub enum GameInputDirection {
-    Forward,
-    Backwards,
-    Left,
-    Right,
-    Up,
+pub enum GameState {
+    Playing { score: u32 },
+    Won { score: u32 },
+    Over { score: u32 },
+}
+
+impl GameState {
+    pub fn score(&self) -> u32 {
+        match self {
+            Self::Playing { score } | Self::Won { score } | Self::Over { score } => *score,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Self::Playing { .. })
+    }
+}
+
+/// Options a game is created with
+#[derive(Debug, Clone, Default)]
+pub struct GameConfig {
+    //Runtime images, AssetLoader::image_or keeps the baked ones when there are none
+    pub assets: AssetLoader,
+    //Games draw every random number from a generator seeded with it, so sessions can be replayed
+    pub seed: u64,
+}
+
+/// Splits tick durations into fixed steps, so game speed does not depend on the tick rate
+#[derive(Debug, Clone)]
+pub struct StepTimer {
+    step: Duration,
+    elapsed: Duration,
+}
+
+impl StepTimer {
+    pub fn new(step: Duration) -> Self {
+        Self {
+            step,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    //Number of steps that passed, the rest is kept for the next tick
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        self.elapsed += dt;
+        let mut steps = 0;
+        while self.elapsed >= self.step {
+            self.elapsed -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+}
+
+//Keeps huge usize coordinates outside of the canvas instead of wrapping
+fn coord(value: usize) -> i32 {
+    value.min(i32::MAX as usize) as i32
+}
+
+//First and last step of a line where one of its axes can be between low and high. The axis moves
+//distance / major pixels per step and is rounded, so one more pixel is kept on both ends
+fn clipped_steps(
+    start: i64,
+    sign: i64,
+    distance: i64,
+    major: i64,
+    low: i32,
+    high: i32,
+) -> Option<(i64, i64)> {
+    let (low, high) = (low as i64, high as i64);
+    let (low, high) = match sign {
+        0 if (low..=high).contains(&start) => return Some((0, major)),
+        0 => return None,
+        1 => (low - start, high - start),
+        _ => (start - high, start - low),
+    };
+    let (low, high, major, distance) = (low as i128, high as i128, major as i128, distance as i128);
+
+    let first = ((low - 1) * major).div_euclid(distance).max(0);
+    let last = (((high + 1) * major).div_euclid(distance) + 1).min(major);
+    (first <= last).then_some((first as i64, last as i64))
+}
+
+//X of the midpoint circle walk at step y (0 to the end of the octant), without walking the steps
+//before it. It is the largest x with x * (x - 1) + y * y < radius * radius
+fn midpoint_x(y: i64, radius: i64) -> i64 {
+    if y == 0 {
+        return radius;
+    }
+    let target = (radius as i128).pow(2) - (y as i128).pow(2);
+    if target <= 0 {
+        return 0;
+    }
+
+    //Square root of the float is only close for huge circles
+    let mut x = (target as f64).sqrt() as i128 + 1;
+    while x * (x - 1) >= target {
+        x -= 1;
+    }
+    while (x + 1) * x < target {
+        x += 1;
+    }
+    x as i64
+}
+
+//Map colors can't be mixed, so opacity is an ordered dither. Full opacity passes every pixel
+fn dithered(x: usize, y: usize, opacity: f32) -> bool {
+    (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 < opacity
 }
 
 impl VideoCanvas {
     pub fn new(width: usize, height: usize, start_color: &Color) -> Self {
         let vec: Vec<u8> = vec![start_color.convert_to_mc(); width * height];
 
-        Self { width, height, vec }
+        Self::from_vec(width, height, vec)
     }
 
-    pub fn new_from_image(image: &BakedImage) -> Self {
-        Self {
-            vec: image.data.to_vec(),
-            width: image.width as usize,
-            height: image.height as usize,
+    //Frame that is already converted to map colors
+    pub fn from_mc_frame(width: usize, height: usize, frame: &[i8]) -> Self {
+        Self::from_vec(width, height, bytemuck::cast_slice(frame).to_vec())
+    }
+
+    fn from_vec(width: usize, height: usize, vec: Vec<u8>) -> Self {
+        let mut canvas = Self {
+            width,
+            height,
+            vec,
+            clip: (0, 0, -1, -1),
+            opacity: 1.0,
+        };
+        canvas.reset_clip();
+        canvas
+    }
+
+    pub fn to_mc_frame(&self) -> Vec<i8> {
+        bytemuck::cast_slice(self.vec.as_slice()).to_vec()
+    }
+
+    //Fills the whole canvas, the clip rectangle is ignored
+    pub fn clear(&mut self, color: &Color) {
+        self.vec.fill(color.convert_to_mc());
+    }
+
+    /// Limits drawing to a rectangle (corners in any order), until reset_clip is called
+    pub fn set_clip(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
+        self.clip = (
+            x1.min(x2).max(0),
+            y1.min(y2).max(0),
+            x1.max(x2).min(coord(self.width) - 1),
+            y1.max(y2).min(coord(self.height) - 1),
+        );
+    }
+
+    pub fn reset_clip(&mut self) {
+        self.clip = (0, 0, coord(self.width) - 1, coord(self.height) - 1);
+    }
+
+    /// Draws the overlay (same size) on top of this canvas
+    ///
+    /// Map colors can't be mixed, so opacity is an ordered dither (share of copied pixels)
+    pub fn blend(&mut self, overlay: &VideoCanvas, opacity: f32) {
+        for (i, (pixel, overlay_pixel)) in self.vec.iter_mut().zip(overlay.vec.iter()).enumerate() {
+            if dithered(i % self.width, i / self.width, opacity) {
+                *pixel = *overlay_pixel;
+            }
         }
     }
 
+    /// Drawing writes only a share of the pixels (the same ones blend would copy), until
+    /// reset_opacity is called. Faded elements are drawn in place this way
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    pub fn reset_opacity(&mut self) {
+        self.opacity = 1.0;
+    }
+
+    fn put(&mut self, x: i32, y: i32, index: u8) {
+        let (x1, y1, x2, y2) = self.clip;
+        if x < x1 || y < y1 || x > x2 || y > y2 || !dithered(x as usize, y as usize, self.opacity) {
+            return;
+        }
+
+        self.vec[(y as usize * self.width) + x as usize] = index;
+    }
+
+    fn horizontal_line(&mut self, x1: i32, x2: i32, y: i32, index: u8) {
+        let (clip_x1, clip_y1, clip_x2, clip_y2) = self.clip;
+        let (x1, x2) = (x1.min(x2).max(clip_x1), x1.max(x2).min(clip_x2));
+        if y < clip_y1 || y > clip_y2 || x1 > x2 {
+            return;
+        }
+
+        if self.opacity < 1.0 {
+            for x in x1..=x2 {
+                self.put(x, y, index);
+            }
+            return;
+        }
+
+        let row = y as usize * self.width;
+        self.vec[(row + x1 as usize)..=(row + x2 as usize)].fill(index);
+    }
+
+    //Rows of the clip rectangle between y1 and y2, so huge shapes do not loop over empty rows
+    fn clipped_rows(&self, y1: i32, y2: i32) -> std::ops::RangeInclusive<i32> {
+        y1.min(y2).max(self.clip.1)..=y1.max(y2).min(self.clip.3)
+    }
+
     #[allow(dead_code)]
     pub fn draw_pixel(&mut self, x: usize, y: usize, color: &Color) {
-        self.vec[(y * self.width) + x] = color.convert_to_mc();
+        self.put(coord(x), coord(y), color.convert_to_mc());
+    }
+
+    /// Mixes color into the pixel by alpha (0 to 255), the result is the closest map color
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: &Color, alpha: u8) {
+        let (x1, y1, x2, y2) = self.clip;
+        if x < x1 || y < y1 || x > x2 || y > y2 || alpha == 0 {
+            return;
+        }
+        if !dithered(x as usize, y as usize, self.opacity) {
+            return;
+        }
+
+        let pixel = &mut self.vec[(y as usize * self.width) + x as usize];
+        let background = map_index_to_color(*pixel as i8);
+        let mix = |foreground: u8, background: u8| {
+            ((foreground as u32 * alpha as u32 + background as u32 * (255 - alpha as u32)) / 255)
+                as u8
+        };
+
+        *pixel = Color::new(
+            mix(color.red, background.red),
+            mix(color.green, background.green),
+            mix(color.blue, background.blue),
+        )
+        .convert_to_mc();
     }
 
     pub fn draw_square(&mut self, x1: usize, y1: usize, x2: usize, y2: usize, color: &Color) {
-        let x1 = x1.min(x2);
-        let x2 = x1.max(x2);
+        self.fill_rect(coord(x1), coord(y1), coord(x2), coord(y2), color);
+    }
+
+    pub fn fill_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: &Color) {
+        let index = color.convert_to_mc();
+
+        for y in self.clipped_rows(y1, y2) {
+            self.horizontal_line(x1, x2, y, index);
+        }
+    }
+
+    pub fn draw_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: &Color) {
+        let index = color.convert_to_mc();
+
+        self.horizontal_line(x1, x2, y1, index);
+        self.horizontal_line(x1, x2, y2, index);
+        for y in self.clipped_rows(y1, y2) {
+            self.put(x1, y, index);
+            self.put(x2, y, index);
+        }
+    }
+
+    /// Bresenham line, both ends are drawn
+    pub fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: &Color) {
+        let index = color.convert_to_mc();
+        let (x1, y1) = (x1 as i64, y1 as i64);
+        let (dx, dy) = ((x2 as i64 - x1).abs(), (y2 as i64 - y1).abs());
+        let (step_x, step_y) = ((x2 as i64 - x1).signum(), (y2 as i64 - y1).signum());
+        let (major, minor) = (dx.max(dy), dx.min(dy));
+
+        //Only steps that can land inside of the clip rectangle are walked
+        let (clip_x1, clip_y1, clip_x2, clip_y2) = self.clip;
+        let Some((first_x, last_x)) = clipped_steps(x1, step_x, dx, major, clip_x1, clip_x2) else {
+            return;
+        };
+        let Some((first_y, last_y)) = clipped_steps(y1, step_y, dy, major, clip_y1, clip_y2) else {
+            return;
+        };
+
+        let (minor, major) = (minor as i128, major as i128);
+        for step in first_x.max(first_y)..=last_x.min(last_y) {
+            //Same pixels as stepping with the error term, halves are rounded away from the start
+            let minor_step = ((2 * minor * step as i128 + major) / (2 * major).max(1)) as i64;
+            let (x, y) = match dx >= dy {
+                true => (x1 + step_x * step, y1 + step_y * minor_step),
+                false => (x1 + step_x * minor_step, y1 + step_y * step),
+            };
+            self.put(x as i32, y as i32, index);
+        }
+    }
+
+    /// Midpoint circle outline
+    pub fn draw_circle(&mut self, center_x: i32, center_y: i32, radius: i32, color: &Color) {
+        if radius < 0 {
+            return;
+        }
+        let (clip_x1, clip_y1, clip_x2, clip_y2) = self.clip;
+        let (center_x, center_y, radius) = (center_x as i64, center_y as i64, radius as i64);
+
+        //Circles that can't be seen are skipped
+        if center_x + radius < clip_x1 as i64
+            || center_x - radius > clip_x2 as i64
+            || center_y + radius < clip_y1 as i64
+            || center_y - radius > clip_y2 as i64
+        {
+            return;
+        }
+        //Clip rectangle inside of the circle, the outline is at least radius - 1 away
+        let farthest = |center: i64, low: i32, high: i32| {
+            (center - low as i64)
+                .abs()
+                .max((center - high as i64).abs()) as i128
+        };
+        let (far_x, far_y) = (
+            farthest(center_x, clip_x1, clip_x2),
+            farthest(center_y, clip_y1, clip_y2),
+        );
+        if radius > 1 && far_x * far_x + far_y * far_y < (radius as i128 - 1).pow(2) {
+            return;
+        }
+
+        let index = color.convert_to_mc();
+
+        //Step y draws points y away from the center on one of the axes, so only the steps
+        //that put that axis inside of the clip rectangle are walked
+        let mut steps: Vec<(i64, i64)> =
+            [(center_y, clip_y1, clip_y2), (center_x, clip_x1, clip_x2)]
+                .into_iter()
+                .flat_map(|(center, low, high)| {
+                    let (low, high) = (low as i64, high as i64);
+                    [(low - center, high - center), (center - high, center - low)]
+                })
+                .map(|(first, last)| (first.max(0), last.min(radius)))
+                .filter(|(first, last)| first <= last)
+                .collect();
+        steps.sort_unstable();
+
+        let mut next = 0;
+        for (first, last) in steps {
+            for y in first.max(next)..=last {
+                let x = midpoint_x(y, radius);
+                //End of the octant, later steps are even further
+                if x < y {
+                    return;
+                }
+
+                for (offset_x, offset_y) in [
+                    (x, y),
+                    (y, x),
+                    (-y, x),
+                    (-x, y),
+                    (-x, -y),
+                    (-y, -x),
+                    (y, -x),
+                    (x, -y),
+                ] {
+                    let (pixel_x, pixel_y) = (center_x + offset_x, center_y + offset_y);
+                    if let (Ok(pixel_x), Ok(pixel_y)) =
+                        (i32::try_from(pixel_x), i32::try_from(pixel_y))
+                    {
+                        self.put(pixel_x, pixel_y, index);
+                    }
+                }
+            }
+            next = next.max(last + 1);
+        }
+    }
 
-        let y1 = y1.min(y2);
-        let y2 = y1.max(y2);
+    pub fn fill_circle(&mut self, center_x: i32, center_y: i32, radius: i32, color: &Color) {
+        if radius < 0 {
+            return;
+        }
+        let index = color.convert_to_mc();
+        let radius = radius as i64;
+
+        let rows = self.clipped_rows(
+            (center_y as i64 - radius).max(i32::MIN as i64) as i32,
+            (center_y as i64 + radius).min(i32::MAX as i64) as i32,
+        );
+        for y in rows {
+            let offset_y = y as i64 - center_y as i64;
+            let half_width = ((radius * radius - offset_y * offset_y) as f64).sqrt() as i64;
+
+            let x1 = (center_x as i64 - half_width).max(i32::MIN as i64) as i32;
+            let x2 = (center_x as i64 + half_width).min(i32::MAX as i64) as i32;
+            self.horizontal_line(x1, x2, y, index);
+        }
+
+        //Edge pixels of the outline are not always inside of the radius
+        self.draw_circle(center_x, center_y, radius as i32, color);
+    }
+
+    /// Closed outline through every point
+    pub fn draw_polygon(&mut self, points: &[(i32, i32)], color: &Color) {
+        match points {
+            [] => {}
+            [(x, y)] => self.draw_line(*x, *y, *x, *y, color),
+            _ => {
+                for (i, (x1, y1)) in points.iter().enumerate() {
+                    let (x2, y2) = points[(i + 1) % points.len()];
+                    self.draw_line(*x1, *y1, x2, y2, color);
+                }
+            }
+        }
+    }
+
+    /// Even-odd scanline fill, the outline is included like in fill_rect
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)], color: &Color) {
+        let index = color.convert_to_mc();
+        let (Some(min_y), Some(max_y)) = (
+            points.iter().map(|(_, y)| *y).min(),
+            points.iter().map(|(_, y)| *y).max(),
+        ) else {
+            return;
+        };
 
-        let width = x2 - x1;
+        let mut crossings: Vec<f64> = Vec::with_capacity(points.len());
+        for y in self.clipped_rows(min_y, max_y) {
+            //Pixel centers
+            let sample_y = y as f64 + 0.5;
 
-        let data_to_copy: Vec<u8> = vec![color.convert_to_mc(); width + 1];
+            crossings.clear();
+            for (i, (x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(i + 1) % points.len()];
+                let (x1, y1, x2, y2) = (*x1 as f64, *y1 as f64, x2 as f64, y2 as f64);
 
-        for y in y1..=y2 {
-            self.vec[((y * self.width) + x1)..=((y * self.width) + x2)]
-                .copy_from_slice(&data_to_copy);
+                if (y1 <= sample_y) != (y2 <= sample_y) {
+                    crossings.push(x1 + (sample_y - y1) / (y2 - y1) * (x2 - x1));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+
+            for pair in crossings.chunks_exact(2) {
+                let x1 = (pair[0] - 0.5).ceil();
+                let x2 = (pair[1] - 0.5).ceil() - 1.0;
+                if x1 <= x2 {
+                    self.horizontal_line(x1 as i32, x2 as i32, y, index);
+                }
+            }
         }
+
+        self.draw_polygon(points, color);
     }
 
     /// Draws baked image
     ///
     /// X and Y are the top left coordinates of the image
     pub fn draw_image(&mut self, x: usize, y: usize, image: &BakedImage) {
-        let y1 = y + image.height as usize;
+        self.blit(
+            coord(x),
+            coord(y),
+            image,
+            (0, 0, image.width, image.height),
+            false,
+        );
+    }
 
-        for (i, y) in (y..y1).enumerate() {
-            self.vec[((y * self.width) + x)..((y * self.width) + x + image.width as usize)]
-                .copy_from_slice(
-                    &image.data[(i * image.width as usize)..((i + 1) * image.width as usize)],
-                );
+    /// Same as draw_image, but transparent pixels of the image (map color 0) are skipped
+    pub fn draw_image_transparent(&mut self, x: i32, y: i32, image: &BakedImage) {
+        self.blit(x, y, image, (0, 0, image.width, image.height), true);
+    }
+
+    /// Draws a named frame of the sprite, transparent pixels are skipped
+    pub fn draw_sprite(
+        &mut self,
+        x: i32,
+        y: i32,
+        sprite: &Sprite,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        match sprite.frame_index(name) {
+            Some(index) => self.draw_sprite_frame(x, y, sprite, index),
+            None => Err(anyhow!("Sprite has no frame named {name:?}")),
         }
     }
 
-    //We brake the "No result rule" in this function
-    //We have no idea what is going to happen
-    //it might fail
-    #[allow(dead_code)]
-    pub fn draw_default_text(
+    pub fn draw_sprite_frame(
         &mut self,
-        x: usize,
-        y: usize,
-        font_size: i32,
-        font_spacing: usize,
-        color: &Color,
-        text: &str,
+        x: i32,
+        y: i32,
+        sprite: &Sprite,
+        index: usize,
     ) -> anyhow::Result<()> {
-        let font = DEFAULT_FONT.load()?;
-        let mut text_x_offset = 0;
-
-        for text_char in text.chars() {
-            let glyph_id = match font.glyph_for_char(text_char) {
-                Some(val) => val,
-                None => {
-                    return Err(anyhow!(format!(
-                        "This font does not support character {text_char:?}"
-                    )))
-                }
-            };
+        let Some(frame) = sprite.frame(index) else {
+            return Err(anyhow!(
+                "Sprite has no frame {index} ({} frames)",
+                sprite.len()
+            ));
+        };
 
-            let mut canvas = Canvas::new(Vector2I::new(font_size, font_size), Format::A8);
-            font.rasterize_glyph(
-                &mut canvas,
-                glyph_id,
-                font_size as f32,
-                Transform2F::from_translation(Vector2F::new(0.0, font_size as f32)),
-                HintingOptions::None,
-                RasterizationOptions::GrayscaleAa,
-            )?;
-
-            let font_size = font_size as usize;
-
-            for loop_y in 0..font_size {
-                for loop_x in 0..font_size {
-                    if canvas.pixels[loop_y * font_size + loop_x] != 0 {
-                        self.draw_pixel(x + text_x_offset + loop_x, loop_y + y, color);
-                    }
+        let sheet = &sprite.sheet;
+        if frame.x as u64 + frame.width as u64 > sheet.width as u64
+            || frame.y as u64 + frame.height as u64 > sheet.height as u64
+            || sheet.data.len() < sheet.width as usize * sheet.height as usize
+        {
+            return Err(anyhow!("Sprite frame {index} is outside of its sheet"));
+        }
+
+        self.blit(
+            x,
+            y,
+            sheet,
+            (frame.x, frame.y, frame.width, frame.height),
+            true,
+        );
+        Ok(())
+    }
+
+    //Region is x, y, width and height of the drawn part of the image
+    fn blit(
+        &mut self,
+        x: i32,
+        y: i32,
+        image: &BakedImage,
+        region: (u32, u32, u32, u32),
+        transparent: bool,
+    ) {
+        let (clip_x1, clip_y1, clip_x2, clip_y2) = self.clip;
+        let (region_x, region_y) = (region.0 as i64, region.1 as i64);
+        let (region_width, region_height) = (region.2 as i64, region.3 as i64);
+        let image_width = image.width as i64;
+        let (x, y) = (x as i64, y as i64);
+
+        //Visible part of the region, in region coordinates
+        let start_x = (clip_x1 as i64 - x).max(0);
+        let end_x = (clip_x2 as i64 - x + 1).min(region_width);
+        let start_y = (clip_y1 as i64 - y).max(0);
+        let end_y = (clip_y2 as i64 - y + 1).min(region_height);
+        if start_x >= end_x || start_y >= end_y {
+            return;
+        }
+
+        let opacity = self.opacity;
+        for image_y in start_y..end_y {
+            let source = &image.data[((region_y + image_y) * image_width + region_x) as usize..]
+                [start_x as usize..end_x as usize];
+            let (first_x, row_y) = ((x + start_x) as usize, (y + image_y) as usize);
+            let row = row_y * self.width + first_x;
+            let target = &mut self.vec[row..row + source.len()];
+
+            if !transparent && opacity >= 1.0 {
+                target.copy_from_slice(source);
+                continue;
+            }
+            for (offset, (pixel, image_pixel)) in target.iter_mut().zip(source).enumerate() {
+                if (!transparent || *image_pixel != 0) && dithered(first_x + offset, row_y, opacity)
+                {
+                    *pixel = *image_pixel;
                 }
             }
-
-            text_x_offset += font_spacing;
         }
+    }
 
-        Ok(())
+    /// Draws text with its top left corner at x, y. Lines are aligned inside of the widest one
+    pub fn draw_text(
+        &mut self,
+        x: i32,
+        y: i32,
+        style: &TextStyle,
+        text: &str,
+    ) -> anyhow::Result<TextMetrics> {
+        let layout = TextLayout::new(text, style, None)?;
+        layout.draw(self, x, y)?;
+
+        Ok(layout.metrics())
+    }
+
+    /// Draws text wrapped inside of a box (corners in any order). Text below the box is cut off
+    pub fn draw_text_box(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        style: &TextStyle,
+        text: &str,
+    ) -> anyhow::Result<TextMetrics> {
+        let (x1, y1, x2, y2) = (x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2));
+        let width = (x2 as i64 - x1 as i64 + 1) as usize;
+        let layout = TextLayout::new(text, style, Some(width))?;
+
+        //Box is clipped by the current clip rectangle, which is restored afterwards
+        let clip = self.clip;
+        self.clip = (
+            clip.0.max(x1),
+            clip.1.max(y1),
+            clip.2.min(x2),
+            clip.3.min(y2),
+        );
+        let result = layout.draw(self, x1, y1);
+        self.clip = clip;
+        result?;
+
+        Ok(layout.metrics())
     }
 
     fn draw_to_minecraft(
@@ -163,11 +664,12 @@ impl VideoCanvas {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BakedImage {
     pub width: u32,
     pub height: u32,
-    pub data: &'static [u8],
+    //Borrowed for images baked into the library, owned for assets loaded at runtime
+    pub data: Cow<'static, [u8]>,
 }
 
 #[macro_export]
@@ -199,19 +701,102 @@ macro_rules! bake_image {
         BakedImage {
             width,
             height,
-            data,
+            data: std::borrow::Cow::Borrowed(data),
         }
     }};
 }
 
-pub trait Game {
+//Games are ticked on a thread of their own
+pub trait Game: Send {
     fn width(&self) -> i32;
     fn height(&self) -> i32;
+    //Ticks per second, the game thread keeps this rate no matter how often the JVM polls frames
     fn fps(&self) -> i32;
-    fn new() -> Self
+    fn new(config: GameConfig) -> anyhow::Result<Self>
     where
         Self: Sized;
-    fn draw(&mut self, input_rx: &Receiver<GameInputDirection>) -> anyhow::Result<VideoCanvas>;
+    //Advances the game by dt, input received since the last tick is applied first
+    fn tick(&mut self, dt: Duration) -> anyhow::Result<()>;
+    //The canvas has the size of the game and has to be drawn whole
+    fn render(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()>;
+    //Only called for players that joined
+    fn on_input(&mut self, player_id: PlayerId, event: InputEvent);
+    //Player 0 joins right after the game is created, the count never exceeds max_players of the GameEntry
+    fn on_join(&mut self, _player_id: PlayerId) {}
+    fn on_leave(&mut self, _player_id: PlayerId) {}
+    //Starts a new game with the same config and players
+    fn reset(&mut self);
+    fn state(&self) -> GameState;
+}
+
+/// Game that can be started by its id, see apps::games
+#[derive(Debug)]
+pub struct GameEntry {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    //Size of the canvas, the screen has to be at least this large
+    pub width: usize,
+    pub height: usize,
+    pub max_players: usize,
+    pub create: fn(GameConfig) -> anyhow::Result<Box<dyn Game>>,
+}
+
+//Constructor of a GameEntry
+pub fn create_game<G: Game + 'static>(config: GameConfig) -> anyhow::Result<Box<dyn Game>> {
+    Ok(Box::new(G::new(config)?))
+}
+
+//"<id>", optionally followed by "?" and options joined with "&": "seed=<number>", "record=<file>"
+//or "replay=<file>" (the seed is taken from the recording)
+#[derive(Debug, Default, PartialEq)]
+struct GameOptions {
+    id: String,
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+}
+
+impl GameOptions {
+    fn parse(file_name: &str) -> anyhow::Result<Self> {
+        let (id, query) = file_name.split_once('?').unwrap_or((file_name, ""));
+        let mut options = Self {
+            id: id.to_string(),
+            ..Default::default()
+        };
+
+        for option in query.split('&').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("seed", seed)) => {
+                    options.seed = Some(
+                        seed.parse()
+                            .map_err(|_| anyhow!("Invalid game seed ({seed})"))?,
+                    )
+                }
+                Some(("record", file)) => options.record = Some(file.to_string()),
+                Some(("replay", file)) => options.replay = Some(file.to_string()),
+                _ => return Err(anyhow!("Invalid game option ({option})")),
+            }
+        }
+
+        if options.replay.is_some() && (options.seed.is_some() || options.record.is_some()) {
+            return Err(anyhow!("Replayed games cannot be seeded or recorded"));
+        }
+        Ok(options)
+    }
+}
+
+//Every third tick is sent whole, so map clients that missed a delta catch up
+static KEYFRAME_TICKS: u64 = 3;
+//After a stall the game skips ahead instead of running all the missed ticks at once
+static MAX_CATCH_UP_TICKS: u32 = 10;
+
+//Frame of the newest game state, written by the game thread
+struct LatestFrame {
+    tick: u64,
+    data: Arc<Vec<i8>>,
+    //The game thread stops after the first error
+    error: Option<String>,
 }
 
 pub struct GamePlayer {
@@ -219,142 +804,65 @@ pub struct GamePlayer {
     pub height: usize,
     fps: i32,
     splitted_frames: Vec<SplittedFrame>,
-    all_frames_x: usize,
-    all_frames_y: usize,
-    game: Box<dyn Game>,
-    input_rx: Receiver<GameInputDirection>,
-    input_tx: Sender<GameInputDirection>,
-    last_frame: Vec<i8>,
-    frame_counter: u8,
+    event_tx: Sender<(PlayerId, GameEvent)>,
+    latest: Arc<Mutex<LatestFrame>>,
+    //Tick of the frame the JVM got last, None before the first one
+    sent_tick: Option<u64>,
+    keyframe_tick: u64,
+    last_frame: Arc<Vec<i8>>,
 }
 
 impl VideoPlayer for GamePlayer {
-    fn create(file_name: String, _server_options: ServerOptions) -> anyhow::Result<Self> {
-        let game: Box<dyn Game> = match file_name.as_str() {
-            "falling_blocks" => Box::new(FallingBlocks::new()),
-            "snake" => Box::new(SnakeGame::new()),
-            _ => return Err(anyhow!("This game is not implemented!")),
-        };
+    fn create(file_name: String, server_options: ServerOptions) -> anyhow::Result<Self> {
+        let (event_tx, event_rx) = channel::<(PlayerId, GameEvent)>();
+        let mut game_loop = GameLoop::create(&file_name, &server_options, event_rx)?;
 
-        let (width, height, fps) = (game.width() as usize, game.height() as usize, game.fps());
-        let (input_tx, input_rx) = channel::<GameInputDirection>();
+        let (width, height, fps) = (game_loop.width, game_loop.height, game_loop.game.fps());
+        let splitted_frames = game_loop.splitted_frames.clone();
+        let latest = Arc::new(Mutex::new(LatestFrame {
+            tick: 0,
+            data: Arc::new(game_loop.render()?),
+            error: None,
+        }));
 
-        let (splitted_frames, all_frames_x, all_frames_y) =
-            SplittedFrame::initialize_frames(width, height)?;
+        let game_latest = Arc::downgrade(&latest);
+        thread::spawn(move || game_loop.run(game_latest));
 
         Ok(Self {
             width,
             height,
             fps,
             splitted_frames,
-            all_frames_x,
-            all_frames_y,
-            game,
-            input_rx,
-            input_tx,
-            last_frame: Vec::new(),
-            frame_counter: 0,
+            event_tx,
+            latest,
+            sent_tick: None,
+            keyframe_tick: 0,
+            last_frame: Arc::new(Vec::new()),
         })
     }
 
+    //The game runs at its own pace, every call sends what changed since the previous one
     fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
-        let frame = if self.frame_counter == 0 {
-            let canvas = self.game.draw(&self.input_rx)?;
-            let frame = canvas.draw_to_minecraft(
-                &mut self.splitted_frames,
-                self.all_frames_x,
-                self.all_frames_y,
-            )?;
-            self.last_frame = frame.clone();
-
-            Ok(wrap_frame(frame))
-        } else {
-            let new_frame = self.game.draw(&self.input_rx)?.draw_to_minecraft(
-                &mut self.splitted_frames,
-                self.all_frames_x,
-                self.all_frames_y,
-            )?;
-            let mut frame_str_info = String::new();
-            let mut frame_data = Vec::<i8>::with_capacity(65536);
-
-            let mut offset: usize = 0;
-            for (frame_inxex, frame) in self.splitted_frames.iter().enumerate() {
-                let (mut x1, mut y1, mut x2, mut y2) = (128usize, 128usize, 0usize, 0usize);
-                for y in 0..frame.height as usize {
-                    for x in 0..frame.width as usize {
-                        let old_pixel = self.last_frame[offset + (y * frame.width as usize) + x];
-                        let new_pixel = new_frame[offset + (y * frame.width as usize) + x];
-
-                        if old_pixel != new_pixel {
-                            if x1 > x {
-                                x1 = x;
-                            }
-
-                            if y1 > y {
-                                y1 = y;
-                            }
-
-                            if x > x2 {
-                                x2 = x;
-                            }
-
-                            if y > y2 {
-                                y2 = y;
-                            }
-                        }
-                    }
-                }
-
-                if x1 != 128 && y1 != 128 {
-                    let width = x2 - x1 + 1; // + 1 due to the fact that x2 is inclusive;
-                    let height = y2 - y1 + 1;
-
-                    let mut data: Vec<i8> = Vec::with_capacity(width * height);
-
-                    for y in y1..=y2 {
-                        data.extend_from_slice(
-                            &new_frame[(offset + ((y * frame.width as usize) + x1))
-                                ..=(offset + ((y * frame.width as usize) + x2))],
-                        )
-                    }
-                    //Format: {frame_inxex}_{width}_{height}_{x1}_{y1}$
-                    frame_str_info.push_str(&format!(
-                        "{frame_inxex:?}_{width:?}_{height:?}_{x1:?}_{y1:?}$"
-                    ));
-                    frame_data.extend(data);
-                }
-
-                offset += frame.frame_length as usize;
-            }
-
-            let frame_str_info = match frame_str_info.strip_suffix('$') {
-                Some(string) => string,
-                None => return Ok(wrap_frame(vec![1])), //No change = no new packets
-            };
-
-            let frame_str_arr: &[i8] = bytemuck::cast_slice(frame_str_info.as_bytes());
-
-            let mut final_data =
-                Vec::<i8>::with_capacity(frame_str_arr.len() + 5 + frame_data.len());
-            final_data.push(0); //Magic value
-            final_data.extend_from_slice(bytemuck::cast_slice(
-                &(frame_str_arr.len() as i32).to_be_bytes(),
-            ));
-            final_data.extend_from_slice(frame_str_arr);
-            final_data.extend(frame_data);
-
-            self.last_frame = new_frame;
-
-            Ok(wrap_frame(final_data))
+        let (tick, data) = match self.latest.lock() {
+            Ok(latest) => match &latest.error {
+                Some(error) => return Err(anyhow!("Game stopped: {error}")),
+                None => (latest.tick, latest.data.clone()),
+            },
+            Err(_) => return Err(anyhow!("Unable to lock game frame mutex")),
         };
 
-        if self.frame_counter + 1 != 3 {
-            self.frame_counter += 1;
-        } else {
-            self.frame_counter = 0;
+        let frame = match self.sent_tick {
+            Some(sent_tick) if sent_tick == tick => vec![1], //No new tick = no new packets
+            Some(_) if tick < self.keyframe_tick + KEYFRAME_TICKS => self.delta_frame(&data),
+            _ => {
+                self.keyframe_tick = tick;
+                data.to_vec()
+            }
         };
 
-        frame
+        self.sent_tick = Some(tick);
+        self.last_frame = data;
+        Ok(wrap_frame(frame))
     }
 
     fn video_data(&self) -> anyhow::Result<super::player_context::VideoData> {
@@ -368,20 +876,899 @@ impl VideoPlayer for GamePlayer {
     fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
         match msg {
             NativeCommunication::GameInput { input } => {
-                for ele in &input {
-                    self.input_tx.send(*ele)?;
+                for event in input {
+                    self.event_tx.send(event)?;
                 }
             }
             _ => {
                 return Err(anyhow!(
-                    "Gamep player does not accept jvm messages other than GameInputDirection"
+                    "Gamep player does not accept jvm messages other than GameInput"
                 ))
             }
         }
         Ok(())
     }
 
+    //The game thread stops by itself once the player is dropped
     fn destroy(&self) -> anyhow::Result<()> {
         Ok(())
     }
 }
+
+impl GamePlayer {
+    //Only the boxes around the pixels that differ from the last sent frame
+    fn delta_frame(&self, new_frame: &[i8]) -> Vec<i8> {
+        let mut frame_str_info = String::new();
+        let mut frame_data = Vec::<i8>::with_capacity(65536);
+
+        let mut offset: usize = 0;
+        for (frame_inxex, frame) in self.splitted_frames.iter().enumerate() {
+            let (mut x1, mut y1, mut x2, mut y2) = (128usize, 128usize, 0usize, 0usize);
+            for y in 0..frame.height as usize {
+                for x in 0..frame.width as usize {
+                    let old_pixel = self.last_frame[offset + (y * frame.width as usize) + x];
+                    let new_pixel = new_frame[offset + (y * frame.width as usize) + x];
+
+                    if old_pixel != new_pixel {
+                        if x1 > x {
+                            x1 = x;
+                        }
+
+                        if y1 > y {
+                            y1 = y;
+                        }
+
+                        if x > x2 {
+                            x2 = x;
+                        }
+
+                        if y > y2 {
+                            y2 = y;
+                        }
+                    }
+                }
+            }
+
+            if x1 != 128 && y1 != 128 {
+                let width = x2 - x1 + 1; // + 1 due to the fact that x2 is inclusive;
+                let height = y2 - y1 + 1;
+
+                let mut data: Vec<i8> = Vec::with_capacity(width * height);
+
+                for y in y1..=y2 {
+                    data.extend_from_slice(
+                        &new_frame[(offset + ((y * frame.width as usize) + x1))
+                            ..=(offset + ((y * frame.width as usize) + x2))],
+                    )
+                }
+                //Format: {frame_inxex}_{width}_{height}_{x1}_{y1}$
+                frame_str_info.push_str(&format!(
+                    "{frame_inxex:?}_{width:?}_{height:?}_{x1:?}_{y1:?}$"
+                ));
+                frame_data.extend(data);
+            }
+
+            offset += frame.frame_length as usize;
+        }
+
+        let frame_str_info = match frame_str_info.strip_suffix('$') {
+            Some(string) => string,
+            None => return vec![1], //No change = no new packets
+        };
+
+        let frame_str_arr: &[i8] = bytemuck::cast_slice(frame_str_info.as_bytes());
+
+        let mut final_data = Vec::<i8>::with_capacity(frame_str_arr.len() + 5 + frame_data.len());
+        final_data.push(0); //Magic value
+        final_data.extend_from_slice(bytemuck::cast_slice(
+            &(frame_str_arr.len() as i32).to_be_bytes(),
+        ));
+        final_data.extend_from_slice(frame_str_arr);
+        final_data.extend(frame_data);
+
+        final_data
+    }
+}
+
+//Everything the game thread owns
+struct GameLoop {
+    game: Box<dyn Game>,
+    width: usize,
+    height: usize,
+    tick_duration: Duration,
+    splitted_frames: Vec<SplittedFrame>,
+    all_frames_x: usize,
+    all_frames_y: usize,
+    max_players: usize,
+    //Input of every player that joined, delivered on the next tick
+    players: BTreeMap<PlayerId, VecDeque<InputEvent>>,
+    event_rx: Receiver<(PlayerId, GameEvent)>,
+    last_state: GameState,
+    //Ticks since the game was created
+    tick: u64,
+    recorder: Option<GameRecorder<BufWriter<File>>>,
+    //Live input is ignored until the recording ends
+    replay: Option<GameReplay>,
+}
+
+impl GameLoop {
+    fn create(
+        file_name: &str,
+        server_options: &ServerOptions,
+        event_rx: Receiver<(PlayerId, GameEvent)>,
+    ) -> anyhow::Result<Self> {
+        let options = GameOptions::parse(file_name)?;
+        let entry =
+            apps::find_game(&options.id).ok_or_else(|| anyhow!("This game is not implemented!"))?;
+
+        let replay = match &options.replay {
+            Some(file) => Some(GameReplay::new(BufReader::new(File::open(file)?))?),
+            None => None,
+        };
+        let seed = match &replay {
+            Some(replay) if replay.header().game_id != entry.id => {
+                return Err(anyhow!(
+                    "This recording is of another game ({})",
+                    replay.header().game_id
+                ))
+            }
+            Some(replay) => replay.header().seed,
+            None => options.seed.unwrap_or_else(rand::random),
+        };
+        let recorder = match &options.record {
+            Some(file) => Some(GameRecorder::new(
+                BufWriter::new(File::create(file)?),
+                &RecordingHeader {
+                    game_id: entry.id.to_string(),
+                    seed,
+                },
+            )?),
+            None => None,
+        };
+        println!("[ProjectAyaya] Starting game {} with seed {seed}", entry.id);
+
+        let config = GameConfig {
+            assets: AssetLoader::new(server_options.asset_dir.as_deref()),
+            seed,
+        };
+        let mut game = (entry.create)(config)?;
+
+        //The player that started the game, the plugin numbers everyone else
+        game.on_join(0);
+        let players = BTreeMap::from([(0, VecDeque::new())]);
+
+        let (width, height) = (game.width() as usize, game.height() as usize);
+        let tick_duration = Duration::from_secs(1) / game.fps().max(1) as u32;
+        let last_state = game.state();
+
+        let (splitted_frames, all_frames_x, all_frames_y) =
+            SplittedFrame::initialize_frames(width, height)?;
+
+        Ok(Self {
+            game,
+            width,
+            height,
+            tick_duration,
+            splitted_frames,
+            all_frames_x,
+            all_frames_y,
+            max_players: entry.max_players,
+            players,
+            event_rx,
+            last_state,
+            tick: 0,
+            recorder,
+            replay,
+        })
+    }
+
+    //Fixed timestep, only the newest state is rendered
+    fn run(mut self, latest: Weak<Mutex<LatestFrame>>) {
+        let mut next_tick = Instant::now() + self.tick_duration;
+
+        loop {
+            thread::sleep(next_tick.saturating_duration_since(Instant::now()));
+
+            let mut result = Ok(());
+            let mut ticks = 0;
+            while result.is_ok() && Instant::now() >= next_tick {
+                if ticks == MAX_CATCH_UP_TICKS {
+                    next_tick = Instant::now() + self.tick_duration;
+                    break;
+                }
+                result = self.advance();
+                next_tick += self.tick_duration;
+                ticks += 1;
+            }
+            let result = result.and_then(|_| self.render());
+
+            //The player was destroyed
+            let Some(latest) = latest.upgrade() else {
+                return;
+            };
+            let Ok(mut latest) = latest.lock() else {
+                return;
+            };
+            match result {
+                Ok(data) => {
+                    latest.tick = self.tick;
+                    latest.data = Arc::new(data);
+                }
+                Err(err) => {
+                    println!("[ProjectAyaya] Game stopped: {err:?}");
+                    latest.error = Some(err.to_string());
+                    return;
+                }
+            }
+        }
+    }
+
+    //One tick of 1 / fps
+    fn advance(&mut self) -> anyhow::Result<()> {
+        let events: Vec<(PlayerId, GameEvent)> = match &mut self.replay {
+            Some(replay) => {
+                self.event_rx.try_iter().for_each(drop);
+                replay.events(self.tick)
+            }
+            None => self.event_rx.try_iter().collect(),
+        };
+        if self
+            .replay
+            .as_ref()
+            .is_some_and(|replay| replay.is_finished())
+        {
+            println!("[ProjectAyaya] Replay finished, the game takes input again");
+            self.replay = None;
+        }
+
+        //Events are recorded before they are handled, so joins of a full game are refused again when replayed
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.tick, &events)?;
+        }
+        for (player_id, event) in events {
+            self.handle_event(player_id, event);
+        }
+
+        for (player_id, queue) in self.players.iter_mut() {
+            for event in queue.drain(..) {
+                //Jumping on the end screen starts a new game
+                if self.last_state.is_finished() && event == InputEvent::KeyDown(Key::Jump) {
+                    self.game.reset();
+                    self.last_state = self.game.state();
+                    continue;
+                }
+                self.game.on_input(*player_id, event);
+            }
+        }
+
+        self.game.tick(self.tick_duration)?;
+        self.tick += 1;
+
+        let state = self.game.state();
+        if state.is_finished() && !self.last_state.is_finished() {
+            println!("[ProjectAyaya] Game finished with score {}", state.score());
+        }
+        self.last_state = state;
+
+        Ok(())
+    }
+
+    fn render(&mut self) -> anyhow::Result<Vec<i8>> {
+        let mut canvas = VideoCanvas::new(self.width, self.height, &Color::BLACK);
+        self.game.render(&mut canvas)?;
+        canvas.draw_to_minecraft(
+            &mut self.splitted_frames,
+            self.all_frames_x,
+            self.all_frames_y,
+        )
+    }
+
+    fn handle_event(&mut self, player_id: PlayerId, event: GameEvent) {
+        match event {
+            GameEvent::Join if self.players.contains_key(&player_id) => {}
+            GameEvent::Join if self.players.len() >= self.max_players => {
+                println!("[ProjectAyaya] Player {player_id} cannot join, the game is full");
+            }
+            GameEvent::Join => {
+                self.players.insert(player_id, VecDeque::new());
+                self.game.on_join(player_id);
+            }
+            GameEvent::Leave => {
+                //Queued input of the player is dropped with the queue
+                if self.players.remove(&player_id).is_some() {
+                    self.game.on_leave(player_id);
+                }
+            }
+            GameEvent::Input(input) => {
+                let Some(queue) = self.players.get_mut(&player_id) else {
+                    return;
+                };
+                //A flooding player only loses their own input
+                if queue.len() < MAX_QUEUED_INPUT {
+                    queue.push_back(input);
+                }
+            }
+        }
+    }
+}
+
+//Colors need the real conversion table
+#[cfg(all(test, not(feature = "skip_buildrs")))]
+mod tests {
+    use std::fs;
+
+    use rand::Rng;
+
+    use super::*;
+    use crate::player::golden::{check_golden, hash_frames};
+    use crate::player::sprite::SpriteFrame;
+
+    static BACKGROUND: Color = Color::new(255, 255, 255);
+    static RED: Color = Color::new(200, 0, 0);
+
+    fn canvas() -> VideoCanvas {
+        VideoCanvas::new(16, 8, &BACKGROUND)
+    }
+
+    fn drawn(canvas: &VideoCanvas) -> Vec<(usize, usize)> {
+        let red = RED.convert_to_mc();
+        (0..canvas.height)
+            .flat_map(|y| (0..canvas.width).map(move |x| (x, y)))
+            .filter(|(x, y)| canvas.vec[y * canvas.width + x] == red)
+            .collect()
+    }
+
+    #[test]
+    fn test_draw_square_corners() {
+        //Corners in any order, both are inclusive
+        let mut canvas = canvas();
+        canvas.draw_square(5, 5, 2, 3, &RED);
+        let pixels = drawn(&canvas);
+
+        assert!(pixels.len() == 4 * 3);
+        assert!(pixels.contains(&(2, 3)) && pixels.contains(&(5, 5)));
+
+        let mut outline = self::canvas();
+        outline.draw_rect(2, 3, 5, 5, &RED);
+        assert!(drawn(&outline).len() == 4 * 3 - 2);
+    }
+
+    #[test]
+    fn test_out_of_bounds_is_ignored() {
+        let mut canvas = canvas();
+
+        canvas.draw_pixel(16, 0, &RED);
+        canvas.draw_pixel(usize::MAX, usize::MAX, &RED);
+        assert!(drawn(&canvas).is_empty());
+
+        canvas.draw_square(14, 6, usize::MAX, 100, &RED);
+        assert!(drawn(&canvas).len() == 2 * 2);
+
+        let mut canvas = self::canvas();
+        canvas.draw_line(-50, -50, 50, 50, &RED);
+        assert!(drawn(&canvas) == (0..8).map(|i| (i, i)).collect::<Vec<_>>());
+
+        canvas.fill_rect(i32::MIN, i32::MIN, i32::MAX, i32::MAX, &RED);
+        assert!(drawn(&canvas).len() == 16 * 8);
+
+        let mut canvas = self::canvas();
+        canvas.draw_circle(8, 4, i32::MAX, &RED);
+        canvas.fill_circle(-1000, -1000, 10, &RED);
+        canvas.draw_rect(-5, -5, 100, 100, &RED);
+        canvas.fill_polygon(&[(-100, 3), (i32::MAX, 3), (0, i32::MIN)], &RED);
+        assert!(drawn(&canvas).iter().all(|(_, y)| *y <= 3));
+    }
+
+    #[test]
+    fn test_draw_line() {
+        let mut canvas = canvas();
+        canvas.draw_line(0, 0, 7, 3, &RED);
+        let pixels = drawn(&canvas);
+
+        //One pixel per column when the line is more wide than tall
+        assert!(pixels.len() == 8);
+        assert!(pixels.contains(&(0, 0)) && pixels.contains(&(7, 3)));
+        assert!((0..8).all(|x| pixels.iter().filter(|pixel| pixel.0 == x).count() == 1));
+
+        let mut vertical = self::canvas();
+        vertical.draw_line(3, 7, 3, 1, &RED);
+        assert!(drawn(&vertical) == (1..8).map(|y| (3, y)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_line_clipping() {
+        //Stepping with the error term from the first pixel, as lines were drawn before clipping
+        let stepped = |x1: i64, y1: i64, x2: i64, y2: i64| {
+            let (mut x, mut y) = (x1, y1);
+            let (dx, dy) = ((x2 - x).abs(), -(y2 - y).abs());
+            let (step_x, step_y) = ((x2 - x).signum(), (y2 - y).signum());
+            let mut error = dx + dy;
+            let mut pixels = Vec::new();
+
+            loop {
+                if (0..16).contains(&x) && (0..8).contains(&y) {
+                    pixels.push((x as usize, y as usize));
+                }
+                if x == x2 && y == y2 {
+                    break;
+                }
+                let doubled_error = error * 2;
+                if doubled_error >= dy {
+                    error += dy;
+                    x += step_x;
+                }
+                if doubled_error <= dx {
+                    error += dx;
+                    y += step_y;
+                }
+            }
+            pixels.sort_by_key(|(x, y)| (*y, *x));
+            pixels
+        };
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..2000 {
+            let [x1, y1, x2, y2] = [(); 4].map(|_| rng.gen_range(-40..40));
+            let mut canvas = canvas();
+            canvas.draw_line(x1, y1, x2, y2, &RED);
+            assert!(drawn(&canvas) == stepped(x1 as i64, y1 as i64, x2 as i64, y2 as i64));
+        }
+
+        //Only the visible part is walked
+        let mut canvas = canvas();
+        canvas.draw_line(0, 0, i32::MAX, 0, &RED);
+        assert!(drawn(&canvas) == (0..16).map(|x| (x, 0)).collect::<Vec<_>>());
+        canvas.draw_line(i32::MIN, i32::MAX, i32::MAX, i32::MIN, &RED);
+        canvas.draw_line(i32::MAX, 3, i32::MAX, i32::MIN, &RED);
+        assert!(drawn(&canvas).len() == 16);
+    }
+
+    #[test]
+    fn test_circles() {
+        let mut canvas = VideoCanvas::new(32, 32, &BACKGROUND);
+        canvas.draw_circle(16, 16, 10, &RED);
+        let outline = drawn(&canvas);
+
+        //Symmetric and on the radius
+        for (x, y) in &outline {
+            assert!(outline.contains(&(32 - x, *y)) && outline.contains(&(*x, 32 - y)));
+            let distance = ((*x as f64 - 16.0).powi(2) + (*y as f64 - 16.0).powi(2)).sqrt();
+            assert!((distance - 10.0).abs() < 1.0);
+        }
+
+        let mut filled = VideoCanvas::new(32, 32, &BACKGROUND);
+        filled.fill_circle(16, 16, 10, &RED);
+        let filled = drawn(&filled);
+        assert!(outline.iter().all(|pixel| filled.contains(pixel)));
+        let area = std::f64::consts::PI * 100.0;
+        assert!((filled.len() as f64 - area).abs() < area * 0.15);
+
+        let mut dot = self::canvas();
+        dot.fill_circle(3, 3, 0, &RED);
+        dot.draw_circle(3, 3, -1, &RED);
+        assert!(drawn(&dot) == vec![(3, 3)]);
+
+        //Step by step walk, that the clipped walk has to match
+        let midpoint_walk = |radius: i64| {
+            let (mut x, mut y, mut error) = (radius, 0i64, 1 - radius);
+            let mut steps = Vec::new();
+            while x >= y {
+                steps.push((x, y));
+                y += 1;
+                if error < 0 {
+                    error += 2 * y + 1;
+                } else {
+                    x -= 1;
+                    error += 2 * (y - x) + 1;
+                }
+            }
+            steps
+        };
+        for radius in (0..600).chain([46_341, 1_000_000]) {
+            let steps = midpoint_walk(radius);
+            assert!(steps.iter().all(|(x, y)| midpoint_x(*y, radius) == *x));
+            let end = steps.last().unwrap().1 + 1;
+            assert!(midpoint_x(end, radius) < end);
+        }
+
+        //Only the part of a large circle inside of the canvas is walked, with the same pixels
+        let mut arc = self::canvas();
+        arc.draw_circle(8, 1004, 1000, &RED);
+        let expected: Vec<(usize, usize)> = (0..arc.height)
+            .flat_map(|y| (0..arc.width).map(move |x| (x, y)))
+            .filter(|(x, y)| {
+                midpoint_walk(1000).iter().any(|(step_x, step_y)| {
+                    [(*step_x, *step_y), (*step_y, *step_x)]
+                        .iter()
+                        .any(|(offset_x, offset_y)| {
+                            (*x as i64 - 8).abs() == *offset_x
+                                && (*y as i64 - 1004).abs() == *offset_y
+                        })
+                })
+            })
+            .collect();
+        assert!(!expected.is_empty() && drawn(&arc) == expected);
+
+        //Circles that can't be seen are not walked
+        let mut huge = self::canvas();
+        huge.draw_circle(i32::MIN, i32::MIN, i32::MAX, &RED);
+        huge.draw_circle(i32::MAX, 4, i32::MAX / 2, &RED);
+        huge.draw_circle(0, 0, i32::MAX, &RED);
+        assert!(drawn(&huge).is_empty());
+    }
+
+    #[test]
+    fn test_polygons() {
+        let square = [(2, 2), (6, 2), (6, 5), (2, 5)];
+
+        let mut polygon = canvas();
+        polygon.fill_polygon(&square, &RED);
+        let mut rect = canvas();
+        rect.fill_rect(2, 2, 6, 5, &RED);
+        assert!(drawn(&polygon) == drawn(&rect));
+
+        let mut outline = canvas();
+        outline.draw_polygon(&square, &RED);
+        let mut rect = canvas();
+        rect.draw_rect(2, 2, 6, 5, &RED);
+        assert!(drawn(&outline) == drawn(&rect));
+
+        //Triangle gets narrower towards the top
+        let mut triangle = canvas();
+        triangle.fill_polygon(&[(0, 7), (14, 7), (7, 0)], &RED);
+        let pixels = drawn(&triangle);
+        let row_width = |y: usize| pixels.iter().filter(|pixel| pixel.1 == y).count();
+        assert!((1..8).all(|y| row_width(y) >= row_width(y - 1)));
+        assert!(row_width(7) == 15);
+
+        let mut empty = canvas();
+        empty.fill_polygon(&[], &RED);
+        empty.draw_polygon(&[], &RED);
+        assert!(drawn(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_clip() {
+        let mut canvas = canvas();
+        canvas.set_clip(9, 5, 4, 2);
+        canvas.fill_rect(0, 0, 15, 7, &RED);
+        canvas.draw_line(0, 0, 15, 7, &RED);
+
+        let pixels = drawn(&canvas);
+        assert!(pixels.len() == 6 * 4);
+        assert!(pixels
+            .iter()
+            .all(|(x, y)| (4..=9).contains(x) && (2..=5).contains(y)));
+
+        canvas.reset_clip();
+        canvas.draw_pixel(0, 0, &RED);
+        assert!(drawn(&canvas).contains(&(0, 0)));
+
+        //Clip outside of the canvas draws nothing
+        let mut canvas = self::canvas();
+        canvas.set_clip(20, 20, 30, 30);
+        canvas.fill_rect(0, 0, 15, 7, &RED);
+        assert!(drawn(&canvas).is_empty());
+    }
+
+    #[test]
+    fn test_opacity() {
+        let mut overlay = canvas();
+        overlay.fill_rect(2, 1, 12, 6, &RED);
+        overlay.draw_circle(8, 4, 3, &Color::BLACK);
+        let mut blended = canvas();
+        blended.blend(&overlay, 0.4);
+
+        //Same pixels as drawing on a copy and blending it
+        let mut in_place = canvas();
+        in_place.set_opacity(0.4);
+        in_place.fill_rect(2, 1, 12, 6, &RED);
+        in_place.draw_circle(8, 4, 3, &Color::BLACK);
+        assert!(in_place.vec == blended.vec);
+        assert!(!drawn(&in_place).is_empty() && drawn(&in_place).len() < 11 * 6);
+
+        in_place.set_opacity(0.0);
+        in_place.fill_rect(0, 0, 15, 7, &RED);
+        assert!(in_place.vec == blended.vec);
+
+        in_place.reset_opacity();
+        in_place.fill_rect(0, 0, 15, 7, &RED);
+        assert!(drawn(&in_place).len() == 16 * 8);
+    }
+
+    #[test]
+    fn test_image_transparency() {
+        static DATA: [u8; 6] = [0, 1, 0, 1, 1, 1];
+        let red = RED.convert_to_mc();
+        let image = BakedImage {
+            width: 3,
+            height: 2,
+            data: Cow::Borrowed(&DATA),
+        };
+
+        let mut canvas = canvas();
+        canvas.fill_rect(0, 0, 15, 7, &RED);
+        canvas.draw_image_transparent(-1, 6, &image);
+        //(0, 6) is image pixel (1, 0), (1, 6) is transparent, the second row is cut off
+        assert!(canvas.vec[6 * 16] == 1 && canvas.vec[6 * 16 + 1] == red);
+        assert!(canvas.vec[7 * 16..7 * 16 + 2] == [1, 1]);
+
+        canvas.draw_image(14, 0, &image);
+        assert!(canvas.vec[14..16] == [0, 1] && canvas.vec[16 + 14..16 + 16] == [1, 1]);
+
+        canvas.draw_image(usize::MAX, 0, &image);
+        canvas.draw_image_transparent(i32::MIN, i32::MAX, &image);
+    }
+
+    #[test]
+    fn test_draw_sprite() {
+        static FRAMES: [SpriteFrame; 3] = [
+            SpriteFrame {
+                x: 0,
+                y: 0,
+                width: 2,
+                height: 2,
+                delay_ms: 0,
+            },
+            SpriteFrame {
+                x: 2,
+                y: 0,
+                width: 2,
+                height: 2,
+                delay_ms: 0,
+            },
+            SpriteFrame {
+                x: 3,
+                y: 0,
+                width: 2,
+                height: 2,
+                delay_ms: 0,
+            },
+        ];
+        static SPRITE: Sprite = Sprite {
+            sheet: BakedImage {
+                width: 4,
+                height: 2,
+                data: Cow::Borrowed(&[1, 2, 0, 3, 4, 5, 6, 7]),
+            },
+            frames: &FRAMES,
+            names: &[("left", 0), ("right", 1), ("broken", 2)],
+        };
+        let red = RED.convert_to_mc();
+
+        let mut canvas = canvas();
+        canvas.fill_rect(0, 0, 15, 7, &RED);
+        canvas.draw_sprite(1, 1, &SPRITE, "right").unwrap();
+        //Only the frame is drawn, 0 is transparent
+        assert!(canvas.vec[16 + 1..16 + 4] == [red, 3, red]);
+        assert!(canvas.vec[32 + 1..32 + 4] == [6, 7, red]);
+
+        canvas.draw_sprite_frame(15, 7, &SPRITE, 0).unwrap();
+        assert!(canvas.vec[7 * 16 + 15] == 1);
+
+        assert!(canvas.draw_sprite(0, 0, &SPRITE, "up").is_err());
+        assert!(canvas.draw_sprite_frame(0, 0, &SPRITE, 3).is_err());
+        assert!(canvas.draw_sprite(0, 0, &SPRITE, "broken").is_err());
+    }
+
+    #[test]
+    fn test_step_timer() {
+        let mut timer = StepTimer::new(Duration::from_millis(100));
+        assert!(timer.advance(Duration::from_millis(50)) == 0);
+        assert!(timer.advance(Duration::from_millis(60)) == 1);
+        //The remaining 10 ms count towards the next steps
+        assert!(timer.advance(Duration::from_millis(290)) == 3);
+        assert!(timer.advance(Duration::ZERO) == 0);
+    }
+
+    fn game_loop(file_name: &str) -> (GameLoop, Sender<(PlayerId, GameEvent)>) {
+        let (event_tx, event_rx) = channel();
+        let game_loop = GameLoop::create(file_name, &no_server(), event_rx).unwrap();
+        (game_loop, event_tx)
+    }
+
+    #[test]
+    fn test_game_loop_ticks() {
+        let (mut game_loop, event_tx) = game_loop("snake");
+        game_loop.advance().unwrap();
+        assert!(game_loop.width == 640 && game_loop.render().unwrap().len() == 5 * 5 * 128 * 128);
+
+        //Dies at the top wall after one step of grace
+        event_tx
+            .send((0, GameEvent::Input(InputEvent::KeyDown(Key::Forward))))
+            .unwrap();
+        game_loop.advance().unwrap();
+        game_loop.advance().unwrap();
+        assert!(game_loop.last_state == GameState::Over { score: 1 });
+
+        //Jumping starts a new game
+        event_tx
+            .send((0, GameEvent::Input(InputEvent::KeyDown(Key::Jump))))
+            .unwrap();
+        game_loop.advance().unwrap();
+        assert!(game_loop.last_state == GameState::Playing { score: 1 });
+        assert!(game_loop.tick == 4);
+    }
+
+    #[test]
+    fn test_game_loop_join_and_leave() {
+        let (mut game_loop, event_tx) = game_loop("pong");
+        assert!(game_loop.players.keys().eq([&0]));
+
+        //Pong has two sides, the third player is refused
+        for event in [
+            (3, GameEvent::Join),
+            (7, GameEvent::Join),
+            (3, GameEvent::Join),
+        ] {
+            event_tx.send(event).unwrap();
+        }
+        game_loop.advance().unwrap();
+        assert!(game_loop.players.keys().eq([&0, &3]));
+
+        //Input of players that left or never joined is dropped
+        for event in [
+            (3, GameEvent::Input(InputEvent::KeyDown(Key::Forward))),
+            (3, GameEvent::Leave),
+            (7, GameEvent::Input(InputEvent::KeyDown(Key::Forward))),
+            (7, GameEvent::Join),
+        ] {
+            event_tx.send(event).unwrap();
+        }
+        while let Ok((player_id, event)) = game_loop.event_rx.try_recv() {
+            game_loop.handle_event(player_id, event);
+        }
+        assert!(game_loop.players.keys().eq([&0, &7]));
+        assert!(game_loop.players.values().all(|queue| queue.is_empty()));
+    }
+
+    #[test]
+    fn test_game_player_runs_without_polling() {
+        let mut player = GamePlayer::create("pong".to_string(), no_server()).unwrap();
+        let first = player.load_frame().unwrap().data().clone();
+        assert!(first.len() == 5 * 3 * 128 * 128);
+        //Polling again before the next tick sends nothing
+        let sent_tick = player.sent_tick;
+        let frame = player.load_frame().unwrap().data().clone();
+        assert!(player.sent_tick != sent_tick || frame == vec![1]);
+
+        //Pong runs at 20 fps, the game moves on while nobody asks for frames
+        thread::sleep(Duration::from_millis(300));
+        let tick = player.latest.lock().unwrap().tick;
+        assert!(tick >= 3);
+
+        //A slow poll gets the newest state right away, whole because keyframes follow game ticks
+        let frame = player.load_frame().unwrap().data().clone();
+        assert!(frame.len() == first.len() && player.sent_tick >= Some(tick));
+    }
+
+    #[test]
+    fn test_game_options() {
+        let options = GameOptions::parse("snake?seed=42&record=/tmp/snake.rec").unwrap();
+        assert!(options.id == "snake" && options.seed == Some(42));
+        assert!(options.record.as_deref() == Some("/tmp/snake.rec") && options.replay.is_none());
+        assert!(GameOptions::parse("pong").unwrap().seed.is_none());
+
+        assert!(GameOptions::parse("snake?seed=abc").is_err());
+        assert!(GameOptions::parse("snake?speed=2").is_err());
+        assert!(GameOptions::parse("snake?seed=1&replay=/tmp/snake.rec").is_err());
+    }
+
+    fn no_server() -> ServerOptions {
+        ServerOptions {
+            use_server: false,
+            bind_ip: "".to_string(),
+            port: 0,
+            asset_dir: None,
+        }
+    }
+
+    fn recording_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ayaya_{name}_{}.rec", std::process::id()))
+    }
+
+    //Input is sent before the tick with the same index, every tick is rendered
+    fn play(file_name: &str, input: &[(u64, PlayerId, GameEvent)], ticks: u64) -> Vec<Vec<i8>> {
+        let (mut game_loop, event_tx) = game_loop(file_name);
+
+        (0..ticks)
+            .map(|tick| {
+                for (_, player_id, event) in input.iter().filter(|(at, _, _)| *at == tick) {
+                    event_tx.send((*player_id, *event)).unwrap();
+                }
+                game_loop.advance().unwrap();
+                game_loop.render().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_replay_frames() {
+        let path = recording_path("snake_roundtrip");
+        let input = [
+            (3, 0, GameEvent::Input(InputEvent::KeyDown(Key::Right))),
+            (9, 1, GameEvent::Join),
+            (14, 0, GameEvent::Input(InputEvent::KeyDown(Key::Backwards))),
+            (14, 1, GameEvent::Input(InputEvent::KeyDown(Key::Forward))),
+        ];
+
+        let recorded = play(
+            &format!("snake?seed=7&record={}", path.display()),
+            &input,
+            40,
+        );
+        //Live input is ignored while replaying
+        let replayed = play(&format!("snake?replay={}", path.display()), &input[..1], 40);
+        fs::remove_file(&path).unwrap();
+
+        assert!(recorded == replayed);
+        assert!(recorded != play("snake?seed=7", &[], 40));
+    }
+
+    #[test]
+    fn test_replay_of_another_game() {
+        let path = recording_path("pong_as_snake");
+        let header = RecordingHeader {
+            game_id: "pong".to_string(),
+            seed: 1,
+        };
+        GameRecorder::new(File::create(&path).unwrap(), &header).unwrap();
+
+        let player = GamePlayer::create(format!("snake?replay={}", path.display()), no_server());
+        fs::remove_file(&path).unwrap();
+        assert!(player.is_err());
+    }
+
+    #[test]
+    fn test_game_golden_frames() {
+        let key_down = |key| GameEvent::Input(InputEvent::KeyDown(key));
+        let key_up = |key| GameEvent::Input(InputEvent::KeyUp(key));
+
+        for (game_id, events, ticks) in [
+            (
+                "snake",
+                vec![
+                    (2, 0, key_down(Key::Right)),
+                    (6, 1, GameEvent::Join),
+                    (8, 0, key_down(Key::Backwards)),
+                    (12, 1, key_down(Key::Forward)),
+                ],
+                30,
+            ),
+            (
+                "falling_blocks",
+                vec![
+                    (1, 0, key_down(Key::Left)),
+                    (4, 0, key_down(Key::Jump)),
+                    (7, 0, key_down(Key::Backwards)),
+                    (20, 0, key_down(Key::Right)),
+                ],
+                40,
+            ),
+            (
+                "pong",
+                vec![
+                    (0, 1, GameEvent::Join),
+                    (10, 0, key_down(Key::Forward)),
+                    (25, 0, key_up(Key::Forward)),
+                    (30, 1, key_down(Key::Backwards)),
+                ],
+                60,
+            ),
+        ] {
+            let path = recording_path(&format!("golden_{game_id}"));
+            let header = RecordingHeader {
+                game_id: game_id.to_string(),
+                seed: 1234,
+            };
+            let mut recorder = GameRecorder::new(File::create(&path).unwrap(), &header).unwrap();
+            for (tick, player_id, event) in events {
+                recorder.record(tick, &[(player_id, event)]).unwrap();
+            }
+
+            let frames = play(&format!("{game_id}?replay={}", path.display()), &[], ticks);
+            fs::remove_file(&path).unwrap();
+            check_golden(&format!("game_{game_id}"), hash_frames(&frames));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::anyhow;
+
+use super::game_input::{decode_events, encode_events, GameEvent, PlayerId};
+use crate::frame_cache::{read_u32, read_u64, write_u32, write_u64};
+
+static MAGIC: &[u8; 4] = b"AYAR";
+static FORMAT_VERSION: u32 = 1;
+
+/// Game session that can be played again. Games draw random numbers only from the seed and
+/// advance by a fixed step every tick, so the same events at the same ticks give the same frames
+///
+/// Layout (all numbers are big endian):
+/// - magic, format version
+/// - game id length (u8), game id, seed (u64)
+/// - every tick with events: tick index (u64), length (u32), events in the sendGameInput format
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingHeader {
+    pub game_id: String,
+    pub seed: u64,
+}
+
+pub struct GameRecorder<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> GameRecorder<W> {
+    pub fn new(mut writer: W, header: &RecordingHeader) -> anyhow::Result<Self> {
+        let game_id = header.game_id.as_bytes();
+        if game_id.len() > u8::MAX as usize {
+            return Err(anyhow!("Game id is too long"));
+        }
+
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, FORMAT_VERSION)?;
+        writer.write_all(&[game_id.len() as u8])?;
+        writer.write_all(game_id)?;
+        write_u64(&mut writer, header.seed)?;
+        writer.flush()?;
+
+        Ok(Self { writer })
+    }
+
+    //Every tick is flushed, so a crashed server still leaves a usable recording
+    pub fn record(&mut self, tick: u64, events: &[(PlayerId, GameEvent)]) -> anyhow::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let data = encode_events(events);
+        write_u64(&mut self.writer, tick)?;
+        write_u32(&mut self.writer, data.len() as u32)?;
+        self.writer.write_all(&data)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Recording read back into memory, events are taken out tick by tick
+pub struct GameReplay {
+    header: RecordingHeader,
+    ticks: VecDeque<(u64, Vec<(PlayerId, GameEvent)>)>,
+}
+
+impl GameReplay {
+    pub fn new<R: Read>(mut reader: R) -> anyhow::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("This is not a game recording"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "Unsupported game recording version (got {version}, expected {FORMAT_VERSION})"
+            ));
+        }
+
+        let mut length = [0u8; 1];
+        reader.read_exact(&mut length)?;
+        let mut game_id = vec![0u8; length[0] as usize];
+        reader.read_exact(&mut game_id)?;
+        let game_id = String::from_utf8(game_id)?;
+        let seed = read_u64(&mut reader)?;
+
+        let mut ticks = VecDeque::new();
+        loop {
+            //The recording ends after the last complete tick
+            let tick = match read_u64(&mut reader) {
+                Ok(tick) => tick,
+                Err(err)
+                    if err
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|err| err.kind() == ErrorKind::UnexpectedEof) =>
+                {
+                    break
+                }
+                Err(err) => return Err(err),
+            };
+
+            //The length is not trusted, the buffer only grows by the bytes the file still has
+            let length = read_u32(&mut reader)? as usize;
+            let mut data = Vec::new();
+            reader.by_ref().take(length as u64).read_to_end(&mut data)?;
+            if data.len() != length {
+                return Err(anyhow!("Game recording is truncated"));
+            }
+            ticks.push_back((tick, decode_events(&data)?));
+        }
+
+        Ok(Self {
+            header: RecordingHeader { game_id, seed },
+            ticks,
+        })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Events recorded at the tick. Ticks have to be asked for in order
+    pub fn events(&mut self, tick: u64) -> Vec<(PlayerId, GameEvent)> {
+        let mut events = Vec::new();
+        while self
+            .ticks
+            .front()
+            .is_some_and(|(event_tick, _)| *event_tick <= tick)
+        {
+            if let Some((_, tick_events)) = self.ticks.pop_front() {
+                events.extend(tick_events);
+            }
+        }
+        events
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.ticks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::game_input::{InputEvent, Key};
+
+    #[test]
+    fn test_record_and_replay() {
+        let header = RecordingHeader {
+            game_id: "snake".to_string(),
+            seed: 42,
+        };
+        let jump = GameEvent::Input(InputEvent::KeyDown(Key::Jump));
+
+        let mut data = Vec::new();
+        let mut recorder = GameRecorder::new(&mut data, &header).unwrap();
+        recorder.record(0, &[(0, jump)]).unwrap();
+        recorder.record(1, &[]).unwrap();
+        recorder
+            .record(5, &[(1, GameEvent::Join), (1, jump)])
+            .unwrap();
+
+        let mut replay = GameReplay::new(data.as_slice()).unwrap();
+        assert!(*replay.header() == header);
+        assert!(replay.events(0) == [(0, jump)]);
+        assert!(replay.events(1).is_empty());
+        assert!(!replay.is_finished());
+        assert!(replay.events(5) == [(1, GameEvent::Join), (1, jump)]);
+        assert!(replay.is_finished());
+
+        //Cut inside of the last tick
+        assert!(GameReplay::new(&data[..data.len() - 1]).is_err());
+        //Cut between ticks
+        assert!(
+            GameReplay::new(&data[..data.len() - 16])
+                .unwrap()
+                .ticks
+                .len()
+                == 1
+        );
+        assert!(GameReplay::new(&b"AYAC"[..]).is_err());
+
+        //Tick that claims 4 GiB of events in a file of a few bytes
+        let mut data = data[..data.len() - 16].to_vec();
+        write_u64(&mut data, 5).unwrap();
+        write_u32(&mut data, u32::MAX).unwrap();
+        data.extend_from_slice(&[0; 4]);
+        assert!(GameReplay::new(data.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//Golden hashes are stored in test_data/golden and a missing one fails the test. Set
+//AYAYA_UPDATE_GOLDEN=1 to record them (for example after adding a test or changing the color table).
+//The videos are generated here as RGB frames, not with lavfi sources: those are converted from YUV by
+//swscale, which can round differently between ffmpeg builds
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+//FNV-1a, DefaultHasher is not guaranteed to be stable between rust versions
+pub fn hash_frames(frames: &[Vec<i8>]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+
+    for byte in frames.iter().flatten() {
+        hash ^= *byte as u8 as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+pub fn check_golden(name: &str, hash: u64) {
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/golden");
+    let golden_path = golden_dir.join(format!("{name}.hash"));
+    let hash = format!("{hash:016x}");
+
+    if env::var("AYAYA_UPDATE_GOLDEN").is_ok() {
+        fs::create_dir_all(&golden_dir).unwrap();
+        fs::write(&golden_path, format!("{hash}\n")).unwrap();
+        println!("Recorded golden hash of {name} ({hash})");
+        return;
+    }
+
+    let golden = fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+        panic!(
+            "Golden hash of {name} is missing (got {hash}), record it with AYAYA_UPDATE_GOLDEN=1"
+        )
+    });
+    assert!(
+        golden.trim() == hash,
+        "Output of {name} does not match the golden hash (got {hash}, expected {})",
+        golden.trim()
+    );
+}
+
+//RGB24 frames of a gradient with a moving diagonal pattern in the blue channel
+pub fn gradient_frames(width: usize, height: usize, count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|frame| {
+            let mut data = Vec::with_capacity(width * height * 3);
+            for y in 0..height {
+                for x in 0..width {
+                    data.push((x * 255 / (width - 1)) as u8);
+                    data.push((y * 255 / (height - 1)) as u8);
+                    data.push(((x + y + frame * 8) % 256) as u8);
+                }
+            }
+            data
+        })
+        .collect()
+}
+
+//RGB24 frames of 75% color bars over a gray ramp that moves every frame
+pub fn color_bar_frames(width: usize, height: usize, count: usize) -> Vec<Vec<u8>> {
+    static BARS: [[u8; 3]; 7] = [
+        [191, 191, 191],
+        [191, 191, 0],
+        [0, 191, 191],
+        [0, 191, 0],
+        [191, 0, 191],
+        [191, 0, 0],
+        [0, 0, 191],
+    ];
+
+    (0..count)
+        .map(|frame| {
+            let mut data = Vec::with_capacity(width * height * 3);
+            for y in 0..height {
+                for x in 0..width {
+                    if y < height * 2 / 3 {
+                        data.extend_from_slice(&BARS[x * BARS.len() / width]);
+                    } else {
+                        let gray = ((x + frame * 10) % width * 255 / width) as u8;
+                        data.extend_from_slice(&[gray, gray, gray]);
+                    }
+                }
+            }
+            data
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorlib::{get_cached_index, Color};
+    use crate::splitting::SplittedFrame;
+
+    //Same hashes the harness checks for the players. This runs without ffmpeg, so a change of the
+    //color table shows up here too
+    #[test]
+    fn test_golden_sources() {
+        for (name, width, height, frames) in [
+            ("single_gradient", 256, 256, gradient_frames(256, 256, 30)),
+            ("single_color_bars", 200, 150, color_bar_frames(200, 150, 20)),
+        ] {
+            let (splitted_frames, all_frames_x, all_frames_y) =
+                SplittedFrame::initialize_frames(width, height).unwrap();
+
+            let frames: Vec<Vec<i8>> = frames
+                .iter()
+                .map(|frame| {
+                    //Same lookup as transform_frame_to_mc, which needs the ffmpeg feature
+                    let transformed: Vec<i8> = frame
+                        .chunks_exact(3)
+                        .map(|rgb| get_cached_index(&Color::new(rgb[0], rgb[1], rgb[2])))
+                        .collect();
+                    SplittedFrame::split_frames(
+                        &transformed,
+                        &splitted_frames,
+                        width,
+                        all_frames_x,
+                        all_frames_y,
+                    )
+                    .unwrap()
+                })
+                .collect();
+
+            check_golden(name, hash_frames(&frames));
+        }
+    }
+}
@@ -0,0 +1,701 @@
+//Headless end to end tests for the video players. Synthetic videos are generated with the ffmpeg
+//CLI (lavfi sources, or RGB frames from golden.rs) and stored as rawvideo, so decoding is lossless
+//and the output is the same on every machine. The tests need the ffmpeg binary, so they are ignored
+//unless the ffmpeg_cli feature is enabled (cargo test --features ffmpeg_cli), a missing binary fails them.
+//The X11 player is not covered: it needs an X display to grab, and the map server it streams
+//through is tested with the other players
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use flate2::read::ZlibDecoder;
+
+use crate::audio_sink::{WavSink, PCM_CHANNELS, PCM_SAMPLE_RATE};
+use crate::colorlib::Color;
+use crate::frame_cache;
+use crate::map_server::ServerOptions;
+use crate::media_tracks::{self, TrackKind, TrackSelection};
+use crate::splitting::SplittedFrame;
+use crate::subtitles::{SubtitleOptions, SubtitleStyle};
+
+use super::audio_player::{AudioPlayer, PcmDecoder};
+use super::baked_video_player::BakedVideoPlayer;
+use super::golden::{check_golden, color_bar_frames, gradient_frames, hash_frames};
+use super::multi_video_player::MultiVideoPlayer;
+use super::osd::OsdCommand;
+use super::player_context::{NativeCommunication, VideoPlayer};
+use super::single_video_player::SingleVideoPlayer;
+use super::sync_clock::MasterClock;
+use super::synced_player::SyncedPlayer;
+
+static SOLID_COLOR: &str = "color=c=0x3366cc:size=256x128:rate=10:duration=3";
+static TEST_SOURCE: &str = "testsrc=size=256x256:rate=10:duration=3";
+//Long enough that the multi threaded player is still decoding when we seek
+static LONG_TEST_SOURCE: &str = "testsrc=size=128x128:rate=10:duration=20";
+static SINE: &str = "sine=frequency=440:sample_rate=44100:duration=3";
+static HIGH_SINE: &str = "sine=frequency=880:sample_rate=44100:duration=3";
+
+//Removes the video when the test ends (even if it fails)
+struct SyntheticVideo {
+    path: PathBuf,
+}
+
+impl SyntheticVideo {
+    fn create(name: &str, source: &str) -> Self {
+        Self::create_with_audio(name, source, None)
+    }
+
+    fn create_with_audio(name: &str, source: &str, audio_source: Option<&str>) -> Self {
+        let path = env::temp_dir().join(format!("ayaya_{name}_{}.nut", std::process::id()));
+
+        let mut command = Command::new("ffmpeg");
+        command.args(["-y", "-loglevel", "error", "-f", "lavfi", "-i", source]);
+        if let Some(audio_source) = audio_source {
+            command.args(["-f", "lavfi", "-i", audio_source, "-c:a", "pcm_s16le"]);
+        }
+
+        Self::generate(command, path, source, None)
+    }
+
+    //RGB24 frames at 10 fps
+    fn from_frames(name: &str, width: usize, height: usize, frames: &[Vec<u8>]) -> Self {
+        let path = env::temp_dir().join(format!("ayaya_{name}_{}.nut", std::process::id()));
+
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{width}x{height}"), "-r", "10", "-i", "-"]);
+
+        Self::generate(command, path, name, Some(&frames.concat()))
+    }
+
+    //Japanese track is 440 Hz, the english one (default) is 880 Hz
+    fn create_bilingual(name: &str, source: &str) -> Self {
+        let path = env::temp_dir().join(format!("ayaya_{name}_{}.nut", std::process::id()));
+
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error", "-f", "lavfi", "-i", source])
+            .args(["-f", "lavfi", "-i", SINE, "-f", "lavfi", "-i", HIGH_SINE])
+            .args(["-map", "0", "-map", "1", "-map", "2", "-c:a", "pcm_s16le"])
+            .args([
+                "-metadata:s:a:0",
+                "language=jpn",
+                "-metadata:s:a:1",
+                "language=eng",
+            ])
+            .args(["-disposition:a:0", "0", "-disposition:a:1", "default"]);
+
+        Self::generate(command, path, source, None)
+    }
+
+    fn generate(mut command: Command, path: PathBuf, source: &str, input: Option<&[u8]>) -> Self {
+        let mut child = command
+            .args(["-c:v", "rawvideo", "-pix_fmt", "rgb24"])
+            .arg(&path)
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("ffmpeg binary not found, the harness tests need it");
+
+        if let Some(input) = input {
+            //Dropping stdin closes it, so ffmpeg sees the end of the frames
+            child.stdin.take().unwrap().write_all(input).unwrap();
+        }
+
+        let status = child.wait().unwrap();
+        assert!(status.success(), "ffmpeg couldn't generate {source} ({status})");
+        Self { path }
+    }
+
+    fn file_name(&self) -> String {
+        self.path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for SyntheticVideo {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn no_server() -> ServerOptions {
+    ServerOptions {
+        use_server: false,
+        bind_ip: "".to_string(),
+        port: 0,
+        asset_dir: None,
+    }
+}
+
+fn load_all_frames(player: &mut dyn VideoPlayer) -> Vec<Vec<i8>> {
+    let mut frames = Vec::new();
+
+    //Every player reports the end of the video with an error
+    while let Ok(frame) = player.load_frame() {
+        frames.push(frame.data().clone());
+        assert!(
+            frames.len() < 10_000,
+            "Player never reached the end of the video"
+        );
+    }
+
+    frames
+}
+
+fn single_player_frames(video: &SyntheticVideo) -> Vec<Vec<i8>> {
+    let mut player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    let frames = load_all_frames(&mut player);
+    player.destroy().unwrap();
+    frames
+}
+
+fn expected_frame_length(width: usize, height: usize) -> usize {
+    let (_, all_frames_x, all_frames_y) = SplittedFrame::initialize_frames(width, height).unwrap();
+    all_frames_x * all_frames_y * 128 * 128
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_single_player_solid_color() {
+    let video = SyntheticVideo::create("solid_color", SOLID_COLOR);
+
+    let player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    let video_data = player.video_data().unwrap();
+    assert!(video_data.width == 256 && video_data.height == 128 && video_data.fps == 10);
+
+    //Rawvideo is lossless so we know exactly what every frame has to look like
+    let (splitted_frames, all_frames_x, all_frames_y) =
+        SplittedFrame::initialize_frames(256, 128).unwrap();
+    let color = Color::hex("3366cc").unwrap().convert_to_mc() as i8;
+    let expected = SplittedFrame::split_frames(
+        &vec![color; 256 * 128],
+        &splitted_frames,
+        256,
+        all_frames_x,
+        all_frames_y,
+    )
+    .unwrap();
+
+    let frames = single_player_frames(&video);
+    assert!(frames.len() == 30);
+    assert!(frames.iter().all(|frame| *frame == expected));
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_single_player_golden() {
+    for (name, width, height, source_frames) in [
+        ("single_gradient", 256, 256, gradient_frames(256, 256, 30)),
+        ("single_color_bars", 200, 150, color_bar_frames(200, 150, 20)),
+    ] {
+        let video = SyntheticVideo::from_frames(name, width, height, &source_frames);
+
+        let player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+        let video_data = player.video_data().unwrap();
+        assert!(video_data.width == width as i32 && video_data.height == height as i32);
+
+        let frames = single_player_frames(&video);
+        assert!(frames.len() == source_frames.len());
+        assert!(frames
+            .iter()
+            .all(|frame| frame.len() == expected_frame_length(width, height)));
+
+        check_golden(name, hash_frames(&frames));
+    }
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_single_player_seek() {
+    let video = SyntheticVideo::create("single_seek", TEST_SOURCE);
+    let frames = single_player_frames(&video);
+
+    let mut player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    assert!(player
+        .handle_jvm_msg(NativeCommunication::StartRendering { fps: 10 })
+        .is_err());
+
+    player
+        .handle_jvm_msg(NativeCommunication::VideoSeek { second: 2 })
+        .unwrap();
+    assert!(*player.load_frame().unwrap().data() == frames[20]);
+    assert!(*player.load_frame().unwrap().data() == frames[21]);
+
+    player
+        .handle_jvm_msg(NativeCommunication::VideoSeek { second: 1 })
+        .unwrap();
+    assert!(*player.load_frame().unwrap().data() == frames[10]);
+
+    player.destroy().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_single_player_subtitles() {
+    let video = SyntheticVideo::create("subtitles", SOLID_COLOR);
+    let plain_frames = single_player_frames(&video);
+    let subtitles = |track: &str| NativeCommunication::Subtitles {
+        options: Some(SubtitleOptions {
+            track: track.to_string(),
+            style: SubtitleStyle::default(),
+        }),
+    };
+
+    //Frames 10-19 are in the cue
+    let sidecar = video.path.with_extension("srt");
+    fs::write(&sidecar, "1\n00:00:01,000 --> 00:00:02,000\nAyaya\n").unwrap();
+
+    let mut player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    player.handle_jvm_msg(subtitles("auto")).unwrap();
+    let frames = load_all_frames(&mut player);
+    player.destroy().unwrap();
+    fs::remove_file(&sidecar).unwrap();
+
+    assert!(frames.len() == plain_frames.len());
+    for (index, (frame, plain_frame)) in frames.iter().zip(plain_frames.iter()).enumerate() {
+        assert!((frame != plain_frame) == (10..20).contains(&index));
+    }
+
+    //Video has no subtitle streams
+    let player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    assert!(player.handle_jvm_msg(subtitles("eng")).is_err());
+    assert!(player
+        .handle_jvm_msg(NativeCommunication::Subtitles { options: None })
+        .is_ok());
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_single_player_osd() {
+    let video = SyntheticVideo::create("osd", SOLID_COLOR);
+    let plain_frames = single_player_frames(&video);
+
+    let mut player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    for _ in 0..5 {
+        player.load_frame().unwrap();
+    }
+    player
+        .handle_jvm_msg(NativeCommunication::Osd {
+            command: OsdCommand::Paused,
+        })
+        .unwrap();
+    assert!(*player.load_frame().unwrap().data() != plain_frames[5]);
+
+    //Glyph fades out in 400 ms (4 frames)
+    player
+        .handle_jvm_msg(NativeCommunication::Osd {
+            command: OsdCommand::Resumed,
+        })
+        .unwrap();
+    let frames = load_all_frames(&mut player);
+    assert!(frames[0] != plain_frames[6]);
+    assert!(frames[4..] == plain_frames[10..]);
+    player.destroy().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_paused_osd() {
+    let video = SyntheticVideo::create("paused_osd", SOLID_COLOR);
+    let inner = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    let mut player = SyncedPlayer::new(Box::new(inner), MasterClock::wall_clock()).unwrap();
+    let frame = player.load_frame().unwrap().data().clone();
+
+    //No frame is decoded while paused, the glyph is drawn over the frame on screen
+    player
+        .handle_jvm_msg(NativeCommunication::StopRendering)
+        .unwrap();
+    player
+        .handle_jvm_msg(NativeCommunication::Osd {
+            command: OsdCommand::Paused,
+        })
+        .unwrap();
+    let paused_frame = player.load_frame().unwrap().data().clone();
+    assert!(paused_frame != frame);
+    assert!(*player.load_frame().unwrap().data() == paused_frame);
+
+    player
+        .handle_jvm_msg(NativeCommunication::Osd {
+            command: OsdCommand::Resumed,
+        })
+        .unwrap();
+    assert!(*player.load_frame().unwrap().data() == frame);
+    player.destroy().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_multi_player() {
+    let video = SyntheticVideo::create("multi", TEST_SOURCE);
+    let frames = single_player_frames(&video);
+
+    let mut player = MultiVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    let video_data = player.video_data().unwrap();
+    assert!(video_data.width == 256 && video_data.height == 256 && video_data.fps == 10);
+
+    //Frames are converted out of order, but they have to come out in order
+    let multi_frames = load_all_frames(&mut player);
+    assert!(multi_frames.len() == frames.len());
+    assert!(hash_frames(&multi_frames) == hash_frames(&frames));
+
+    player.destroy().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_multi_player_seek() {
+    let video = SyntheticVideo::create("multi_seek", LONG_TEST_SOURCE);
+    let frames = single_player_frames(&video);
+    assert!(frames.len() == 200);
+
+    let mut player = MultiVideoPlayer::create(video.file_name(), no_server()).unwrap();
+    assert!(*player.load_frame().unwrap().data() == frames[0]);
+
+    player
+        .handle_jvm_msg(NativeCommunication::VideoSeek { second: 15 })
+        .unwrap();
+    assert!(*player.load_frame().unwrap().data() == frames[150]);
+    assert!(*player.load_frame().unwrap().data() == frames[151]);
+
+    player.destroy().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_map_server() {
+    let video = SyntheticVideo::create("map_server", LONG_TEST_SOURCE);
+    let frames = single_player_frames(&video);
+
+    //Let the OS pick a free port
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let mut player = MultiVideoPlayer::create(
+        video.file_name(),
+        ServerOptions {
+            use_server: true,
+            bind_ip: "127.0.0.1".to_string(),
+            port: port as i32,
+            asset_dir: None,
+        },
+    )
+    .unwrap();
+
+    assert!(player.load_frame().is_err());
+    //Nothing to stop yet, the server keeps waiting for the start
+    player
+        .handle_jvm_msg(NativeCommunication::StopRendering)
+        .unwrap();
+    player
+        .handle_jvm_msg(NativeCommunication::StartRendering { fps: 10 })
+        .unwrap();
+    //Already rendering, ignored
+    player
+        .handle_jvm_msg(NativeCommunication::StartRendering { fps: 10 })
+        .unwrap();
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+
+    //Frames are sent as u32 length + zlib compressed frame
+    for _ in 0..3 {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut compressed = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut compressed).unwrap();
+
+        let mut frame = Vec::<u8>::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut frame)
+            .unwrap();
+        let frame: Vec<i8> = bytemuck::cast_vec(frame);
+
+        assert!(frames.contains(&frame));
+    }
+
+    player
+        .handle_jvm_msg(NativeCommunication::StopRendering)
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    player.destroy().unwrap();
+}
+
+fn read_var_int(data: &[u8]) -> (usize, i32) {
+    let mut value = 0i32;
+    for (i, byte) in data.iter().enumerate().take(5) {
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return (i + 1, value);
+        }
+    }
+    panic!("VarInt is too big");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_blazing_player() {
+    use super::blazing_linux_player::LinuxBlazingPlayer;
+
+    let video = SyntheticVideo::create("blazing", TEST_SOURCE);
+    let frames = single_player_frames(&video);
+
+    //Map ids from 100, no compression, 1.18.2, no bundles
+    let file_name = format!("100$$$-1$$$758$$$false$$${}", video.file_name());
+    let mut player = LinuxBlazingPlayer::create(file_name, no_server()).unwrap();
+    let video_data = player.video_data().unwrap();
+    assert!(video_data.width == 256 && video_data.height == 256 && video_data.fps == 10);
+
+    //Every frame is a stream of map packets, their data put together is the split frame
+    let encoded_frames = load_all_frames(&mut player);
+    assert!(encoded_frames.len() == frames.len());
+
+    for (encoded, frame) in encoded_frames.iter().zip(frames.iter()) {
+        let stream: &[u8] = bytemuck::cast_slice(encoded);
+        let mut offset = 0usize;
+        let mut map_id = 100;
+        let mut data = Vec::<i8>::new();
+
+        while offset != stream.len() {
+            let (packet_len_size, packet_len) = read_var_int(&stream[offset..]);
+            let body = &stream[offset + packet_len_size..][..packet_len as usize];
+
+            assert!(body[0] == 0x27);
+            let (map_id_len, packet_map_id) = read_var_int(&body[1..]);
+            assert!(packet_map_id == map_id);
+            let (data_len_size, data_len) = read_var_int(&body[1 + map_id_len + 7..]);
+            let packet_data = &body[1 + map_id_len + 7 + data_len_size..];
+            assert!(packet_data.len() == data_len as usize);
+            data.extend_from_slice(bytemuck::cast_slice(packet_data));
+
+            map_id += 1;
+            offset += packet_len_size + packet_len as usize;
+        }
+
+        assert!(map_id == 104);
+        assert!(data == *frame);
+    }
+
+    player.destroy().unwrap();
+}
+
+//Converts through a YUV table in C, so the colors are not compared with the other players
+#[cfg(feature = "external_player")]
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_external_player() {
+    use super::external_player::ExternalPlayer;
+
+    let video = SyntheticVideo::create("external", TEST_SOURCE);
+
+    let mut player = ExternalPlayer::create(video.file_name(), no_server()).unwrap();
+    let video_data = player.video_data().unwrap();
+    assert!(video_data.width == 256 && video_data.height == 256 && video_data.fps == 10);
+
+    let frames = load_all_frames(&mut player);
+    assert!(frames.len() == 30);
+    assert!(frames.iter().all(|frame| frame.len() == 256 * 256));
+    assert!(frames.iter().any(|frame| frame != &frames[0]));
+
+    player.destroy().unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_baked_player() {
+    let video = SyntheticVideo::create("baked", TEST_SOURCE);
+    let frames = single_player_frames(&video);
+
+    let baked_path = env::temp_dir().join(format!("ayaya_baked_{}.ayc", std::process::id()));
+    let baked_file_name = baked_path.to_str().unwrap().to_string();
+    frame_cache::bake(video.file_name(), baked_file_name.clone()).unwrap();
+
+    let mut player = BakedVideoPlayer::create(baked_file_name.clone(), no_server()).unwrap();
+    let video_data = player.video_data().unwrap();
+    assert!(video_data.width == 256 && video_data.height == 256 && video_data.fps == 10);
+
+    let baked_frames = load_all_frames(&mut player);
+    assert!(baked_frames == frames);
+
+    player
+        .handle_jvm_msg(NativeCommunication::VideoSeek { second: 2 })
+        .unwrap();
+    assert!(*player.load_frame().unwrap().data() == frames[20]);
+
+    player.destroy().unwrap();
+    fs::remove_file(baked_path).unwrap();
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_transcoder_options() {
+    let video = SyntheticVideo::create("transcoder", TEST_SOURCE);
+
+    let options = frame_cache::TranscodeOptions {
+        width: Some(128),
+        height: Some(128),
+        dither: true,
+    };
+    let mut transcoder = frame_cache::Transcoder::new(&video.file_name(), &options).unwrap();
+    assert!(transcoder.source_size() == (256, 256));
+    assert!(transcoder.header().width == 128 && transcoder.header().all_frames_x == 1);
+
+    let mut frames = 0;
+    while let Some(frame) = transcoder.next_frame().unwrap() {
+        assert!(frame.converted.len() == 128 * 128);
+        assert!(frame.splitted.len() == expected_frame_length(128, 128));
+        frames += 1;
+    }
+    assert!(frames == 30);
+    assert!(transcoder.next_frame().unwrap().is_none());
+}
+
+//Seconds of decoded stereo PCM
+fn pcm_seconds(samples: usize) -> f64 {
+    samples as f64 / PCM_CHANNELS as f64 / PCM_SAMPLE_RATE as f64
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_pcm_decoder() {
+    let video = SyntheticVideo::create_with_audio("pcm", TEST_SOURCE, Some(SINE));
+
+    let mut decoder = PcmDecoder::open(&video.file_name()).unwrap();
+    let mut samples = Vec::new();
+    while let Some(decoded) = decoder.decode().unwrap() {
+        samples.extend(decoded);
+    }
+    //Resampled to 48kHz stereo
+    assert!((pcm_seconds(samples.len()) - 3.0).abs() < 0.05);
+    //lavfi sine has an amplitude of 1/8
+    assert!(samples.iter().any(|sample| *sample > 0.1));
+
+    decoder.seek(Duration::from_secs(2)).unwrap();
+    let mut remaining = 0;
+    while let Some(decoded) = decoder.decode().unwrap() {
+        remaining += decoded.len();
+    }
+    assert!((pcm_seconds(remaining) - 1.0).abs() < 0.1);
+
+    //Video without audio
+    let silent_video = SyntheticVideo::create("pcm_silent", TEST_SOURCE);
+    assert!(PcmDecoder::open(&silent_video.file_name()).is_err());
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_audio_player_wav_sink() {
+    let video = SyntheticVideo::create_with_audio("audio_player", TEST_SOURCE, Some(SINE));
+
+    let wav_path = env::temp_dir().join(format!("ayaya_audio_player_{}.wav", std::process::id()));
+    let sink = WavSink::create(wav_path.to_str().unwrap()).unwrap();
+    let player = SingleVideoPlayer::create(video.file_name(), no_server()).unwrap();
+
+    let mut player = AudioPlayer::create_with_sink(
+        video.file_name(),
+        Box::new(player),
+        Box::new(sink),
+        TrackSelection::Default,
+    )
+    .unwrap();
+    for _ in 0..5 {
+        assert!(player.load_frame().unwrap().data().len() == expected_frame_length(256, 256));
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    //Audio follows the wall clock, so only about half a second (+ lead) was written
+    player.destroy().unwrap();
+    let clock_position = player.clock().unwrap().position();
+    assert!(clock_position >= Duration::from_millis(400));
+
+    //Header is patched when the audio thread finishes
+    let mut data_len = 0;
+    for _ in 0..50 {
+        let wav = fs::read(&wav_path).unwrap();
+        data_len = u32::from_le_bytes(wav[40..44].try_into().unwrap());
+        if data_len != 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let written = pcm_seconds(data_len as usize / 2);
+    assert!(written > 0.3 && written < 2.0);
+    fs::remove_file(wav_path).unwrap();
+}
+
+//Sign changes of the left channel per second
+fn zero_crossings(samples: &[f32]) -> f64 {
+    let left: Vec<f32> = samples
+        .iter()
+        .step_by(PCM_CHANNELS as usize)
+        .copied()
+        .collect();
+    let crossings = left
+        .windows(2)
+        .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+        .count();
+
+    crossings as f64 / pcm_seconds(samples.len())
+}
+
+#[test]
+#[cfg_attr(not(feature = "ffmpeg_cli"), ignore = "needs the ffmpeg binary")]
+fn test_audio_tracks() {
+    let video = SyntheticVideo::create_bilingual("tracks", TEST_SOURCE);
+
+    let tracks = media_tracks::probe(&video.file_name()).unwrap();
+    let audio_tracks: Vec<_> = tracks
+        .iter()
+        .filter(|track| track.kind == TrackKind::Audio)
+        .collect();
+    assert!(audio_tracks.len() == 2);
+    assert!(audio_tracks[0].language.as_deref() == Some("jpn") && !audio_tracks[0].default);
+    assert!(audio_tracks[1].language.as_deref() == Some("eng") && audio_tracks[1].default);
+
+    let mut decoder = PcmDecoder::open(&video.file_name()).unwrap();
+    assert!(decoder.stream_index() == audio_tracks[1].index);
+    let english = decoder.decode().unwrap().unwrap();
+
+    let language = TrackSelection::Language("jpn".to_string());
+    let mut decoder = PcmDecoder::open_stream(&video.file_name(), &language).unwrap();
+    assert!(decoder.stream_index() == audio_tracks[0].index);
+    let japanese = decoder.decode().unwrap().unwrap();
+
+    //880 Hz crosses zero twice as often as 440 Hz
+    let ratio = zero_crossings(&english) / zero_crossings(&japanese);
+    assert!((ratio - 2.0).abs() < 0.2);
+
+    //Runtime switch continues at the given position
+    decoder
+        .switch_stream(audio_tracks[1].index, Duration::from_secs(2))
+        .unwrap();
+    assert!(decoder.stream_index() == audio_tracks[1].index);
+    let mut remaining = 0;
+    while let Some(decoded) = decoder.decode().unwrap() {
+        remaining += decoded.len();
+    }
+    assert!((pcm_seconds(remaining) - 1.0).abs() < 0.1);
+
+    //Failed switch keeps the old stream
+    decoder.seek(Duration::from_secs(2)).unwrap();
+    assert!(decoder.switch_stream(99, Duration::ZERO).is_err());
+    assert!(decoder.stream_index() == audio_tracks[1].index);
+    assert!(decoder.position() == Duration::from_secs(2));
+    assert!(decoder.decode().unwrap().is_some());
+
+    let missing = TrackSelection::Language("ger".to_string());
+    assert!(PcmDecoder::open_stream(&video.file_name(), &missing).is_err());
+}
@@ -0,0 +1,538 @@
+use std::cell::Cell;
+use std::collections::BTreeSet;
+
+use anyhow::anyhow;
+use font_kit::font::Font;
+
+static GPOS_TAG: u32 = u32::from_be_bytes(*b"GPOS");
+static KERN_FEATURE: &[u8; 4] = b"kern";
+
+static PAIR_ADJUSTMENT: u16 = 2;
+static EXTENSION: u16 = 9;
+//Reads allowed for every byte of the table. Offsets of a malformed table can point at the same
+//records over and over, real fonts only share a few coverage and class tables
+static READS_PER_BYTE: usize = 8;
+
+/// Pair kerning of a font, read from the "kern" feature of its GPOS table
+///
+/// Only the horizontal advance of the first glyph is used, which is what latin fonts set
+#[derive(Debug, Default)]
+pub struct KerningTable {
+    //Lookups of the kern feature, every lookup is applied
+    lookups: Vec<Vec<PairSubtable>>,
+}
+
+#[derive(Debug)]
+enum PairSubtable {
+    //Second glyph and adjustment, sorted by the second glyph, for every covered glyph
+    Glyphs {
+        coverage: Coverage,
+        sets: Vec<Vec<(u16, i16)>>,
+    },
+    Classes {
+        coverage: Coverage,
+        first: ClassDef,
+        second: ClassDef,
+        second_count: usize,
+        values: Vec<i16>,
+    },
+}
+
+//Start glyph, end glyph (inclusive) and coverage index of the start glyph
+#[derive(Debug)]
+struct Coverage(Vec<(u16, u16, u16)>);
+
+//Start glyph, end glyph (inclusive) and class. Glyphs that are not listed are class 0
+#[derive(Debug)]
+struct ClassDef(Vec<(u16, u16, u16)>);
+
+fn find_range(ranges: &[(u16, u16, u16)], glyph: u16) -> Option<&(u16, u16, u16)> {
+    let index = ranges.partition_point(|(_, end, _)| *end < glyph);
+    ranges.get(index).filter(|(start, _, _)| *start <= glyph)
+}
+
+impl Coverage {
+    fn parse(data: &GposData, offset: usize) -> anyhow::Result<Self> {
+        let mut ranges = Vec::new();
+        match read_u16(data, offset)? {
+            1 => {
+                let count = read_u16(data, offset + 2)? as usize;
+                data.check_array(offset + 4, count, 2)?;
+                for i in 0..count {
+                    let glyph = read_u16(data, offset + 4 + i * 2)?;
+                    ranges.push((glyph, glyph, i as u16));
+                }
+            }
+            2 => {
+                let count = read_u16(data, offset + 2)? as usize;
+                data.check_array(offset + 4, count, 6)?;
+                for i in 0..count {
+                    let record = offset + 4 + i * 6;
+                    ranges.push((
+                        read_u16(data, record)?,
+                        read_u16(data, record + 2)?,
+                        read_u16(data, record + 4)?,
+                    ));
+                }
+            }
+            format => return Err(anyhow!("Unknown coverage format {format}")),
+        }
+        ranges.sort_unstable();
+
+        Ok(Self(ranges))
+    }
+
+    fn index(&self, glyph: u16) -> Option<usize> {
+        find_range(&self.0, glyph)
+            .map(|(start, _, index)| *index as usize + (glyph - start) as usize)
+    }
+}
+
+impl ClassDef {
+    fn parse(data: &GposData, offset: usize) -> anyhow::Result<Self> {
+        let mut ranges = Vec::new();
+        match read_u16(data, offset)? {
+            1 => {
+                let start = read_u16(data, offset + 2)?;
+                let count = read_u16(data, offset + 4)?;
+                data.check_array(offset + 6, count as usize, 2)?;
+                for i in 0..count {
+                    let class = read_u16(data, offset + 6 + i as usize * 2)?;
+                    let glyph = start.saturating_add(i);
+                    ranges.push((glyph, glyph, class));
+                }
+            }
+            2 => {
+                let count = read_u16(data, offset + 2)? as usize;
+                data.check_array(offset + 4, count, 6)?;
+                for i in 0..count {
+                    let record = offset + 4 + i * 6;
+                    ranges.push((
+                        read_u16(data, record)?,
+                        read_u16(data, record + 2)?,
+                        read_u16(data, record + 4)?,
+                    ));
+                }
+            }
+            format => return Err(anyhow!("Unknown class definition format {format}")),
+        }
+        ranges.sort_unstable();
+
+        Ok(Self(ranges))
+    }
+
+    fn class(&self, glyph: u16) -> usize {
+        find_range(&self.0, glyph).map_or(0, |(_, _, class)| *class as usize)
+    }
+}
+
+impl PairSubtable {
+    fn parse(data: &GposData, offset: usize) -> anyhow::Result<Self> {
+        let coverage = Coverage::parse(data, offset + read_u16(data, offset + 2)? as usize)?;
+        let first_format = read_u16(data, offset + 4)?;
+        let second_format = read_u16(data, offset + 6)?;
+
+        //Value records only have the fields that are set in their format
+        let first_size = value_size(first_format);
+        let pair_size = first_size + value_size(second_format);
+        let read_advance = |record: usize| -> anyhow::Result<i16> {
+            match first_format & 0x4 {
+                0 => Ok(0),
+                _ => Ok(read_u16(data, record + value_size(first_format & 0x3))? as i16),
+            }
+        };
+
+        match read_u16(data, offset)? {
+            1 => {
+                let set_count = read_u16(data, offset + 8)? as usize;
+                data.check_array(offset + 10, set_count, 2)?;
+
+                let mut sets = Vec::new();
+                for i in 0..set_count {
+                    let set = offset + read_u16(data, offset + 10 + i * 2)? as usize;
+                    let pair_count = read_u16(data, set)? as usize;
+                    data.check_array(set + 2, pair_count, 2 + pair_size)?;
+
+                    let mut pairs = Vec::new();
+                    for j in 0..pair_count {
+                        let record = set + 2 + j * (2 + pair_size);
+                        pairs.push((read_u16(data, record)?, read_advance(record + 2)?));
+                    }
+                    pairs.sort_unstable();
+                    sets.push(pairs);
+                }
+
+                Ok(Self::Glyphs { coverage, sets })
+            }
+            2 => {
+                let first = ClassDef::parse(data, offset + read_u16(data, offset + 8)? as usize)?;
+                let second = ClassDef::parse(data, offset + read_u16(data, offset + 10)? as usize)?;
+                let first_count = read_u16(data, offset + 12)? as usize;
+                let second_count = read_u16(data, offset + 14)? as usize;
+
+                //Without an advance every pair is 0, which a missing value means too
+                let mut values = Vec::new();
+                if first_format & 0x4 != 0 {
+                    data.check_array(offset + 16, first_count * second_count, pair_size)?;
+                    values.reserve(first_count * second_count);
+                    for i in 0..first_count * second_count {
+                        values.push(read_advance(offset + 16 + i * pair_size)?);
+                    }
+                }
+
+                Ok(Self::Classes {
+                    coverage,
+                    first,
+                    second,
+                    second_count,
+                    values,
+                })
+            }
+            format => Err(anyhow!("Unknown pair adjustment format {format}")),
+        }
+    }
+
+    //None if this subtable does not cover the pair, so the next one should be used
+    fn kerning(&self, left: u16, right: u16) -> Option<i16> {
+        match self {
+            Self::Glyphs { coverage, sets } => {
+                let pairs = sets.get(coverage.index(left)?)?;
+                let index = pairs
+                    .binary_search_by_key(&right, |(glyph, _)| *glyph)
+                    .ok()?;
+                Some(pairs[index].1)
+            }
+            Self::Classes {
+                coverage,
+                first,
+                second,
+                second_count,
+                values,
+            } => {
+                coverage.index(left)?;
+                let right_class = second.class(right);
+                if right_class >= *second_count {
+                    return Some(0);
+                }
+                let index = first.class(left) * second_count + right_class;
+                Some(values.get(index).copied().unwrap_or(0))
+            }
+        }
+    }
+}
+
+/// GPOS bytes with the number of reads that are left
+struct GposData<'a> {
+    bytes: &'a [u8],
+    reads_left: Cell<usize>,
+}
+
+impl<'a> GposData<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            reads_left: Cell::new(bytes.len().saturating_mul(READS_PER_BYTE)),
+        }
+    }
+
+    //Arrays are checked before anything is allocated for them
+    fn check_array(&self, start: usize, count: usize, size: usize) -> anyhow::Result<()> {
+        match count
+            .checked_mul(size)
+            .and_then(|len| len.checked_add(start))
+        {
+            Some(end) if end <= self.bytes.len() => Ok(()),
+            _ => Err(anyhow!("GPOS array at {start} is outside of the table")),
+        }
+    }
+}
+
+fn read_u16(data: &GposData, offset: usize) -> anyhow::Result<u16> {
+    let reads_left = data.reads_left.get();
+    if reads_left == 0 {
+        return Err(anyhow!("GPOS table has overlapping records"));
+    }
+    data.reads_left.set(reads_left - 1);
+
+    match offset
+        .checked_add(2)
+        .and_then(|end| data.bytes.get(offset..end))
+    {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(anyhow!("GPOS table is truncated at {offset}")),
+    }
+}
+
+fn read_u32(data: &GposData, offset: usize) -> anyhow::Result<u32> {
+    Ok(((read_u16(data, offset)? as u32) << 16) | read_u16(data, offset + 2)? as u32)
+}
+
+//Every field of a value record is 16 bits
+fn value_size(format: u16) -> usize {
+    (format & 0xFF).count_ones() as usize * 2
+}
+
+impl KerningTable {
+    //Fonts without kerning (or with a table that can't be read) get an empty table
+    pub fn from_font(font: &Font) -> Self {
+        let Some(gpos) = font.load_font_table(GPOS_TAG) else {
+            return Self::default();
+        };
+
+        match Self::parse(&gpos) {
+            Ok(val) => val,
+            Err(err) => {
+                println!("[ProjectAyaya] Unable to read kerning of the font! Reason: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Malformed tables return an error, offsets outside of the table never panic
+    pub fn parse(gpos: &[u8]) -> anyhow::Result<Self> {
+        let gpos = &GposData::new(gpos);
+        let feature_list = read_u16(gpos, 6)? as usize;
+        let lookup_list = read_u16(gpos, 8)? as usize;
+
+        //Every script has its own kern feature, they usually share the lookups
+        let mut lookup_indices = BTreeSet::new();
+        let feature_count = read_u16(gpos, feature_list)? as usize;
+        gpos.check_array(feature_list + 2, feature_count, 6)?;
+        for i in 0..feature_count {
+            let record = feature_list + 2 + i * 6;
+            if gpos.bytes.get(record..record + 4) != Some(KERN_FEATURE.as_slice()) {
+                continue;
+            }
+
+            let feature = feature_list + read_u16(gpos, record + 4)? as usize;
+            let lookup_count = read_u16(gpos, feature + 2)? as usize;
+            gpos.check_array(feature + 4, lookup_count, 2)?;
+            for j in 0..lookup_count {
+                lookup_indices.insert(read_u16(gpos, feature + 4 + j * 2)?);
+            }
+        }
+
+        let mut lookups = Vec::new();
+        for index in lookup_indices {
+            let lookup =
+                lookup_list + read_u16(gpos, lookup_list + 2 + index as usize * 2)? as usize;
+            let lookup_type = read_u16(gpos, lookup)?;
+
+            let subtable_count = read_u16(gpos, lookup + 4)? as usize;
+            gpos.check_array(lookup + 6, subtable_count, 2)?;
+
+            let mut subtables = Vec::new();
+            for i in 0..subtable_count {
+                let mut subtable = lookup + read_u16(gpos, lookup + 6 + i * 2)? as usize;
+                let mut subtable_type = lookup_type;
+
+                //Extension subtables point to the real one with a 32 bit offset
+                if subtable_type == EXTENSION {
+                    subtable_type = read_u16(gpos, subtable + 2)?;
+                    subtable += read_u32(gpos, subtable + 4)? as usize;
+                }
+                if subtable_type == PAIR_ADJUSTMENT {
+                    subtables.push(PairSubtable::parse(gpos, subtable)?);
+                }
+            }
+
+            if !subtables.is_empty() {
+                lookups.push(subtables);
+            }
+        }
+
+        Ok(Self { lookups })
+    }
+
+    /// Advance adjustment between two glyphs, in font units
+    pub fn kerning(&self, left: u32, right: u32) -> i32 {
+        let (Ok(left), Ok(right)) = (u16::try_from(left), u16::try_from(right)) else {
+            return 0;
+        };
+
+        self.lookups
+            .iter()
+            .filter_map(|subtables| {
+                subtables
+                    .iter()
+                    .find_map(|subtable| subtable.kerning(left, right))
+            })
+            .map(|value| value as i32)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lookups.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::game_player::DEFAULT_FONT;
+
+    //GPOS with a kern and a mark feature. The kern lookup has a format 1 subtable for
+    //glyph 5 and a format 2 one for glyphs 10..=12
+    fn test_table() -> Vec<u8> {
+        let words: &[u16] = &[
+            //Header: version, script list, feature list at 10, lookup list at 36
+            1,
+            0,
+            0,
+            10,
+            36,
+            //Feature list: "mark" at 20 and "kern" at 14 from it
+            2,
+            0x6D61,
+            0x726B,
+            20,
+            0x6B65,
+            0x726E,
+            14,
+            //Kern feature uses lookup 0, mark feature uses lookup 1
+            0,
+            1,
+            0,
+            0,
+            1,
+            1,
+            //Lookup list, lookup 0 at 4 from it
+            1,
+            4,
+            //Pair adjustment lookup with subtables at 10 and 34 from it
+            PAIR_ADJUSTMENT,
+            0,
+            2,
+            10,
+            34,
+            //Format 1: coverage at 12, XAdvance only, pair set at 18
+            1,
+            12,
+            0x4,
+            0,
+            1,
+            18,
+            1,
+            1,
+            5,
+            1,
+            7,
+            -80i16 as u16,
+            //Format 2: coverage at 32, XPlacement and XAdvance, class defs at 42 and 54
+            2,
+            32,
+            0x5,
+            0,
+            42,
+            54,
+            2,
+            2,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            3,
+            -40i16 as u16,
+            2,
+            1,
+            10,
+            12,
+            0,
+            //Glyph 11 is class 1 on the left, glyph 3 is class 1 on the right
+            1,
+            10,
+            3,
+            0,
+            1,
+            0,
+            2,
+            1,
+            3,
+            3,
+            1,
+        ];
+
+        words.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parse_pairs() {
+        let table = KerningTable::parse(&test_table()).unwrap();
+
+        assert!(table.kerning(5, 7) == -80);
+        assert!(table.kerning(5, 8) == 0);
+        assert!(table.kerning(7, 5) == 0);
+        assert!(table.kerning(11, 3) == -40);
+        assert!(table.kerning(10, 3) == 0);
+        assert!(table.kerning(11, 4) == 0);
+        assert!(table.kerning(11, 70_000) == 0);
+    }
+
+    #[test]
+    fn test_truncated_table() {
+        let data = test_table();
+        assert!(KerningTable::parse(&data[..data.len() - 4]).is_err());
+        assert!(KerningTable::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_table() {
+        //Every truncation and every word set to a large offset, a huge count or zero has to
+        //parse or fail without panicking
+        let data = test_table();
+        for len in 0..data.len() {
+            assert!(KerningTable::parse(&data[..len]).is_err());
+        }
+        for word in 0..data.len() / 2 {
+            for value in [0u16, 1, 0x7FFF, 0xFFFF] {
+                let mut data = data.clone();
+                data[word * 2..word * 2 + 2].copy_from_slice(&value.to_be_bytes());
+                let _ = KerningTable::parse(&data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oversized_arrays() {
+        //Class matrix of 65535 * 65535 pairs in a table of a few bytes
+        let mut data = test_table();
+        data[86..90].copy_from_slice(&[0xFF; 4]);
+        assert!(KerningTable::parse(&data).is_err());
+
+        //Extension subtable pointing far past the end of the table
+        let mut data = test_table();
+        let extension = (data.len() - 40) as u16;
+        data[40..42].copy_from_slice(&EXTENSION.to_be_bytes());
+        data[46..48].copy_from_slice(&extension.to_be_bytes());
+        data.extend_from_slice(&[0, 1, 0, PAIR_ADJUSTMENT as u8, 0xFF, 0xFF, 0xFF, 0xF0]);
+        assert!(KerningTable::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_overlapping_pair_sets() {
+        //65535 pair sets at 8, where the set count is read as 65535 pairs that overlap the
+        //set offsets. The coverage at 2 reads the formats as a range covering every glyph
+        let mut words: Vec<u16> = vec![1, 0, 0, 10, 24, 1, 0x6B65, 0x726E, 8, 0, 1, 0];
+        words.extend([1, 4, PAIR_ADJUSTMENT, 0, 1, 8]);
+        words.extend([1, 2, 0x4, 0, 0xFFFF]);
+        words.extend(std::iter::repeat_n(8, 0xFFFF));
+        words.extend(std::iter::repeat_n(0, 0xFFFF));
+        let data: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+        assert!(KerningTable::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_default_font_kerning() {
+        let font = DEFAULT_FONT.load().unwrap();
+        let table = KerningTable::from_font(&font);
+        let glyph = |char| font.glyph_for_char(char).unwrap();
+
+        assert!(!table.is_empty());
+        assert!(table.kerning(glyph('A'), glyph('V')) < 0);
+        assert!(table.kerning(glyph('T'), glyph('o')) < 0);
+        assert!(table.kerning(glyph('H'), glyph('H')) == 0);
+    }
+}
@@ -1,9 +1,24 @@
 #[cfg(feature = "discord")]
 pub mod discord_audio;
+#[cfg(any(feature = "discord", test))]
+pub mod discord_commands;
+#[cfg(any(feature = "discord", test))]
+pub mod voice_sessions;
 
+pub mod baked_video_player;
+pub mod game_input;
 pub mod game_player;
+pub mod game_replay;
+pub mod kerning;
+pub mod osd;
 pub(crate) mod player_context;
+pub mod sprite;
+pub mod sync_clock;
+pub mod synced_player;
+pub mod text;
 
+#[cfg(feature = "ffmpeg")]
+pub mod audio_player;
 #[cfg(feature = "ffmpeg")]
 pub mod multi_video_player;
 #[cfg(feature = "ffmpeg")]
@@ -14,6 +29,11 @@ pub mod x11_player;
 #[cfg(all(target_os = "linux", feature = "ffmpeg"))]
 pub mod blazing_linux_player;
 
+#[cfg(all(test, feature = "ffmpeg", not(feature = "skip_buildrs")))]
+mod harness;
+#[cfg(all(test, not(feature = "skip_buildrs")))]
+mod golden;
+
 #[cfg(all(feature = "external_player", feature = "ffmpeg"))]
 //#[cfg(feature = "ffmpeg")]
 pub mod external_player;
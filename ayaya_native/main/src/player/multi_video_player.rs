@@ -17,14 +17,21 @@ use ffmpeg::{rescale, Error, Rescale};
 use tokio::sync::oneshot::error::TryRecvError;
 use tokio::sync::{broadcast, oneshot};
 
+use crate::assets::AssetLoader;
 use crate::colorlib::transform_frame_to_mc;
 use crate::map_server::{MapServer, MapServerData, ServerOptions};
 use crate::player::player_context::{receive_and_process_decoded_frames, VideoData};
+use crate::subtitles::{self, SubtitleOverlay};
 use crate::{ffmpeg_set_multithreading, SplittedFrame, VideoPlayer, TOKIO_RUNTIME};
 
-use super::player_context::{wrap_frame, FrameWithIdentifier, NativeCommunication, VideoFrame};
+use super::osd::{Osd, PausedFrame};
+use super::player_context::{
+    frame_position, wrap_frame, FrameWithIdentifier, NativeCommunication, VideoFrame,
+};
+use super::sync_clock::MasterClock;
 
 pub struct MultiVideoPlayer {
+    file_name: String,
     width: i32,
     height: i32,
     fps: i32,
@@ -35,6 +42,8 @@ pub struct MultiVideoPlayer {
     stop_tx: oneshot::Sender<bool>,
     seek_tx: broadcast::Sender<i32>,
     seek_rx: broadcast::Receiver<i32>,
+    subtitle_tx: mpsc::Sender<Option<SubtitleOverlay>>,
+    osd: Arc<Mutex<Osd>>,
 }
 
 impl MultiVideoPlayer {
@@ -65,37 +74,15 @@ impl VideoPlayer for MultiVideoPlayer {
         let (data_tx, data_rx) = mpsc::sync_channel::<i32>(3);
         let (frames_tx, frames_rx) = mpsc::sync_channel::<FrameWithIdentifier>(100);
 
-        let mut reciver: Option<Arc<Mutex<tokio::sync::mpsc::Receiver<FrameWithIdentifier>>>> =
-            None;
-        let (server_tx, server_rx) = oneshot::channel::<anyhow::Result<MapServerData>>();
-
-        match map_server_options.use_server {
-            true => {
-                let frame_index_clone = frame_index.clone();
-                handle.spawn(async move {
-                    let result = MapServer::create(
-                        &map_server_options.clone(),
-                        frame_index_clone,
-                        global_rx,
-                    )
-                    .await;
-                    server_tx
-                        .send(result)
-                        .expect("Cannot send map server creation result");
-                });
-            }
-            false => {
-                reciver = Some(Arc::new(Mutex::new(global_rx)));
-                server_tx.send(Ok(None)).unwrap();
-            }
-        };
-
-        let map_server = server_rx.blocking_recv()??;
+        let assets = AssetLoader::new(map_server_options.asset_dir.as_deref());
         let (processing_sleep_tx, processing_sleep_rx) = mpsc::sync_channel::<bool>(3);
 
         let (stop_tx, mut stop_rx) = oneshot::channel::<bool>();
         let (seek_tx, seek_rx) = broadcast::channel::<i32>(5);
         let mut seek_rx_clone = seek_rx.resubscribe();
+        let (subtitle_tx, subtitle_rx) = mpsc::channel::<Option<SubtitleOverlay>>();
+        let (osd_tx, osd_rx) = mpsc::sync_channel::<Arc<Mutex<Osd>>>(1);
+        let file_name_clone = file_name.clone();
 
         thread::spawn(move || {
             ffmpeg::init().expect("Couldn't init ffmpeg!");
@@ -107,6 +94,7 @@ impl VideoPlayer for MultiVideoPlayer {
                     .expect("Couldn't create async video stream");
 
                 let video_stream_index = input.index();
+                let time_base = input.time_base();
 
                 let context_decoder =
                     ffmpeg::codec::context::Context::from_parameters(input.parameters())
@@ -139,6 +127,18 @@ impl VideoPlayer for MultiVideoPlayer {
                 .expect("Couldn't get async scaler");
 
                 let mut frame_id: i64 = 0;
+                let mut subtitles: Option<SubtitleOverlay> = None;
+                let duration = match ictx.duration() {
+                    duration if duration > 0 => Some(Duration::from_micros(duration as u64)),
+                    _ => None,
+                };
+                let osd = Arc::new(Mutex::new(Osd::new(
+                    duration,
+                    assets,
+                    width as usize,
+                    height as usize,
+                )));
+                osd_tx.send(osd.clone()).unwrap();
 
                 'main: loop {
                     if processing_sleep_rx.try_recv().is_ok() {
@@ -177,7 +177,7 @@ impl VideoPlayer for MultiVideoPlayer {
                         frame_id = -1;
                     }
 
-                    let frame = match MultiVideoPlayer::decode_frame(
+                    let mut frame = match MultiVideoPlayer::decode_frame(
                         &mut ictx,
                         video_stream_index,
                         &mut decoder,
@@ -198,12 +198,42 @@ impl VideoPlayer for MultiVideoPlayer {
                         }
                     };
 
+                    while let Ok(overlay) = subtitle_rx.try_recv() {
+                        subtitles = overlay;
+                    }
+                    let position = frame_position(&frame, time_base);
+
+                    //Drawn here and not in the transform task, because the overlay is not shared
+                    let burned = match &mut subtitles {
+                        Some(overlay) => {
+                            let stride = frame.stride(0);
+                            overlay.burn(
+                                frame.data_mut(0),
+                                width as usize,
+                                height as usize,
+                                stride,
+                                position,
+                            )
+                        }
+                        None => Ok(()),
+                    };
+                    if let Err(err) = burned {
+                        println!("[ProjectAyaya] Unable to draw subtitles! Reason: {err}");
+                        subtitles = None;
+                    }
+
                     let (splitted_frames, all_frames_x, all_frames_y) = frame_initial_split.clone();
+                    //Layer is taken here, frames are converted out of order
+                    let osd_layer = osd.lock().expect("Couldn't lock OSD").layer(position);
 
                     let sender = frames_tx.clone();
 
                     handle.spawn(async move {
                         let vec = transform_frame_to_mc(frame.data(0), width as usize, height as usize, frame.stride(0));
+                        let vec = match osd_layer {
+                            Some(layer) => layer.draw_on_frame(vec, width as usize, height as usize),
+                            None => vec,
+                        };
                         let vec = SplittedFrame::split_frames(vec.as_slice(), &splitted_frames, width as usize, all_frames_x, all_frames_y).expect("Couldn't split frames async");
 
                         let frame_with_id = FrameWithIdentifier {
@@ -235,7 +265,8 @@ impl VideoPlayer for MultiVideoPlayer {
 
         thread::spawn(move || {
             let mut frame_hash_map: HashMap<u64, FrameWithIdentifier> = HashMap::new();
-            let mut last_id: i64 = 0;
+            //Frame ids start at zero
+            let mut last_id: i64 = -1;
 
             'decode_loop: loop {
                 if last_id - frame_index.load(Relaxed) > 80 {
@@ -252,7 +283,7 @@ impl VideoPlayer for MultiVideoPlayer {
                     }
                 }
 
-                let cached_frame = frame_hash_map.remove(&(last_id as u64 + 1_u64));
+                let cached_frame = frame_hash_map.remove(&((last_id + 1) as u64));
 
                 if let Some(cached_frame) = cached_frame {
                     last_id += 1;
@@ -277,7 +308,6 @@ impl VideoPlayer for MultiVideoPlayer {
                     }
                 };
 
-                //We have a single dropped frame somewhere here but I don't care enough to fix this
                 if frame.id == last_id + 1 || frame.id == -1 {
                     if frame.id == -1 {
                         frame_hash_map.clear();
@@ -303,8 +333,40 @@ impl VideoPlayer for MultiVideoPlayer {
         let width = data_rx.recv().unwrap();
         let height = data_rx.recv().unwrap();
         let fps = data_rx.recv().unwrap();
+        let osd = osd_rx.recv().unwrap();
+
+        let mut reciver: Option<Arc<Mutex<tokio::sync::mpsc::Receiver<FrameWithIdentifier>>>> =
+            None;
+        let (server_tx, server_rx) = oneshot::channel::<anyhow::Result<MapServerData>>();
+
+        //Created after the decoder, the server redraws paused frames at the video size
+        match map_server_options.use_server {
+            true => {
+                let paused_frame = PausedFrame::new(osd.clone(), width as usize, height as usize)?;
+                let frame_index = frame_index_clone.clone();
+                TOKIO_RUNTIME.handle().spawn(async move {
+                    let result = MapServer::create(
+                        &map_server_options.clone(),
+                        frame_index,
+                        global_rx,
+                        Some(paused_frame),
+                    )
+                    .await;
+                    server_tx
+                        .send(result)
+                        .expect("Cannot send map server creation result");
+                });
+            }
+            false => {
+                reciver = Some(Arc::new(Mutex::new(global_rx)));
+                server_tx.send(Ok(None)).unwrap();
+            }
+        };
+
+        let map_server = server_rx.blocking_recv()??;
 
         let multi_video_player = MultiVideoPlayer {
+            file_name: file_name_clone,
             width,
             height,
             fps,
@@ -314,6 +376,8 @@ impl VideoPlayer for MultiVideoPlayer {
             stop_tx,
             seek_tx,
             seek_rx,
+            subtitle_tx,
+            osd,
         };
         Ok(multi_video_player)
     }
@@ -367,12 +431,38 @@ impl VideoPlayer for MultiVideoPlayer {
         Ok(())
     }
 
+    fn clock(&self) -> Option<Arc<MasterClock>> {
+        self.map_server.as_ref().map(|server| server.clock())
+    }
+
+    fn osd(&self) -> Option<Arc<Mutex<Osd>>> {
+        Some(self.osd.clone())
+    }
+
     fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
-        match &self.map_server {
-            Some(server) => {
-                let server = server.clone();
-                server.send_message(msg)?;
+        //Subtitles and the OSD are drawn by the decoding thread, so they work with the map server too
+        if let NativeCommunication::Subtitles { options } = msg {
+            let overlay = subtitles::create_overlay(&self.file_name, options)?;
+            self.subtitle_tx.send(overlay)?;
+            return Ok(());
+        }
+        if let NativeCommunication::Osd { command } = msg {
+            match self.osd.lock() {
+                Ok(mut osd) => osd.apply(command),
+                Err(_) => return Err(anyhow!("Unable to lock OSD mutex")),
             }
+            return Ok(());
+        }
+
+        match &self.map_server {
+            //The map server only handles start and stop, the caller gets an error instead of a logged message
+            Some(server) => match msg {
+                NativeCommunication::StartRendering { .. } | NativeCommunication::StopRendering => {
+                    let server = server.clone();
+                    server.send_message(msg)?;
+                }
+                _ => return Err(anyhow!("Map server only accepts start and stop messages!")),
+            },
             None => match msg {
                 NativeCommunication::VideoSeek { second } => {
                     self.seek_tx.send(second)?;
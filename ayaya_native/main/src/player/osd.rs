@@ -0,0 +1,734 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::assets::AssetLoader;
+use crate::colorlib::Color;
+use crate::splitting::SplittedFrame;
+
+use super::game_player::{BakedImage, VideoCanvas};
+use super::text::{measure_text, TextLayout, TextMask, TextStyle};
+
+//Timing is in video time, so frames decoded ahead of playback still fade at the right moment
+static FADE: Duration = Duration::from_millis(400);
+static HOLD: Duration = Duration::from_secs(3);
+static MARGIN: usize = 4;
+
+static TEXT_COLOR: Color = Color::new(255, 255, 255);
+static BAR_COLOR: Color = Color::new(60, 60, 60);
+static BACKGROUND_COLOR: Color = Color::new(25, 25, 25);
+
+/// "title:<text>", "logo:<asset name>", "progress", "pause", "resume" or "hide"
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsdCommand {
+    Title(String),
+    Logo(String),
+    Progress,
+    Paused,
+    Resumed,
+    Hide,
+}
+
+impl OsdCommand {
+    pub fn parse(command: &str) -> anyhow::Result<Self> {
+        //Characters that the font does not have are drawn as the replacement character
+        if let Some(title) = command.strip_prefix("title:") {
+            let title = title.trim();
+
+            return match title.is_empty() {
+                true => Err(anyhow!("OSD title is empty")),
+                false => Ok(Self::Title(title.to_string())),
+            };
+        }
+
+        //The name is checked by the asset loader
+        if let Some(logo) = command.strip_prefix("logo:") {
+            return Ok(Self::Logo(logo.trim().to_string()));
+        }
+
+        match command {
+            "progress" => Ok(Self::Progress),
+            "pause" => Ok(Self::Paused),
+            "resume" => Ok(Self::Resumed),
+            "hide" => Ok(Self::Hide),
+            _ => Err(anyhow!("Invalid OSD command ({command})")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+    //Position of the first frame that showed the element
+    start: Option<Duration>,
+    fade_in: bool,
+    //None keeps the element until it is replaced
+    hold: Option<Duration>,
+}
+
+impl Fade {
+    fn new(fade_in: bool, hold: Option<Duration>) -> Self {
+        Self {
+            start: None,
+            fade_in,
+            hold,
+        }
+    }
+
+    //None when the element faded out
+    fn opacity(&mut self, position: Duration) -> Option<f32> {
+        //Seeking back restarts the animation
+        let start = match self.start {
+            Some(start) if start <= position => start,
+            _ => *self.start.insert(position),
+        };
+        let elapsed = position - start;
+
+        let fade_in = match self.fade_in {
+            true => FADE,
+            false => Duration::ZERO,
+        };
+        if elapsed < fade_in {
+            return Some(elapsed.as_secs_f32() / FADE.as_secs_f32());
+        }
+
+        let Some(hold) = self.hold else {
+            return Some(1.0);
+        };
+        let fade_out = elapsed - fade_in;
+        if fade_out < hold {
+            return Some(1.0);
+        }
+
+        let fade_out = fade_out - hold;
+        match fade_out < FADE {
+            true => Some(1.0 - fade_out.as_secs_f32() / FADE.as_secs_f32()),
+            false => None,
+        }
+    }
+}
+
+/// On screen display of a player. Commands change it, every frame gets a layer to draw
+pub struct Osd {
+    duration: Option<Duration>,
+    assets: AssetLoader,
+    //Video size, titles are laid out for it when they are set
+    width: usize,
+    height: usize,
+    title: Option<(Arc<TextMask>, Fade)>,
+    //Stays until it is hidden or replaced
+    logo: Option<(Arc<BakedImage>, Fade)>,
+    progress: Option<Fade>,
+    pause: Option<Fade>,
+}
+
+impl Osd {
+    pub fn new(
+        duration: Option<Duration>,
+        assets: AssetLoader,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        Self {
+            duration,
+            assets,
+            width,
+            height,
+            title: None,
+            logo: None,
+            progress: None,
+            pause: None,
+        }
+    }
+
+    pub fn apply(&mut self, command: OsdCommand) {
+        match command {
+            OsdCommand::Title(title) => match layout_title(&title, self.width, self.height) {
+                Ok(title) => self.title = Some((Arc::new(title), Fade::new(true, Some(HOLD)))),
+                Err(err) => println!("[ProjectAyaya] Unable to show OSD title! Reason: {err}"),
+            },
+            OsdCommand::Logo(name) => match self.assets.image(&name) {
+                Ok(logo) => self.logo = Some((logo, Fade::new(true, None))),
+                Err(err) => println!("[ProjectAyaya] Unable to show OSD logo! Reason: {err}"),
+            },
+            OsdCommand::Progress => self.progress = Some(Fade::new(true, Some(HOLD))),
+            //Paused players may not load another frame for a long time, so the glyph shows at once
+            OsdCommand::Paused => self.pause = Some(Fade::new(false, None)),
+            OsdCommand::Resumed => {
+                if self.pause.is_some() {
+                    self.pause = Some(Fade::new(false, Some(Duration::ZERO)));
+                }
+            }
+            OsdCommand::Hide => {
+                self.title = None;
+                self.logo = None;
+                self.progress = None;
+                self.pause = None;
+            }
+        }
+    }
+
+    //None when there is nothing to draw, so the frame does not have to be copied
+    pub fn layer(&mut self, position: Duration) -> Option<OsdLayer> {
+        let title = match &mut self.title {
+            Some((title, fade)) => fade
+                .opacity(position)
+                .map(|opacity| (title.clone(), opacity)),
+            None => None,
+        };
+        let logo = match &mut self.logo {
+            Some((logo, fade)) => fade
+                .opacity(position)
+                .map(|opacity| (logo.clone(), opacity)),
+            None => None,
+        };
+        let progress = self
+            .progress
+            .as_mut()
+            .and_then(|fade| fade.opacity(position));
+        let pause = self.pause.as_mut().and_then(|fade| fade.opacity(position));
+
+        //Faded out elements are removed
+        if title.is_none() {
+            self.title = None;
+        }
+        if progress.is_none() {
+            self.progress = None;
+        }
+        if pause.is_none() {
+            self.pause = None;
+        }
+
+        if title.is_none() && logo.is_none() && progress.is_none() && pause.is_none() {
+            return None;
+        }
+
+        Some(OsdLayer {
+            title,
+            logo,
+            progress: progress.map(|opacity| (position, self.duration, opacity)),
+            pause,
+        })
+    }
+
+    //Only the pause glyph, the frame on screen already has everything else
+    fn pause_layer(&self) -> Option<OsdLayer> {
+        //Resumed glyphs have a hold and fade out on the next frames
+        self.pause
+            .filter(|fade| fade.hold.is_none())
+            .map(|_| OsdLayer {
+                title: None,
+                logo: None,
+                progress: None,
+                pause: Some(1.0),
+            })
+    }
+}
+
+/// Paused players do not decode new frames, so the pause glyph is drawn over the frame on screen
+pub struct PausedFrame {
+    osd: Arc<Mutex<Osd>>,
+    splitted_frames: Vec<SplittedFrame>,
+    width: usize,
+    height: usize,
+    all_frames_x: usize,
+    all_frames_y: usize,
+    //Frame without the glyph, while the glyph is on screen
+    clean_frame: Option<Vec<i8>>,
+}
+
+impl PausedFrame {
+    pub fn new(osd: Arc<Mutex<Osd>>, width: usize, height: usize) -> anyhow::Result<Self> {
+        let (splitted_frames, all_frames_x, all_frames_y) =
+            SplittedFrame::initialize_frames(width, height)?;
+
+        Ok(Self {
+            osd,
+            splitted_frames,
+            width,
+            height,
+            all_frames_x,
+            all_frames_y,
+            clean_frame: None,
+        })
+    }
+
+    //Takes the split frame on screen. Returns the frame to show instead, None when it is up to date
+    pub fn redraw(&mut self, frame: &[i8]) -> anyhow::Result<Option<Vec<i8>>> {
+        let layer = match self.osd.lock() {
+            Ok(osd) => osd.pause_layer(),
+            Err(_) => return Err(anyhow!("Unable to lock OSD mutex")),
+        };
+
+        match (layer, self.clean_frame.take()) {
+            (Some(_), Some(clean_frame)) => {
+                self.clean_frame = Some(clean_frame);
+                Ok(None)
+            }
+            (Some(layer), None) => {
+                let merged = SplittedFrame::merge_frames(
+                    frame,
+                    &self.splitted_frames,
+                    self.width,
+                    self.height,
+                    self.all_frames_x,
+                    self.all_frames_y,
+                )?;
+                let merged = layer.draw_on_frame(merged, self.width, self.height);
+                let redrawn = SplittedFrame::split_frames(
+                    merged.as_slice(),
+                    &self.splitted_frames,
+                    self.width,
+                    self.all_frames_x,
+                    self.all_frames_y,
+                )?;
+
+                self.clean_frame = Some(frame.to_vec());
+                Ok(Some(redrawn))
+            }
+            (None, clean_frame) => Ok(clean_frame),
+        }
+    }
+
+    //A new frame was shown, it has the OSD of its own
+    pub fn reset(&mut self) {
+        self.clean_frame = None;
+    }
+}
+
+/// What the OSD shows on a single frame, with the opacity of every element
+#[derive(Debug)]
+pub struct OsdLayer {
+    title: Option<(Arc<TextMask>, f32)>,
+    logo: Option<(Arc<BakedImage>, f32)>,
+    //Position, video duration (unknown for streams)
+    progress: Option<(Duration, Option<Duration>, f32)>,
+    pause: Option<f32>,
+}
+
+fn format_timestamp(position: Duration) -> String {
+    let seconds = position.as_secs();
+    match seconds >= 3600 {
+        true => format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        ),
+        false => format!("{}:{:02}", seconds / 60, seconds % 60),
+    }
+}
+
+struct OsdText {
+    style: TextStyle,
+    font_size: usize,
+    line_height: usize,
+}
+
+impl OsdText {
+    fn new(height: usize) -> anyhow::Result<Self> {
+        let font_size = (height / 12).clamp(8, 32);
+        let style = TextStyle::new(font_size as f32, TEXT_COLOR);
+        let line_height = measure_text("0", &style, None)?.height;
+
+        Ok(Self {
+            style,
+            font_size,
+            line_height,
+        })
+    }
+
+    fn width(&self, text: &str) -> anyhow::Result<usize> {
+        Ok(measure_text(text, &self.style, None)?.width)
+    }
+
+    fn draw(&self, canvas: &mut VideoCanvas, x: usize, y: usize, text: &str) -> anyhow::Result<()> {
+        canvas.draw_text(x as i32, y as i32, &self.style, text)?;
+        Ok(())
+    }
+}
+
+//Long titles are cut at the end. Done once when the title is set, drawing only blends the mask
+fn layout_title(title: &str, width: usize, height: usize) -> anyhow::Result<TextMask> {
+    let text = OsdText::new(height)?;
+    let max_width = width.saturating_sub(MARGIN * 2);
+
+    //Longest prefix that fits, text only gets wider with more characters
+    let ends: Vec<usize> = title
+        .char_indices()
+        .map(|(index, char)| index + char.len_utf8())
+        .collect();
+    let (mut low, mut high) = (0, ends.len());
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        match text.width(&title[..ends[middle - 1]])? <= max_width {
+            true => low = middle,
+            false => high = middle - 1,
+        }
+    }
+
+    let title = match low {
+        0 => "",
+        count => title[..ends[count - 1]].trim_end(),
+    };
+    TextLayout::new(title, &text.style, None)?.to_mask()
+}
+
+impl OsdLayer {
+    pub fn draw(&self, canvas: &mut VideoCanvas) -> anyhow::Result<()> {
+        let text = OsdText::new(canvas.height)?;
+        //Elements that would not fit are skipped
+        if canvas.width < text.font_size + MARGIN * 2
+            || canvas.height < text.line_height * 2 + MARGIN * 4
+        {
+            return Ok(());
+        }
+
+        //Elements are drawn in place, faded ones write only a share of their pixels
+        let drawn = self.draw_elements(canvas, &text);
+        canvas.reset_opacity();
+        drawn
+    }
+
+    fn draw_elements(&self, canvas: &mut VideoCanvas, text: &OsdText) -> anyhow::Result<()> {
+        if let Some((title, opacity)) = &self.title {
+            canvas.set_opacity(*opacity);
+            title.draw(canvas, MARGIN as i32, MARGIN as i32);
+        }
+
+        //Top right corner, logos larger than the frame are not drawn
+        if let Some((logo, opacity)) = &self.logo {
+            let (width, height) = (logo.width as usize, logo.height as usize);
+            if width + MARGIN * 2 <= canvas.width && height + MARGIN * 2 <= canvas.height {
+                canvas.set_opacity(*opacity);
+                canvas.draw_image_transparent(
+                    (canvas.width - MARGIN - width) as i32,
+                    MARGIN as i32,
+                    logo,
+                );
+            }
+        }
+
+        if let Some((position, duration, opacity)) = &self.progress {
+            canvas.set_opacity(*opacity);
+            self.draw_progress(canvas, text, *position, *duration)?;
+        }
+
+        if let Some(opacity) = self.pause {
+            canvas.set_opacity(opacity);
+            Self::draw_pause(canvas);
+        }
+
+        Ok(())
+    }
+
+    fn draw_progress(
+        &self,
+        canvas: &mut VideoCanvas,
+        text: &OsdText,
+        position: Duration,
+        duration: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        let bar_height = (canvas.height / 40).clamp(2, 8);
+        let (x1, x2) = (MARGIN, canvas.width - MARGIN - 1);
+        let (y1, y2) = (
+            canvas.height - MARGIN - bar_height,
+            canvas.height - MARGIN - 1,
+        );
+        let text_y = y1 - MARGIN / 2 - text.line_height;
+
+        let elapsed = format_timestamp(position);
+        let elapsed_width = text.width(&elapsed)?;
+        if x1 + elapsed_width <= canvas.width {
+            text.draw(canvas, x1, text_y, &elapsed)?;
+        }
+
+        //Without a duration only the position is known
+        let Some(duration) = duration.filter(|duration| !duration.is_zero()) else {
+            return Ok(());
+        };
+
+        let total = format_timestamp(duration);
+        let total_x = x2 + 1 - text.width(&total)?.min(x2 + 1);
+        if total_x > x1 + elapsed_width {
+            text.draw(canvas, total_x, text_y, &total)?;
+        }
+
+        canvas.draw_square(x1, y1, x2, y2, &BAR_COLOR);
+        let played = (position.as_secs_f64() / duration.as_secs_f64()).min(1.0);
+        let played_x = x1 + ((x2 - x1) as f64 * played) as usize;
+        canvas.draw_square(x1, y1, played_x, y2, &TEXT_COLOR);
+
+        Ok(())
+    }
+
+    //Two bars in the middle of the video
+    fn draw_pause(canvas: &mut VideoCanvas) {
+        let size = (canvas.height.min(canvas.width) / 4).clamp(6, 64);
+        let (x, y) = ((canvas.width - size) / 2, (canvas.height - size) / 2);
+        let bar_width = size / 3;
+
+        canvas.draw_square(
+            x - MARGIN.min(x),
+            y - MARGIN.min(y),
+            (x + size + MARGIN).min(canvas.width - 1),
+            (y + size + MARGIN).min(canvas.height - 1),
+            &BACKGROUND_COLOR,
+        );
+        canvas.draw_square(x, y, x + bar_width - 1, y + size - 1, &TEXT_COLOR);
+        canvas.draw_square(
+            x + size - bar_width,
+            y,
+            x + size - 1,
+            y + size - 1,
+            &TEXT_COLOR,
+        );
+    }
+
+    //Takes a frame from transform_frame_to_mc. The OSD is not worth failing the frame over
+    pub fn draw_on_frame(&self, frame: Vec<i8>, width: usize, height: usize) -> Vec<i8> {
+        let mut canvas = VideoCanvas::from_mc_frame(width, height, &frame);
+
+        match self.draw(&mut canvas) {
+            Ok(_) => canvas.to_mc_frame(),
+            Err(err) => {
+                println!("[ProjectAyaya] Unable to draw OSD! Reason: {err}");
+                frame
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert!(
+            OsdCommand::parse("title: Ayaya.mp4 ").unwrap()
+                == OsdCommand::Title("Ayaya.mp4".to_string())
+        );
+        //Unsupported characters are kept, the layout draws the replacement character
+        assert!(
+            OsdCommand::parse("title:Op \u{1F600}").unwrap()
+                == OsdCommand::Title("Op \u{1F600}".to_string())
+        );
+        assert!(OsdCommand::parse("title:  ").is_err());
+        assert!(OsdCommand::parse("pause").unwrap() == OsdCommand::Paused);
+        assert!(
+            OsdCommand::parse("logo: server ").unwrap() == OsdCommand::Logo("server".to_string())
+        );
+        assert!(OsdCommand::parse("volume").is_err());
+    }
+
+    #[test]
+    fn test_fade_timing() {
+        let mut osd = Osd::new(
+            Some(Duration::from_secs(60)),
+            AssetLoader::default(),
+            256,
+            128,
+        );
+        assert!(osd.layer(millis(0)).is_none());
+
+        //Starts at the first frame after the command, not at zero
+        osd.apply(OsdCommand::Title("Ayaya".to_string()));
+        let title_opacity = |osd: &mut Osd, position| {
+            osd.layer(millis(position))
+                .and_then(|layer| layer.title)
+                .map(|(_, opacity)| opacity)
+        };
+        assert!(title_opacity(&mut osd, 10_000) == Some(0.0));
+        assert!(title_opacity(&mut osd, 10_200) == Some(0.5));
+        assert!(title_opacity(&mut osd, 12_000) == Some(1.0));
+        assert!(title_opacity(&mut osd, 13_600).unwrap() > 0.4);
+        assert!(title_opacity(&mut osd, 13_800).is_none());
+        assert!(osd.layer(millis(10_000)).is_none());
+
+        //Seeking back restarts the fade in
+        osd.apply(OsdCommand::Progress);
+        let layer = osd.layer(millis(5000)).unwrap();
+        assert!(layer.progress == Some((millis(5000), Some(Duration::from_secs(60)), 0.0)));
+        assert!(osd.layer(millis(1000)).unwrap().progress.unwrap().2 == 0.0);
+        osd.apply(OsdCommand::Hide);
+        assert!(osd.layer(millis(1000)).is_none());
+    }
+
+    #[test]
+    fn test_title_layout() {
+        let (width, height) = (256, 128);
+        let mut osd = Osd::new(None, AssetLoader::default(), width, height);
+
+        osd.apply(OsdCommand::Title("Ayaya".to_string()));
+        let short_width = osd.title.as_ref().unwrap().0.width;
+        assert!(short_width > 0 && short_width < width / 2);
+
+        //Long titles are cut to the frame width
+        osd.apply(OsdCommand::Title("Ayaya ".repeat(100)));
+        let long_width = osd.title.as_ref().unwrap().0.width;
+        assert!(long_width <= width - MARGIN * 2 && long_width > width / 2);
+
+        //Nothing fits, the title is empty
+        let mut osd = Osd::new(None, AssetLoader::default(), MARGIN * 2, height);
+        osd.apply(OsdCommand::Title("Ayaya".to_string()));
+        assert!(osd.title.as_ref().unwrap().0.width == 0);
+    }
+
+    #[test]
+    fn test_pause_glyph() {
+        let mut osd = Osd::new(None, AssetLoader::default(), 256, 128);
+
+        //Resuming without a pause does nothing
+        osd.apply(OsdCommand::Resumed);
+        assert!(osd.layer(millis(0)).is_none());
+
+        osd.apply(OsdCommand::Paused);
+        assert!(osd.layer(millis(1000)).unwrap().pause == Some(1.0));
+        assert!(osd.layer(millis(60_000)).unwrap().pause == Some(1.0));
+
+        osd.apply(OsdCommand::Resumed);
+        assert!(osd.layer(millis(60_000)).unwrap().pause == Some(1.0));
+        assert!(osd.layer(millis(60_300)).unwrap().pause.unwrap() < 0.5);
+        assert!(osd.layer(millis(60_400)).is_none());
+    }
+
+    #[cfg(not(feature = "skip_buildrs"))]
+    #[test]
+    fn test_paused_frame() {
+        let (width, height) = (256, 256);
+        let frame = vec![1i8; width * height];
+        let osd = Arc::new(Mutex::new(Osd::new(
+            None,
+            AssetLoader::default(),
+            width,
+            height,
+        )));
+        let mut paused_frame = PausedFrame::new(osd.clone(), width, height).unwrap();
+        assert!(paused_frame.redraw(&frame).unwrap().is_none());
+
+        osd.lock().unwrap().apply(OsdCommand::Paused);
+        let redrawn = paused_frame.redraw(&frame).unwrap().unwrap();
+        assert!(redrawn != frame);
+        //Only changes are returned
+        assert!(paused_frame.redraw(&redrawn).unwrap().is_none());
+
+        //Resuming takes the glyph off the frame on screen
+        osd.lock().unwrap().apply(OsdCommand::Resumed);
+        assert!(paused_frame.redraw(&redrawn).unwrap().unwrap() == frame);
+        assert!(paused_frame.redraw(&frame).unwrap().is_none());
+
+        //Pausing on a newly shown frame draws over that frame
+        osd.lock().unwrap().apply(OsdCommand::Paused);
+        paused_frame.redraw(&frame).unwrap();
+        paused_frame.reset();
+        let next_frame = vec![2i8; width * height];
+        assert!(paused_frame.redraw(&next_frame).unwrap().unwrap()[0] == 2);
+    }
+
+    #[test]
+    fn test_blend_dither() {
+        let frame = vec![1i8; 16 * 8];
+        let overlay = VideoCanvas::from_mc_frame(16, 8, &[2i8; 16 * 8]);
+
+        let covered = |opacity: f32| {
+            let mut canvas = VideoCanvas::from_mc_frame(16, 8, &frame);
+            canvas.blend(&overlay, opacity);
+            canvas
+                .to_mc_frame()
+                .iter()
+                .filter(|pixel| **pixel == 2)
+                .count()
+        };
+
+        assert!(covered(0.0) == 0);
+        assert!(covered(0.5) == 64);
+        assert!(covered(0.25) == 32);
+        assert!(covered(1.0) == 128);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert!(format_timestamp(millis(75_900)) == "1:15");
+        assert!(format_timestamp(Duration::from_secs(3725)) == "1:02:05");
+    }
+
+    #[cfg(not(feature = "skip_buildrs"))]
+    #[test]
+    fn test_draw_layer() {
+        let (width, height) = (256, 128);
+        let frame = vec![Color::new(0, 120, 0).convert_to_mc() as i8; width * height];
+
+        let mut osd = Osd::new(
+            Some(Duration::from_secs(60)),
+            AssetLoader::default(),
+            width,
+            height,
+        );
+        osd.apply(OsdCommand::Progress);
+        osd.apply(OsdCommand::Paused);
+        osd.layer(millis(30_000));
+        let drawn = osd
+            .layer(millis(31_000))
+            .unwrap()
+            .draw_on_frame(frame.clone(), width, height);
+        assert!(drawn.len() == frame.len());
+
+        let white = TEXT_COLOR.convert_to_mc() as i8;
+        let row = |y: usize| &drawn[y * width..(y + 1) * width];
+        //Half of the bar is played
+        let bar = row(height - MARGIN - 1);
+        assert!(bar[MARGIN] == white && bar[width / 2 - 8] == white);
+        assert!(bar[width - MARGIN - 8] == BAR_COLOR.convert_to_mc() as i8);
+        //Pause glyph in the middle, nothing at the top
+        assert!(row(height / 2).contains(&white));
+        assert!(row(0).iter().all(|pixel| *pixel == frame[0]));
+
+        //Missing logos are ignored
+        osd.apply(OsdCommand::Logo("missing".to_string()));
+        assert!(osd.logo.is_none());
+
+        //Too small frames are left alone
+        let tiny = vec![0i8; 8 * 8];
+        assert!(
+            osd.layer(millis(31_000))
+                .unwrap()
+                .draw_on_frame(tiny.clone(), 8, 8)
+                == tiny
+        );
+    }
+
+    #[cfg(not(feature = "skip_buildrs"))]
+    #[test]
+    fn test_draw_logo() {
+        let red = Color::new(255, 0, 0).convert_to_mc();
+        let logo = Arc::new(BakedImage {
+            width: 2,
+            height: 1,
+            data: vec![red, 0].into(),
+        });
+        let (width, height) = (64, 64);
+        let frame = vec![Color::new(0, 120, 0).convert_to_mc() as i8; width * height];
+
+        let mut osd = Osd::new(None, AssetLoader::default(), width, height);
+        osd.logo = Some((logo, Fade::new(false, None)));
+        let drawn = osd
+            .layer(millis(0))
+            .unwrap()
+            .draw_on_frame(frame.clone(), width, height);
+        let row = &drawn[MARGIN * width..(MARGIN + 1) * width];
+        assert!(row[width - MARGIN - 2] == red as i8);
+        //Transparent pixel keeps the video
+        assert!(row[width - MARGIN - 1] == frame[0]);
+        assert!(drawn.iter().filter(|pixel| **pixel == red as i8).count() == 1);
+
+        //Kept until hidden
+        assert!(osd.layer(millis(600_000)).unwrap().logo.is_some());
+        osd.apply(OsdCommand::Hide);
+        assert!(osd.layer(millis(600_000)).is_none());
+    }
+}
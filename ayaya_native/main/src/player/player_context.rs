@@ -1,6 +1,7 @@
 use std::{
     fmt::Debug,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::anyhow;
@@ -9,12 +10,16 @@ use anyhow::anyhow;
 use {
     ffmpeg::frame::Video,
     ffmpeg::software::scaling::Context,
-    ffmpeg::{Error, Packet},
+    ffmpeg::{Error, Packet, Rational, Rescale},
 };
 
 use crate::map_server::ServerOptions;
+use crate::media_tracks::TrackSelection;
+use crate::subtitles::SubtitleOptions;
 
-use super::game_player::GameInputDirection;
+use super::game_input::{GameEvent, PlayerId};
+use super::osd::{Osd, OsdCommand};
+use super::sync_clock::{MasterClock, SyncStats};
 
 macro_rules! get_context {
     (
@@ -46,8 +51,14 @@ pub struct VideoData {
 pub enum NativeCommunication {
     StartRendering { fps: i32 },
     StopRendering,
-    GameInput { input: Vec<GameInputDirection> },
+    GameInput { input: Vec<(PlayerId, GameEvent)> },
     VideoSeek { second: i32 },
+    //Only players with an audio sink accept it
+    AudioTrack { track: TrackSelection },
+    //None turns subtitles off. Only the single and multi video players accept it
+    Subtitles { options: Option<SubtitleOptions> },
+    //Shown from the next decoded frame. Only the single and multi video players accept it
+    Osd { command: OsdCommand },
 }
 
 pub struct FrameWithIdentifier {
@@ -71,6 +82,13 @@ pub fn wrap_to_ptr(to_wrap: Box<dyn VideoPlayer>) -> i64 {
     Box::into_raw(Box::new(arc)) as *const () as i64
 }
 
+//Player stays alive after destroy, for callers that must not keep the pointer locked while using it.
+//Pointer has to be valid when it is retained
+#[cfg(feature = "discord")]
+pub fn retain(ptr: i64) -> Arc<Mutex<Box<dyn VideoPlayer>>> {
+    get_context!(ptr)
+}
+
 pub fn load_frame(ptr: i64) -> anyhow::Result<Box<dyn VideoFrame>> {
     let player_context = get_context!(ptr);
     let mut player_context = lock_mutex!(player_context);
@@ -92,6 +110,20 @@ pub fn pass_jvm_msg(ptr: i64, msg: NativeCommunication) -> anyhow::Result<()> {
     player_context.handle_jvm_msg(msg)
 }
 
+pub fn clock(ptr: i64) -> anyhow::Result<Option<Arc<MasterClock>>> {
+    let player_context = get_context!(ptr);
+    let player_context = lock_mutex!(player_context);
+
+    Ok(player_context.clock())
+}
+
+pub fn sync_stats(ptr: i64) -> anyhow::Result<SyncStats> {
+    match clock(ptr)? {
+        Some(clock) => Ok(clock.stats()),
+        None => Err(anyhow!("This player is not synchronized to a clock")),
+    }
+}
+
 pub fn destroy(ptr: i64) -> anyhow::Result<()> {
     let player_context = unsafe { Box::from_raw(ptr as *mut Arc<Mutex<Box<dyn VideoPlayer>>>) };
     let player_context = lock_mutex!(player_context);
@@ -125,9 +157,18 @@ pub fn receive_and_process_decoded_frames(
     scaler
         .run(&decoded, &mut rgb_frame)
         .expect("Scaler run failed");
+    //Scaler does not copy the timestamp, subtitles need it
+    rgb_frame.set_pts(decoded.timestamp());
     Ok(rgb_frame)
 }
 
+//Position of a frame from receive_and_process_decoded_frames, time_base is the one of its stream
+#[cfg(feature = "ffmpeg")]
+pub fn frame_position(frame: &Video, time_base: Rational) -> Duration {
+    let millis = frame.pts().unwrap_or(0).rescale(time_base, (1, 1000));
+    Duration::from_millis(millis.max(0) as u64)
+}
+
 pub trait VideoFrame {
     fn data(&self) -> &Vec<i8>;
 }
@@ -156,6 +197,14 @@ pub trait VideoPlayer {
     fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>>;
     fn video_data(&self) -> anyhow::Result<VideoData>;
     fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()>;
+    //Master clock that the player follows. None when the JVM alone decides when frames are shown
+    fn clock(&self) -> Option<Arc<MasterClock>> {
+        None
+    }
+    //On screen display of the player. None when the player has no OSD
+    fn osd(&self) -> Option<Arc<Mutex<Osd>>> {
+        None
+    }
     fn destroy(&self) -> anyhow::Result<()>;
     //Note: This should free any resources of the implementation. Also self is being moved to the destroy fn so it will be dropped without drop call
 }
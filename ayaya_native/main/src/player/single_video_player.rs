@@ -1,5 +1,7 @@
 #[cfg(feature = "ffmpeg")]
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::anyhow;
 use ffmpeg::decoder::Video;
@@ -8,17 +10,22 @@ use ffmpeg::format::{input, Pixel};
 use ffmpeg::media::Type;
 use ffmpeg::software::scaling::{Context, Flags};
 use ffmpeg::Error::Eof;
-use ffmpeg::{rescale, Error, Rescale};
+use ffmpeg::{rescale, Error, Rational, Rescale};
 
+use crate::assets::AssetLoader;
 use crate::colorlib::transform_frame_to_mc;
 use crate::map_server::ServerOptions;
 use crate::player::player_context::{receive_and_process_decoded_frames, VideoData, VideoPlayer};
+use crate::subtitles::{self, SubtitleOverlay};
 use crate::{ffmpeg_set_multithreading, SplittedFrame};
 
-use super::player_context::{wrap_frame, NativeCommunication, VideoFrame};
+use super::osd::Osd;
+use super::player_context::{frame_position, wrap_frame, NativeCommunication, VideoFrame};
 
 pub struct SingleVideoPlayer {
+    file_name: String,
     video_stream_index: usize,
+    time_base: Rational,
     scaler: Context,
     input: Input,
     decoder: Video,
@@ -27,6 +34,11 @@ pub struct SingleVideoPlayer {
     all_frames_y: usize,
     seek_tx: Sender<i32>,
     seek_rx: Receiver<i32>,
+    subtitle_tx: Sender<Option<SubtitleOverlay>>,
+    subtitle_rx: Receiver<Option<SubtitleOverlay>>,
+    subtitles: Option<SubtitleOverlay>,
+    //Shared, so paused frames can be redrawn with the pause glyph
+    osd: Arc<Mutex<Osd>>,
     width: usize,
     height: usize,
     fps: i32,
@@ -46,12 +58,13 @@ impl VideoPlayer for SingleVideoPlayer {
                 .ok_or(Error::StreamNotFound)?;
 
             let video_stream_index = input.index();
+            let time_base = input.time_base();
 
             let context_decoder =
                 ffmpeg::codec::context::Context::from_parameters(input.parameters())?;
 
             let mut decoder = context_decoder.decoder();
-            ffmpeg_set_multithreading(&mut decoder, file_name);
+            ffmpeg_set_multithreading(&mut decoder, file_name.clone());
 
             let decoder = decoder.video()?;
 
@@ -71,11 +84,19 @@ impl VideoPlayer for SingleVideoPlayer {
             )?;
 
             let (seek_tx, seek_rx) = channel::<i32>();
+            let (subtitle_tx, subtitle_rx) = channel::<Option<SubtitleOverlay>>();
+            //Streams have no duration, the progress bar is not drawn then
+            let duration = match ictx.duration() {
+                duration if duration > 0 => Some(Duration::from_micros(duration as u64)),
+                _ => None,
+            };
             let (splitted_frames, all_frames_x, all_frames_y) =
                 SplittedFrame::initialize_frames(width as usize, height as usize)?;
 
             let single_video_player = Self {
+                file_name,
                 video_stream_index,
+                time_base,
                 scaler,
                 input: ictx,
                 decoder,
@@ -84,6 +105,15 @@ impl VideoPlayer for SingleVideoPlayer {
                 all_frames_y,
                 seek_tx,
                 seek_rx,
+                subtitle_tx,
+                subtitle_rx,
+                subtitles: None,
+                osd: Arc::new(Mutex::new(Osd::new(
+                    duration,
+                    AssetLoader::new(server_options.asset_dir.as_deref()),
+                    width as usize,
+                    height as usize,
+                ))),
                 width: width as usize,
                 height: height as usize,
                 fps,
@@ -102,20 +132,53 @@ impl VideoPlayer for SingleVideoPlayer {
             self.decoder.flush();
         }
 
+        while let Ok(subtitles) = self.subtitle_rx.try_recv() {
+            self.subtitles = subtitles;
+        }
+
         while let Some((stream, packet)) = self.input.packets().next() {
             if stream.index() == self.video_stream_index {
                 self.decoder.send_packet(&packet)?;
-                let frame_data = receive_and_process_decoded_frames(
+                let mut frame_data = receive_and_process_decoded_frames(
                     &mut self.decoder,
                     &mut self.scaler,
                     &packet,
                 )?;
+
+                let position = frame_position(&frame_data, self.time_base);
+                let burned = match &mut self.subtitles {
+                    Some(subtitles) => {
+                        let stride = frame_data.stride(0);
+                        subtitles.burn(
+                            frame_data.data_mut(0),
+                            self.width,
+                            self.height,
+                            stride,
+                            position,
+                        )
+                    }
+                    None => Ok(()),
+                };
+                //Frame is still shown, without subtitles
+                if let Err(err) = burned {
+                    println!("[ProjectAyaya] Unable to draw subtitles! Reason: {err}");
+                    self.subtitles = None;
+                }
+
                 let transformed_frame = transform_frame_to_mc(
                     frame_data.data(0),
                     self.width,
                     self.height,
                     frame_data.stride(0),
                 );
+                let osd_layer = match self.osd.lock() {
+                    Ok(mut osd) => osd.layer(position),
+                    Err(_) => return Err(anyhow!("Unable to lock OSD mutex")),
+                };
+                let transformed_frame = match osd_layer {
+                    Some(layer) => layer.draw_on_frame(transformed_frame, self.width, self.height),
+                    None => transformed_frame,
+                };
 
                 let transformed_frame = SplittedFrame::split_frames(
                     transformed_frame.as_slice(),
@@ -149,8 +212,21 @@ impl VideoPlayer for SingleVideoPlayer {
             NativeCommunication::VideoSeek { second } => {
                 self.seek_tx.send(second)?;
             }
-            _ => return Err(anyhow!("Expected VideoSeek msg")),
+            NativeCommunication::Subtitles { options } => {
+                //Stream subtitles are opened here, so a bad track fails the message
+                let overlay = subtitles::create_overlay(&self.file_name, options)?;
+                self.subtitle_tx.send(overlay)?;
+            }
+            NativeCommunication::Osd { command } => match self.osd.lock() {
+                Ok(mut osd) => osd.apply(command),
+                Err(_) => return Err(anyhow!("Unable to lock OSD mutex")),
+            },
+            _ => return Err(anyhow!("Expected VideoSeek, Subtitles or Osd msg")),
         };
         Ok(())
     }
+
+    fn osd(&self) -> Option<Arc<Mutex<Osd>>> {
+        Some(self.osd.clone())
+    }
 }
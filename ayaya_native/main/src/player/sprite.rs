@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use super::game_player::BakedImage;
+
+/// Part of the sheet that is one frame. Frames of sprite sheets have no delay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub delay_ms: u32,
+}
+
+/// Frames of a sprite sheet (split by its .sheet file) or of an animated GIF/APNG
+///
+/// Created by build.rs, use bake_sprite! to get one
+#[derive(Debug)]
+pub struct Sprite {
+    pub sheet: BakedImage,
+    pub frames: &'static [SpriteFrame],
+    //Frame name, index in frames
+    pub names: &'static [(&'static str, usize)],
+}
+
+impl Sprite {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frame(&self, index: usize) -> Option<&SpriteFrame> {
+        self.frames.get(index)
+    }
+
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(frame_name, _)| *frame_name == name)
+            .map(|(_, index)| *index)
+    }
+
+    /// Length of one loop of the animation
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.frames.iter().map(|frame| frame.delay_ms as u64).sum())
+    }
+
+    /// Frame that is shown after elapsed time, the animation loops forever
+    pub fn frame_at(&self, elapsed: Duration) -> Option<usize> {
+        let duration = self.duration().as_millis();
+        if duration == 0 {
+            return (!self.is_empty()).then_some(0);
+        }
+
+        let mut position = elapsed.as_millis() % duration;
+        for (index, frame) in self.frames.iter().enumerate() {
+            if position < frame.delay_ms as u128 {
+                return Some(index);
+            }
+            position -= frame.delay_ms as u128;
+        }
+
+        None
+    }
+}
+
+#[macro_export]
+macro_rules! bake_sprite {
+    (
+        $NAME: ident
+    ) => {
+        include!(concat!(
+            env!("OUT_DIR"),
+            "/",
+            stringify!($NAME),
+            ".sprite.rs"
+        ))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    static ANIMATION: Sprite = Sprite {
+        sheet: BakedImage {
+            width: 1,
+            height: 3,
+            data: Cow::Borrowed(&[1, 2, 3]),
+        },
+        frames: &[
+            SpriteFrame {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+                delay_ms: 100,
+            },
+            SpriteFrame {
+                x: 0,
+                y: 1,
+                width: 1,
+                height: 1,
+                delay_ms: 50,
+            },
+            SpriteFrame {
+                x: 0,
+                y: 2,
+                width: 1,
+                height: 1,
+                delay_ms: 250,
+            },
+        ],
+        names: &[],
+    };
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_frame_at() {
+        assert!(ANIMATION.duration() == millis(400));
+
+        assert!(ANIMATION.frame_at(millis(0)) == Some(0));
+        assert!(ANIMATION.frame_at(millis(99)) == Some(0));
+        assert!(ANIMATION.frame_at(millis(100)) == Some(1));
+        assert!(ANIMATION.frame_at(millis(150)) == Some(2));
+        assert!(ANIMATION.frame_at(millis(399)) == Some(2));
+        //Loops
+        assert!(ANIMATION.frame_at(millis(4_120)) == Some(1));
+    }
+
+    #[test]
+    fn test_sprite_sheet() {
+        let sheet: Sprite = crate::bake_sprite!(tetris_digits);
+        assert!(sheet.duration().is_zero());
+        assert!(sheet.frame_index("10").is_none());
+
+        //skip_buildrs bakes sprites without frames
+        if cfg!(feature = "skip_buildrs") {
+            assert!(sheet.is_empty() && sheet.frame_at(millis(10)).is_none());
+            return;
+        }
+
+        assert!(sheet.len() == 10);
+        assert!(sheet.frame_at(millis(10)) == Some(0));
+        for digit in 0..10 {
+            let index = sheet.frame_index(&digit.to_string()).unwrap();
+            let frame = sheet.frame(index).unwrap();
+            assert!(frame.x == digit * 40 && frame.y == 0);
+            assert!(frame.width == 40 && frame.height == 40);
+        }
+
+        //Assets without a sheet are a single frame
+        let image: Sprite = crate::bake_sprite!(tetris_gameover);
+        assert!(image.len() == 1);
+        assert!(image.frame(0).unwrap().width == image.sheet.width);
+    }
+}
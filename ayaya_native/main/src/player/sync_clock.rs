@@ -0,0 +1,368 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+//After a seek the audio output needs some time before it reports the new position. Until it is
+//this close to the expected position we keep using the wall clock
+static SEEK_TOLERANCE: Duration = Duration::from_millis(500);
+//Limit of frames dropped at once, so a large jump does not block the caller for too long
+static MAX_DROPPED_FRAMES: usize = 60;
+static REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Something that knows what the listeners hear right now, usually an audio output
+pub trait ClockSource: Send + Sync {
+    //None when the position is not known (yet)
+    fn position(&self) -> Option<Duration>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SyncStats {
+    //Positive when the video is ahead of the clock
+    pub drift_ms: i64,
+    pub dropped_frames: u64,
+    pub repeated_frames: u64,
+}
+
+struct ClockState {
+    //Position at the time of the anchor
+    position: Duration,
+    //Some while playing
+    anchor: Option<Instant>,
+    started: bool,
+    source: Option<Arc<dyn ClockSource>>,
+    awaiting_source: bool,
+    seek_generation: u64,
+    stats: SyncStats,
+}
+
+/// Master clock of the playback. Audio driven when it has a source, wall clock otherwise.
+/// The clock starts paused at zero
+pub struct MasterClock {
+    state: Mutex<ClockState>,
+}
+
+impl MasterClock {
+    pub fn wall_clock() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(ClockState {
+                position: Duration::ZERO,
+                anchor: None,
+                started: false,
+                source: None,
+                awaiting_source: false,
+                seek_generation: 0,
+                stats: SyncStats::default(),
+            }),
+        })
+    }
+
+    pub fn audio(source: Arc<dyn ClockSource>) -> Arc<Self> {
+        let clock = Self::wall_clock();
+        clock.set_source(Some(source));
+        clock
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ClockState> {
+        //Nothing can panic while the lock is held
+        self.state.lock().expect("Sync clock mutex poisoned")
+    }
+
+    pub fn set_source(&self, source: Option<Arc<dyn ClockSource>>) {
+        let mut state = self.lock();
+        state.awaiting_source = false;
+        state.source = source;
+    }
+
+    pub fn play(&self) {
+        let mut state = self.lock();
+        state.started = true;
+        if state.anchor.is_none() {
+            state.anchor = Some(Instant::now());
+        }
+    }
+
+    pub fn pause(&self) {
+        let position = self.position();
+        let mut state = self.lock();
+        state.position = position;
+        state.anchor = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.lock().anchor.is_some()
+    }
+
+    //True if play was called at least once
+    pub fn is_started(&self) -> bool {
+        self.lock().started
+    }
+
+    pub fn seek(&self, position: Duration) {
+        let mut state = self.lock();
+        state.position = position;
+        if state.anchor.is_some() {
+            state.anchor = Some(Instant::now());
+        }
+        state.awaiting_source = state.source.is_some();
+        state.seek_generation += 1;
+    }
+
+    pub fn position(&self) -> Duration {
+        let mut state = self.lock();
+
+        let Some(anchor) = state.anchor else {
+            return state.position;
+        };
+
+        let now = Instant::now();
+        let wall_position = state.position + now.duration_since(anchor);
+
+        let audio_position = state.source.as_ref().and_then(|source| source.position());
+        if let Some(audio_position) = audio_position {
+            let settled = wall_position.max(audio_position) - wall_position.min(audio_position)
+                < SEEK_TOLERANCE;
+
+            if !state.awaiting_source || settled {
+                //Re-anchor, so pausing keeps the last audio position
+                state.awaiting_source = false;
+                state.position = audio_position;
+                state.anchor = Some(now);
+                return audio_position;
+            }
+        }
+
+        wall_position
+    }
+
+    //Index of the frame that should be visible right now
+    pub fn frame(&self, fps: i32) -> i64 {
+        (self.position().as_millis() as i64 * fps as i64) / 1000
+    }
+
+    pub fn seek_generation(&self) -> u64 {
+        self.lock().seek_generation
+    }
+
+    pub fn stats(&self) -> SyncStats {
+        self.lock().stats
+    }
+
+    fn set_stats(&self, stats: SyncStats) {
+        self.lock().stats = stats;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameAction {
+    //Show the previous frame again, the video is ahead of the clock
+    Repeat,
+    //Throw away skip frames and show the next one
+    Show { skip: usize },
+}
+
+/// Decides which frame a player should show, so the video follows the master clock
+pub struct FrameSync {
+    clock: Arc<MasterClock>,
+    fps: i32,
+    //Frame that the player will return on the next load
+    next_frame: i64,
+    seek_generation: u64,
+    stats: SyncStats,
+    reported_stats: SyncStats,
+    last_report: Instant,
+}
+
+impl FrameSync {
+    pub fn new(clock: Arc<MasterClock>, fps: i32) -> Self {
+        let seek_generation = clock.seek_generation();
+
+        Self {
+            clock,
+            fps: fps.max(1),
+            next_frame: 0,
+            seek_generation,
+            stats: SyncStats::default(),
+            reported_stats: SyncStats::default(),
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn clock(&self) -> &Arc<MasterClock> {
+        &self.clock
+    }
+
+    pub fn plan(&mut self) -> FrameAction {
+        let seek_generation = self.clock.seek_generation();
+        if seek_generation != self.seek_generation {
+            //The player was seeked together with the clock
+            self.seek_generation = seek_generation;
+            self.next_frame = self.clock.frame(self.fps);
+        }
+
+        let target = self.clock.frame(self.fps);
+        let action = if self.next_frame > target {
+            self.stats.repeated_frames += 1;
+            FrameAction::Repeat
+        } else {
+            let skip = ((target - self.next_frame) as usize).min(MAX_DROPPED_FRAMES);
+            self.stats.dropped_frames += skip as u64;
+            self.next_frame += skip as i64 + 1;
+            FrameAction::Show { skip }
+        };
+
+        //Time of the frame that is visible now
+        let shown_ms = (self.next_frame - 1).max(0) * 1000 / self.fps as i64;
+        self.stats.drift_ms = shown_ms - self.clock.position().as_millis() as i64;
+        self.clock.set_stats(self.stats);
+        self.report();
+
+        action
+    }
+
+    fn report(&mut self) {
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+
+        let dropped = self.stats.dropped_frames - self.reported_stats.dropped_frames;
+        let repeated = self.stats.repeated_frames - self.reported_stats.repeated_frames;
+        if dropped != 0 || repeated != 0 {
+            println!(
+                "[ProjectAyaya] A/V drift: {}ms (dropped {dropped} and repeated {repeated} frames in the last {}s)",
+                self.stats.drift_ms,
+                REPORT_INTERVAL.as_secs()
+            );
+        }
+        self.reported_stats = self.stats;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+    use std::thread;
+
+    use super::*;
+
+    struct ManualSource {
+        position_ms: AtomicU64,
+    }
+
+    impl ManualSource {
+        fn new(position_ms: u64) -> Arc<Self> {
+            Arc::new(Self {
+                position_ms: AtomicU64::new(position_ms),
+            })
+        }
+
+        fn set(&self, position_ms: u64) {
+            self.position_ms.store(position_ms, Relaxed);
+        }
+    }
+
+    impl ClockSource for ManualSource {
+        fn position(&self) -> Option<Duration> {
+            Some(Duration::from_millis(self.position_ms.load(Relaxed)))
+        }
+    }
+
+    #[test]
+    fn test_wall_clock() {
+        let clock = MasterClock::wall_clock();
+        assert!(!clock.is_started() && clock.position() == Duration::ZERO);
+
+        clock.play();
+        thread::sleep(Duration::from_millis(50));
+        clock.pause();
+
+        let paused_position = clock.position();
+        assert!(paused_position >= Duration::from_millis(50));
+        thread::sleep(Duration::from_millis(20));
+        assert!(clock.position() == paused_position);
+
+        clock.seek(Duration::from_secs(30));
+        assert!(clock.position() == Duration::from_secs(30));
+        assert!(clock.frame(24) == 720);
+    }
+
+    #[test]
+    fn test_audio_clock() {
+        let source = ManualSource::new(1_000);
+        let clock = MasterClock::audio(source.clone());
+
+        //Paused clock does not follow the audio
+        assert!(clock.position() == Duration::ZERO);
+
+        clock.play();
+        assert!(clock.position() == Duration::from_millis(1_000));
+        source.set(2_500);
+        assert!(clock.position() == Duration::from_millis(2_500));
+
+        clock.pause();
+        source.set(4_000);
+        assert!(clock.position() == Duration::from_millis(2_500));
+    }
+
+    #[test]
+    fn test_audio_clock_seek() {
+        let source = ManualSource::new(1_000);
+        let clock = MasterClock::audio(source.clone());
+        clock.play();
+
+        //Audio still reports the old position, so the wall clock is used
+        clock.seek(Duration::from_secs(60));
+        let position = clock.position();
+        assert!(position >= Duration::from_secs(60) && position < Duration::from_secs(61));
+
+        source.set(60_100);
+        assert!(clock.position() == Duration::from_millis(60_100));
+    }
+
+    #[test]
+    fn test_frame_sync() {
+        let source = ManualSource::new(0);
+        let clock = MasterClock::audio(source.clone());
+        clock.play();
+        let mut frame_sync = FrameSync::new(clock.clone(), 10);
+
+        assert!(frame_sync.plan() == FrameAction::Show { skip: 0 });
+        //Clock did not move, so the video is ahead
+        assert!(frame_sync.plan() == FrameAction::Repeat);
+
+        //Clock is now at frame 5, we showed frame 0
+        source.set(500);
+        assert!(frame_sync.plan() == FrameAction::Show { skip: 4 });
+        assert!(frame_sync.plan() == FrameAction::Repeat);
+
+        let stats = clock.stats();
+        assert!(stats.dropped_frames == 4 && stats.repeated_frames == 2 && stats.drift_ms == 0);
+
+        source.set(550);
+        frame_sync.plan();
+        assert!(clock.stats().drift_ms == -50);
+    }
+
+    #[test]
+    fn test_frame_sync_seek() {
+        let source = ManualSource::new(0);
+        let clock = MasterClock::audio(source.clone());
+        clock.play();
+        let mut frame_sync = FrameSync::new(clock.clone(), 10);
+        assert!(frame_sync.plan() == FrameAction::Show { skip: 0 });
+
+        //Player and clock are seeked together, so nothing is dropped
+        clock.seek(Duration::from_secs(20));
+        source.set(20_000);
+        assert!(frame_sync.plan() == FrameAction::Show { skip: 0 });
+        assert!(clock.stats().dropped_frames == 0);
+
+        //Large jumps are caught up in steps
+        source.set(40_000);
+        assert!(
+            frame_sync.plan()
+                == FrameAction::Show {
+                    skip: MAX_DROPPED_FRAMES
+                }
+        );
+    }
+}
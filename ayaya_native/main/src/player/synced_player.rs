@@ -0,0 +1,252 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::map_server::ServerOptions;
+
+use super::osd::{Osd, PausedFrame};
+use super::player_context::{wrap_frame, NativeCommunication, VideoData, VideoFrame, VideoPlayer};
+use super::sync_clock::{FrameAction, FrameSync, MasterClock};
+
+//Makes a JVM paced player follow the master clock. Frames are dropped when the JVM asks for them
+//too late and repeated when it asks too early
+pub struct SyncedPlayer {
+    inner: Box<dyn VideoPlayer>,
+    frame_sync: FrameSync,
+    last_frame: Option<Vec<i8>>,
+    paused_frame: Option<PausedFrame>,
+}
+
+impl SyncedPlayer {
+    pub fn new(inner: Box<dyn VideoPlayer>, clock: Arc<MasterClock>) -> anyhow::Result<Self> {
+        let video_data = inner.video_data()?;
+        let paused_frame = match inner.osd() {
+            Some(osd) => Some(PausedFrame::new(
+                osd,
+                video_data.width as usize,
+                video_data.height as usize,
+            )?),
+            None => None,
+        };
+
+        Ok(Self {
+            inner,
+            frame_sync: FrameSync::new(clock, video_data.fps),
+            last_frame: None,
+            paused_frame,
+        })
+    }
+
+    fn show_next_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
+        let frame = self.inner.load_frame()?;
+        self.last_frame = Some(frame.data().clone());
+        if let Some(paused_frame) = &mut self.paused_frame {
+            paused_frame.reset();
+        }
+        Ok(frame)
+    }
+}
+
+impl VideoPlayer for SyncedPlayer {
+    fn create(_file_name: String, _server_options: ServerOptions) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(anyhow!("Please use SyncedPlayer::new!"))
+    }
+
+    fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
+        //JVM paced players never get StartRendering
+        let clock = self.frame_sync.clock().clone();
+        if !clock.is_started() {
+            clock.play();
+        }
+
+        match (self.frame_sync.plan(), &self.last_frame) {
+            (FrameAction::Repeat, Some(last_frame)) => {
+                //Nothing is decoded while paused, so OSD changes are drawn over the last frame
+                let redrawn = match &mut self.paused_frame {
+                    Some(paused_frame) if !clock.is_playing() => paused_frame.redraw(last_frame)?,
+                    _ => None,
+                };
+                let frame = redrawn.unwrap_or_else(|| last_frame.clone());
+                self.last_frame = Some(frame.clone());
+                Ok(wrap_frame(frame))
+            }
+            (FrameAction::Repeat, None) => self.show_next_frame(),
+            (FrameAction::Show { skip }, _) => {
+                for _ in 0..skip {
+                    self.inner.load_frame()?;
+                }
+                self.show_next_frame()
+            }
+        }
+    }
+
+    fn video_data(&self) -> anyhow::Result<VideoData> {
+        self.inner.video_data()
+    }
+
+    fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
+        let clock = self.frame_sync.clock();
+
+        match msg {
+            NativeCommunication::StartRendering { .. } => clock.play(),
+            NativeCommunication::StopRendering => clock.pause(),
+            NativeCommunication::VideoSeek { second } => {
+                self.inner.handle_jvm_msg(msg)?;
+                clock.seek(Duration::from_secs(second.max(0) as u64));
+            }
+            _ => return self.inner.handle_jvm_msg(msg),
+        };
+        Ok(())
+    }
+
+    fn clock(&self) -> Option<Arc<MasterClock>> {
+        Some(self.frame_sync.clock().clone())
+    }
+
+    fn osd(&self) -> Option<Arc<Mutex<Osd>>> {
+        self.inner.osd()
+    }
+
+    fn destroy(&self) -> anyhow::Result<()> {
+        self.inner.destroy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+    use crate::player::sync_clock::ClockSource;
+
+    use super::*;
+
+    struct ManualSource {
+        position_ms: AtomicU64,
+    }
+
+    impl ManualSource {
+        fn set_position(&self, position_ms: u64) {
+            self.position_ms.store(position_ms, Relaxed);
+        }
+    }
+
+    impl ClockSource for ManualSource {
+        fn position(&self) -> Option<Duration> {
+            Some(Duration::from_millis(self.position_ms.load(Relaxed)))
+        }
+    }
+
+    //Every frame is filled with its index
+    struct CountingPlayer {
+        next_frame: i8,
+        seeks: Mutex<Vec<i32>>,
+    }
+
+    impl VideoPlayer for CountingPlayer {
+        fn create(_file_name: String, _server_options: ServerOptions) -> anyhow::Result<Self> {
+            Ok(Self {
+                next_frame: 0,
+                seeks: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn load_frame(&mut self) -> anyhow::Result<Box<dyn VideoFrame>> {
+            let seek = self.seeks.lock().unwrap().pop();
+            if let Some(second) = seek {
+                self.next_frame = (second * 10) as i8;
+            }
+
+            self.next_frame += 1;
+            Ok(wrap_frame(vec![self.next_frame - 1; 4]))
+        }
+
+        fn video_data(&self) -> anyhow::Result<VideoData> {
+            Ok(VideoData {
+                width: 2,
+                height: 2,
+                fps: 10,
+            })
+        }
+
+        fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
+            match msg {
+                NativeCommunication::VideoSeek { second } => {
+                    self.seeks.lock().unwrap().push(second)
+                }
+                _ => return Err(anyhow!("Expected VideoSeek msg")),
+            };
+            Ok(())
+        }
+
+        fn destroy(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn synced_player() -> (SyncedPlayer, Arc<ManualSource>) {
+        let source = Arc::new(ManualSource {
+            position_ms: AtomicU64::new(0),
+        });
+        let inner = CountingPlayer::create(
+            "".to_string(),
+            ServerOptions {
+                use_server: false,
+                bind_ip: "".to_string(),
+                port: 0,
+                asset_dir: None,
+            },
+        )
+        .unwrap();
+
+        let player =
+            SyncedPlayer::new(Box::new(inner), MasterClock::audio(source.clone())).unwrap();
+        (player, source)
+    }
+
+    fn frame_index(player: &mut SyncedPlayer) -> i8 {
+        player.load_frame().unwrap().data()[0]
+    }
+
+    #[test]
+    fn test_drop_and_repeat() {
+        let (mut player, source) = synced_player();
+
+        assert!(frame_index(&mut player) == 0);
+        assert!(frame_index(&mut player) == 0);
+
+        source.set_position(300);
+        assert!(frame_index(&mut player) == 3);
+
+        let stats = player.clock().unwrap().stats();
+        assert!(stats.dropped_frames == 2 && stats.repeated_frames == 1);
+    }
+
+    #[test]
+    fn test_pause_and_seek() {
+        let (mut player, source) = synced_player();
+        assert!(frame_index(&mut player) == 0);
+
+        //Clock is frozen while paused, even if the audio position changes
+        player
+            .handle_jvm_msg(NativeCommunication::StopRendering)
+            .unwrap();
+        source.set_position(500);
+        assert!(frame_index(&mut player) == 0);
+
+        player
+            .handle_jvm_msg(NativeCommunication::VideoSeek { second: 8 })
+            .unwrap();
+        assert!(player.clock().unwrap().position() == Duration::from_secs(8));
+        player
+            .handle_jvm_msg(NativeCommunication::StartRendering { fps: 10 })
+            .unwrap();
+
+        source.set_position(8_000);
+        assert!(frame_index(&mut player) == 80);
+        assert!(player.clock().unwrap().stats().dropped_frames == 0);
+    }
+}
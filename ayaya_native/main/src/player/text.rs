@@ -0,0 +1,588 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use anyhow::anyhow;
+use font_kit::{
+    canvas::{Canvas, Format, RasterizationOptions},
+    font::Font,
+    hinting::HintingOptions,
+};
+use once_cell::unsync::OnceCell;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+
+use crate::colorlib::Color;
+
+use super::game_player::{VideoCanvas, DEFAULT_FONT};
+use super::kerning::KerningTable;
+
+//Drawn for characters that the font does not have, the first one the font supports is used
+static REPLACEMENT_CHARS: [char; 2] = ['\u{FFFD}', '?'];
+static MAX_FONT_SIZE: f32 = 1024.0;
+
+thread_local! {
+    //Fonts can't be moved between threads, so every thread loads its own copy
+    static DEFAULT_FACE: OnceCell<Rc<FontFace>> = const { OnceCell::new() };
+}
+
+/// Horizontal alignment of every line inside of the text box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextStyle {
+    pub font_size: f32,
+    pub color: Color,
+    pub align: TextAlign,
+    //Multiplier of the line height of the font
+    pub line_spacing: f32,
+}
+
+impl TextStyle {
+    pub fn new(font_size: f32, color: Color) -> Self {
+        Self {
+            font_size,
+            color,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+/// Size of laid out text, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextMetrics {
+    pub width: usize,
+    pub height: usize,
+    pub lines: usize,
+}
+
+struct FontFace {
+    font: Font,
+    kerning: KerningTable,
+    units_per_em: f32,
+    ascent: f32,
+    line_height: f32,
+    replacement: Option<u32>,
+    //Font units
+    advances: RefCell<HashMap<u32, f32>>,
+}
+
+impl FontFace {
+    fn load(font: Font) -> Self {
+        let metrics = font.metrics();
+        let replacement = REPLACEMENT_CHARS
+            .iter()
+            .find_map(|char| font.glyph_for_char(*char));
+
+        Self {
+            kerning: KerningTable::from_font(&font),
+            units_per_em: metrics.units_per_em as f32,
+            ascent: metrics.ascent,
+            line_height: metrics.ascent - metrics.descent + metrics.line_gap,
+            replacement,
+            advances: RefCell::new(HashMap::new()),
+            font,
+        }
+    }
+
+    fn default_font() -> anyhow::Result<Rc<Self>> {
+        DEFAULT_FACE.with(|face| {
+            face.get_or_try_init(|| Ok(Rc::new(Self::load(DEFAULT_FONT.load()?))))
+                .cloned()
+        })
+    }
+
+    //None for characters that are not drawn at all
+    fn glyph(&self, char: char) -> Option<u32> {
+        match char.is_control() {
+            true => None,
+            false => self.font.glyph_for_char(char).or(self.replacement),
+        }
+    }
+
+    fn advance(&self, glyph_id: u32) -> anyhow::Result<f32> {
+        if let Some(advance) = self.advances.borrow().get(&glyph_id) {
+            return Ok(*advance);
+        }
+
+        let advance = self.font.advance(glyph_id)?.x();
+        self.advances.borrow_mut().insert(glyph_id, advance);
+        Ok(advance)
+    }
+}
+
+#[derive(Debug)]
+struct PlacedGlyph {
+    id: u32,
+    //Pixels from the start of the line
+    x: f32,
+    space: bool,
+}
+
+#[derive(Debug, Default)]
+struct Line {
+    glyphs: Vec<PlacedGlyph>,
+    width: f32,
+}
+
+/// Text positioned with the glyph metrics and kerning of the font, ready to be drawn
+pub struct TextLayout {
+    face: Rc<FontFace>,
+    style: TextStyle,
+    lines: Vec<Line>,
+    //Lines are aligned inside of this width
+    box_width: f32,
+}
+
+impl TextLayout {
+    /// Lines longer than max_width are wrapped at spaces. Words that don't fit on a line of
+    /// their own are split between characters
+    pub fn new(text: &str, style: &TextStyle, max_width: Option<usize>) -> anyhow::Result<Self> {
+        if !(style.font_size > 0.0 && style.font_size <= MAX_FONT_SIZE) {
+            return Err(anyhow!("Invalid font size ({})", style.font_size));
+        }
+        if !(style.line_spacing.is_finite() && style.line_spacing >= 0.0) {
+            return Err(anyhow!("Invalid line spacing ({})", style.line_spacing));
+        }
+
+        let face = FontFace::default_font()?;
+        let scale = style.font_size / face.units_per_em;
+
+        let mut lines = Vec::new();
+        if !text.is_empty() {
+            for paragraph in text.split('\n') {
+                let glyphs: Vec<(u32, bool)> = paragraph
+                    .chars()
+                    .map(|char| if char == '\t' { ' ' } else { char })
+                    .filter_map(|char| face.glyph(char).map(|id| (id, char == ' ')))
+                    .collect();
+
+                wrap(
+                    &face,
+                    &glyphs,
+                    scale,
+                    max_width.map(|val| val as f32),
+                    &mut lines,
+                )?;
+            }
+        }
+
+        let width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+
+        Ok(Self {
+            face,
+            style: style.clone(),
+            lines,
+            box_width: max_width.map_or(width, |val| val as f32),
+        })
+    }
+
+    fn scale(&self) -> f32 {
+        self.style.font_size / self.face.units_per_em
+    }
+
+    fn line_height(&self) -> f32 {
+        (self.face.line_height * self.scale() * self.style.line_spacing).ceil()
+    }
+
+    pub fn metrics(&self) -> TextMetrics {
+        let width = self.lines.iter().map(|line| line.width).fold(0.0, f32::max);
+
+        TextMetrics {
+            width: width.ceil() as usize,
+            height: (self.line_height() * self.lines.len() as f32) as usize,
+            lines: self.lines.len(),
+        }
+    }
+
+    /// Draws the text with its top left corner at x, y
+    pub fn draw(&self, canvas: &mut VideoCanvas, x: i32, y: i32) -> anyhow::Result<()> {
+        let color = self.style.color;
+        let max_y = canvas.height;
+
+        self.rasterize(x, y, Some(max_y), &mut |pixel_x, pixel_y, alpha| {
+            canvas.blend_pixel(pixel_x, pixel_y, &color, alpha)
+        })
+    }
+
+    /// Rasterizes the glyphs once, so the text can be drawn again without the font
+    pub fn to_mask(&self) -> anyhow::Result<TextMask> {
+        let mut pixels = Vec::new();
+        self.rasterize(0, 0, None, &mut |x, y, alpha| pixels.push((x, y, alpha)))?;
+
+        let x1 = pixels.iter().map(|pixel| pixel.0).min().unwrap_or(0);
+        let y1 = pixels.iter().map(|pixel| pixel.1).min().unwrap_or(0);
+        let width = pixels
+            .iter()
+            .map(|pixel| pixel.0 - x1 + 1)
+            .max()
+            .unwrap_or(0) as usize;
+        let height = pixels
+            .iter()
+            .map(|pixel| pixel.1 - y1 + 1)
+            .max()
+            .unwrap_or(0) as usize;
+
+        let mut alpha = vec![0u8; width * height];
+        for (x, y, coverage) in pixels {
+            let pixel = &mut alpha[(y - y1) as usize * width + (x - x1) as usize];
+            //Edges of neighbouring glyphs add up, like blending them one after the other
+            *pixel = (*pixel as u32 + coverage as u32 * (255 - *pixel as u32) / 255) as u8;
+        }
+
+        Ok(TextMask {
+            x: x1,
+            y: y1,
+            width,
+            height,
+            color: self.style.color,
+            alpha,
+        })
+    }
+
+    //Lines that start below max_y are skipped
+    fn rasterize(
+        &self,
+        x: i32,
+        y: i32,
+        max_y: Option<usize>,
+        pixel: &mut dyn FnMut(i32, i32, u8),
+    ) -> anyhow::Result<()> {
+        let ascent = (self.face.ascent * self.scale()).round();
+
+        for (index, line) in self.lines.iter().enumerate() {
+            let offset = match self.style.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => ((self.box_width - line.width) / 2.0).floor(),
+                TextAlign::Right => (self.box_width - line.width).floor(),
+            };
+            let baseline = y as f32 + index as f32 * self.line_height() + ascent;
+            if max_y.is_some_and(|max_y| baseline - ascent > max_y as f32) {
+                break;
+            }
+
+            for glyph in line.glyphs.iter().filter(|glyph| !glyph.space) {
+                self.rasterize_glyph(glyph.id, x as f32 + offset + glyph.x, baseline, pixel)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    //Coverage of the anti-aliased edges, mixed with the canvas by the caller
+    fn rasterize_glyph(
+        &self,
+        glyph_id: u32,
+        x: f32,
+        baseline: f32,
+        pixel: &mut dyn FnMut(i32, i32, u8),
+    ) -> anyhow::Result<()> {
+        let font = &self.face.font;
+        let (origin_x, origin_y) = (x.floor() as i32, baseline as i32);
+        //Fraction of the pixel is kept, so kerned glyphs are not rounded together
+        let transform = Transform2F::from_translation(Vector2F::new(x - x.floor(), 0.0));
+
+        let bounds = font.raster_bounds(
+            glyph_id,
+            self.style.font_size,
+            transform,
+            HintingOptions::None,
+            RasterizationOptions::GrayscaleAa,
+        )?;
+        if bounds.width() <= 0 || bounds.height() <= 0 {
+            return Ok(());
+        }
+
+        let mut glyph_canvas = Canvas::new(bounds.size(), Format::A8);
+        font.rasterize_glyph(
+            &mut glyph_canvas,
+            glyph_id,
+            self.style.font_size,
+            Transform2F::from_translation(-bounds.origin().to_f32()) * transform,
+            HintingOptions::None,
+            RasterizationOptions::GrayscaleAa,
+        )?;
+
+        for glyph_y in 0..bounds.height() {
+            for glyph_x in 0..bounds.width() {
+                let alpha =
+                    glyph_canvas.pixels[glyph_y as usize * glyph_canvas.stride + glyph_x as usize];
+                if alpha > 0 {
+                    pixel(
+                        origin_x.saturating_add(bounds.origin_x() + glyph_x),
+                        origin_y.saturating_add(bounds.origin_y() + glyph_y),
+                        alpha,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Rasterized text from TextLayout::to_mask. Unlike the layout it can be sent between threads
+#[derive(Debug, Clone)]
+pub struct TextMask {
+    //Offset of the first pixel from the top left corner of the text
+    x: i32,
+    y: i32,
+    pub width: usize,
+    pub height: usize,
+    color: Color,
+    alpha: Vec<u8>,
+}
+
+impl TextMask {
+    /// Coverage of every pixel, positioned from the top left corner of the text
+    pub fn pixels(&self) -> impl Iterator<Item = (i32, i32, u8)> + '_ {
+        self.alpha.iter().enumerate().map(|(index, alpha)| {
+            (
+                self.x + (index % self.width) as i32,
+                self.y + (index / self.width) as i32,
+                *alpha,
+            )
+        })
+    }
+
+    /// Draws the text with its top left corner at x, y, the same way TextLayout::draw does
+    pub fn draw(&self, canvas: &mut VideoCanvas, x: i32, y: i32) {
+        if self.width == 0 {
+            return;
+        }
+
+        for (index, alpha) in self.alpha.iter().enumerate() {
+            canvas.blend_pixel(
+                x.saturating_add(self.x + (index % self.width) as i32),
+                y.saturating_add(self.y + (index / self.width) as i32),
+                &self.color,
+                *alpha,
+            );
+        }
+    }
+}
+
+//Splits one paragraph into lines, spaces at the end of wrapped lines are dropped
+fn wrap(
+    face: &FontFace,
+    glyphs: &[(u32, bool)],
+    scale: f32,
+    max_width: Option<f32>,
+    lines: &mut Vec<Line>,
+) -> anyhow::Result<()> {
+    let mut start = 0;
+
+    loop {
+        let mut line = Line::default();
+        let mut pen_x = 0.0;
+        let mut previous = None;
+        //Index of the last space that comes after a word
+        let mut break_at = None;
+        let mut has_word = false;
+        let mut end = start;
+
+        while end < glyphs.len() {
+            let (id, space) = glyphs[end];
+            let kerning = match previous {
+                Some(previous) => face.kerning.kerning(previous, id) as f32 * scale,
+                None => 0.0,
+            };
+            let x = pen_x + kerning;
+            let right = x + face.advance(id)? * scale;
+
+            //Spaces can hang over the edge, they are trimmed anyway
+            if let Some(max_width) = max_width {
+                if !space && right > max_width && !line.glyphs.is_empty() {
+                    if let Some(break_at) = break_at {
+                        line.glyphs.truncate(break_at - start);
+                        end = break_at;
+                    }
+                    break;
+                }
+            }
+
+            match space {
+                true if has_word => break_at = Some(end),
+                true => {}
+                false => has_word = true,
+            }
+            line.glyphs.push(PlacedGlyph { id, x, space });
+            pen_x = right;
+            previous = Some(id);
+            end += 1;
+        }
+
+        while line.glyphs.last().is_some_and(|glyph| glyph.space) {
+            line.glyphs.pop();
+        }
+        if let Some(last) = line.glyphs.last() {
+            line.width = last.x + face.advance(last.id)? * scale;
+        }
+        lines.push(line);
+
+        //Wrapped lines don't start with a space
+        while end < glyphs.len() && glyphs[end].1 {
+            end += 1;
+        }
+        if end >= glyphs.len() {
+            return Ok(());
+        }
+        start = end;
+    }
+}
+
+/// Size of the text without drawing it
+pub fn measure_text(
+    text: &str,
+    style: &TextStyle,
+    max_width: Option<usize>,
+) -> anyhow::Result<TextMetrics> {
+    Ok(TextLayout::new(text, style, max_width)?.metrics())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static WHITE: Color = Color::new(255, 255, 255);
+
+    fn width(text: &str) -> usize {
+        measure_text(text, &TextStyle::new(32.0, WHITE), None)
+            .unwrap()
+            .width
+    }
+
+    #[test]
+    fn test_glyph_metrics() {
+        //Characters have their own advance, pairs are kerned
+        assert!(width("iiii") < width("WWWW"));
+        assert!(width("AV") < width("A") + width("V"));
+        //Widths are rounded up
+        assert!(width("HH") + 1 >= width("H") * 2 && width("HH") <= width("H") * 2);
+
+        let metrics = measure_text("Ayaya", &TextStyle::new(32.0, WHITE), None).unwrap();
+        assert!(metrics.lines == 1);
+        assert!(metrics.height >= 32 && metrics.height < 64);
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let style = TextStyle::new(20.0, WHITE);
+        let measure = |text: &str, max_width| measure_text(text, &style, max_width).unwrap();
+
+        let max_width = measure("hello world", None).width;
+        let metrics = measure("hello world hello world", Some(max_width));
+        assert!(metrics.lines == 2 && metrics.width <= max_width);
+
+        //Words that don't fit are split
+        let metrics = measure("Ayayayayayayayaya", Some(40));
+        assert!(metrics.lines > 2 && metrics.width <= 40);
+
+        assert!(measure("a\n\nb", None).lines == 3);
+        assert!(measure("a\n\nb", None).height == measure("a", None).height * 3);
+        assert!(
+            measure("", None)
+                == TextMetrics {
+                    width: 0,
+                    height: 0,
+                    lines: 0
+                }
+        );
+        //Spaces at the end of wrapped lines don't count
+        assert!(measure("hello     world", Some(max_width - 1)).lines == 2);
+    }
+
+    #[test]
+    fn test_missing_glyphs() {
+        let emoji = width("\u{1F600}");
+        assert!(emoji > 0);
+        assert!(emoji == width("\u{FFFD}") || emoji == width("?"));
+        //Control characters are skipped
+        assert!(width("a\rb") == width("ab"));
+    }
+
+    #[test]
+    fn test_invalid_style() {
+        assert!(measure_text("a", &TextStyle::new(0.0, WHITE), None).is_err());
+        assert!(measure_text("a", &TextStyle::new(f32::NAN, WHITE), None).is_err());
+        let style = TextStyle {
+            line_spacing: -1.0,
+            ..TextStyle::new(12.0, WHITE)
+        };
+        assert!(measure_text("a", &style, None).is_err());
+    }
+
+    //Colors need the real conversion table
+    #[cfg(not(feature = "skip_buildrs"))]
+    #[test]
+    fn test_draw_alignment() {
+        let background = Color::new(0, 0, 0);
+        let drawn_columns = |align| {
+            let mut canvas = VideoCanvas::new(200, 40, &background);
+            let style = TextStyle {
+                align,
+                ..TextStyle::new(20.0, WHITE)
+            };
+            canvas
+                .draw_text_box(10, 5, 189, 34, &style, "Ayaya")
+                .unwrap();
+
+            let frame = canvas.to_mc_frame();
+            let background = background.convert_to_mc() as i8;
+            let columns: Vec<usize> = (0..200)
+                .filter(|x| (0..40).any(|y| frame[y * 200 + x] != background))
+                .collect();
+            let rows: Vec<usize> = (0..40)
+                .filter(|y| (0..200).any(|x| frame[y * 200 + x] != background))
+                .collect();
+            assert!(rows.first().unwrap() >= &5 && rows.last().unwrap() <= &34);
+
+            //Edges are mixed into shades between the two colors
+            let white = WHITE.convert_to_mc() as i8;
+            assert!(frame.contains(&white));
+            assert!(frame
+                .iter()
+                .any(|pixel| *pixel != white && *pixel != background));
+
+            (*columns.first().unwrap(), *columns.last().unwrap())
+        };
+
+        let text_width = width("Ayaya") * 20 / 32;
+        let (left, _) = drawn_columns(TextAlign::Left);
+        assert!((10..14).contains(&left));
+        let (_, right) = drawn_columns(TextAlign::Right);
+        assert!((186..=189).contains(&right));
+        let (center_left, center_right) = drawn_columns(TextAlign::Center);
+        assert!((center_left as i32 - (199 - center_right) as i32).abs() <= 3);
+        assert!(center_right - center_left >= text_width / 2);
+    }
+
+    #[cfg(not(feature = "skip_buildrs"))]
+    #[test]
+    fn test_text_mask() {
+        let background = Color::new(0, 0, 0);
+        let layout = TextLayout::new("Ayaya jump", &TextStyle::new(20.0, WHITE), None).unwrap();
+        let mask = layout.to_mask().unwrap();
+        assert!(mask.width > 0 && mask.height > 0);
+
+        let mut drawn = VideoCanvas::new(200, 40, &background);
+        layout.draw(&mut drawn, 10, 5).unwrap();
+        let mut masked = VideoCanvas::new(200, 40, &background);
+        mask.draw(&mut masked, 10, 5);
+        assert!(drawn.to_mc_frame() == masked.to_mc_frame());
+
+        //Clipped like any other drawing
+        mask.draw(&mut masked, -500, 30);
+        assert!(
+            TextLayout::new("", &TextStyle::new(20.0, WHITE), None)
+                .unwrap()
+                .to_mask()
+                .unwrap()
+                .width
+                == 0
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU64;
+
+use anyhow::anyhow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceChannel {
+    pub guild_id: NonZeroU64,
+    pub channel_id: NonZeroU64,
+}
+
+impl VoiceChannel {
+    pub fn new(guild_id: u64, channel_id: u64) -> anyhow::Result<Self> {
+        let guild_id = match NonZeroU64::new(guild_id) {
+            Some(val) => val,
+            None => return Err(anyhow!("Guild ID is zero")),
+        };
+
+        let channel_id = match NonZeroU64::new(channel_id) {
+            Some(val) => val,
+            None => return Err(anyhow!("Channel ID is zero")),
+        };
+
+        Ok(Self {
+            guild_id,
+            channel_id,
+        })
+    }
+}
+
+/// Voice session of every player handle. Discord allows one voice connection per guild, so
+/// screens can play at the same time as long as they use different guilds
+#[derive(Debug, Default, Clone)]
+pub struct VoiceSessions {
+    sessions: HashMap<i64, VoiceChannel>,
+    //Shown by the slash commands
+    titles: HashMap<i64, String>,
+    //Guilds of players that are still being created, their handle is not known yet
+    reserved: HashSet<NonZeroU64>,
+}
+
+impl VoiceSessions {
+    //Takes over the reservation of the guild
+    pub fn insert(&mut self, handle: i64, channel: VoiceChannel) -> anyhow::Result<()> {
+        if let Some(other) = self.handle_for_guild(channel.guild_id) {
+            if other != handle {
+                return Err(anyhow!(
+                    "Guild {} is already used by another screen",
+                    channel.guild_id
+                ));
+            }
+        }
+
+        self.reserved.remove(&channel.guild_id);
+        self.sessions.insert(handle, channel);
+        Ok(())
+    }
+
+    pub fn reserve(&mut self, guild_id: NonZeroU64) -> anyhow::Result<()> {
+        if self.is_guild_used(guild_id) {
+            return Err(anyhow!(
+                "Guild {guild_id} is already used by another screen"
+            ));
+        }
+
+        self.reserved.insert(guild_id);
+        Ok(())
+    }
+
+    pub fn cancel_reservation(&mut self, guild_id: NonZeroU64) {
+        self.reserved.remove(&guild_id);
+    }
+
+    pub fn remove(&mut self, handle: i64) -> Option<VoiceChannel> {
+        self.titles.remove(&handle);
+        self.sessions.remove(&handle)
+    }
+
+    pub fn remove_guild(&mut self, guild_id: NonZeroU64) -> Option<i64> {
+        let handle = self.handle_for_guild(guild_id)?;
+        self.remove(handle);
+        Some(handle)
+    }
+
+    pub fn set_title(&mut self, handle: i64, title: String) {
+        if self.sessions.contains_key(&handle) {
+            self.titles.insert(handle, title);
+        }
+    }
+
+    pub fn title(&self, handle: i64) -> Option<&str> {
+        self.titles.get(&handle).map(|title| title.as_str())
+    }
+
+    pub fn handle_for_guild(&self, guild_id: NonZeroU64) -> Option<i64> {
+        self.sessions
+            .iter()
+            .find(|(_, channel)| channel.guild_id == guild_id)
+            .map(|(handle, _)| *handle)
+    }
+
+    #[cfg(feature = "discord")]
+    pub fn handles(&self) -> impl Iterator<Item = i64> + '_ {
+        self.sessions.keys().copied()
+    }
+
+    pub fn is_guild_used(&self, guild_id: NonZeroU64) -> bool {
+        self.reserved.contains(&guild_id) || self.handle_for_guild(guild_id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_channel() {
+        let channel = VoiceChannel::new(1, 2).unwrap();
+        assert!(channel.guild_id.get() == 1 && channel.channel_id.get() == 2);
+
+        assert!(VoiceChannel::new(0, 2).is_err());
+        assert!(VoiceChannel::new(1, 0).is_err());
+    }
+
+    #[test]
+    fn test_sessions_per_guild() {
+        let mut sessions = VoiceSessions::default();
+        let first_guild = VoiceChannel::new(10, 11).unwrap();
+        let second_guild = VoiceChannel::new(20, 21).unwrap();
+
+        sessions.insert(1, first_guild).unwrap();
+        sessions.insert(2, second_guild).unwrap();
+        assert!(sessions.handle_for_guild(first_guild.guild_id) == Some(1));
+        assert!(sessions.handle_for_guild(second_guild.guild_id) == Some(2));
+
+        //Second channel of an used guild
+        assert!(sessions
+            .insert(3, VoiceChannel::new(10, 12).unwrap())
+            .is_err());
+        //Same screen can move to another channel
+        sessions
+            .insert(1, VoiceChannel::new(10, 12).unwrap())
+            .unwrap();
+        assert!(sessions.remove(1).unwrap().channel_id.get() == 12);
+        sessions.insert(1, first_guild).unwrap();
+
+        assert!(sessions.remove_guild(first_guild.guild_id) == Some(1));
+        assert!(!sessions.is_guild_used(first_guild.guild_id));
+        sessions.insert(3, first_guild).unwrap();
+
+        sessions.set_title(2, "episode.mp4".to_string());
+        sessions.set_title(4, "unknown.mp4".to_string());
+        assert!(sessions.title(2) == Some("episode.mp4") && sessions.title(4).is_none());
+
+        assert!(sessions.remove(2) == Some(second_guild));
+        assert!(sessions.title(2).is_none());
+        assert!(sessions.remove(2).is_none());
+        assert!(sessions.handle_for_guild(first_guild.guild_id) == Some(3));
+    }
+
+    #[test]
+    fn test_reserved_guild() {
+        let mut sessions = VoiceSessions::default();
+        let channel = VoiceChannel::new(10, 11).unwrap();
+
+        sessions.reserve(channel.guild_id).unwrap();
+        assert!(sessions.is_guild_used(channel.guild_id));
+        assert!(sessions.reserve(channel.guild_id).is_err());
+        assert!(sessions.handle_for_guild(channel.guild_id).is_none());
+
+        //Player was created, the reservation becomes its session
+        sessions.insert(1, channel).unwrap();
+        assert!(sessions.reserve(channel.guild_id).is_err());
+        sessions.remove(1);
+        assert!(!sessions.is_guild_used(channel.guild_id));
+
+        //Player creation failed
+        sessions.reserve(channel.guild_id).unwrap();
+        sessions.cancel_reservation(channel.guild_id);
+        assert!(!sessions.is_guild_used(channel.guild_id));
+    }
+}
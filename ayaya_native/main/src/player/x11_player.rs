@@ -106,6 +106,7 @@ impl VideoPlayer for X11Player {
                             &map_server_options.clone(),
                             frame_index_clone,
                             jvm_rx,
+                            None,
                         )
                         .await;
                         server_tx
@@ -240,10 +241,14 @@ impl VideoPlayer for X11Player {
 
     fn handle_jvm_msg(&self, msg: NativeCommunication) -> anyhow::Result<()> {
         match &self.map_server {
-            Some(server) => {
-                let server = server.clone();
-                server.send_message(msg)?;
-            }
+            //The map server only handles start and stop, the caller gets an error instead of a logged message
+            Some(server) => match msg {
+                NativeCommunication::StartRendering { .. } | NativeCommunication::StopRendering => {
+                    let server = server.clone();
+                    server.send_message(msg)?;
+                }
+                _ => return Err(anyhow!("Map server only accepts start and stop messages!")),
+            },
             None => {
                 return Err(anyhow!("X11 player does not support native messages!"));
             }
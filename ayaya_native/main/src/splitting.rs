@@ -131,6 +131,53 @@ impl SplittedFrame {
         Ok(final_data)
     }
 
+    //Reverse of split_frames, for drawing over frames that were already split
+    pub fn merge_frames(
+        data: &[i8],
+        frames: &[SplittedFrame],
+        width: usize,
+        height: usize,
+        all_frames_x: usize,
+        all_frames_y: usize,
+    ) -> anyhow::Result<Vec<i8>> {
+        if all_frames_y * all_frames_x != frames.len() {
+            return Err(anyhow::Error::msg(
+                "Frame list size does not match required lenght",
+            ));
+        }
+        if data.len() < frames.iter().map(|frame| frame.frame_length).sum() {
+            return Err(anyhow::Error::msg("Splitted frame is too short"));
+        }
+
+        let mut final_data = vec![0i8; width * height];
+
+        let mut i = 0usize;
+        let mut y_i = 0usize;
+
+        let mut data_index = 0;
+
+        for y in 0..all_frames_y {
+            let mut x_i = 0;
+            for _x in 0..all_frames_x {
+                let frame = &frames[i];
+
+                for y1 in 0..frame.height {
+                    let final_data_index = y_i * width + x_i + y1 * width;
+                    final_data[final_data_index..final_data_index + frame.width]
+                        .copy_from_slice(&data[data_index..data_index + frame.width]);
+
+                    data_index += frame.width
+                }
+
+                x_i += frame.width;
+                i += 1;
+            }
+            y_i += frames[y * all_frames_x].height;
+        }
+
+        Ok(final_data)
+    }
+
     //#[cfg(feature = "external_player")]
     pub fn prepare_external_ranges(
         frames: &Vec<SplittedFrame>,
@@ -234,6 +281,49 @@ mod tests {
         assert!(do_vecs_match(&fast, &normalized_normal))
     }
 
+    #[test]
+    fn test_merge_frames() {
+        //Not a multiple of 128, so the edge frames are smaller
+        let width = 300usize;
+        let height = 200usize;
+        let values: Vec<i8> = rand::thread_rng()
+            .sample_iter(rand::distributions::Standard)
+            .take(width * height)
+            .collect();
+
+        let (splitted_frames, all_frames_x, all_frames_y) =
+            SplittedFrame::initialize_frames(width, height).unwrap();
+        let splitted = SplittedFrame::split_frames(
+            values.as_slice(),
+            &splitted_frames,
+            width,
+            all_frames_x,
+            all_frames_y,
+        )
+        .unwrap();
+
+        let merged = SplittedFrame::merge_frames(
+            splitted.as_slice(),
+            &splitted_frames,
+            width,
+            height,
+            all_frames_x,
+            all_frames_y,
+        )
+        .unwrap();
+        assert!(do_vecs_match(&merged, &values));
+
+        assert!(SplittedFrame::merge_frames(
+            &splitted[..100],
+            &splitted_frames,
+            width,
+            height,
+            all_frames_x,
+            all_frames_y
+        )
+        .is_err());
+    }
+
     #[bench]
     fn bench_frame_split(b: &mut Bencher) {
         let width = 3840usize;
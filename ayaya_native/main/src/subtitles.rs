@@ -0,0 +1,738 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::colorlib::Color;
+use crate::media_tracks::TrackSelection;
+use crate::player::text::{TextAlign, TextLayout, TextStyle};
+
+//Anti aliased edges would become random map colors, so glyphs are thresholded instead
+static ALPHA_THRESHOLD: u8 = 128;
+static OUTLINE: u8 = 1;
+static FILL: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleCue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+//Removes <i>, <font ...> and {\an8} style tags
+fn strip_tags(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut closing: Option<char> = None;
+
+    for char in text.chars() {
+        match (closing, char) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, _) => output.push(char),
+            (Some(end), _) if end == char => closing = None,
+            _ => {}
+        }
+    }
+
+    output.trim().to_string()
+}
+
+//"00:01:02,500" (SRT) or "00:01:02.500" (WebVTT like)
+fn parse_srt_time(time: &str) -> Option<Duration> {
+    let (time, millis) = time.trim().split_once([',', '.'])?;
+    let mut parts = time.split(':').map(|part| part.parse::<u64>());
+
+    let hours = parts.next()?.ok()?;
+    let minutes = parts.next()?.ok()?;
+    let seconds = parts.next()?.ok()?;
+    let millis = millis.parse::<u64>().ok()?;
+
+    Some(Duration::from_millis(
+        ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis,
+    ))
+}
+
+pub fn parse_srt(srt: &str) -> Vec<SubtitleCue> {
+    let srt = srt.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+
+    srt.split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.trim_matches('\n').lines();
+
+            //Index line is optional in practice
+            let mut timing = lines.next()?;
+            if !timing.contains("-->") {
+                timing = lines.next()?;
+            }
+
+            let (start, end) = timing.split_once("-->")?;
+            //Some files have position hints after the end time
+            let end = end.split_whitespace().next()?;
+
+            let text: Vec<String> = lines
+                .map(strip_tags)
+                .filter(|line| !line.is_empty())
+                .collect();
+            if text.is_empty() {
+                return None;
+            }
+
+            Some(SubtitleCue {
+                start: parse_srt_time(start)?,
+                end: parse_srt_time(end)?,
+                text: text.join("\n"),
+            })
+        })
+        .collect()
+}
+
+//FFmpeg gives ASS events as "ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text",
+//older versions as a full "Dialogue:" line
+pub fn ass_dialogue_text(event: &str) -> String {
+    let text = match event.strip_prefix("Dialogue:") {
+        Some(dialogue) => dialogue.splitn(10, ',').nth(9),
+        None => event.splitn(9, ',').nth(8),
+    }
+    .unwrap_or(event);
+
+    let text = text
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ");
+
+    text.lines()
+        .map(strip_tags)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Cues of a subtitle stream, sorted by start. Embedded streams are filled in while the video plays
+#[derive(Debug, Default)]
+pub struct SubtitleTrack {
+    cues: Vec<SubtitleCue>,
+}
+
+impl SubtitleTrack {
+    pub fn new(mut cues: Vec<SubtitleCue>) -> Self {
+        cues.sort_by_key(|cue| cue.start);
+        Self { cues }
+    }
+
+    pub fn push(&mut self, cue: SubtitleCue) {
+        let index = self.cues.partition_point(|other| other.start <= cue.start);
+        self.cues.insert(index, cue);
+    }
+
+    pub fn len(&self) -> usize {
+        self.cues.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+
+    //Overlapping cues are shown together, the earlier one on top
+    pub fn text_at(&self, position: Duration) -> Option<String> {
+        let started = self.cues.partition_point(|cue| cue.start <= position);
+
+        let text: Vec<&str> = self.cues[..started]
+            .iter()
+            .filter(|cue| cue.end > position)
+            .map(|cue| cue.text.as_str())
+            .collect();
+
+        match text.is_empty() {
+            true => None,
+            false => Some(text.join("\n")),
+        }
+    }
+}
+
+/// Where the subtitles come from: "file:<path>" (SRT), "auto" (<video name>.srt next to the video,
+/// otherwise the default stream) or a subtitle stream of the video, same format as TrackSelection
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubtitleSource {
+    Stream(TrackSelection),
+    File(String),
+}
+
+impl SubtitleSource {
+    pub fn parse(spec: &str, video_file: &str) -> anyhow::Result<Self> {
+        if let Some(path) = spec.strip_prefix("file:") {
+            return match path.is_empty() {
+                true => Err(anyhow!("Subtitle file path is empty")),
+                false => Ok(Self::File(path.to_string())),
+            };
+        }
+
+        if spec == "auto" {
+            let sidecar = Path::new(video_file).with_extension("srt");
+            return match sidecar.is_file() {
+                true => Ok(Self::File(sidecar.to_string_lossy().to_string())),
+                false => Ok(Self::Stream(TrackSelection::Default)),
+            };
+        }
+
+        Ok(Self::Stream(TrackSelection::parse(spec)?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtitlePosition {
+    Bottom,
+    Top,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleStyle {
+    pub font_size: u32,
+    pub position: SubtitlePosition,
+    //Distance from the top or bottom edge of the video
+    pub margin: u32,
+    pub color: Color,
+    pub outline_color: Color,
+    pub outline_width: u32,
+}
+
+impl Default for SubtitleStyle {
+    fn default() -> Self {
+        Self {
+            font_size: 24,
+            position: SubtitlePosition::Bottom,
+            margin: 8,
+            color: Color::new(255, 255, 255),
+            outline_color: Color::BLACK,
+            outline_width: 2,
+        }
+    }
+}
+
+impl SubtitleStyle {
+    //Colors are snapped to the map palette, so they are not changed again by transform_frame_to_mc
+    pub fn palette_safe(mut self) -> Self {
+        self.color = self.color.to_palette();
+        self.outline_color = self.outline_color.to_palette();
+        self
+    }
+}
+
+/// Subtitles picked by the plugin, track uses the SubtitleSource format
+#[derive(Debug, Clone)]
+pub struct SubtitleOptions {
+    pub track: String,
+    pub style: SubtitleStyle,
+}
+
+/// Rasterized text, every pixel is transparent, OUTLINE or FILL
+#[derive(Debug)]
+pub struct TextBitmap {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl TextBitmap {
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+//Lines are centered. Characters that the font does not have are drawn as the replacement character
+pub fn rasterize(text: &str, style: &SubtitleStyle) -> anyhow::Result<TextBitmap> {
+    let mut text_style = TextStyle::new(style.font_size as f32, style.color);
+    text_style.align = TextAlign::Center;
+    let layout = TextLayout::new(text, &text_style, None)?;
+    let metrics = layout.metrics();
+    let outline = style.outline_width as usize;
+
+    let bitmap_width = metrics.width + outline * 2;
+    let bitmap_height = metrics.height + outline * 2;
+    let mut pixels = vec![0u8; bitmap_width * bitmap_height];
+
+    for (x, y, alpha) in layout.to_mask()?.pixels() {
+        let (x, y) = (x + outline as i32, y + outline as i32);
+        if alpha < ALPHA_THRESHOLD
+            || x < 0
+            || y < 0
+            || x as usize >= bitmap_width
+            || y as usize >= bitmap_height
+        {
+            continue;
+        }
+
+        pixels[y as usize * bitmap_width + x as usize] = FILL;
+    }
+
+    //Every pixel close enough to the text becomes outline
+    let radius = outline as isize;
+    let filled = pixels.clone();
+    for y in 0..bitmap_height as isize {
+        for x in 0..bitmap_width as isize {
+            if filled[y as usize * bitmap_width + x as usize] == FILL {
+                continue;
+            }
+
+            let near_text = (-radius..=radius).any(|offset_y| {
+                (-radius..=radius).any(|offset_x| {
+                    let (near_x, near_y) = (x + offset_x, y + offset_y);
+                    offset_x * offset_x + offset_y * offset_y <= radius * radius
+                        && near_x >= 0
+                        && near_y >= 0
+                        && (near_x as usize) < bitmap_width
+                        && (near_y as usize) < bitmap_height
+                        && filled[near_y as usize * bitmap_width + near_x as usize] == FILL
+                })
+            });
+
+            if near_text {
+                pixels[y as usize * bitmap_width + x as usize] = OUTLINE;
+            }
+        }
+    }
+
+    Ok(TextBitmap {
+        width: bitmap_width,
+        height: bitmap_height,
+        pixels,
+    })
+}
+
+/// Burns subtitles of a track into RGB24 frames
+pub struct SubtitleOverlay {
+    track: Arc<Mutex<SubtitleTrack>>,
+    style: SubtitleStyle,
+    //Text is rasterized again only when it changes
+    cached: Option<(String, TextBitmap)>,
+}
+
+impl SubtitleOverlay {
+    pub fn new(track: Arc<Mutex<SubtitleTrack>>, style: SubtitleStyle) -> Self {
+        Self {
+            track,
+            style,
+            cached: None,
+        }
+    }
+
+    pub fn burn(
+        &mut self,
+        frame: &mut [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        position: Duration,
+    ) -> anyhow::Result<()> {
+        let text = match self.track.lock() {
+            Ok(track) => track.text_at(position),
+            Err(_) => return Err(anyhow!("Cannot lock subtitle track")),
+        };
+        let Some(text) = text else {
+            return Ok(());
+        };
+
+        let bitmap = match self.cached.take() {
+            Some((cached_text, bitmap)) if cached_text == text => bitmap,
+            _ => rasterize(&text, &self.style)?,
+        };
+
+        self.draw(frame, width, height, stride, &bitmap);
+        self.cached = Some((text, bitmap));
+        Ok(())
+    }
+
+    //Text that does not fit is clipped
+    fn draw(
+        &self,
+        frame: &mut [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        bitmap: &TextBitmap,
+    ) {
+        let x0 = (width as isize - bitmap.width as isize) / 2;
+        let y0 = match self.style.position {
+            SubtitlePosition::Top => self.style.margin as isize,
+            SubtitlePosition::Bottom => {
+                height as isize - self.style.margin as isize - bitmap.height as isize
+            }
+        };
+
+        for bitmap_y in 0..bitmap.height {
+            let y = y0 + bitmap_y as isize;
+            if y < 0 || y as usize >= height {
+                continue;
+            }
+
+            for bitmap_x in 0..bitmap.width {
+                let x = x0 + bitmap_x as isize;
+                if x < 0 || x as usize >= width {
+                    continue;
+                }
+
+                let color = match bitmap.get(bitmap_x, bitmap_y) {
+                    pixel if pixel == FILL => &self.style.color,
+                    pixel if pixel == OUTLINE => &self.style.outline_color,
+                    _ => continue,
+                };
+
+                let offset = y as usize * stride + x as usize * 3;
+                frame[offset..offset + 3].copy_from_slice(&[color.red, color.green, color.blue]);
+            }
+        }
+    }
+}
+
+//None when the plugin turned subtitles off
+#[cfg(feature = "ffmpeg")]
+pub fn create_overlay(
+    video_file: &str,
+    options: Option<SubtitleOptions>,
+) -> anyhow::Result<Option<SubtitleOverlay>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+
+    let source = SubtitleSource::parse(&options.track, video_file)?;
+    let track = load_track(video_file, &source)?;
+    Ok(Some(SubtitleOverlay::new(
+        track,
+        options.style.palette_safe(),
+    )))
+}
+
+#[cfg(feature = "ffmpeg")]
+pub fn load_track(
+    video_file: &str,
+    source: &SubtitleSource,
+) -> anyhow::Result<Arc<Mutex<SubtitleTrack>>> {
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    let selection = match source {
+        SubtitleSource::File(path) => {
+            let cues = parse_srt(&std::fs::read_to_string(path)?);
+            return Ok(Arc::new(Mutex::new(SubtitleTrack::new(cues))));
+        }
+        SubtitleSource::Stream(selection) => selection.clone(),
+    };
+
+    let track = Arc::new(Mutex::new(SubtitleTrack::default()));
+    let (ready_tx, ready_rx) = channel::<anyhow::Result<()>>();
+    let (video_file, weak_track) = (video_file.to_string(), Arc::downgrade(&track));
+
+    //Packets of the whole file have to be read, so cues are added while the video is playing.
+    //FFmpeg contexts can't be moved between threads, so the decoder is created there.
+    //Thread stops once the overlay is dropped, for example when other subtitles are picked
+    thread::spawn(move || {
+        let stream = match open_subtitle_stream(&video_file, &selection) {
+            Ok(val) => {
+                let _ = ready_tx.send(Ok(()));
+                val
+            }
+            Err(err) => {
+                let _ = ready_tx.send(Err(err));
+                return;
+            }
+        };
+
+        if let Err(err) = decode_subtitle_stream(stream, &weak_track) {
+            println!("[ProjectAyaya] Unable to decode subtitles! Reason: {err}");
+        }
+    });
+
+    match ready_rx.recv() {
+        Ok(result) => result?,
+        Err(_) => return Err(anyhow!("Subtitle thread exited before opening the file")),
+    };
+    Ok(track)
+}
+
+#[cfg(feature = "ffmpeg")]
+struct SubtitleStream {
+    input: ffmpeg::format::context::Input,
+    stream_index: usize,
+    time_base: ffmpeg::Rational,
+    decoder: ffmpeg::decoder::Subtitle,
+}
+
+#[cfg(feature = "ffmpeg")]
+fn open_subtitle_stream(
+    video_file: &str,
+    selection: &TrackSelection,
+) -> anyhow::Result<SubtitleStream> {
+    use crate::media_tracks::{self, TrackKind};
+
+    ffmpeg::init()?;
+    let input = ffmpeg::format::input(&video_file)?;
+
+    let tracks = media_tracks::from_input(&input);
+    let stream_index = match media_tracks::select(&tracks, TrackKind::Subtitle, selection)? {
+        Some(val) => val,
+        None => return Err(anyhow!("Video has no subtitle tracks")),
+    };
+
+    let stream = input
+        .stream(stream_index)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let time_base = stream.time_base();
+    let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .subtitle()?;
+
+    Ok(SubtitleStream {
+        input,
+        stream_index,
+        time_base,
+        decoder,
+    })
+}
+
+#[cfg(feature = "ffmpeg")]
+fn decode_subtitle_stream(
+    mut stream: SubtitleStream,
+    track: &std::sync::Weak<Mutex<SubtitleTrack>>,
+) -> anyhow::Result<()> {
+    use ffmpeg::subtitle::Rect;
+    use ffmpeg::Rescale;
+
+    for (packet_stream, packet) in stream.input.packets() {
+        let Some(track) = track.upgrade() else {
+            return Ok(());
+        };
+        if packet_stream.index() != stream.stream_index {
+            continue;
+        }
+
+        let mut subtitle = ffmpeg::Subtitle::new();
+        if !stream.decoder.decode(&packet, &mut subtitle)? {
+            continue;
+        }
+        let Some(pts) = packet.pts() else {
+            continue;
+        };
+
+        //Display times of the subtitle are relative to the packet
+        let packet_ms = pts.rescale(stream.time_base, (1, 1000)).max(0) as u64;
+        let end_ms = match subtitle.end() > subtitle.start() {
+            true => subtitle.end() as u64,
+            false => packet
+                .duration()
+                .rescale(stream.time_base, (1, 1000))
+                .max(0) as u64,
+        };
+
+        //Bitmap subtitles (DVD, PGS) are not supported
+        let text: Vec<String> = subtitle
+            .rects()
+            .filter_map(|rect| match rect {
+                Rect::Text(text) => Some(strip_tags(text.get())),
+                Rect::Ass(ass) => Some(ass_dialogue_text(ass.get())),
+                _ => None,
+            })
+            .filter(|text| !text.is_empty())
+            .collect();
+        if text.is_empty() {
+            continue;
+        }
+
+        let cue = SubtitleCue {
+            start: Duration::from_millis(packet_ms + subtitle.start() as u64),
+            end: Duration::from_millis(packet_ms + end_ms),
+            text: text.join("\n"),
+        };
+
+        match track.lock() {
+            Ok(mut track) => track.push(cue),
+            Err(_) => return Err(anyhow!("Cannot lock subtitle track")),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    static SRT: &str = "\u{feff}1\r\n00:00:01,000 --> 00:00:03,500\r\n<i>Hello</i> there\r\n\r\n2\r\n00:00:03,000 --> 00:00:05,000 X1:40\r\n{\\an8}Second line\r\nand more\r\n\r\n3\r\nbroken --> timing\r\ntext\r\n";
+
+    fn cue(start_ms: u64, end_ms: u64, text: &str) -> SubtitleCue {
+        SubtitleCue {
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_srt() {
+        let cues = parse_srt(SRT);
+
+        assert!(cues.len() == 2);
+        assert!(cues[0] == cue(1000, 3500, "Hello there"));
+        assert!(cues[1] == cue(3000, 5000, "Second line\nand more"));
+    }
+
+    #[test]
+    fn test_ass_dialogue_text() {
+        assert!(
+            ass_dialogue_text("0,0,Default,,0,0,0,,{\\i1}Hello,{\\i0} world\\Nsecond\\hline")
+                == "Hello, world\nsecond line"
+        );
+        assert!(
+            ass_dialogue_text("Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Old format")
+                == "Old format"
+        );
+    }
+
+    #[test]
+    fn test_text_at() {
+        let mut track = SubtitleTrack::new(parse_srt(SRT));
+        track.push(cue(0, 500, "Intro"));
+
+        assert!(track.text_at(Duration::from_millis(100)).unwrap() == "Intro");
+        assert!(track.text_at(Duration::from_millis(700)).is_none());
+        assert!(
+            track.text_at(Duration::from_millis(3200)).unwrap()
+                == "Hello there\nSecond line\nand more"
+        );
+        //End is exclusive
+        assert!(track.text_at(Duration::from_millis(5000)).is_none());
+        assert!(track.len() == 3);
+    }
+
+    #[test]
+    fn test_subtitle_source() {
+        let video = env::temp_dir().join(format!("ayaya_sidecar_{}.mkv", std::process::id()));
+        let video = video.to_str().unwrap();
+
+        assert!(
+            SubtitleSource::parse("auto", video).unwrap()
+                == SubtitleSource::Stream(TrackSelection::Default)
+        );
+        assert!(
+            SubtitleSource::parse("eng", video).unwrap()
+                == SubtitleSource::Stream(TrackSelection::Language("eng".to_string()))
+        );
+        assert!(
+            SubtitleSource::parse("file:/tmp/a.srt", video).unwrap()
+                == SubtitleSource::File("/tmp/a.srt".to_string())
+        );
+        assert!(SubtitleSource::parse("file:", video).is_err());
+
+        let sidecar = Path::new(video).with_extension("srt");
+        fs::write(&sidecar, SRT).unwrap();
+        assert!(
+            SubtitleSource::parse("auto", video).unwrap()
+                == SubtitleSource::File(sidecar.to_string_lossy().to_string())
+        );
+        fs::remove_file(sidecar).unwrap();
+    }
+
+    #[test]
+    fn test_rasterize_outline() {
+        let style = SubtitleStyle::default();
+        let bitmap = rasterize("Hi\nlonger line", &style).unwrap();
+
+        let count = |value: u8| {
+            (0..bitmap.height)
+                .flat_map(|y| (0..bitmap.width).map(move |x| (x, y)))
+                .filter(|(x, y)| bitmap.get(*x, *y) == value)
+                .count()
+        };
+        assert!(count(FILL) > 0 && count(OUTLINE) > 0);
+
+        //Two lines and the outline around them
+        assert!(bitmap.height > style.font_size as usize * 2);
+        //Outline border is never filled
+        assert!((0..bitmap.width).all(|x| bitmap.get(x, 0) != FILL));
+
+        //Unsupported characters are drawn as the replacement character
+        let bitmap = rasterize("\u{1F600}", &style).unwrap();
+        assert!((0..bitmap.height).any(|y| (0..bitmap.width).any(|x| bitmap.get(x, y) == FILL)));
+    }
+
+    #[test]
+    fn test_burn_subtitles() {
+        let (width, height) = (256, 128);
+        let stride = width * 3 + 16;
+        let mut frame = vec![0x40u8; stride * height];
+
+        let track = Arc::new(Mutex::new(SubtitleTrack::new(vec![cue(
+            1000, 2000, "Ayaya",
+        )])));
+        let style = SubtitleStyle {
+            color: Color::new(255, 0, 0),
+            outline_color: Color::new(0, 0, 255),
+            ..SubtitleStyle::default()
+        };
+        let mut overlay = SubtitleOverlay::new(track.clone(), style.clone());
+
+        //Nothing to show yet
+        overlay
+            .burn(&mut frame, width, height, stride, Duration::ZERO)
+            .unwrap();
+        assert!(frame.iter().all(|value| *value == 0x40));
+
+        overlay
+            .burn(
+                &mut frame,
+                width,
+                height,
+                stride,
+                Duration::from_millis(1500),
+            )
+            .unwrap();
+        let pixel = |x: usize, y: usize| &frame[y * stride + x * 3..y * stride + x * 3 + 3];
+
+        let mut rows_with_text =
+            (0..height).filter(|y| (0..width).any(|x| pixel(x, *y) == [255, 0, 0]));
+        let first_row = rows_with_text.next().unwrap();
+        let last_row = rows_with_text.next_back().unwrap();
+        //Bottom of the video, above the margin
+        assert!(first_row > height / 2 && last_row < height - style.margin as usize);
+        assert!((0..height).any(|y| (0..width).any(|x| pixel(x, y) == [0, 0, 255])));
+
+        //Only the text and outline colors are written, no blending
+        assert!((0..height).all(|y| (0..width).all(|x| {
+            let rgb = pixel(x, y);
+            rgb == [0x40; 3] || rgb == [255, 0, 0] || rgb == [0, 0, 255]
+        })));
+        //Stride padding is left untouched
+        assert!(
+            (0..height).all(|y| frame[y * stride + width * 3..(y + 1) * stride]
+                .iter()
+                .all(|value| *value == 0x40))
+        );
+
+        //Text larger than the frame is clipped instead of panicking
+        let mut small_frame = vec![0u8; 16 * 8 * 3];
+        let mut overlay = SubtitleOverlay::new(track, style);
+        overlay
+            .burn(&mut small_frame, 16, 8, 16 * 3, Duration::from_millis(1500))
+            .unwrap();
+    }
+
+    #[cfg(not(feature = "skip_buildrs"))]
+    #[test]
+    fn test_palette_safe_style() {
+        use crate::colorlib::map_index_to_color;
+
+        let style = SubtitleStyle {
+            color: Color::new(250, 240, 10),
+            outline_color: Color::new(3, 5, 9),
+            ..SubtitleStyle::default()
+        }
+        .palette_safe();
+
+        //Converting the snapped colors again does not change them
+        for color in [style.color, style.outline_color] {
+            assert!(map_index_to_color(color.convert_to_mc() as i8) == color);
+        }
+    }
+}
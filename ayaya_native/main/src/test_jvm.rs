@@ -0,0 +1,587 @@
+//Tests of the exported JNI functions. They run inside of an embedded JVM. The library is written in
+//kotlin and there is no kotlin compiler here, so java classes with the same constructors, getters and
+//enum constants are generated from the enum and data classes in library/ and compiled with javac.
+//The tests need javac and libjvm (set JAVA_HOME if the JVM is not detected), so they are ignored
+//unless the jdk feature is enabled (cargo test --features jdk), a missing JDK fails them
+
+use std::env;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use jni::objects::{JByteArray, JClass, JObject, JObjectArray, JString, JValue};
+use jni::sys::jlong;
+use jni::{InitArgsBuilder, JNIEnv, JNIVersion, JavaVM};
+use once_cell::sync::Lazy;
+
+use crate::frame_cache::{FrameCacheHeader, FrameCacheWriter};
+use crate::*;
+
+static LIBRARY_PACKAGE: &str = "me/wcaleniewolny/ayaya/library";
+
+//Kotlin source of the library, relative to the manifest
+static LIBRARY_SOURCES: &str = "../../library/src/main/kotlin/me/wcaleniewolny/ayaya/library";
+
+//Only one JVM can be created per process, so every test shares it
+static JVM: Lazy<JavaVM> = Lazy::new(|| {
+    let library_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(LIBRARY_SOURCES);
+    let temp_dir = env::temp_dir().join(format!("ayaya_jvm_classes_{}", std::process::id()));
+    let source_dir = temp_dir.join("src");
+    let class_dir = temp_dir.join("classes");
+    fs::create_dir_all(&source_dir).unwrap();
+
+    let mut sources = Vec::<PathBuf>::new();
+    for entry in fs::read_dir(&library_dir).unwrap() {
+        let path = entry.unwrap().path();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            //Written in java, so we can use the real thing
+            Some("java") => sources.push(path),
+            Some("kt") => {
+                for (name, source) in java_classes(&fs::read_to_string(&path).unwrap()) {
+                    let java_path = source_dir.join(format!("{name}.java"));
+                    fs::write(&java_path, source).unwrap();
+                    sources.push(java_path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let status = Command::new("javac")
+        .arg("-d")
+        .arg(&class_dir)
+        .args(&sources)
+        .status()
+        .expect("javac not found, the JVM tests need a JDK");
+    assert!(status.success(), "Couldn't compile the generated library classes");
+
+    let args = InitArgsBuilder::new()
+        .version(JNIVersion::V8)
+        .option(format!("-Djava.class.path={}", class_dir.display()))
+        .build()
+        .unwrap();
+
+    JavaVM::new(args).expect("Couldn't start the JVM")
+});
+
+fn java_type(kotlin_type: &str) -> String {
+    match kotlin_type {
+        "Int" => "int",
+        "Long" => "long",
+        "Float" => "float",
+        "Double" => "double",
+        "Boolean" => "boolean",
+        "Int?" => "Integer",
+        "Long?" => "Long",
+        "Float?" => "Float",
+        "Double?" => "Double",
+        "Boolean?" => "Boolean",
+        "ByteArray" => "byte[]",
+        "IntArray" => "int[]",
+        "LongArray" => "long[]",
+        //Strings and classes of the library
+        other => other.trim_end_matches('?'),
+    }
+    .to_string()
+}
+
+//Java source of every top level enum and data class in a kotlin file. Anything else (sealed
+//classes, objects, functions) is skipped, the native side does not create those
+fn java_classes(kotlin: &str) -> Vec<(String, String)> {
+    let without_comments: String = kotlin
+        .lines()
+        .map(|line| line.split("//").next().unwrap())
+        .filter(|line| {
+            let line = line.trim_start();
+            !line.starts_with("/**") && !line.starts_with('*')
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut classes = Vec::new();
+    for (start, _) in without_comments.match_indices("\nenum class ") {
+        let declaration = &without_comments[start + "\nenum class ".len()..];
+        let name = declaration.split(|c: char| !c.is_alphanumeric()).next().unwrap();
+        let body = &declaration[declaration.find('{').unwrap() + 1..declaration.find('}').unwrap()];
+        let constants: Vec<&str> = body
+            .split(';')
+            .next()
+            .unwrap()
+            .split(',')
+            .map(|constant| constant.trim())
+            .filter(|constant| !constant.is_empty())
+            .collect();
+
+        let source = format!(
+            "package me.wcaleniewolny.ayaya.library;\n\npublic enum {name} {{\n    {}\n}}\n",
+            constants.join(",\n    ")
+        );
+        classes.push((name.to_string(), source));
+    }
+
+    for (start, _) in without_comments.match_indices("\ndata class ") {
+        let declaration = &without_comments[start + "\ndata class ".len()..];
+        let name = declaration.split(|c: char| !c.is_alphanumeric()).next().unwrap();
+        let parameters = &declaration[declaration.find('(').unwrap() + 1..declaration.find(')').unwrap()];
+
+        let mut fields = String::new();
+        let mut arguments = Vec::new();
+        let mut assignments = String::new();
+        let mut getters = String::new();
+        let parameters = parameters
+            .split(',')
+            .map(|parameter| parameter.trim())
+            .filter(|parameter| !parameter.is_empty());
+        for (i, parameter) in parameters.enumerate() {
+            //Default values do not change the constructor, kotlin only adds a synthetic one
+            let parameter = parameter.split('=').next().unwrap().trim();
+            let parameter = parameter.trim_start_matches("val ").trim_start_matches("var ");
+            let (property, kotlin_type) = parameter.split_once(':').unwrap();
+            let (property, java_type) = (property.trim(), java_type(kotlin_type.trim()));
+            let getter = match property.strip_prefix("is") {
+                Some(rest) if rest.starts_with(char::is_uppercase) => property.to_string(),
+                _ => format!("get{}{}", property[..1].to_uppercase(), &property[1..]),
+            };
+            //Properties can be java keywords (MediaTrack.default)
+            let field = format!("value{i}");
+
+            fields.push_str(&format!("    private final {java_type} {field};\n"));
+            arguments.push(format!("{java_type} {field}"));
+            assignments.push_str(&format!("        this.{field} = {field};\n"));
+            getters.push_str(&format!(
+                "\n    public {java_type} {getter}() {{\n        return {field};\n    }}\n"
+            ));
+        }
+
+        let source = format!(
+            "package me.wcaleniewolny.ayaya.library;\n\npublic final class {name} {{\n{fields}\n    public {name}({}) {{\n{assignments}    }}\n{getters}}}\n",
+            arguments.join(", ")
+        );
+        classes.push((name.to_string(), source));
+    }
+
+    classes
+}
+
+fn class() -> JClass<'static> {
+    JClass::from(JObject::null())
+}
+
+fn enum_value<'local>(env: &mut JNIEnv<'local>, name: &str, value: &str) -> JObject<'local> {
+    env.get_static_field(
+        format!("{LIBRARY_PACKAGE}/{name}"),
+        value,
+        format!("L{LIBRARY_PACKAGE}/{name};"),
+    )
+    .unwrap()
+    .l()
+    .unwrap()
+}
+
+fn enum_name(env: &mut JNIEnv, value: &JObject) -> String {
+    let name = env
+        .call_method(value, "name", "()Ljava/lang/String;", &[])
+        .unwrap()
+        .l()
+        .unwrap();
+    env.get_string(&JString::from(name)).unwrap().into()
+}
+
+fn server_options<'local>(env: &mut JNIEnv<'local>) -> JObject<'local> {
+    let bind_ip = env.new_string("").unwrap();
+    env.new_object(
+        format!("{LIBRARY_PACKAGE}/MapServerOptions"),
+        "(ZLjava/lang/String;ILjava/lang/String;)V",
+        &[
+            JValue::Bool(0),
+            JValue::Object(&bind_ip),
+            JValue::Int(0),
+            JValue::Object(&JObject::null()),
+        ],
+    )
+    .unwrap()
+}
+
+//Returns the message of the pending java exception and clears it
+fn take_exception(env: &mut JNIEnv) -> Option<String> {
+    if !env.exception_check().unwrap() {
+        return None;
+    }
+
+    let exception = env.exception_occurred().unwrap();
+    env.exception_clear().unwrap();
+
+    assert!(env
+        .is_instance_of(&exception, "java/lang/RuntimeException")
+        .unwrap());
+    let message = env
+        .call_method(&exception, "getMessage", "()Ljava/lang/String;", &[])
+        .unwrap()
+        .l()
+        .unwrap();
+    Some(env.get_string(&JString::from(message)).unwrap().into())
+}
+
+//Baked videos do not need ffmpeg, so they are the easiest way to get a real player
+struct BakedTestVideo {
+    path: PathBuf,
+    frames: Vec<Vec<i8>>,
+}
+
+impl BakedTestVideo {
+    fn create(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("ayaya_jvm_{name}_{}.ayc", std::process::id()));
+        let header = FrameCacheHeader::new(256, 128, 20).unwrap();
+        let mut writer =
+            FrameCacheWriter::new(BufWriter::new(fs::File::create(&path).unwrap()), &header)
+                .unwrap();
+
+        let frames: Vec<Vec<i8>> = (0..40)
+            .map(|i| vec![(i % 100) as i8; 2 * 128 * 128])
+            .collect();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.finish().unwrap();
+
+        Self { path, frames }
+    }
+
+    fn init(&self, env: &mut JNIEnv) -> jlong {
+        let file_name = env.new_string(self.path.to_str().unwrap()).unwrap();
+        let render_type = enum_value(env, "NativeRenderType", "BAKED");
+        let server_options = server_options(env);
+
+        let ptr = Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_init(
+            unsafe { env.unsafe_clone() },
+            class(),
+            file_name,
+            render_type,
+            server_options,
+            false,
+        );
+        assert!(take_exception(env).is_none());
+        ptr
+    }
+}
+
+impl Drop for BakedTestVideo {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn load_frame(env: &mut JNIEnv, ptr: jlong) -> Vec<i8> {
+    let frame = Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_loadFrame(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+    );
+    assert!(take_exception(env).is_none());
+
+    let frame = unsafe { JByteArray::from_raw(frame) };
+    let frame = env.convert_byte_array(frame).unwrap();
+    bytemuck::cast_vec(frame)
+}
+
+fn communicate(env: &mut JNIEnv, ptr: jlong, message: &str, info: &str) -> Option<String> {
+    let message = enum_value(env, "NativeLibCommunication", message);
+    let info = env.new_string(info).unwrap();
+
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_communicate(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+        message,
+        info,
+    );
+    take_exception(env)
+}
+
+fn send_game_input(env: &mut JNIEnv, ptr: jlong, events: &[u8]) -> Option<String> {
+    let events = env.byte_array_from_slice(events).unwrap();
+
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_sendGameInput(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+        events,
+    );
+    take_exception(env)
+}
+
+fn set_subtitles(env: &mut JNIEnv, ptr: jlong, font_size: Option<i32>) -> Option<String> {
+    let options = match font_size {
+        Some(font_size) => {
+            let track = env.new_string("auto").unwrap();
+            let position = enum_value(env, "SubtitlePosition", "TOP");
+            env.new_object(
+                format!("{LIBRARY_PACKAGE}/SubtitleOptions"),
+                format!("(Ljava/lang/String;IL{LIBRARY_PACKAGE}/SubtitlePosition;IIII)V"),
+                &[
+                    JValue::Object(&track),
+                    JValue::Int(font_size),
+                    JValue::Object(&position),
+                    JValue::Int(8),
+                    JValue::Int(0xffffff),
+                    JValue::Int(0),
+                    JValue::Int(2),
+                ],
+            )
+            .unwrap()
+        }
+        None => JObject::null(),
+    };
+
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_setSubtitles(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+        options,
+    );
+    take_exception(env)
+}
+
+fn verify_capabilities(env: &mut JNIEnv, file_name: &str, width: i32, height: i32) -> String {
+    let file_name = env.new_string(file_name).unwrap();
+
+    let response =
+        Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_verifyScreenCapabilities(
+            unsafe { env.unsafe_clone() },
+            class(),
+            file_name,
+            width,
+            height,
+            false,
+        );
+    assert!(take_exception(env).is_none());
+
+    let response = unsafe { JObject::from_raw(response) };
+    enum_name(env, &response)
+}
+
+#[test]
+#[cfg_attr(not(feature = "jdk"), ignore = "needs a JDK")]
+fn test_baked_player_lifecycle() {
+    let vm = &*JVM;
+    let mut env = vm.attach_current_thread().unwrap();
+    let video = BakedTestVideo::create("lifecycle");
+
+    let ptr = video.init(&mut env);
+
+    let video_data = Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_getVideoData(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+    );
+    assert!(take_exception(&mut env).is_none());
+    let video_data = unsafe { JObject::from_raw(video_data) };
+    for (getter, expected) in [("getWidth", 256), ("getHeight", 128), ("getFps", 20)] {
+        let value = env
+            .call_method(&video_data, getter, "()I", &[])
+            .unwrap()
+            .i()
+            .unwrap();
+        assert!(value == expected);
+    }
+
+    assert!(load_frame(&mut env, ptr) == video.frames[0]);
+    assert!(load_frame(&mut env, ptr) == video.frames[1]);
+
+    assert!(communicate(&mut env, ptr, "VIDEO_SEEK", "1").is_none());
+    assert!(load_frame(&mut env, ptr) == video.frames[20]);
+
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_destroy(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+    );
+    assert!(take_exception(&mut env).is_none());
+}
+
+#[test]
+#[cfg_attr(not(feature = "jdk"), ignore = "needs a JDK")]
+fn test_invalid_messages_throw() {
+    let vm = &*JVM;
+    let mut env = vm.attach_current_thread().unwrap();
+    let video = BakedTestVideo::create("messages");
+
+    let ptr = video.init(&mut env);
+
+    //Play/pause is supported, so only the other messages throw
+    assert!(communicate(&mut env, ptr, "STOP_RENDERING", "").is_none());
+    assert!(communicate(&mut env, ptr, "START_RENDERING", "20").is_none());
+
+    let error = communicate(&mut env, ptr, "GAME_INPUT", "F_Q").unwrap();
+    assert!(error.contains("Invalid short game input"));
+
+    assert!(communicate(&mut env, ptr, "VIDEO_SEEK", "not a number").is_some());
+
+    //Baked player has no audio sink
+    let error = communicate(&mut env, ptr, "AUDIO_TRACK", "jpn").unwrap();
+    assert!(error.contains("Expected StartRendering, StopRendering or VideoSeek msg"));
+    let error = communicate(&mut env, ptr, "AUDIO_TRACK", "en-US").unwrap();
+    assert!(error.contains("Invalid track"));
+
+    //Baked frames are already converted, so subtitles can't be drawn
+    let error = set_subtitles(&mut env, ptr, Some(24)).unwrap();
+    assert!(error.contains("Expected StartRendering, StopRendering or VideoSeek msg"));
+    let error = set_subtitles(&mut env, ptr, None).unwrap();
+    assert!(error.contains("Expected StartRendering, StopRendering or VideoSeek msg"));
+    let error = set_subtitles(&mut env, ptr, Some(0)).unwrap();
+    assert!(error.contains("Invalid subtitle size"));
+
+    let error = communicate(&mut env, ptr, "OSD", "pause").unwrap();
+    assert!(error.contains("Expected StartRendering, StopRendering or VideoSeek msg"));
+    let error = communicate(&mut env, ptr, "OSD", "volume").unwrap();
+    assert!(error.contains("Invalid OSD command"));
+
+    //Baked player pauses without a clock
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_isPlaying(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+    );
+    let error = take_exception(&mut env).unwrap();
+    assert!(error.contains("not synchronized to a clock"));
+
+    //Binary game input, the baked player is not a game
+    let error = send_game_input(&mut env, ptr, &[0, 0, 4]).unwrap();
+    assert!(error.contains("Expected StartRendering, StopRendering or VideoSeek msg"));
+    let error = send_game_input(&mut env, ptr, &[0, 0]).unwrap();
+    assert!(error.contains("Game input is truncated"));
+
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_destroy(
+        unsafe { env.unsafe_clone() },
+        class(),
+        ptr,
+    );
+    assert!(take_exception(&mut env).is_none());
+}
+
+#[test]
+#[cfg_attr(not(feature = "jdk"), ignore = "needs a JDK")]
+fn test_init_throws() {
+    let vm = &*JVM;
+    let mut env = vm.attach_current_thread().unwrap();
+
+    let file_name = env.new_string("this_file_does_not_exist.ayc").unwrap();
+    let render_type = enum_value(&mut env, "NativeRenderType", "BAKED");
+    let server_options = server_options(&mut env);
+
+    let ptr = Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_init(
+        unsafe { env.unsafe_clone() },
+        class(),
+        file_name,
+        render_type,
+        server_options,
+        false,
+    );
+    assert!(ptr == 0);
+    assert!(take_exception(&mut env).is_some());
+}
+
+#[test]
+#[cfg_attr(not(feature = "jdk"), ignore = "needs a JDK")]
+fn test_verify_capabilities() {
+    let vm = &*JVM;
+    let mut env = vm.attach_current_thread().unwrap();
+    let video = BakedTestVideo::create("capabilities");
+    let file_name = video.path.to_str().unwrap();
+
+    assert!(verify_capabilities(&mut env, file_name, 256, 128) == "OK");
+    assert!(verify_capabilities(&mut env, file_name, 128, 128) == "TO_SMALL");
+    assert!(verify_capabilities(&mut env, file_name, 512, 256) == "TO_LARGE");
+
+    //Screen size is checked before the file is opened
+    let file_name = env.new_string(file_name).unwrap();
+    let response =
+        Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_verifyScreenCapabilities(
+            unsafe { env.unsafe_clone() },
+            class(),
+            file_name,
+            100,
+            128,
+            false,
+        );
+    assert!(response.is_null());
+    assert!(take_exception(&mut env)
+        .unwrap()
+        .contains("not divisble by 128"));
+}
+
+#[test]
+#[cfg_attr(not(feature = "jdk"), ignore = "needs a JDK")]
+fn test_get_games() {
+    let vm = &*JVM;
+    let mut env = vm.attach_current_thread().unwrap();
+
+    let games = Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_getGames(
+        unsafe { env.unsafe_clone() },
+        class(),
+    );
+    assert!(take_exception(&mut env).is_none());
+    let games = unsafe { JObjectArray::from_raw(games) };
+    assert!(env.get_array_length(&games).unwrap() as usize == apps::games().len());
+
+    for (i, entry) in apps::games().iter().enumerate() {
+        let game = env.get_object_array_element(&games, i as i32).unwrap();
+        let id = env
+            .call_method(&game, "getId", "()Ljava/lang/String;", &[])
+            .unwrap()
+            .l()
+            .unwrap();
+        let id: String = env.get_string(&id.into()).unwrap().into();
+        let width = env.call_method(&game, "getWidth", "()I", &[]).unwrap();
+        let max_players = env.call_method(&game, "getMaxPlayers", "()I", &[]).unwrap();
+
+        assert!(id == entry.id);
+        assert!(width.i().unwrap() as usize == entry.width);
+        assert!(max_players.i().unwrap() as usize == entry.max_players);
+    }
+}
+
+#[test]
+#[cfg_attr(not(feature = "jdk"), ignore = "needs a JDK")]
+fn test_encrypted_connection() {
+    let vm = &*JVM;
+    let mut env = vm.attach_current_thread().unwrap();
+
+    let invalid_secret = env.byte_array_from_slice(&[0u8; 15]).unwrap();
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_createEncryptedConnection(
+        unsafe { env.unsafe_clone() },
+        class(),
+        invalid_secret,
+    );
+    assert!(take_exception(&mut env).unwrap().contains("16 bytes long"));
+
+    let shared_secret = env.byte_array_from_slice(&[7u8; 16]).unwrap();
+    let connection_ptr =
+        Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_createEncryptedConnection(
+            unsafe { env.unsafe_clone() },
+            class(),
+            shared_secret,
+        );
+    assert!(take_exception(&mut env).is_none());
+
+    let data = env.byte_array_from_slice(&[1u8; 64]).unwrap();
+    let encrypted = Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_encryptForConnection(
+        unsafe { env.unsafe_clone() },
+        class(),
+        connection_ptr,
+        data,
+    );
+    assert!(take_exception(&mut env).is_none());
+    let encrypted = env
+        .convert_byte_array(unsafe { JByteArray::from_raw(encrypted) })
+        .unwrap();
+    assert!(encrypted.len() == 64 && encrypted != vec![1u8; 64]);
+
+    Java_me_wcaleniewolny_ayaya_library_NativeRenderControler_destroyEncryptedConnection(
+        unsafe { env.unsafe_clone() },
+        class(),
+        connection_ptr,
+    );
+    assert!(take_exception(&mut env).is_none());
+}
@@ -0,0 +1,9 @@
+package me.wcaleniewolny.ayaya.library
+
+/**
+ * Game compiled into the native library, see [NativeRenderControler.getGames]
+ * @param id Name passed to [NativeRenderControler.init] with NativeRenderType.GAME
+ * @param width Width of the game in pixels, the screen has to be at least this large
+ * @param maxPlayers Players that can play at once, see [GameInputEvent.Join]
+ */
+data class GameInfo(val id: String, val name: String, val description: String, val width: Int, val height: Int, val maxPlayers: Int)
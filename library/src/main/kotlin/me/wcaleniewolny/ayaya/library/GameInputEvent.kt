@@ -0,0 +1,98 @@
+package me.wcaleniewolny.ayaya.library
+
+import java.io.ByteArrayOutputStream
+import java.io.DataOutputStream
+
+/**
+ * Keys with a held state. Movement keys are relative to the screen, not to the world
+ */
+enum class GameKey {
+    FORWARD,
+    BACKWARDS,
+    LEFT,
+    RIGHT,
+    JUMP,
+    SNEAK,
+    SPRINT
+}
+
+/**
+ * Buttons that only fire once
+ */
+enum class GameAction {
+    ATTACK,
+    USE,
+    DROP,
+    SWAP_HANDS
+}
+
+/**
+ * Input of a single game player, sent with [NativeRenderControler.sendGameInput]
+ */
+sealed class GameInputEvent {
+    data class KeyDown(val key: GameKey) : GameInputEvent()
+    data class KeyUp(val key: GameKey) : GameInputEvent()
+    data class Action(val action: GameAction) : GameInputEvent()
+
+    /**
+     * @param slot Selected hotbar slot (0-8)
+     */
+    data class Hotbar(val slot: Int) : GameInputEvent()
+
+    /**
+     * Camera rotation since the last look event, in degrees
+     */
+    data class Look(val yaw: Float, val pitch: Float) : GameInputEvent()
+
+    /**
+     * The player starts playing. The player that started the game (id 0) joins automatically
+     */
+    object Join : GameInputEvent()
+
+    /**
+     * The player stops playing, events of players that did not join are ignored
+     */
+    object Leave : GameInputEvent()
+
+    // Player id byte, type byte and the payload. DataOutputStream writes floats as big endian like the native side expects
+    fun write(playerId: Int, output: DataOutputStream) {
+        output.writeByte(playerId)
+        when (this) {
+            is KeyDown -> {
+                output.writeByte(0)
+                output.writeByte(key.ordinal)
+            }
+            is KeyUp -> {
+                output.writeByte(1)
+                output.writeByte(key.ordinal)
+            }
+            is Action -> {
+                output.writeByte(2)
+                output.writeByte(action.ordinal)
+            }
+            is Hotbar -> {
+                output.writeByte(3)
+                output.writeByte(slot)
+            }
+            is Look -> {
+                output.writeByte(4)
+                output.writeFloat(yaw)
+                output.writeFloat(pitch)
+            }
+            Join -> output.writeByte(5)
+            Leave -> output.writeByte(6)
+        }
+    }
+
+    companion object {
+        /**
+         * @param events Player id and the event, in the order they happened
+         */
+        fun encode(events: List<Pair<Int, GameInputEvent>>): ByteArray {
+            val bytes = ByteArrayOutputStream()
+            val output = DataOutputStream(bytes)
+            events.forEach { (playerId, event) -> event.write(playerId, output) }
+            return bytes.toByteArray()
+        }
+    }
+}
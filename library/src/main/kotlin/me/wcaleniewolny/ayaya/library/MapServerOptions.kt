@@ -1,3 +1,9 @@
 package me.wcaleniewolny.ayaya.library
 
-data class MapServerOptions(val useServer: Boolean, val bindIp: String, val port: Int)
\ No newline at end of file
+data class MapServerOptions(
+    val useServer: Boolean,
+    val bindIp: String,
+    val port: Int,
+    // PNG files in this directory replace or extend the images built into the native library
+    val assetDirectory: String? = null
+)
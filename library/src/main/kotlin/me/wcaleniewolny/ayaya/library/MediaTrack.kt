@@ -0,0 +1,7 @@
+package me.wcaleniewolny.ayaya.library
+
+/**
+ * @param index Stream index in the file, can be used to select the track
+ * @param language ISO 639-2 tag (for example "jpn"), null when the file does not specify it
+ */
+data class MediaTrack(val index: Int, val type: MediaTrackType, val language: String?, val title: String?, val codec: String, val default: Boolean)
@@ -0,0 +1,6 @@
+package me.wcaleniewolny.ayaya.library
+
+enum class MediaTrackType {
+    AUDIO,
+    SUBTITLE
+}
@@ -4,5 +4,7 @@ public enum NativeLibCommunication {
     START_RENDERING,
     STOP_RENDERING,
     GAME_INPUT,
-    VIDEO_SEEK
+    VIDEO_SEEK,
+    AUDIO_TRACK,
+    OSD
 }
@@ -11,6 +11,22 @@ object NativeRenderControler {
      */
     external fun loadFrame(ptr: Long): ByteArray
 
+    /**
+     * Audio/video synchronization statistics of players that follow a master clock (discord audio or map server)
+     * @param ptr Pointer acquired by calling init function
+     * @return [drift in milliseconds (positive when the video is ahead), dropped frames, repeated frames]
+     * @throws java.lang.RuntimeException if the player is not synchronized to a clock
+     */
+    external fun getSyncStats(ptr: Long): LongArray
+
+    /**
+     * Playback state of players that follow a master clock. Discord slash commands can pause and resume them too
+     * @param ptr Pointer acquired by calling init function
+     * @return false while STOP_RENDERING (or /pause) is in effect
+     * @throws java.lang.RuntimeException if the player is not synchronized to a clock
+     */
+    external fun isPlaying(ptr: Long): Boolean
+
     /**
      * Make sure that the target screen has enough resolution to handle output
      * @param fileName absolute path to file to be checked
@@ -32,6 +48,37 @@ object NativeRenderControler {
      */
     external fun init(fileName: String, type: NativeRenderType, serverOptions: MapServerOptions, useDiscord: Boolean): Long
 
+    /**
+     * Same as [NativeRenderControler.init], but audio of the video is played on the given sink
+     * @param audioSink "discord" (channel from [DiscordOptions]), "discord:<guildId>:<channelId>", "wav:<path>" (writes a WAV file)
+     * or "tcp:<ip>:<port>" (raw PCM stream for a client mod). Screens can use discord at the same time when they use different guilds
+     * @param audioTrack "" (default track), stream index ("2") or language ("jpn"), see [NativeRenderControler.getMediaTracks].
+     * Can be changed later with [NativeLibCommunication.AUDIO_TRACK]
+     * @return returns pointer to native memory. WARNING!! CHANGING THAT POINTER WILL CORRUPT MEMORY!
+     * @throws java.lang.RuntimeException if the sink is invalid, the guild is already used or the video has no matching audio track
+     */
+    external fun initWithAudioSink(fileName: String, type: NativeRenderType, serverOptions: MapServerOptions, audioSink: String, audioTrack: String): Long
+
+    /**
+     * List audio and subtitle tracks of a video
+     * @param fileName Video to probe
+     * @throws java.lang.RuntimeException if the file cannot be opened
+     */
+    external fun getMediaTracks(fileName: String): Array<MediaTrack>
+
+    /**
+     * List games that can be played with NativeRenderType.GAME
+     */
+    external fun getGames(): Array<GameInfo>
+
+    /**
+     * Draw subtitles onto the video (SINGLE_THREADED and MULTI_THREADED only). Bitmap subtitles (DVD, PGS) are not supported
+     * @param ptr Pointer acquired by calling init function
+     * @param options Subtitles to draw, null turns them off
+     * @throws java.lang.RuntimeException if the player does not support subtitles or the track cannot be loaded
+     */
+    external fun setSubtitles(ptr: Long, options: SubtitleOptions?)
+
     /**
      *
       */
@@ -54,9 +101,71 @@ object NativeRenderControler {
     /**
      * @param ptr Pointer acquired by calling init function
      * @param message Message to send
+     * @param additionalInfo fps for START_RENDERING, inputs joined with "_" for GAME_INPUT (deprecated, every input is a key press of player 0, use [NativeRenderControler.sendGameInput]), second for VIDEO_SEEK, track for AUDIO_TRACK (same format as in [NativeRenderControler.initWithAudioSink]),
+     * "title:<text>", "logo:<asset name>", "progress", "pause", "resume" or "hide" for OSD (SINGLE_THREADED and MULTI_THREADED only, shown from the next decoded frame. While STOP_RENDERING pauses the video the pause glyph is drawn over the frame on screen)
      * @throws java.lang.RuntimeException if rust panics during native call
      */
     external fun communicate(ptr: Long, message: NativeLibCommunication, additionalInfo: String)
 
+    /**
+     * Send input to a game (NativeRenderType.GAME)
+     * @param ptr Pointer acquired by calling init function
+     * @param events Events encoded by [GameInputEvent.encode]
+     * @throws java.lang.RuntimeException if the events are invalid or the player is not a game
+     */
+    external fun sendGameInput(ptr: Long, events: ByteArray)
+
+    /**
+     * Create native AES/CFB8 encryption state for an online-mode player connection.
+     * The cipher is a stream cipher, so it has to be created before the server encrypts its first byte (when the login key packet is handled).
+     * Once created, ALL bytes sent to that player must be encrypted by it (including vanilla packets), in the order they are written
+     * @param sharedSecret 16 byte shared secret negotiated during login
+     * @return returns pointer to native memory. WARNING!! CHANGING THAT POINTER WILL CORRUPT MEMORY!
+     * @throws java.lang.RuntimeException if rust panics during native call
+     */
+    external fun createEncryptedConnection(sharedSecret: ByteArray): Long
+
+    /**
+     * @param connectionPtr Pointer acquired by calling createEncryptedConnection function
+     * @param data Data to encrypt
+     * @return encrypted data, ready to be written into the channel
+     * @see me.wcaleniewolny.ayaya.library.NativeRenderControler.createEncryptedConnection
+     * @throws java.lang.RuntimeException if rust panics during native call
+     */
+    external fun encryptForConnection(connectionPtr: Long, data: ByteArray): ByteArray
+
+    /**
+     * Free native encryption state. After that using connectionPtr is an illegal operation.
+     * @param connectionPtr Pointer acquired by calling createEncryptedConnection function
+     * @throws java.lang.RuntimeException if rust panics during native call
+     */
+    external fun destroyEncryptedConnection(connectionPtr: Long)
+
+    /**
+     * Check if NativeRenderType.BLAZING can build packets for a protocol version (1.18.2 and 1.19.4 - 1.20.4 are supported)
+     * @param protocolVersion Protocol version of the server
+     * @throws java.lang.RuntimeException with the supported versions if the protocol version is not supported
+     */
+    external fun verifyBlazingProtocol(protocolVersion: Int)
+
+    /**
+     * Decode a video once and save it as pre-rendered map frames. The output can be played with NativeRenderType.BAKED
+     * @param fileName Video to bake
+     * @param outputFileName File that the baked video will be written to
+     * @throws java.lang.RuntimeException if the video cannot be decoded or the output cannot be written
+     */
+    external fun bakeVideo(fileName: String, outputFileName: String)
+
+    /**
+     * Extract audio of a video into a resource pack. Audio is split into streamed sounds named "ayaya:<packName>.<index>"
+     * @param fileName Video to extract the audio from
+     * @param outputFileName Resource pack zip that will be written
+     * @param packName Name of the sound events, only a-z, 0-9, _, . and - are allowed
+     * @param segmentSeconds Length of a single sound
+     * @return Video frame at which every sound has to be started
+     * @throws java.lang.RuntimeException if the video has no audio or the resource pack cannot be written
+     */
+    external fun extractAudio(fileName: String, outputFileName: String, packName: String, segmentSeconds: Int): IntArray
+
 
 }
\ No newline at end of file
@@ -6,5 +6,6 @@ enum class NativeRenderType {
     GAME,
     X11,
     EXTERNAL,
-    BLAZING
+    BLAZING,
+    BAKED
 }
\ No newline at end of file
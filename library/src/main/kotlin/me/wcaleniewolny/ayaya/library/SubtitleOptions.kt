@@ -0,0 +1,9 @@
+package me.wcaleniewolny.ayaya.library
+
+/**
+ * @param track "auto" (<video name>.srt next to the video, otherwise the default subtitle stream), "file:<path>" (SRT file)
+ * or a subtitle stream, same format as the audio track in [NativeRenderControler.initWithAudioSink]
+ * @param margin Distance from the top or bottom edge of the video, in pixels
+ * @param color RGB (0xRRGGBB), changed to the closest map color
+ */
+data class SubtitleOptions(val track: String, val fontSize: Int, val position: SubtitlePosition, val margin: Int, val color: Int, val outlineColor: Int, val outlineWidth: Int)
@@ -6,16 +6,15 @@ import me.wcaleniewolny.ayaya.library.NativeRenderControler
 import me.wcaleniewolny.ayaya.library.WindowsBootstrap
 import me.wcaleniewolny.ayaya.minecraft.command.VideoCommand
 import me.wcaleniewolny.ayaya.minecraft.command.VideoCommandCompletion
+import me.wcaleniewolny.ayaya.minecraft.display.encryption.NativeEncryption
 import me.wcaleniewolny.ayaya.minecraft.game.NativeGameController
 import me.wcaleniewolny.ayaya.minecraft.screen.ScreenController
 import net.kyori.adventure.text.minimessage.MiniMessage
+import net.minecraft.SharedConstants
 import org.bukkit.Bukkit
 import org.bukkit.command.CommandSender
 import org.bukkit.plugin.java.JavaPlugin
 import java.util.logging.Level
-import javax.crypto.Cipher
-import javax.crypto.spec.IvParameterSpec
-import javax.crypto.spec.SecretKeySpec
 
 class MapMinecraftClient : JavaPlugin() {
 
@@ -28,25 +27,16 @@ class MapMinecraftClient : JavaPlugin() {
         if (!loadNativeLib()) {
             return
         }
-
-        val charset = Charsets.UTF_8
-        val key = "hello from aes!!"
-        val iv = "shitty iv do not"
-        val plaintext = "hello world from javaaaaaaaaaaa ".toByteArray(charset)
-        val plaintextSecond = " goodbye world! ".toByteArray(charset)
-        val keySpec = SecretKeySpec(key.toByteArray(charset), "AES")
-        val ivSpec = IvParameterSpec(iv.toByteArray(charset))
-        val cipherE = Cipher.getInstance("AES/CFB8/NoPadding")
-        val cipherD = Cipher.getInstance("AES/CFB8/NoPadding")
-        cipherE.init(Cipher.ENCRYPT_MODE, keySpec, ivSpec);
-        cipherD.init(Cipher.DECRYPT_MODE, keySpec, ivSpec);
-        val outputJava = cipherE.update(plaintext)
-        val outputRust = NativeRenderControler.loadFrame(1000)
-        val outputJavaSec = cipherE.update(plaintext)
-
-        println(String(cipherD.update(outputJava)))
-        println(String(cipherD.update(outputRust)))
-        println(String(cipherD.update(outputJavaSec)))
+        NativeEncryption.register()
+
+        // Blazing player builds packets itself, it supports 1.18.2 and 1.19.4 - 1.20.4
+        val blazingSupported = try {
+            NativeRenderControler.verifyBlazingProtocol(SharedConstants.getProtocolVersion())
+            true
+        } catch (exception: RuntimeException) {
+            logger.log(Level.SEVERE, "Blazing player is disabled on this server version! ${exception.message}")
+            false
+        }
 
         if (config.getBoolean("useDiscordBot")) {
             NativeRenderControler.initDiscordBot(
@@ -73,7 +63,8 @@ class MapMinecraftClient : JavaPlugin() {
             VideoCommand(
                 screenController,
                 this.config,
-                this
+                this,
+                blazingSupported
             )
         )
 
@@ -142,6 +133,8 @@ class MapMinecraftClient : JavaPlugin() {
     }
 
     override fun onDisable() {
+        NativeEncryption.unregister()
+
         if (windowsBootstrapPtr != 0L) {
             WindowsBootstrap.cleanup(windowsBootstrapPtr)
         }
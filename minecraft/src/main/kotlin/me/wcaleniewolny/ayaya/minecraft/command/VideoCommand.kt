@@ -3,6 +3,8 @@ package me.wcaleniewolny.ayaya.minecraft.command
 import co.aikar.commands.BaseCommand
 import co.aikar.commands.CommandHelp
 import co.aikar.commands.annotation.*
+import me.wcaleniewolny.ayaya.library.NativeRenderControler
+import me.wcaleniewolny.ayaya.minecraft.game.GameJoinResult
 import me.wcaleniewolny.ayaya.minecraft.screen.Screen
 import me.wcaleniewolny.ayaya.minecraft.screen.ScreenController
 import me.wcaleniewolny.ayaya.minecraft.screen.ScreenFacing
@@ -20,7 +22,8 @@ import kotlin.math.min
 class VideoCommand(
     private val screenController: ScreenController,
     private val fileConfiguration: FileConfiguration,
-    private val plugin: JavaPlugin
+    private val plugin: JavaPlugin,
+    private val blazingSupported: Boolean
 ) : BaseCommand() {
 
     private val X11CaptureRegex = Pattern.compile("[0-9]+(?i-)x[0-9]+@[0-9]+")
@@ -71,6 +74,10 @@ class VideoCommand(
             sender.sendColoredMessage(fileConfiguration.getString("mapServerPlaybackNotAllowed")!!)
             return
         }
+        if (videoPlayType == VideoPlayType.BLAZING && !blazingSupported) {
+            sender.sendColoredMessage(fileConfiguration.getString("blazingUnsupportedVersion")!!)
+            return
+        }
         // val allowMapServer = plugin.config.getBoolean("allowMapServer")
 
         screenController.startPlayback(videoPlayType, file, sender, screen, discord != null && discord)
@@ -104,6 +111,86 @@ class VideoCommand(
         screenController.startGame(game, screen, sender)
     }
 
+    @Subcommand("replay")
+    @Description("Plays back a recorded game on selected screen")
+    @Syntax("[screen_id] [recording]")
+    @CommandCompletion("@screens @recordings @nothing")
+    fun onReplay(
+        sender: Player,
+        @Values("@screens") screenId: String,
+        @Values("@recordings") recording: String
+    ) {
+        val screenOptional = lookupScreen(sender, screenId)
+        if (screenOptional.isEmpty) {
+            return
+        }
+        val screen = screenOptional.get()
+
+        if (!screen.useGame) {
+            sender.sendColoredMessage(fileConfiguration.getString("screenNoGaming")!!)
+            return
+        }
+
+        if (screen.renderService.isPresent) {
+            sender.sendColoredMessage(fileConfiguration.getString("unableToStartPlayback")!!)
+            return
+        }
+
+        val recordingsDir = File(plugin.dataFolder, "recordings")
+        val file = File(recordingsDir, recording)
+        if (!file.normalize().path.startsWith(recordingsDir.normalize().path)) {
+            sender.sendColoredMessage(fileConfiguration.getString("pathTraversalAttempt")!!)
+            return
+        }
+
+        if (!file.exists()) {
+            sender.sendColoredMessage(fileConfiguration.getString("fileDoesNotExist")!!)
+            return
+        }
+
+        // Recordings are named <game>-<screen>-<time>.rec
+        val game = recording.substringBefore('-')
+        if (NativeRenderControler.getGames().none { it.id == game }) {
+            sender.sendColoredMessage(fileConfiguration.getString("recordingInvalid")!!)
+            return
+        }
+
+        screenController.startGame(game, screen, sender, file)
+    }
+
+    @Subcommand("join")
+    @Description("Joins game played on selected screen")
+    @Syntax("[screen_id]")
+    @CommandCompletion("@screens @nothing")
+    fun onJoin(
+        sender: Player,
+        @Values("@screens") screenId: String
+    ) {
+        val screenOptional = lookupScreen(sender, screenId)
+        if (screenOptional.isEmpty) {
+            return
+        }
+
+        val message = when (screenController.joinGame(screenOptional.get(), sender)) {
+            GameJoinResult.JOINED -> "success"
+            GameJoinResult.NOT_RUNNING -> "gameNotRunning"
+            GameJoinResult.FULL -> "gameFull"
+            GameJoinResult.ALREADY_PLAYING -> "gameAlreadyJoined"
+        }
+        sender.sendColoredMessage(fileConfiguration.getString(message)!!)
+    }
+
+    @Subcommand("leave")
+    @Description("Leaves the game you are playing")
+    fun onLeave(sender: Player) {
+        if (!screenController.leaveGame(sender)) {
+            sender.sendColoredMessage(fileConfiguration.getString("gameNotJoined")!!)
+            return
+        }
+
+        sender.sendColoredMessage(fileConfiguration.getString("success")!!)
+    }
+
     @Subcommand("seek")
     @Syntax("[screen_id]")
     @CommandCompletion("@screens @nothing")
@@ -128,6 +215,82 @@ class VideoCommand(
         }
     }
 
+    @Subcommand("tracks")
+    @Syntax("[video]")
+    @CommandCompletion("@video @nothing")
+    @Description("Lists audio and subtitle tracks of a video")
+    fun onTracks(
+        sender: CommandSender,
+        @Values("@video") video: String
+    ) {
+        val file = File(File(plugin.dataFolder, "video"), video)
+
+        if (!file.normalize().path.startsWith(File(plugin.dataFolder, "video").normalize().path)) {
+            sender.sendColoredMessage(fileConfiguration.getString("pathTraversalAttempt")!!)
+            return
+        }
+
+        if (!file.exists()) {
+            sender.sendColoredMessage(fileConfiguration.getString("fileDoesNotExist")!!)
+            return
+        }
+
+        val tracks = try {
+            NativeRenderControler.getMediaTracks(file.absolutePath)
+        } catch (exception: RuntimeException) {
+            sender.sendColoredMessage(fileConfiguration.getString("mediaTracksFailed")!!)
+            return
+        }
+
+        if (tracks.isEmpty()) {
+            sender.sendColoredMessage(fileConfiguration.getString("mediaTracksEmpty")!!)
+            return
+        }
+
+        tracks.forEach { track ->
+            val description = listOfNotNull(
+                track.index.toString(),
+                track.type.name.lowercase(),
+                track.language,
+                track.title,
+                track.codec,
+                if (track.default) "default" else null
+            ).joinToString(" ")
+            sender.sendColoredMessage(fileConfiguration.getString("mediaTrack")!!.replace("$", description))
+        }
+    }
+
+    @Subcommand("audiotrack")
+    @Syntax("[screen_id] [track]")
+    @CommandCompletion("@screens @nothing")
+    @Description("Switches the audio track of a video played with discord")
+    fun onAudioTrack(
+        sender: CommandSender,
+        @Values("@screens") screenId: String,
+        track: String
+    ) {
+        val screenOptional = lookupScreen(sender, screenId)
+        if (screenOptional.isEmpty) {
+            return
+        }
+
+        val renderServiceOptional = screenOptional.get().renderService
+        if (renderServiceOptional.isEmpty) {
+            sender.sendColoredMessage(fileConfiguration.getString("unableToPausePlayback")!!)
+            return
+        }
+
+        try {
+            renderServiceOptional.get().switchAudioTrack(track)
+        } catch (exception: RuntimeException) {
+            sender.sendColoredMessage(
+                fileConfiguration.getString("audioTrackSwitchFailed")!!.replace("$", exception.message ?: "")
+            )
+            return
+        }
+        sender.sendColoredMessage(fileConfiguration.getString("success")!!)
+    }
+
     @Subcommand("x11")
     @Syntax("[screen_id]")
     @CommandCompletion("@screens @nothing")
@@ -143,6 +306,10 @@ class VideoCommand(
             sender.sendColoredMessage(fileConfiguration.getString("mapServerPlaybackNotAllowed")!!)
             return
         }
+        if (videoPlayType == VideoPlayType.BLAZING && !blazingSupported) {
+            sender.sendColoredMessage(fileConfiguration.getString("blazingUnsupportedVersion")!!)
+            return
+        }
 
         if (!System.getProperty("os.name").contains("Linux", true)) {
             sender.sendColoredMessage(fileConfiguration.getString("x11NotLinux")!!)
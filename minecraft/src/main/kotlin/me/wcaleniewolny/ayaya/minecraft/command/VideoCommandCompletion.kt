@@ -1,6 +1,7 @@
 package me.wcaleniewolny.ayaya.minecraft.command
 
 import co.aikar.commands.PaperCommandManager
+import me.wcaleniewolny.ayaya.library.NativeRenderControler
 import me.wcaleniewolny.ayaya.minecraft.screen.ScreenController
 import org.bukkit.plugin.java.JavaPlugin
 import java.io.File
@@ -10,6 +11,9 @@ class VideoCommandCompletion(
 ) {
 
     fun init(plugin: JavaPlugin, manager: PaperCommandManager) {
+        //Games are compiled into the native library, so the list never changes
+        val games = NativeRenderControler.getGames().map { it.id }
+
         val dir = File(plugin.dataFolder, "video")
         if (!dir.exists()) {
             dir.mkdirs()
@@ -19,6 +23,11 @@ class VideoCommandCompletion(
             return@registerAsyncCompletion dir.listFiles()?.map { it.name } ?: mutableListOf()
         }
 
+        manager.commandCompletions.registerAsyncCompletion("recordings") {
+            return@registerAsyncCompletion File(plugin.dataFolder, "recordings").listFiles()?.map { it.name }
+                ?: mutableListOf()
+        }
+
         manager.commandCompletions.registerAsyncCompletion("lookingAt") {
             val lookingAt = it.player.getTargetBlock(4) ?: return@registerAsyncCompletion mutableListOf()
 
@@ -38,7 +47,7 @@ class VideoCommandCompletion(
         }
 
         manager.commandCompletions.registerAsyncCompletion("games") {
-            return@registerAsyncCompletion mutableListOf("falling_blocks", "snake")
+            return@registerAsyncCompletion games
         }
     }
 }
@@ -9,7 +9,8 @@ enum class VideoPlayType {
     GAME,
     X11,
     EXTERNAL,
-    BLAZING;
+    BLAZING,
+    BAKED;
 
     fun toNativeRenderType(): NativeRenderType {
         return when (this) {
@@ -20,6 +21,7 @@ enum class VideoPlayType {
             X11 -> NativeRenderType.X11
             EXTERNAL -> NativeRenderType.EXTERNAL
             BLAZING -> NativeRenderType.BLAZING
+            BAKED -> NativeRenderType.BAKED
         }
     }
 }
@@ -0,0 +1,66 @@
+package me.wcaleniewolny.ayaya.minecraft.display.encryption
+
+import io.netty.buffer.ByteBuf
+import io.netty.channel.ChannelHandlerContext
+import io.netty.channel.ChannelInboundHandlerAdapter
+import io.netty.handler.codec.MessageToByteEncoder
+import io.papermc.paper.network.ChannelInitializeListenerHolder
+import me.wcaleniewolny.ayaya.library.NativeRenderControler
+import net.kyori.adventure.key.Key
+import net.minecraft.network.protocol.login.ServerboundKeyPacket
+import net.minecraft.server.MinecraftServer
+
+// Online-mode connections are encrypted by native code, so raw packets of the blazing player can be written into them.
+// CFB8 is a stream cipher, a native cipher can't join a stream that is already running. The server's encoder is replaced
+// while the login key packet is handled: encryption has just been enabled and nothing has been encrypted yet
+object NativeEncryption {
+    private val listenerKey = Key.key("ayaya", "native_encryption")
+
+    fun register() {
+        ChannelInitializeListenerHolder.addListener(listenerKey) { channel ->
+            channel.pipeline().addBefore("packet_handler", "ayaya_key_capture", KeyCaptureHandler())
+        }
+    }
+
+    fun unregister() {
+        ChannelInitializeListenerHolder.removeListener(listenerKey)
+    }
+}
+
+private class KeyCaptureHandler : ChannelInboundHandlerAdapter() {
+    override fun channelRead(ctx: ChannelHandlerContext, msg: Any) {
+        if (msg !is ServerboundKeyPacket) {
+            ctx.fireChannelRead(msg)
+            return
+        }
+
+        // Invalid packets are rejected by the server, the connection is closed anyway
+        val sharedSecret = try {
+            msg.getSecretKey(MinecraftServer.getServer().keyPair.private).encoded
+        } catch (exception: Exception) {
+            null
+        }
+
+        val pipeline = ctx.pipeline()
+        ctx.fireChannelRead(msg)
+
+        // The server enables encryption while it handles the packet
+        if (sharedSecret != null && pipeline.get("encrypt") != null) {
+            val connectionPtr = NativeRenderControler.createEncryptedConnection(sharedSecret)
+            pipeline.replace("encrypt", "encrypt", NativeCipherEncoder(connectionPtr))
+        }
+        pipeline.remove(this)
+    }
+}
+
+private class NativeCipherEncoder(private val connectionPtr: Long) : MessageToByteEncoder<ByteBuf>() {
+    override fun encode(ctx: ChannelHandlerContext, msg: ByteBuf, out: ByteBuf) {
+        val data = ByteArray(msg.readableBytes())
+        msg.readBytes(data)
+        out.writeBytes(NativeRenderControler.encryptForConnection(connectionPtr, data))
+    }
+
+    override fun handlerRemoved(ctx: ChannelHandlerContext) {
+        NativeRenderControler.destroyEncryptedConnection(connectionPtr)
+    }
+}
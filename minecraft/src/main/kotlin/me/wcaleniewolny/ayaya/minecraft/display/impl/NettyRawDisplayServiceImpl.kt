@@ -18,7 +18,8 @@ class NettyRawDisplayServiceImpl (
             val friendly = FriendlyByteBuf(buf)
 
             println("Sending shit!")
-            (player as CraftPlayer).handle.connection.connection.channel.pipeline().firstContext().writeAndFlush(Unpooled.wrappedBuffer(data))
+            // Packets are already framed, only the encryption of online-mode connections is left (see NativeEncryption)
+            (player as CraftPlayer).handle.connection.connection.channel.pipeline().context("prepender").writeAndFlush(Unpooled.wrappedBuffer(data))
         //writeAndFlush(Unpooled.wrappedBuffer(data))
         }
 
@@ -0,0 +1,8 @@
+package me.wcaleniewolny.ayaya.minecraft.game
+
+enum class GameJoinResult {
+    JOINED,
+    NOT_RUNNING,
+    FULL,
+    ALREADY_PLAYING
+}
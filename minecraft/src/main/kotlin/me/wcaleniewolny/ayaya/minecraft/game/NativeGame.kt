@@ -1,5 +1,7 @@
 package me.wcaleniewolny.ayaya.minecraft.game
 
+import me.wcaleniewolny.ayaya.library.GameInputEvent
+import me.wcaleniewolny.ayaya.library.GameKey
 import me.wcaleniewolny.ayaya.minecraft.screen.Screen
 import org.bukkit.entity.Player
 import java.util.concurrent.ConcurrentLinkedQueue
@@ -7,13 +9,13 @@ import java.util.concurrent.ConcurrentLinkedQueue
 data class NativeGame(
     val screen: Screen,
     val player: Player,
-    val moveEventQueue: ConcurrentLinkedQueue<MoveDirection>
-)
-
-enum class MoveDirection(val shortName: String) {
-    FORWARD("F"),
-    BACKWARDS("B"),
-    LEFT("L"),
-    RIGHT("R"),
-    UP("U")
+    // Native id of the player, the one that started the game is 0
+    val playerId: Int,
+    val inputQueue: ConcurrentLinkedQueue<GameInputEvent>
+) {
+    // The server only sees moves, not held keys, so every move is a short press
+    fun press(key: GameKey) {
+        inputQueue.add(GameInputEvent.KeyDown(key))
+        inputQueue.add(GameInputEvent.KeyUp(key))
+    }
 }
@@ -1,10 +1,14 @@
 package me.wcaleniewolny.ayaya.minecraft.game
 
-import me.wcaleniewolny.ayaya.library.NativeLibCommunication
+import me.wcaleniewolny.ayaya.library.GameAction
+import me.wcaleniewolny.ayaya.library.GameInfo
+import me.wcaleniewolny.ayaya.library.GameInputEvent
+import me.wcaleniewolny.ayaya.library.GameKey
 import me.wcaleniewolny.ayaya.library.NativeRenderControler
 import me.wcaleniewolny.ayaya.minecraft.screen.Screen
 import org.bukkit.Bukkit
 import org.bukkit.Location
+import org.bukkit.block.Action
 import org.bukkit.block.BlockFace
 import org.bukkit.entity.Player
 import org.bukkit.event.EventHandler
@@ -14,30 +18,42 @@ import org.bukkit.event.block.BlockBreakEvent
 import org.bukkit.event.entity.EntityDamageByEntityEvent
 import org.bukkit.event.entity.EntityDamageEvent
 import org.bukkit.event.player.PlayerDropItemEvent
+import org.bukkit.event.player.PlayerInteractEvent
+import org.bukkit.event.player.PlayerItemHeldEvent
 import org.bukkit.event.player.PlayerMoveEvent
 import org.bukkit.event.player.PlayerQuitEvent
+import org.bukkit.event.player.PlayerSwapHandItemsEvent
+import org.bukkit.event.player.PlayerToggleSneakEvent
+import org.bukkit.event.player.PlayerToggleSprintEvent
+import org.bukkit.inventory.EquipmentSlot
 import org.bukkit.plugin.java.JavaPlugin
 import org.bukkit.potion.PotionEffect
 import org.bukkit.potion.PotionEffectType
 import org.bukkit.util.Vector
 import java.util.*
 import java.util.concurrent.ConcurrentLinkedQueue
+import java.util.concurrent.CopyOnWriteArrayList
 
 class NativeGameController(private val plugin: JavaPlugin) : Listener {
 
-    private val games = mutableListOf<NativeGame>()
+    // One entry for every player, players of the same screen share the game. Read by render threads
+    private val games = CopyOnWriteArrayList<NativeGame>()
+    private val runningGames = mutableMapOf<String, GameInfo>()
+
+    // Screen name and player id of players that left, they do not have an input queue anymore
+    private val pendingLeaves = ConcurrentLinkedQueue<Pair<String, Int>>()
 
     private fun verifyMove(
         xDelta: Double,
         yDelta: Double,
         zDelta: Double,
-        direction1: MoveDirection,
-        direction2: MoveDirection,
-        direction3: MoveDirection,
-        direction4: MoveDirection
-    ): MoveDirection? {
+        direction1: GameKey,
+        direction2: GameKey,
+        direction3: GameKey,
+        direction4: GameKey
+    ): GameKey? {
         return if (yDelta > 0) {
-            MoveDirection.UP
+            GameKey.JUMP
         } else if (zDelta < 0) {
             direction1
         } else if (zDelta > 0) {
@@ -60,6 +76,13 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
         val from = event.from
         val to = event.to
 
+        // The rotation is reset by cancelling the event, so every rotation is a delta like a captured mouse
+        val yawDelta = Location.normalizeYaw(to.yaw - from.yaw)
+        val pitchDelta = to.pitch - from.pitch
+        if (yawDelta != 0f || pitchDelta != 0f) {
+            game.inputQueue.add(GameInputEvent.Look(yawDelta, pitchDelta))
+        }
+
         if (from.x != to.x || from.y != to.y || from.z != to.z) {
             val playerDirection = player.facing
             val xDelta = to.x - from.x
@@ -72,10 +95,10 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
                         xDelta,
                         yDelta,
                         zDelta,
-                        MoveDirection.FORWARD,
-                        MoveDirection.BACKWARDS,
-                        MoveDirection.LEFT,
-                        MoveDirection.RIGHT
+                        GameKey.FORWARD,
+                        GameKey.BACKWARDS,
+                        GameKey.LEFT,
+                        GameKey.RIGHT
                     ) ?: return
                 }
 
@@ -84,10 +107,10 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
                         xDelta,
                         yDelta,
                         zDelta,
-                        MoveDirection.BACKWARDS,
-                        MoveDirection.FORWARD,
-                        MoveDirection.RIGHT,
-                        MoveDirection.LEFT
+                        GameKey.BACKWARDS,
+                        GameKey.FORWARD,
+                        GameKey.RIGHT,
+                        GameKey.LEFT
                     ) ?: return
                 }
 
@@ -96,10 +119,10 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
                         xDelta,
                         yDelta,
                         zDelta,
-                        MoveDirection.RIGHT,
-                        MoveDirection.LEFT,
-                        MoveDirection.FORWARD,
-                        MoveDirection.BACKWARDS
+                        GameKey.RIGHT,
+                        GameKey.LEFT,
+                        GameKey.FORWARD,
+                        GameKey.BACKWARDS
                     ) ?: return
                 }
 
@@ -108,40 +131,82 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
                         xDelta,
                         yDelta,
                         zDelta,
-                        MoveDirection.LEFT,
-                        MoveDirection.RIGHT,
-                        MoveDirection.BACKWARDS,
-                        MoveDirection.FORWARD
+                        GameKey.LEFT,
+                        GameKey.RIGHT,
+                        GameKey.BACKWARDS,
+                        GameKey.FORWARD
                     ) ?: return
                 }
 
                 else -> return
             }
 
-            game.moveEventQueue.add(direction)
+            game.press(direction)
         }
     }
 
     @EventHandler(priority = EventPriority.HIGH)
-    private fun onDropQuit(event: PlayerQuitEvent) {
-        val gameIndex = games.indexOfFirst { it.player == event.player }
-        if (gameIndex == -1) {
+    private fun onSneakEvent(event: PlayerToggleSneakEvent) {
+        val game = games.firstOrNull { it.player == event.player } ?: return
+
+        game.inputQueue.add(
+            if (event.isSneaking) GameInputEvent.KeyDown(GameKey.SNEAK) else GameInputEvent.KeyUp(GameKey.SNEAK)
+        )
+    }
+
+    @EventHandler(priority = EventPriority.HIGH)
+    private fun onSprintEvent(event: PlayerToggleSprintEvent) {
+        val game = games.firstOrNull { it.player == event.player } ?: return
+
+        game.inputQueue.add(
+            if (event.isSprinting) GameInputEvent.KeyDown(GameKey.SPRINT) else GameInputEvent.KeyUp(GameKey.SPRINT)
+        )
+    }
+
+    @EventHandler(priority = EventPriority.HIGH)
+    private fun onHotbarEvent(event: PlayerItemHeldEvent) {
+        val game = games.firstOrNull { it.player == event.player } ?: return
+
+        game.inputQueue.add(GameInputEvent.Hotbar(event.newSlot))
+    }
+
+    @EventHandler(priority = EventPriority.HIGH)
+    private fun onInteractEvent(event: PlayerInteractEvent) {
+        val game = games.firstOrNull { it.player == event.player } ?: return
+        event.isCancelled = true
+
+        // Right clicks fire once for every hand
+        if (event.hand == EquipmentSlot.OFF_HAND) {
             return
         }
 
-        val game = games[gameIndex]
-        game.player.removePotionEffect(PotionEffectType.SLOW)
-        game.screen.renderService.get().killRendering()
-        game.screen.renderService = Optional.empty()
+        val action = when (event.action) {
+            Action.LEFT_CLICK_AIR, Action.LEFT_CLICK_BLOCK -> GameAction.ATTACK
+            Action.RIGHT_CLICK_AIR, Action.RIGHT_CLICK_BLOCK -> GameAction.USE
+            else -> return
+        }
+        game.inputQueue.add(GameInputEvent.Action(action))
+    }
+
+    @EventHandler(priority = EventPriority.HIGH)
+    private fun onSwapHandsEvent(event: PlayerSwapHandItemsEvent) {
+        val game = games.firstOrNull { it.player == event.player } ?: return
+        event.isCancelled = true
+
+        game.inputQueue.add(GameInputEvent.Action(GameAction.SWAP_HANDS))
+    }
 
-        games.removeAt(gameIndex)
+    @EventHandler(priority = EventPriority.HIGH)
+    private fun onDropQuit(event: PlayerQuitEvent) {
+        leaveGame(event.player)
     }
 
     @EventHandler(priority = EventPriority.HIGH)
     private fun onDropEvent(event: PlayerDropItemEvent) {
-        games.firstOrNull { it.player == event.player } ?: return
+        val game = games.firstOrNull { it.player == event.player } ?: return
 
         event.isCancelled = true
+        game.inputQueue.add(GameInputEvent.Action(GameAction.DROP))
     }
 
     @EventHandler(priority = EventPriority.HIGH)
@@ -162,9 +227,11 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
     @EventHandler(priority = EventPriority.HIGH)
     private fun onPlayerAttack(event: EntityDamageByEntityEvent) {
         val player = if (event.damager is Player) event.damager as Player else return
-        games.firstOrNull { it.player == player } ?: return
+        val game = games.firstOrNull { it.player == player } ?: return
 
         event.isCancelled = true
+        // Hitting an entity is not an interact event
+        game.inputQueue.add(GameInputEvent.Action(GameAction.ATTACK))
     }
 
     fun init() {
@@ -175,15 +242,59 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
         games.forEach { game -> game.player.removePotionEffect(PotionEffectType.SLOW) }
     }
 
-    fun registerGamer(player: Player, screen: Screen) {
-        games.add(NativeGame(screen, player, ConcurrentLinkedQueue()))
+    fun registerGamer(player: Player, screen: Screen, game: String) {
+        runningGames[screen.name] = NativeRenderControler.getGames().first { it.id == game }
+        addGamer(player, screen, 0)
+    }
+
+    fun joinGame(player: Player, screen: Screen): GameJoinResult {
+        val info = runningGames[screen.name] ?: return GameJoinResult.NOT_RUNNING
+        if (games.any { it.player == player }) {
+            return GameJoinResult.ALREADY_PLAYING
+        }
+
+        val gamers = games.filter { it.screen == screen }
+        if (gamers.size >= info.maxPlayers) {
+            return GameJoinResult.FULL
+        }
+
+        val playerId = (0..255).first { id -> gamers.none { it.playerId == id } }
+        addGamer(player, screen, playerId).inputQueue.add(GameInputEvent.Join)
+        return GameJoinResult.JOINED
+    }
+
+    /**
+     * @return false if the player is not playing any game
+     */
+    fun leaveGame(player: Player): Boolean {
+        val game = games.firstOrNull { it.player == player } ?: return false
+        games.remove(game)
+        game.player.removePotionEffect(PotionEffectType.SLOW)
+
+        val screen = game.screen
+        if (games.any { it.screen == screen }) {
+            pendingLeaves.add(Pair(screen.name, game.playerId))
+            return true
+        }
+
+        // The last player stops the game
+        runningGames.remove(screen.name)
+        pendingLeaves.removeIf { it.first == screen.name }
+        screen.renderService.get().killRendering()
+        screen.renderService = Optional.empty()
+        return true
+    }
+
+    private fun addGamer(player: Player, screen: Screen, playerId: Int): NativeGame {
+        val game = NativeGame(screen, player, playerId, ConcurrentLinkedQueue())
+        games.add(game)
 
         val facing = when (screen.mapFace) {
             BlockFace.NORTH -> BlockFace.SOUTH
             BlockFace.SOUTH -> BlockFace.NORTH
             BlockFace.EAST -> BlockFace.WEST
             BlockFace.WEST -> BlockFace.EAST
-            else -> return
+            else -> return game
         }
 
         var location = Location(
@@ -197,32 +308,42 @@ class NativeGameController(private val plugin: JavaPlugin) : Listener {
 
         player.addPotionEffect(PotionEffect(PotionEffectType.SLOW, Int.MAX_VALUE, 0, true, false))
         player.teleport(location)
+        return game
     }
 
     fun unregisterScreen(screen: Screen) {
-        val gameIndex = games.indexOfFirst { it.screen == screen }
-        if (gameIndex == -1) {
-            return
-        }
-        val game = games.get(gameIndex)
-        game.player.removePotionEffect(PotionEffectType.SLOW)
+        val screenGames = games.filter { it.screen == screen }
+        screenGames.forEach { game -> game.player.removePotionEffect(PotionEffectType.SLOW) }
 
-        games.removeAt(gameIndex)
+        games.removeAll(screenGames.toSet())
+        runningGames.remove(screen.name)
+        pendingLeaves.removeIf { it.first == screen.name }
     }
 
     fun renderCallback(ptr: Long, screenName: String) {
-        val game = games.firstOrNull { it.screen.name == screenName } ?: return
-        val stringBuilder = StringBuilder()
+        val events = mutableListOf<Pair<Int, GameInputEvent>>()
+
+        // Leaves go first, a player that joins right after can get the same id
+        val leaves = pendingLeaves.iterator()
+        while (leaves.hasNext()) {
+            val (screen, playerId) = leaves.next()
+            if (screen == screenName) {
+                events.add(Pair(playerId, GameInputEvent.Leave))
+                leaves.remove()
+            }
+        }
 
-        while (true) {
-            val element = game.moveEventQueue.poll() ?: break
-            stringBuilder.append("_${element.shortName}")
+        games.filter { it.screen.name == screenName }.forEach { game ->
+            while (true) {
+                val element = game.inputQueue.poll() ?: break
+                events.add(Pair(game.playerId, element))
+            }
         }
 
-        if (stringBuilder.isEmpty()) {
+        if (events.isEmpty()) {
             return
         }
 
-        NativeRenderControler.communicate(ptr, NativeLibCommunication.GAME_INPUT, stringBuilder.toString())
+        NativeRenderControler.sendGameInput(ptr, GameInputEvent.encode(events))
     }
 }
@@ -9,4 +9,5 @@ interface RenderService {
     fun pauseRendering()
     fun killRendering()
     fun seekSecond(second: Int)
+    fun switchAudioTrack(track: String)
 }
@@ -11,7 +11,11 @@ import me.wcaleniewolny.ayaya.minecraft.render.impl.JavaRenderServiceImpl
 import me.wcaleniewolny.ayaya.minecraft.render.impl.NativeRenderServiceImpl
 import me.wcaleniewolny.ayaya.minecraft.render.impl.RenderThreadGameImpl
 import me.wcaleniewolny.ayaya.minecraft.render.impl.RenderThreadVideoImpl
+import net.minecraft.SharedConstants
+import org.bukkit.Bukkit
+import org.bukkit.craftbukkit.v1_18_R2.CraftServer
 import org.bukkit.plugin.java.JavaPlugin
+import java.io.File
 
 enum class RenderServiceType {
     NATIVE,
@@ -31,17 +35,33 @@ object RenderServiceFactory {
         renderCallback: ((ptr: Long, screenName: String) -> Unit)? = null,
         useDiscord: Boolean = false
     ): RenderService {
-        val nativeFilename = if (videoPlayType != VideoPlayType.BLAZING) filename else "${startID}$$$${filename}"
-        val ptr = NativeRenderControler.init(
-            nativeFilename,
-            videoPlayType.toNativeRenderType(),
-            MapServerOptions(
-                useServer,
-                plugin.config.getString("mapServerLocalIp")!!,
-                plugin.config.getInt("mapServerPort")
-            ),
-            useDiscord
+        val nativeFilename = if (videoPlayType != VideoPlayType.BLAZING) {
+            filename
+        } else {
+            // Blazing player writes raw packets, so it has to use the same compression as the server
+            val compressionThreshold = (Bukkit.getServer() as CraftServer).server.compressionThreshold
+            val protocolVersion = SharedConstants.getProtocolVersion()
+            // Empty lets the native side decide by the protocol version
+            val useBundles = if (plugin.config.contains("blazingBundles")) plugin.config.getBoolean("blazingBundles").toString() else ""
+            "${startID}$$$${compressionThreshold}$$$${protocolVersion}$$$${useBundles}$$$${filename}"
+        }
+        val serverOptions = MapServerOptions(
+            useServer,
+            plugin.config.getString("mapServerLocalIp")!!,
+            plugin.config.getInt("mapServerPort"),
+            File(plugin.dataFolder, "assets").absolutePath
         )
+        val ptr = if (useDiscord) {
+            NativeRenderControler.initWithAudioSink(
+                nativeFilename,
+                videoPlayType.toNativeRenderType(),
+                serverOptions,
+                "discord",
+                plugin.config.getString("discordAudioTrack") ?: ""
+            )
+        } else {
+            NativeRenderControler.init(nativeFilename, videoPlayType.toNativeRenderType(), serverOptions, false)
+        }
 
         val videoData = NativeRenderControler.getVideoData(ptr)
 
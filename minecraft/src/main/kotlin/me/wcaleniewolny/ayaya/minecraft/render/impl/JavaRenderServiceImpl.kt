@@ -31,18 +31,20 @@ open class JavaRenderServiceImpl(
             throw IllegalStateException("Cannot pause rendering due to render thread being not initialized")
         }
 
-        val isRunning = renderThread.renderFrames()
-        if (isRunning.get()) {
-            isRunning.set(false)
-            if (useDiscord) {
-                NativeRenderControler.communicate(renderThread.ptr(), NativeLibCommunication.STOP_RENDERING, "1")
-            }
-        } else {
-            isRunning.set(true)
-            if (useDiscord) {
-                NativeRenderControler.communicate(renderThread.ptr(), NativeLibCommunication.START_RENDERING, "1")
+        // Discord slash commands can pause the video too, so the native clock is the source of truth.
+        // Render thread keeps running, native code repeats the paused frame
+        if (useDiscord) {
+            val message = if (NativeRenderControler.isPlaying(renderThread.ptr())) {
+                NativeLibCommunication.STOP_RENDERING
+            } else {
+                NativeLibCommunication.START_RENDERING
             }
+            NativeRenderControler.communicate(renderThread.ptr(), message, "1")
+            return
         }
+
+        val isRunning = renderThread.renderFrames()
+        isRunning.set(!isRunning.get())
     }
 
     override fun killRendering() {
@@ -53,4 +55,8 @@ open class JavaRenderServiceImpl(
     override fun seekSecond(second: Int) {
         NativeRenderControler.communicate(renderThread.ptr(), NativeLibCommunication.VIDEO_SEEK, second.toString())
     }
+
+    override fun switchAudioTrack(track: String) {
+        NativeRenderControler.communicate(renderThread.ptr(), NativeLibCommunication.AUDIO_TRACK, track)
+    }
 }
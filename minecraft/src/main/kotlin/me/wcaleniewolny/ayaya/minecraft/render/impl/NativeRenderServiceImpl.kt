@@ -15,7 +15,6 @@ import org.bukkit.plugin.messaging.PluginMessageListener
 import java.lang.Thread.sleep
 import java.nio.charset.StandardCharsets
 import java.util.*
-import java.util.concurrent.atomic.AtomicBoolean
 import java.util.concurrent.atomic.AtomicInteger
 import kotlin.math.roundToInt
 
@@ -34,7 +33,6 @@ class NativeRenderServiceImpl(
     private val responders = ArrayList<UUID>()
     private val handshakeNumber = AtomicInteger(0)
     private var isInitialized = false
-    private var isRunning = AtomicBoolean(false)
 
     override fun init(plugin: JavaPlugin) {
         plugin.server.messenger.registerOutgoingPluginChannel(plugin, "fastmap:handshake")
@@ -85,7 +83,6 @@ class NativeRenderServiceImpl(
                 }
 
                 NativeRenderControler.communicate(ptr, NativeLibCommunication.START_RENDERING, videoData.fps.toString())
-                isRunning.set(true)
                 isInitialized = true
             }
         )
@@ -95,12 +92,11 @@ class NativeRenderServiceImpl(
         if (!isInitialized) {
             throw IllegalStateException("Render is not initialized!")
         }
-        if (isRunning.get()) {
+        // Map server clock is paused by discord slash commands too
+        if (NativeRenderControler.isPlaying(ptr)) {
             NativeRenderControler.communicate(ptr, NativeLibCommunication.STOP_RENDERING, "")
-            isRunning.set(false)
         } else {
             NativeRenderControler.communicate(ptr, NativeLibCommunication.START_RENDERING, videoData.fps.toString())
-            isRunning.set(true)
         }
     }
 
@@ -170,4 +166,8 @@ class NativeRenderServiceImpl(
     override fun seekSecond(second: Int) {
         TODO("Not yet implemented")
     }
+
+    override fun switchAudioTrack(track: String) {
+        NativeRenderControler.communicate(ptr, NativeLibCommunication.AUDIO_TRACK, track)
+    }
 }
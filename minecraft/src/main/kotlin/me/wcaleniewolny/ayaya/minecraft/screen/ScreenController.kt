@@ -5,6 +5,7 @@ import me.wcaleniewolny.ayaya.library.VideoRequestCapablyResponse
 import me.wcaleniewolny.ayaya.minecraft.command.VideoPlayType
 import me.wcaleniewolny.ayaya.minecraft.display.broadcaster.impl.MinecraftNativeBroadcaster
 import me.wcaleniewolny.ayaya.minecraft.extenstion.forEachIn
+import me.wcaleniewolny.ayaya.minecraft.game.GameJoinResult
 import me.wcaleniewolny.ayaya.minecraft.game.NativeGameController
 import me.wcaleniewolny.ayaya.minecraft.render.RenderServiceFactory
 import me.wcaleniewolny.ayaya.minecraft.render.RenderServiceType
@@ -206,14 +207,34 @@ class ScreenController(
         )
     }
 
+    /**
+     * @param replay recording to play back instead of a new session, its input replaces the one of the players until it ends
+     */
     fun startGame(
         game: String,
         screen: Screen,
-        player: Player
+        player: Player,
+        replay: File? = null
     ) {
+        val options = mutableListOf<String>()
+        if (replay != null) {
+            options.add("replay=${replay.absolutePath}")
+        } else {
+            if (plugin.config.contains("gameSeed")) {
+                options.add("seed=${plugin.config.getLong("gameSeed")}")
+            }
+            if (plugin.config.getBoolean("recordGames")) {
+                val recordingsDir = File(plugin.dataFolder, "recordings")
+                recordingsDir.mkdirs()
+                val recording = File(recordingsDir, "$game-${screen.name}-${System.currentTimeMillis()}.rec")
+                options.add("record=${recording.absolutePath}")
+            }
+        }
+
         val renderService = RenderServiceFactory.create(
             plugin,
-            game, // Note: in game mode this will load selected game
+            // Note: in game mode this will load selected game, options follow the id like in an url query
+            if (options.isEmpty()) game else "$game?${options.joinToString("&")}",
             screen.name,
             screen.startID,
             false,
@@ -222,12 +243,20 @@ class ScreenController(
             nativeGameController::renderCallback
         )
 
-        nativeGameController.registerGamer(player, screen)
+        nativeGameController.registerGamer(player, screen, game)
 
         screen.renderService = Optional.of(renderService)
         renderService.startRendering()
     }
 
+    fun joinGame(screen: Screen, player: Player): GameJoinResult {
+        return nativeGameController.joinGame(player, screen)
+    }
+
+    fun leaveGame(player: Player): Boolean {
+        return nativeGameController.leaveGame(player)
+    }
+
     fun startX11(
         screen: Screen,
         useMapServer: Boolean,